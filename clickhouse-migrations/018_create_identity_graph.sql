@@ -0,0 +1,54 @@
+-- ============================================================
+-- 018: Identity graph with canonical person IDs
+-- ============================================================
+-- identity_map only holds anonymous_id -> user_id pairs, so a user
+-- with several anonymous devices or a changed user_id ends up split
+-- across several user_uids. identity_graph maps every linked distinct
+-- ID (anonymous or known) to the person_id of its connected component.
+-- ch-writer maintains it from Identify and Alias events; the admin
+-- API resolves user_uid through it.
+--
+-- Rows are only ever replaced, never deleted: a merge re-points every
+-- member of the absorbed person with a higher version.
+
+CREATE TABLE IF NOT EXISTS truesight.identity_graph
+(
+    project_id UUID,
+    distinct_id String,
+    person_id String,
+    person_created_at DateTime64(3),
+    version UInt64
+)
+ENGINE = ReplacingMergeTree(version)
+ORDER BY (project_id, distinct_id);
+
+-- ============================================================
+-- Seed from identity_map: each user_id becomes a person that owns
+-- itself and every anonymous_id identified as it.
+-- ============================================================
+INSERT INTO truesight.identity_graph
+    (project_id, distinct_id, person_id, person_created_at, version)
+WITH persons AS (
+    SELECT project_id, user_id, min(first_seen) AS created_at
+    FROM (SELECT * FROM truesight.identity_map FINAL)
+    WHERE user_id != ''
+    GROUP BY project_id, user_id
+)
+SELECT
+    m.project_id,
+    m.anonymous_id AS distinct_id,
+    m.user_id AS person_id,
+    p.created_at AS person_created_at,
+    0 AS version
+FROM (SELECT * FROM truesight.identity_map FINAL) AS m
+INNER JOIN persons AS p
+    ON p.project_id = m.project_id AND p.user_id = m.user_id
+WHERE m.anonymous_id != m.user_id
+UNION ALL
+SELECT
+    project_id,
+    user_id AS distinct_id,
+    user_id AS person_id,
+    created_at AS person_created_at,
+    0 AS version
+FROM persons;
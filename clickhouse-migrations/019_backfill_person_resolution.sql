@@ -0,0 +1,65 @@
+-- ============================================================
+-- 019: Re-backfill derived user tables with identity graph persons
+-- ============================================================
+-- The admin API resolves the user_uid of users_daily, user_first_seen
+-- and user_stats rows through identity_graph at query time, so links
+-- made later need no rewrite. This one-off backfill replaces the
+-- identity_map resolution of 017, keying every existing row by its
+-- identity_graph person_id.
+
+-- ============================================================
+-- 1. Re-backfill users_daily
+-- ============================================================
+TRUNCATE TABLE truesight.users_daily;
+
+INSERT INTO truesight.users_daily
+    (project_id, user_uid, environment, event_date)
+SELECT
+    e.project_id,
+    COALESCE(NULLIF(g.person_id, ''), NULLIF(e.user_id, ''), e.anonymous_id) AS user_uid,
+    e.environment,
+    toDate(e.server_timestamp) AS event_date
+FROM truesight.events AS e
+LEFT JOIN (SELECT * FROM truesight.identity_graph FINAL) AS g
+    ON g.project_id = e.project_id AND g.distinct_id = e.anonymous_id
+GROUP BY ALL;
+
+-- ============================================================
+-- 2. Re-backfill user_first_seen
+-- ============================================================
+TRUNCATE TABLE truesight.user_first_seen;
+
+INSERT INTO truesight.user_first_seen
+    (project_id, user_uid, environment, first_seen_date)
+SELECT
+    e.project_id,
+    COALESCE(NULLIF(g.person_id, ''), NULLIF(e.user_id, ''), e.anonymous_id) AS user_uid,
+    e.environment,
+    toDate(min(e.server_timestamp)) AS first_seen_date
+FROM truesight.events AS e
+LEFT JOIN (SELECT * FROM truesight.identity_graph FINAL) AS g
+    ON g.project_id = e.project_id AND g.distinct_id = e.anonymous_id
+GROUP BY e.project_id, user_uid, e.environment;
+
+-- ============================================================
+-- 3. Re-backfill user_stats
+-- ============================================================
+TRUNCATE TABLE truesight.user_stats;
+
+INSERT INTO truesight.user_stats
+    (project_id, user_uid, environment, event_count, first_seen, last_seen)
+SELECT
+    e.project_id,
+    COALESCE(NULLIF(g.person_id, ''), NULLIF(e.user_id, ''), e.anonymous_id) AS user_uid,
+    e.environment,
+    count() AS event_count,
+    min(e.server_timestamp) AS first_seen,
+    max(e.server_timestamp) AS last_seen
+FROM truesight.events AS e
+LEFT JOIN (SELECT * FROM truesight.identity_graph FINAL) AS g
+    ON g.project_id = e.project_id AND g.distinct_id = e.anonymous_id
+GROUP BY e.project_id, user_uid, e.environment;
+
+OPTIMIZE TABLE truesight.users_daily FINAL;
+OPTIMIZE TABLE truesight.user_first_seen FINAL;
+OPTIMIZE TABLE truesight.user_stats FINAL;
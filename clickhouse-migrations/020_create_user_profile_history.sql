@@ -0,0 +1,36 @@
+-- ============================================================
+-- 020: User profile property history
+-- ============================================================
+-- user_profiles only keeps the latest traits. ch-writer appends every
+-- property change here, keyed by the server_timestamp of the event that
+-- made it, so queries can read a profile property as of event time
+-- (ASOF JOIN on valid_from). An unset is recorded as an empty value.
+-- The promoted columns are tracked as 'email', 'name' and
+-- 'mobile_number'.
+
+CREATE TABLE IF NOT EXISTS truesight.user_profile_history (
+    project_id UUID,
+    user_uid String,
+    environment LowCardinality(String) DEFAULT 'live',
+    property String,
+    value String,
+    valid_from DateTime64(3)
+) ENGINE = ReplacingMergeTree()
+ORDER BY (project_id, property, environment, user_uid, valid_from);
+
+-- Seed with the current profiles. Their change times are unknown, so
+-- each value is assumed to have held since the profile's first_seen.
+INSERT INTO truesight.user_profile_history
+    (project_id, user_uid, environment, property, value, valid_from)
+SELECT
+    project_id,
+    user_uid,
+    environment,
+    kv.1 AS property,
+    kv.2 AS value,
+    first_seen AS valid_from
+FROM (SELECT * FROM truesight.user_profiles FINAL)
+ARRAY JOIN arrayFilter(x -> x.2 != '', arrayConcat(
+    arrayZip(mapKeys(properties), mapValues(properties)),
+    [('email', ifNull(email, '')), ('name', ifNull(name, '')), ('mobile_number', ifNull(mobile_number, ''))]
+)) AS kv;
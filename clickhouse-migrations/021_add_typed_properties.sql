@@ -0,0 +1,68 @@
+-- ============================================================
+-- 021: Typed property maps
+-- ============================================================
+-- properties_map holds every top-level property as a String, so range
+-- filters compare lexically ('9' > '10') and numbers cannot be summed.
+-- ch-writer also writes each property into the map matching its type:
+--   properties_num      JSON numbers and numeric strings
+--   properties_bool     JSON booleans and 'true' / 'false'
+--   properties_datetime ISO-8601 dates and timestamps (UTC)
+-- The DEFAULT expressions apply the same rules to rows inserted without
+-- them and are materialized for existing rows by 022.
+
+ALTER TABLE truesight.events
+  ADD COLUMN IF NOT EXISTS properties_num Map(String, Float64)
+  DEFAULT CAST(
+    arrayMap(
+      kv -> (kv.1, toFloat64(kv.2)),
+      arrayFilter(
+        kv -> match(kv.2, '^-?[0-9]+(\\.[0-9]+)?([eE][+-]?[0-9]+)?$'),
+        arrayMap(
+          kv -> (kv.1, if(startsWith(kv.2, '"'), JSONExtractString(kv.2), kv.2)),
+          JSONExtractKeysAndValuesRaw(if(properties = '', '{}', properties))
+        )
+      )
+    ),
+    'Map(String, Float64)'
+  )
+  AFTER properties_map;
+
+ALTER TABLE truesight.events
+  ADD COLUMN IF NOT EXISTS properties_bool Map(String, Bool)
+  DEFAULT CAST(
+    arrayMap(
+      kv -> (kv.1, kv.2 = 'true'),
+      arrayFilter(
+        kv -> kv.2 IN ('true', 'false'),
+        arrayMap(
+          kv -> (kv.1, if(startsWith(kv.2, '"'), JSONExtractString(kv.2), kv.2)),
+          JSONExtractKeysAndValuesRaw(if(properties = '', '{}', properties))
+        )
+      )
+    ),
+    'Map(String, Bool)'
+  )
+  AFTER properties_num;
+
+ALTER TABLE truesight.events
+  ADD COLUMN IF NOT EXISTS properties_datetime Map(String, DateTime64(3, 'UTC'))
+  DEFAULT CAST(
+    arrayMap(
+      kv -> (kv.1, parseDateTime64BestEffort(kv.2, 3, 'UTC')),
+      arrayFilter(
+        kv -> match(kv.2, '^[0-9]{4}-[0-9]{2}-[0-9]{2}(T[0-9]{2}:[0-9]{2}:[0-9]{2}(\\.[0-9]+)?(Z|[+-][0-9]{2}:[0-9]{2}))?$'),
+        arrayMap(
+          kv -> (kv.1, JSONExtractString(kv.2)),
+          arrayFilter(
+            kv -> startsWith(kv.2, '"'),
+            JSONExtractKeysAndValuesRaw(if(properties = '', '{}', properties))
+          )
+        )
+      )
+    ),
+    'Map(String, DateTime64(3, \'UTC\'))'
+  )
+  AFTER properties_bool;
+
+ALTER TABLE truesight.events
+  ADD INDEX IF NOT EXISTS idx_props_num_keys mapKeys(properties_num) TYPE bloom_filter GRANULARITY 4;
@@ -0,0 +1,9 @@
+-- Backfill the typed property maps for existing rows.
+-- Each MATERIALIZE COLUMN is a mutation that rewrites parts with the
+-- column's DEFAULT expression.
+
+ALTER TABLE truesight.events MATERIALIZE COLUMN properties_num;
+
+ALTER TABLE truesight.events MATERIALIZE COLUMN properties_bool;
+
+ALTER TABLE truesight.events MATERIALIZE COLUMN properties_datetime;
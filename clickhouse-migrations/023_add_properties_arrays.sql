@@ -0,0 +1,47 @@
+-- ============================================================
+-- 023: Array properties and nested property keys
+-- ============================================================
+-- ch-writer flattens nested property objects into dotted paths in
+-- properties_map (address.city) and writes array properties, and fields of
+-- objects in arrays (items[].sku), to properties_arrays so they can be
+-- queried with has() and length(). Elements are stored as strings.
+-- The DEFAULT expression covers top-level arrays of rows inserted without
+-- the column and is materialized for existing rows by 024.
+
+ALTER TABLE truesight.events
+  ADD COLUMN IF NOT EXISTS properties_arrays Map(String, Array(String))
+  DEFAULT CAST(
+    arrayMap(
+      kv -> (
+        kv.1,
+        arrayMap(
+          v -> if(startsWith(v, '"'), JSONExtractString(v), v),
+          arraySlice(JSONExtractArrayRaw(kv.2), 1, 1000)
+        )
+      ),
+      arrayFilter(
+        kv -> startsWith(kv.2, '['),
+        JSONExtractKeysAndValuesRaw(if(properties = '', '{}', properties))
+      )
+    ),
+    'Map(String, Array(String))'
+  )
+  AFTER properties_datetime;
+
+ALTER TABLE truesight.events
+  ADD INDEX IF NOT EXISTS idx_props_array_keys mapKeys(properties_arrays) TYPE bloom_filter GRANULARITY 4;
+
+-- List array paths alongside map keys in the event catalog.
+DROP VIEW IF EXISTS truesight.event_property_keys_mv;
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS truesight.event_property_keys_mv
+TO truesight.event_property_keys AS
+SELECT
+    project_id,
+    event_name,
+    environment,
+    arrayJoin(arrayDistinct(arrayConcat(mapKeys(properties_map), mapKeys(properties_arrays)))) AS property_key,
+    min(server_timestamp) AS first_seen
+FROM truesight.events
+WHERE length(mapKeys(properties_map)) > 0 OR length(mapKeys(properties_arrays)) > 0
+GROUP BY project_id, event_name, environment, property_key;
@@ -0,0 +1,4 @@
+-- Backfill properties_arrays for existing rows with the column's DEFAULT
+-- expression (top-level arrays only; nested paths apply to new events).
+
+ALTER TABLE truesight.events MATERIALIZE COLUMN properties_arrays;
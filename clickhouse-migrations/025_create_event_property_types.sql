@@ -0,0 +1,92 @@
+-- ============================================================
+-- 025: Property value types in the event catalog
+-- ============================================================
+-- ch-writer records the JSON type of every property path of an event
+-- (string, number, boolean, object, array) in property_types. The DEFAULT
+-- expression types top-level properties of rows inserted without it.
+-- event_property_types counts each (property, type, sdk_version) so that
+-- admin-api can report properties whose type changed, and which SDK
+-- versions sent each type.
+--
+-- The view counts every event inserted once it exists; the backfill only
+-- events still at the inserted_at default, i.e. inserted before the
+-- column. ch-writer and the admin DLQ redrive set inserted_at to the
+-- insert time. The default is a constant, so existing parts need no
+-- MATERIALIZE. An older ch-writer still running during the upgrade
+-- writes the default, so its events are counted twice only if inserted
+-- between the view's creation and the backfill.
+
+ALTER TABLE truesight.events
+  ADD COLUMN IF NOT EXISTS property_types Map(String, LowCardinality(String))
+  DEFAULT CAST(
+    arrayMap(
+      kv -> (
+        kv.1,
+        multiIf(
+          startsWith(kv.2, '"'), 'string',
+          startsWith(kv.2, '{'), 'object',
+          startsWith(kv.2, '['), 'array',
+          kv.2 IN ('true', 'false'), 'boolean',
+          'number'
+        )
+      ),
+      arrayFilter(
+        kv -> kv.2 != 'null',
+        JSONExtractKeysAndValuesRaw(if(properties = '', '{}', properties))
+      )
+    ),
+    'Map(String, LowCardinality(String))'
+  )
+  AFTER properties_arrays;
+
+ALTER TABLE truesight.events
+  ADD COLUMN IF NOT EXISTS inserted_at DateTime64(3)
+  DEFAULT toDateTime64('1970-01-01 00:00:00', 3, 'UTC');
+
+CREATE TABLE IF NOT EXISTS truesight.event_property_types (
+    project_id UUID,
+    event_name LowCardinality(String),
+    environment LowCardinality(String) DEFAULT 'live',
+    property_key String,
+    value_type LowCardinality(String),
+    sdk_version LowCardinality(String),
+    event_count SimpleAggregateFunction(sum, UInt64),
+    first_seen SimpleAggregateFunction(min, DateTime64(3)),
+    last_seen SimpleAggregateFunction(max, DateTime64(3))
+) ENGINE = AggregatingMergeTree()
+ORDER BY (project_id, environment, event_name, property_key, value_type, sdk_version);
+
+CREATE MATERIALIZED VIEW IF NOT EXISTS truesight.event_property_types_mv
+TO truesight.event_property_types AS
+SELECT
+    project_id,
+    event_name,
+    environment,
+    property_key,
+    value_type,
+    sdk_version,
+    count() AS event_count,
+    min(server_timestamp) AS first_seen,
+    max(server_timestamp) AS last_seen
+FROM truesight.events
+ARRAY JOIN mapKeys(property_types) AS property_key, mapValues(property_types) AS value_type
+GROUP BY project_id, event_name, environment, property_key, value_type, sdk_version;
+
+-- Backfill from existing data (top-level properties only).
+INSERT INTO truesight.event_property_types
+SELECT
+    project_id,
+    event_name,
+    environment,
+    property_key,
+    value_type,
+    sdk_version,
+    count() AS event_count,
+    min(server_timestamp) AS first_seen,
+    max(server_timestamp) AS last_seen
+FROM truesight.events
+ARRAY JOIN mapKeys(property_types) AS property_key, mapValues(property_types) AS value_type
+WHERE inserted_at < toDateTime64('1970-01-01 00:00:01', 3, 'UTC')
+GROUP BY project_id, event_name, environment, property_key, value_type, sdk_version;
+
+OPTIMIZE TABLE truesight.event_property_types FINAL;
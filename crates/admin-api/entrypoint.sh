@@ -2,12 +2,9 @@
 set -euo pipefail
 
 echo "Running ClickHouse migrations..."
-export CLICKHOUSE_DATABASE="${CLICKHOUSE_DATABASE:-truesight}"
-export CLICKHOUSE_URL="${CLICKHOUSE_URL:-http://localhost:8123}"
-
-# ClickHouse Cloud requires HTTPS + user/password auth
-CH_MIGRATE_URL="${CLICKHOUSE_URL}/?user=${CLICKHOUSE_USER:-default}&password=${CLICKHOUSE_PASSWORD:-}"
-CLICKHOUSE_URL="$CH_MIGRATE_URL" ./scripts/migrate_clickhouse.sh
+# Reads CLICKHOUSE_URL, CLICKHOUSE_USER, CLICKHOUSE_PASSWORD and
+# CLICKHOUSE_DATABASE; refuses to start on a modified migration.
+./truesight-migrate
 
 echo "Starting admin-api..."
 exec ./admin-api
@@ -0,0 +1,587 @@
+//! Data subject access export worker.
+//!
+//! Exports (see [`truesight_common::data_export`]) are queued in Postgres
+//! and built here one at a time. The user is resolved the same way as by the
+//! users endpoints: [`fetch_user_detail`] for the profile and
+//! [`USER_UID_EXPR`] for the events of every distinct ID linked to the
+//! person. The archive is a tar of
+//!
+//! - `profile.json`: the profile and the linked distinct IDs;
+//! - `identities.json|csv`: the user's `identity_map` rows;
+//! - `events-00001.json|csv`, `events-00002.json|csv`, ...: every event,
+//!   oldest first, [`EVENTS_PER_FILE`] per file, with every column of
+//!   `events` the SDK sent ([`EVENT_COLUMNS`]);
+//!
+//! streamed zstd-compressed to the [`ExportStore`] (`EXPORT_URL`) as it is
+//! built, so no export is ever held in memory whole. Postgres only records
+//! the archive's key until it expires.
+
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use axum::body::Body;
+use chrono::Utc;
+use futures::TryStreamExt;
+use object_store::path::Path;
+use object_store::{ObjectStore, WriteMultipart};
+use serde::{Deserialize, Serialize};
+use tokio_util::io::{ReaderStream, StreamReader};
+use truesight_common::config::AdminConfig;
+use truesight_common::data_export::{DataExport, EXPORT_TTL_DAYS};
+use truesight_common::db::DbPool;
+
+use crate::db::data_exports as db;
+use crate::handlers::query_builder::{USER_UID_EXPR, identity_join};
+use crate::handlers::users_ch::{UserDetailRow, fetch_user_detail};
+
+/// How often the worker looks for new exports.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Events per events file. Progress is recorded after each file.
+const EVENTS_PER_FILE: i64 = 10_000;
+
+/// Archive parts uploaded concurrently; each is about 5 MB.
+const MAX_UPLOAD_PARTS: usize = 4;
+
+#[derive(Debug, Serialize, clickhouse::Row, Deserialize)]
+struct IdentityRow {
+    anonymous_id: String,
+    user_id: String,
+    first_seen: String,
+    last_seen: String,
+}
+
+/// Columns of `events` in an export, in [`ExportEventRow`] order. The
+/// columns derived from `properties` (`properties_map` and the typed
+/// property arrays) are left out as they hold nothing `properties` doesn't,
+/// as is the `inserted_at` bookkeeping column.
+const EVENT_COLUMNS: [&str; 23] = [
+    "event_id",
+    "project_id",
+    "environment",
+    "event_name",
+    "event_type",
+    "user_id",
+    "anonymous_id",
+    "email",
+    "mobile_number",
+    "session_id",
+    "client_timestamp",
+    "server_timestamp",
+    "properties",
+    "app_version",
+    "os_name",
+    "os_version",
+    "device_model",
+    "device_id",
+    "network_type",
+    "locale",
+    "timezone",
+    "sdk_version",
+    "platform",
+];
+
+/// An exported event. Nullable columns are exported as empty strings.
+#[derive(Debug, Serialize, clickhouse::Row, Deserialize)]
+struct ExportEventRow {
+    event_id: String,
+    project_id: String,
+    environment: String,
+    event_name: String,
+    event_type: String,
+    user_id: String,
+    anonymous_id: String,
+    email: String,
+    mobile_number: String,
+    session_id: String,
+    client_timestamp: String,
+    server_timestamp: String,
+    properties: String,
+    app_version: String,
+    os_name: String,
+    os_version: String,
+    device_model: String,
+    device_id: String,
+    network_type: String,
+    locale: String,
+    timezone: String,
+    sdk_version: String,
+    platform: String,
+}
+
+impl ExportEventRow {
+    /// Field values in [`EVENT_COLUMNS`] order.
+    fn fields(&self) -> [&str; 23] {
+        [
+            &self.event_id,
+            &self.project_id,
+            &self.environment,
+            &self.event_name,
+            &self.event_type,
+            &self.user_id,
+            &self.anonymous_id,
+            &self.email,
+            &self.mobile_number,
+            &self.session_id,
+            &self.client_timestamp,
+            &self.server_timestamp,
+            &self.properties,
+            &self.app_version,
+            &self.os_name,
+            &self.os_version,
+            &self.device_model,
+            &self.device_id,
+            &self.network_type,
+            &self.locale,
+            &self.timezone,
+            &self.sdk_version,
+            &self.platform,
+        ]
+    }
+}
+
+#[derive(clickhouse::Row, Deserialize)]
+struct IdRow {
+    id: String,
+}
+
+#[derive(Serialize)]
+struct ProfileFile<'a> {
+    user: &'a UserDetailRow,
+    /// Anonymous and known IDs linked to the person.
+    distinct_ids: &'a [String],
+}
+
+/// Object store holding the export archives.
+pub struct ExportStore {
+    store: Arc<dyn ObjectStore>,
+    root: Path,
+}
+
+impl ExportStore {
+    /// Opens the store at `EXPORT_URL`, or returns `None` when exports are
+    /// disabled. See [`truesight_common::storage::open`] for the URLs.
+    pub fn from_config(config: &AdminConfig) -> Result<Option<Self>> {
+        let Some(url) = config.export_url.as_deref().filter(|u| !u.is_empty()) else {
+            return Ok(None);
+        };
+        let (store, root) = truesight_common::storage::open(
+            url,
+            config.export_s3_endpoint_url.as_deref(),
+            &config.aws_region,
+        )
+        .context("failed to open export store")?;
+        tracing::info!(export_url = url, "data exports enabled");
+        Ok(Some(Self { store, root }))
+    }
+
+    fn key(&self, export: &DataExport) -> Path {
+        self.root.child(format!("{}.tar.zst", export.id))
+    }
+
+    /// Streams the archive stored under `key` as an uncompressed tar.
+    pub async fn download(&self, key: &str) -> object_store::Result<Body> {
+        let compressed = self
+            .store
+            .get(&Path::from(key))
+            .await?
+            .into_stream()
+            .map_err(std::io::Error::other);
+        let tar =
+            async_compression::tokio::bufread::ZstdDecoder::new(StreamReader::new(compressed));
+        Ok(Body::from_stream(ReaderStream::new(tar)))
+    }
+
+    /// Deletes the archive stored under `key`, if it still exists.
+    async fn delete(&self, key: &str) -> Result<()> {
+        match self.store.delete(&Path::from(key)).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("failed to delete archive {key}")),
+        }
+    }
+}
+
+/// Spawns the worker. It runs until admin-api exits.
+pub fn spawn(
+    db_pool: DbPool,
+    clickhouse: Arc<clickhouse::Client>,
+    database: String,
+    store: Arc<ExportStore>,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            match delete_expired_archives(&db_pool, &store).await {
+                Ok(0) => {}
+                Ok(deleted) => tracing::info!(deleted, "deleted expired export archives"),
+                Err(e) => {
+                    tracing::error!(error = %format!("{e:#}"), "failed to delete expired export archives")
+                }
+            }
+            loop {
+                let export = match db::claim_next(&db_pool) {
+                    Ok(Some(export)) => export,
+                    Ok(None) => break,
+                    Err(e) => {
+                        tracing::error!(error = %e, "failed to claim data export");
+                        break;
+                    }
+                };
+                run(&db_pool, &clickhouse, &database, &store, &export).await;
+            }
+        }
+    });
+}
+
+/// Deletes the archives of expired exports from the store. Returns how many
+/// were deleted.
+async fn delete_expired_archives(db_pool: &DbPool, store: &ExportStore) -> Result<usize> {
+    let expired = db::expired_archives(db_pool, Utc::now())?;
+    for (export_id, key) in &expired {
+        store.delete(key).await?;
+        db::clear_archive(db_pool, *export_id)?;
+    }
+    Ok(expired.len())
+}
+
+async fn run(
+    db_pool: &DbPool,
+    clickhouse: &clickhouse::Client,
+    database: &str,
+    store: &ExportStore,
+    export: &DataExport,
+) {
+    tracing::info!(
+        export_id = %export.id,
+        project_id = %export.project_id,
+        format = %export.format,
+        "data export started"
+    );
+
+    let result = match build_archive(db_pool, clickhouse, database, store, export).await {
+        Ok((event_count, key, archive_size)) => {
+            let expires_at = Utc::now() + chrono::Duration::days(EXPORT_TTL_DAYS);
+            match db::complete(
+                db_pool,
+                export.id,
+                event_count,
+                &key,
+                archive_size,
+                expires_at,
+            ) {
+                Ok(()) => Ok(()),
+                Err(e) => {
+                    if let Err(e) = store.delete(&key).await {
+                        tracing::warn!(export_id = %export.id, error = %format!("{e:#}"), "failed to delete archive of failed export");
+                    }
+                    Err(e.into())
+                }
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => tracing::info!(export_id = %export.id, "data export completed"),
+        Err(e) => {
+            tracing::error!(export_id = %export.id, error = %format!("{e:#}"), "data export failed");
+            if let Err(e) = db::fail(db_pool, export.id, &format!("{e:#}")) {
+                tracing::error!(export_id = %export.id, error = %e, "failed to record export failure");
+            }
+        }
+    }
+}
+
+/// Gathers the user's data into a tar archive uploaded to `store`. Returns
+/// the number of events, the archive's key and its uncompressed size.
+async fn build_archive(
+    db_pool: &DbPool,
+    clickhouse: &clickhouse::Client,
+    database: &str,
+    store: &ExportStore,
+    export: &DataExport,
+) -> Result<(i64, String, i64)> {
+    let project_id = export.project_id;
+    let user_uid = export.user_uid.as_str();
+    let csv = export.format == "csv";
+    let ext = if csv { "csv" } else { "json" };
+    let dir = format!("truesight-export-{}", export.id);
+
+    let Some(user) = fetch_user_detail(clickhouse, database, project_id, user_uid, None).await?
+    else {
+        bail!("user '{user_uid}' not found");
+    };
+
+    let mut distinct_ids: Vec<String> = clickhouse
+        .query(&format!(
+            "SELECT distinct_id AS id FROM {database}.identity_graph FINAL \
+             WHERE project_id = ? AND person_id = ? ORDER BY distinct_id"
+        ))
+        .bind(project_id)
+        .bind(user_uid)
+        .fetch_all::<IdRow>()
+        .await
+        .context("failed to read identity_graph")?
+        .into_iter()
+        .map(|r| r.id)
+        .collect();
+    if !distinct_ids.iter().any(|id| id == user_uid) {
+        distinct_ids.insert(0, user_uid.to_string());
+    }
+
+    let identities = clickhouse
+        .query(&format!(
+            "SELECT anonymous_id, user_id, \
+             formatDateTime(first_seen, '%Y-%m-%dT%H:%i:%SZ', 'UTC') AS first_seen, \
+             formatDateTime(last_seen, '%Y-%m-%dT%H:%i:%SZ', 'UTC') AS last_seen \
+             FROM {database}.identity_map FINAL \
+             WHERE project_id = ? AND (has(?, anonymous_id) OR has(?, user_id)) \
+             ORDER BY first_seen"
+        ))
+        .bind(project_id)
+        .bind(&distinct_ids)
+        .bind(&distinct_ids)
+        .fetch_all::<IdentityRow>()
+        .await
+        .context("failed to read identity_map")?;
+
+    let profile_file = serde_json::to_vec_pretty(&ProfileFile {
+        user: &user,
+        distinct_ids: &distinct_ids,
+    })?;
+    let identities_file = if csv {
+        let mut out = csv_row(["anonymous_id", "user_id", "first_seen", "last_seen"]);
+        for row in &identities {
+            out.push_str(&csv_row([
+                &row.anonymous_id,
+                &row.user_id,
+                &row.first_seen,
+                &row.last_seen,
+            ]));
+        }
+        out.into_bytes()
+    } else {
+        serde_json::to_vec_pretty(&identities)?
+    };
+
+    let key = store.key(export);
+    let mut archive = ArchiveWriter::create(store, &key, Utc::now().timestamp()).await?;
+    let written = async {
+        archive
+            .append(&format!("{dir}/profile.json"), &profile_file)
+            .await?;
+        archive
+            .append(&format!("{dir}/identities.{ext}"), &identities_file)
+            .await?;
+        write_events(&mut archive, db_pool, clickhouse, database, export, &dir).await
+    }
+    .await;
+    let event_count = match written {
+        Ok(event_count) => event_count,
+        Err(e) => {
+            archive.abort().await;
+            return Err(e);
+        }
+    };
+    let archive_size = archive.finish().await?;
+
+    Ok((event_count, key.to_string(), archive_size))
+}
+
+/// Appends the user's events, oldest first, in files of [`EVENTS_PER_FILE`]
+/// events. Returns the number of events.
+async fn write_events(
+    archive: &mut ArchiveWriter,
+    db_pool: &DbPool,
+    clickhouse: &clickhouse::Client,
+    database: &str,
+    export: &DataExport,
+    dir: &str,
+) -> Result<i64> {
+    let csv = export.format == "csv";
+    let ext = if csv { "csv" } else { "json" };
+    let ij = identity_join(database);
+    let query = format!(
+        "SELECT toString(event_id) AS event_id, toString(e.project_id) AS project_id, \
+         environment, event_name, event_type, \
+         COALESCE(e.user_id, '') AS user_id, e.anonymous_id AS anonymous_id, \
+         COALESCE(email, '') AS email, COALESCE(mobile_number, '') AS mobile_number, \
+         COALESCE(session_id, '') AS session_id, \
+         formatDateTime(client_timestamp, '%Y-%m-%dT%H:%i:%SZ', 'UTC') AS client_timestamp, \
+         formatDateTime(server_timestamp, '%Y-%m-%dT%H:%i:%SZ', 'UTC') AS server_timestamp, \
+         properties, COALESCE(app_version, '') AS app_version, \
+         os_name, os_version, device_model, device_id, \
+         COALESCE(network_type, '') AS network_type, \
+         locale, timezone, sdk_version, platform \
+         FROM {database}.events AS e{ij} \
+         WHERE e.project_id = ? AND {USER_UID_EXPR} = ? \
+         ORDER BY server_timestamp ASC"
+    );
+    let mut cursor = clickhouse
+        .query(&query)
+        .bind(export.project_id)
+        .bind(export.user_uid.as_str())
+        .fetch::<ExportEventRow>()
+        .context("failed to read events")?;
+
+    let start_file = || {
+        if csv {
+            csv_row(EVENT_COLUMNS).into_bytes()
+        } else {
+            b"[".to_vec()
+        }
+    };
+    let mut file = start_file();
+    let mut file_events: i64 = 0;
+    let mut files = 0;
+    let mut event_count: i64 = 0;
+    loop {
+        let row = cursor.next().await.context("failed to read events")?;
+        if let Some(row) = &row {
+            if csv {
+                file.extend_from_slice(csv_row(row.fields()).as_bytes());
+            } else {
+                if file_events > 0 {
+                    file.push(b',');
+                }
+                file.extend_from_slice(b"\n  ");
+                serde_json::to_writer(&mut file, row)?;
+            }
+            file_events += 1;
+            event_count += 1;
+        }
+
+        // Every archive holds at least one (possibly empty) events file.
+        let full = file_events == EVENTS_PER_FILE;
+        let last = row.is_none() && (file_events > 0 || files == 0);
+        if full || last {
+            if !csv {
+                file.extend_from_slice(b"\n]\n");
+            }
+            files += 1;
+            archive
+                .append(&format!("{dir}/events-{files:05}.{ext}"), &file)
+                .await?;
+            db::update_progress(db_pool, export.id, event_count)?;
+            file = start_file();
+            file_events = 0;
+        }
+        if row.is_none() {
+            return Ok(event_count);
+        }
+    }
+}
+
+/// One CSV record, quoting fields that need it.
+fn csv_row<S: AsRef<str>>(fields: impl IntoIterator<Item = S>) -> String {
+    let mut row = fields
+        .into_iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    row.push_str("\r\n");
+    row
+}
+
+/// Streams a zstd-compressed ustar archive to the export store as a
+/// multipart upload.
+struct ArchiveWriter {
+    encoder: zstd::stream::write::Encoder<'static, Vec<u8>>,
+    upload: WriteMultipart,
+    /// Uncompressed bytes written so far.
+    size: i64,
+    mtime: i64,
+}
+
+impl ArchiveWriter {
+    const BLOCK: usize = 512;
+
+    async fn create(store: &ExportStore, key: &Path, mtime: i64) -> Result<Self> {
+        let upload = store
+            .store
+            .put_multipart(key)
+            .await
+            .context("failed to start archive upload")?;
+        Ok(Self {
+            encoder: zstd::stream::write::Encoder::new(Vec::new(), 3)?,
+            upload: WriteMultipart::new(upload),
+            size: 0,
+            mtime,
+        })
+    }
+
+    /// Appends a regular file and uploads the compressed output so far,
+    /// waiting while too many parts are in flight.
+    async fn append(&mut self, path: &str, data: &[u8]) -> Result<()> {
+        let mut header = [0u8; Self::BLOCK];
+        let name = path.as_bytes();
+        header[..name.len().min(100)].copy_from_slice(&name[..name.len().min(100)]);
+        header[100..108].copy_from_slice(b"0000644\0");
+        header[108..116].copy_from_slice(b"0000000\0");
+        header[116..124].copy_from_slice(b"0000000\0");
+        header[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+        header[136..148].copy_from_slice(format!("{:011o}\0", self.mtime.max(0)).as_bytes());
+        header[148..156].copy_from_slice(b"        ");
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+
+        let checksum: u32 = header.iter().map(|b| *b as u32).sum();
+        header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+
+        let padding = (Self::BLOCK - data.len() % Self::BLOCK) % Self::BLOCK;
+        self.write(&header)?;
+        self.write(data)?;
+        self.write(&[0u8; Self::BLOCK][..padding])?;
+
+        let compressed = self.encoder.get_mut();
+        self.upload.write(compressed);
+        compressed.clear();
+        self.upload
+            .wait_for_capacity(MAX_UPLOAD_PARTS)
+            .await
+            .context("failed to upload archive")
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<()> {
+        self.encoder
+            .write_all(buf)
+            .context("failed to compress archive")?;
+        self.size += buf.len() as i64;
+        Ok(())
+    }
+
+    /// Ends the archive and completes the upload. Returns the uncompressed
+    /// size.
+    async fn finish(mut self) -> Result<i64> {
+        self.write(&[0u8; 2 * Self::BLOCK])?;
+        let compressed = self
+            .encoder
+            .finish()
+            .context("failed to compress archive")?;
+        self.upload.write(&compressed);
+        self.upload
+            .finish()
+            .await
+            .context("failed to upload archive")?;
+        Ok(self.size)
+    }
+
+    /// Abandons the upload of a failed export.
+    async fn abort(self) {
+        if let Err(e) = self.upload.abort().await {
+            tracing::warn!(error = %e, "failed to abort archive upload");
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use truesight_common::data_export::{
+    DataExport, NewDataExport, STATUS_COMPLETED, STATUS_FAILED, STATUS_PENDING, STATUS_RUNNING,
+};
+use truesight_common::db::{DbPool, with_conn_app};
+use truesight_common::error::AppError;
+use truesight_common::schema::data_exports;
+
+/// A running export not updated for this long is assumed abandoned and is
+/// claimed again.
+const STALE_AFTER_MINUTES: i64 = 10;
+
+fn not_found(e: diesel::result::Error) -> AppError {
+    match e {
+        diesel::result::Error::NotFound => AppError::NotFound("Data export not found".into()),
+        _ => AppError::Database(e.to_string()),
+    }
+}
+
+pub fn insert_export(pool: &DbPool, new: NewDataExport) -> Result<DataExport, AppError> {
+    with_conn_app(pool, |conn| {
+        diesel::insert_into(data_exports::table)
+            .values(&new)
+            .returning(DataExport::as_returning())
+            .get_result::<DataExport>(conn)
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+pub fn list_exports(pool: &DbPool, pid: Uuid) -> Result<Vec<DataExport>, AppError> {
+    with_conn_app(pool, |conn| {
+        data_exports::table
+            .filter(data_exports::project_id.eq(pid))
+            .order(data_exports::created_at.desc())
+            .select(DataExport::as_select())
+            .load::<DataExport>(conn)
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+pub fn find_export(pool: &DbPool, pid: Uuid, xid: Uuid) -> Result<DataExport, AppError> {
+    with_conn_app(pool, |conn| {
+        data_exports::table
+            .filter(data_exports::project_id.eq(pid))
+            .filter(data_exports::id.eq(xid))
+            .select(DataExport::as_select())
+            .first::<DataExport>(conn)
+            .map_err(not_found)
+    })
+}
+
+/// Store key of a completed export's archive, or `None` once it was deleted.
+pub fn archive_key(pool: &DbPool, pid: Uuid, xid: Uuid) -> Result<Option<String>, AppError> {
+    with_conn_app(pool, |conn| {
+        data_exports::table
+            .filter(data_exports::project_id.eq(pid))
+            .filter(data_exports::id.eq(xid))
+            .select(data_exports::archive_key)
+            .first::<Option<String>>(conn)
+            .map_err(not_found)
+    })
+}
+
+/// Claims the oldest pending export, or a running one abandoned by another
+/// instance, and marks it running. Returns `None` if there is nothing to do
+/// or another instance claimed it first.
+pub fn claim_next(pool: &DbPool) -> Result<Option<DataExport>, AppError> {
+    let now = Utc::now();
+    let stale_before = now - Duration::minutes(STALE_AFTER_MINUTES);
+    with_conn_app(pool, |conn| {
+        let claimable = data_exports::status
+            .eq(STATUS_PENDING)
+            .or(data_exports::status
+                .eq(STATUS_RUNNING)
+                .and(data_exports::updated_at.lt(stale_before)));
+
+        let Some(candidate) = data_exports::table
+            .filter(claimable)
+            .order(data_exports::created_at.asc())
+            .select(data_exports::id)
+            .first::<Uuid>(conn)
+            .optional()
+            .map_err(|e| AppError::Database(e.to_string()))?
+        else {
+            return Ok(None);
+        };
+
+        diesel::update(
+            data_exports::table
+                .filter(data_exports::id.eq(candidate))
+                .filter(claimable),
+        )
+        .set((
+            data_exports::status.eq(STATUS_RUNNING),
+            data_exports::started_at.eq(now),
+            data_exports::updated_at.eq(now),
+        ))
+        .returning(DataExport::as_returning())
+        .get_result::<DataExport>(conn)
+        .optional()
+        .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+/// Records how many events a running export has written so far.
+pub fn update_progress(pool: &DbPool, xid: Uuid, event_count: i64) -> Result<(), AppError> {
+    with_conn_app(pool, |conn| {
+        diesel::update(data_exports::table.filter(data_exports::id.eq(xid)))
+            .set((
+                data_exports::event_count.eq(event_count),
+                data_exports::updated_at.eq(Utc::now()),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+pub fn complete(
+    pool: &DbPool,
+    xid: Uuid,
+    event_count: i64,
+    archive_key: &str,
+    archive_size: i64,
+    expires_at: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let now = Utc::now();
+    with_conn_app(pool, |conn| {
+        diesel::update(data_exports::table.filter(data_exports::id.eq(xid)))
+            .set((
+                data_exports::status.eq(STATUS_COMPLETED),
+                data_exports::event_count.eq(event_count),
+                data_exports::archive_key.eq(archive_key),
+                data_exports::archive_size.eq(archive_size),
+                data_exports::error.eq(None::<String>),
+                data_exports::completed_at.eq(now),
+                data_exports::expires_at.eq(expires_at),
+                data_exports::updated_at.eq(now),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+pub fn fail(pool: &DbPool, xid: Uuid, error: &str) -> Result<(), AppError> {
+    with_conn_app(pool, |conn| {
+        diesel::update(data_exports::table.filter(data_exports::id.eq(xid)))
+            .set((
+                data_exports::status.eq(STATUS_FAILED),
+                data_exports::error.eq(error),
+                data_exports::updated_at.eq(Utc::now()),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+/// IDs and archive keys of exports past their expiry whose archive is not
+/// deleted yet.
+pub fn expired_archives(
+    pool: &DbPool,
+    now: DateTime<Utc>,
+) -> Result<Vec<(Uuid, String)>, AppError> {
+    with_conn_app(pool, |conn| {
+        data_exports::table
+            .filter(data_exports::archive_key.is_not_null())
+            .filter(data_exports::expires_at.lt(now))
+            .select((
+                data_exports::id,
+                data_exports::archive_key.assume_not_null(),
+            ))
+            .load::<(Uuid, String)>(conn)
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+/// Records that an export's archive was deleted from the store.
+pub fn clear_archive(pool: &DbPool, xid: Uuid) -> Result<(), AppError> {
+    with_conn_app(pool, |conn| {
+        diesel::update(data_exports::table.filter(data_exports::id.eq(xid)))
+            .set((
+                data_exports::archive_key.eq(None::<String>),
+                data_exports::updated_at.eq(Utc::now()),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+/// Expires the exports of erased users. Their archives can no longer be
+/// downloaded and are deleted by the export worker's next sweep.
+pub fn expire_for_users(pool: &DbPool, pid: Uuid, user_uids: &[String]) -> Result<(), AppError> {
+    let now = Utc::now();
+    with_conn_app(pool, |conn| {
+        diesel::update(
+            data_exports::table
+                .filter(data_exports::project_id.eq(pid))
+                .filter(data_exports::user_uid.eq_any(user_uids)),
+        )
+        .set((
+            data_exports::expires_at.eq(now),
+            data_exports::updated_at.eq(now),
+        ))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
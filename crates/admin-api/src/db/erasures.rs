@@ -0,0 +1,171 @@
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use truesight_common::db::{DbPool, with_conn_app};
+use truesight_common::erasure::{
+    ErasureRequest, NewErasureRequest, NewSuppressedIdentifier, STATUS_COMPLETED, STATUS_FAILED,
+    STATUS_PENDING, STATUS_RUNNING,
+};
+use truesight_common::error::AppError;
+use truesight_common::schema::{erasure_requests, suppressed_identifiers};
+
+/// A running erasure not updated for this long is assumed abandoned, e.g. by
+/// an admin-api instance that was restarted, and is claimed again.
+const STALE_AFTER_MINUTES: i64 = 10;
+
+fn not_found(e: diesel::result::Error) -> AppError {
+    match e {
+        diesel::result::Error::NotFound => AppError::NotFound("Erasure request not found".into()),
+        _ => AppError::Database(e.to_string()),
+    }
+}
+
+pub fn insert_erasure(pool: &DbPool, new: NewErasureRequest) -> Result<ErasureRequest, AppError> {
+    with_conn_app(pool, |conn| {
+        diesel::insert_into(erasure_requests::table)
+            .values(&new)
+            .returning(ErasureRequest::as_returning())
+            .get_result::<ErasureRequest>(conn)
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+pub fn list_erasures(pool: &DbPool, pid: Uuid) -> Result<Vec<ErasureRequest>, AppError> {
+    with_conn_app(pool, |conn| {
+        erasure_requests::table
+            .filter(erasure_requests::project_id.eq(pid))
+            .order(erasure_requests::created_at.desc())
+            .select(ErasureRequest::as_select())
+            .load::<ErasureRequest>(conn)
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+pub fn find_erasure(pool: &DbPool, pid: Uuid, eid: Uuid) -> Result<ErasureRequest, AppError> {
+    with_conn_app(pool, |conn| {
+        erasure_requests::table
+            .filter(erasure_requests::project_id.eq(pid))
+            .filter(erasure_requests::id.eq(eid))
+            .select(ErasureRequest::as_select())
+            .first::<ErasureRequest>(conn)
+            .map_err(not_found)
+    })
+}
+
+/// Claims the oldest pending erasure, or a running one abandoned by another
+/// instance, and marks it running. Returns `None` if there is nothing to do
+/// or another instance claimed it first.
+pub fn claim_next(pool: &DbPool) -> Result<Option<ErasureRequest>, AppError> {
+    let now = Utc::now();
+    let stale_before = now - Duration::minutes(STALE_AFTER_MINUTES);
+    with_conn_app(pool, |conn| {
+        let claimable = erasure_requests::status
+            .eq(STATUS_PENDING)
+            .or(erasure_requests::status
+                .eq(STATUS_RUNNING)
+                .and(erasure_requests::updated_at.lt(stale_before)));
+
+        let Some(candidate) = erasure_requests::table
+            .filter(claimable)
+            .order(erasure_requests::created_at.asc())
+            .select(erasure_requests::id)
+            .first::<Uuid>(conn)
+            .optional()
+            .map_err(|e| AppError::Database(e.to_string()))?
+        else {
+            return Ok(None);
+        };
+
+        diesel::update(
+            erasure_requests::table
+                .filter(erasure_requests::id.eq(candidate))
+                .filter(claimable),
+        )
+        .set((
+            erasure_requests::status.eq(STATUS_RUNNING),
+            erasure_requests::started_at.eq(now),
+            erasure_requests::updated_at.eq(now),
+        ))
+        .returning(ErasureRequest::as_returning())
+        .get_result::<ErasureRequest>(conn)
+        .optional()
+        .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+/// Records progress of a running erasure, which also keeps it from being
+/// considered abandoned.
+pub fn update_progress(
+    pool: &DbPool,
+    eid: Uuid,
+    resolved_ids: i32,
+    tables: serde_json::Value,
+) -> Result<(), AppError> {
+    with_conn_app(pool, |conn| {
+        diesel::update(erasure_requests::table.filter(erasure_requests::id.eq(eid)))
+            .set((
+                erasure_requests::resolved_ids.eq(resolved_ids),
+                erasure_requests::tables.eq(tables),
+                erasure_requests::updated_at.eq(Utc::now()),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+/// Marks an erasure completed and clears its plain identifier.
+pub fn complete(pool: &DbPool, eid: Uuid) -> Result<(), AppError> {
+    let now = Utc::now();
+    with_conn_app(pool, |conn| {
+        diesel::update(erasure_requests::table.filter(erasure_requests::id.eq(eid)))
+            .set((
+                erasure_requests::status.eq(STATUS_COMPLETED),
+                erasure_requests::identifier.eq(None::<String>),
+                erasure_requests::error.eq(None::<String>),
+                erasure_requests::completed_at.eq(now),
+                erasure_requests::updated_at.eq(now),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+pub fn fail(pool: &DbPool, eid: Uuid, error: &str) -> Result<(), AppError> {
+    with_conn_app(pool, |conn| {
+        diesel::update(erasure_requests::table.filter(erasure_requests::id.eq(eid)))
+            .set((
+                erasure_requests::status.eq(STATUS_FAILED),
+                erasure_requests::error.eq(error),
+                erasure_requests::updated_at.eq(Utc::now()),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+/// Adds identifiers to the suppression list; already suppressed ones are
+/// skipped.
+pub fn insert_suppressions(
+    pool: &DbPool,
+    new: Vec<NewSuppressedIdentifier>,
+) -> Result<(), AppError> {
+    if new.is_empty() {
+        return Ok(());
+    }
+    with_conn_app(pool, |conn| {
+        diesel::insert_into(suppressed_identifiers::table)
+            .values(&new)
+            .on_conflict((
+                suppressed_identifiers::project_id,
+                suppressed_identifiers::identifier_hash,
+            ))
+            .do_nothing()
+            .execute(conn)
+            .map(|_| ())
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
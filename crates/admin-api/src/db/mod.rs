@@ -1,9 +1,13 @@
 pub mod api_keys;
 pub mod boards;
 pub mod cohorts;
+pub mod data_exports;
+pub mod erasures;
 pub mod funnels;
 pub mod invitations;
 pub mod projects;
+pub mod retention;
 pub mod segments;
 pub mod teams;
 pub mod users;
+pub mod webhooks;
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use uuid::Uuid;
+
+use truesight_common::db::{DbPool, with_conn_app};
+use truesight_common::error::AppError;
+use truesight_common::retention::{NewRetentionPolicy, PURGE_INTERVAL_HOURS, RetentionPolicy};
+use truesight_common::schema::retention_policies;
+
+pub fn list_policies(pool: &DbPool, pid: Uuid) -> Result<Vec<RetentionPolicy>, AppError> {
+    with_conn_app(pool, |conn| {
+        retention_policies::table
+            .filter(retention_policies::project_id.eq(pid))
+            .order(retention_policies::environment.asc())
+            .select(RetentionPolicy::as_select())
+            .load::<RetentionPolicy>(conn)
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+/// Policies of every project, for the purge scheduler.
+pub fn list_all_policies(pool: &DbPool) -> Result<Vec<RetentionPolicy>, AppError> {
+    with_conn_app(pool, |conn| {
+        retention_policies::table
+            .order(retention_policies::created_at.asc())
+            .select(RetentionPolicy::as_select())
+            .load::<RetentionPolicy>(conn)
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+/// Creates the policy of an environment or replaces its retention.
+pub fn upsert_policy(pool: &DbPool, new: NewRetentionPolicy) -> Result<RetentionPolicy, AppError> {
+    with_conn_app(pool, |conn| {
+        diesel::insert_into(retention_policies::table)
+            .values(&new)
+            .on_conflict((
+                retention_policies::project_id,
+                retention_policies::environment,
+            ))
+            .do_update()
+            .set((
+                retention_policies::retention_days.eq(excluded(retention_policies::retention_days)),
+                retention_policies::updated_at.eq(Utc::now()),
+            ))
+            .returning(RetentionPolicy::as_returning())
+            .get_result::<RetentionPolicy>(conn)
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+pub fn delete_policy(pool: &DbPool, pid: Uuid, environment: &str) -> Result<(), AppError> {
+    with_conn_app(pool, |conn| {
+        let rows = diesel::delete(
+            retention_policies::table
+                .filter(retention_policies::project_id.eq(pid))
+                .filter(retention_policies::environment.eq(environment)),
+        )
+        .execute(conn)
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+        if rows == 0 {
+            return Err(AppError::NotFound("Retention policy not found".into()));
+        }
+        Ok(())
+    })
+}
+
+/// Marks a due policy as purged at `now`. Returns `false` if it was purged
+/// within the purge interval, e.g. by another admin-api instance.
+pub fn claim_purge(pool: &DbPool, id: Uuid, now: DateTime<Utc>) -> Result<bool, AppError> {
+    let due_before = now - Duration::hours(PURGE_INTERVAL_HOURS);
+    with_conn_app(pool, |conn| {
+        diesel::update(
+            retention_policies::table
+                .filter(retention_policies::id.eq(id))
+                .filter(
+                    retention_policies::last_purged_at
+                        .is_null()
+                        .or(retention_policies::last_purged_at.lt(due_before)),
+                ),
+        )
+        .set(retention_policies::last_purged_at.eq(now))
+        .execute(conn)
+        .map(|rows| rows == 1)
+        .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+/// Undoes a [`claim_purge`] made at `claimed_at` whose purge failed, restoring
+/// the previous purge time so the policy is due again.
+pub fn release_purge(
+    pool: &DbPool,
+    id: Uuid,
+    claimed_at: DateTime<Utc>,
+    previous: Option<DateTime<Utc>>,
+) -> Result<(), AppError> {
+    with_conn_app(pool, |conn| {
+        diesel::update(
+            retention_policies::table
+                .filter(retention_policies::id.eq(id))
+                .filter(retention_policies::last_purged_at.eq(claimed_at)),
+        )
+        .set(retention_policies::last_purged_at.eq(previous))
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
@@ -0,0 +1,161 @@
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use truesight_common::db::{DbPool, with_conn_app};
+use truesight_common::error::AppError;
+use truesight_common::schema::{webhook_dead_letters, webhook_destinations};
+use truesight_common::webhook::{
+    NewWebhookDestination, UpdateWebhookDestination, WebhookDeadLetter, WebhookDestination,
+};
+
+fn not_found(e: diesel::result::Error) -> AppError {
+    match e {
+        diesel::result::Error::NotFound => AppError::NotFound("Webhook not found".into()),
+        _ => AppError::Database(e.to_string()),
+    }
+}
+
+pub fn list_webhooks(pool: &DbPool, pid: Uuid) -> Result<Vec<WebhookDestination>, AppError> {
+    with_conn_app(pool, |conn| {
+        webhook_destinations::table
+            .filter(webhook_destinations::project_id.eq(pid))
+            .order(webhook_destinations::created_at.desc())
+            .select(WebhookDestination::as_select())
+            .load::<WebhookDestination>(conn)
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+pub fn find_webhook(pool: &DbPool, pid: Uuid, wid: Uuid) -> Result<WebhookDestination, AppError> {
+    with_conn_app(pool, |conn| {
+        webhook_destinations::table
+            .filter(webhook_destinations::project_id.eq(pid))
+            .filter(webhook_destinations::id.eq(wid))
+            .select(WebhookDestination::as_select())
+            .first::<WebhookDestination>(conn)
+            .map_err(not_found)
+    })
+}
+
+pub fn insert_webhook(
+    pool: &DbPool,
+    new: NewWebhookDestination,
+) -> Result<WebhookDestination, AppError> {
+    with_conn_app(pool, |conn| {
+        diesel::insert_into(webhook_destinations::table)
+            .values(&new)
+            .returning(WebhookDestination::as_returning())
+            .get_result::<WebhookDestination>(conn)
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+pub fn update_webhook(
+    pool: &DbPool,
+    pid: Uuid,
+    wid: Uuid,
+    changes: UpdateWebhookDestination,
+) -> Result<WebhookDestination, AppError> {
+    with_conn_app(pool, |conn| {
+        diesel::update(
+            webhook_destinations::table
+                .filter(webhook_destinations::project_id.eq(pid))
+                .filter(webhook_destinations::id.eq(wid)),
+        )
+        .set(&changes)
+        .returning(WebhookDestination::as_returning())
+        .get_result::<WebhookDestination>(conn)
+        .map_err(not_found)
+    })
+}
+
+pub fn delete_webhook(pool: &DbPool, pid: Uuid, wid: Uuid) -> Result<(), AppError> {
+    with_conn_app(pool, |conn| {
+        let rows = diesel::delete(
+            webhook_destinations::table
+                .filter(webhook_destinations::project_id.eq(pid))
+                .filter(webhook_destinations::id.eq(wid)),
+        )
+        .execute(conn)
+        .map_err(|e| AppError::Database(e.to_string()))?;
+
+        if rows == 0 {
+            return Err(AppError::NotFound("Webhook not found".into()));
+        }
+        Ok(())
+    })
+}
+
+/// Dead letters of a destination, newest first. Callers check that the
+/// destination belongs to the project.
+pub fn list_dead_letters(
+    pool: &DbPool,
+    wid: Uuid,
+    limit: i64,
+) -> Result<Vec<WebhookDeadLetter>, AppError> {
+    with_conn_app(pool, |conn| {
+        webhook_dead_letters::table
+            .filter(webhook_dead_letters::destination_id.eq(wid))
+            .order(webhook_dead_letters::created_at.desc())
+            .limit(limit)
+            .select(WebhookDeadLetter::as_select())
+            .load::<WebhookDeadLetter>(conn)
+            .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+/// Deletes every dead letter of a destination and returns how many there were.
+pub fn delete_dead_letters(pool: &DbPool, wid: Uuid) -> Result<usize, AppError> {
+    with_conn_app(pool, |conn| {
+        diesel::delete(
+            webhook_dead_letters::table.filter(webhook_dead_letters::destination_id.eq(wid)),
+        )
+        .execute(conn)
+        .map_err(|e| AppError::Database(e.to_string()))
+    })
+}
+
+/// Removes the events matching `is_erased` from every dead letter of the
+/// project, deleting dead letters left without events. Returns how many
+/// events were removed.
+pub fn erase_dead_letter_events(
+    pool: &DbPool,
+    pid: Uuid,
+    is_erased: impl Fn(&serde_json::Value) -> bool,
+) -> Result<usize, AppError> {
+    with_conn_app(pool, |conn| {
+        conn.transaction(|conn| {
+            let dead_letters = webhook_dead_letters::table
+                .inner_join(webhook_destinations::table)
+                .filter(webhook_destinations::project_id.eq(pid))
+                .select((webhook_dead_letters::id, webhook_dead_letters::events))
+                .for_update()
+                .load::<(Uuid, serde_json::Value)>(conn)?;
+
+            let mut removed = 0;
+            for (id, events) in dead_letters {
+                let events = match events {
+                    serde_json::Value::Array(events) => events,
+                    _ => continue,
+                };
+                let total = events.len();
+                let kept: Vec<_> = events.into_iter().filter(|e| !is_erased(e)).collect();
+                if kept.len() == total {
+                    continue;
+                }
+                removed += total - kept.len();
+
+                let row = webhook_dead_letters::table.filter(webhook_dead_letters::id.eq(id));
+                if kept.is_empty() {
+                    diesel::delete(row).execute(conn)?;
+                } else {
+                    diesel::update(row)
+                        .set(webhook_dead_letters::events.eq(serde_json::Value::Array(kept)))
+                        .execute(conn)?;
+                }
+            }
+            Ok(removed)
+        })
+        .map_err(|e: diesel::result::Error| AppError::Database(e.to_string()))
+    })
+}
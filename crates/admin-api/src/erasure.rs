@@ -0,0 +1,534 @@
+//! Right-to-erasure worker.
+//!
+//! Erasure requests (see [`truesight_common::erasure`]) are queued in
+//! Postgres and run here one at a time. For each request the worker
+//!
+//! 1. resolves every ID linked to the requested identifier through the
+//!    ClickHouse `identity_map` and `identity_graph`, along with the emails
+//!    and mobile numbers seen for those IDs;
+//! 2. adds all of them to the suppression list, so ingestion-api drops the
+//!    user's future events and ch-writer the user's events redriven from the
+//!    DLQ or re-imported from the archive;
+//! 3. removes the user's events from webhook dead letters and, when the DLQ
+//!    tooling is configured, the SQS DLQ (at most [`MAX_DLQ_SWEEP`] messages
+//!    are scanned; events past that are deleted when the DLQ tooling lists
+//!    or redrives them);
+//! 4. issues a delete mutation per table in [`ERASED_TABLES`] and polls
+//!    `system.mutations` until every mutation has finished.
+//!
+//! Once a request completes, ch-writer rewrites the Parquet archive without
+//! the suppressed users' events and sets the request's `archive_purged_at`.
+//!
+//! Claiming a request in Postgres keeps two admin-api instances from running
+//! the same one; a request abandoned mid-run is claimed again and rerun,
+//! which is safe as every step is idempotent.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use truesight_common::db::DbPool;
+use truesight_common::erasure::{
+    ErasureRequest, NewSuppressedIdentifier, TableProgress, identifier_hash,
+};
+use truesight_common::sqs::DlqMessage;
+
+use crate::db::erasures as db;
+use crate::state::DlqTooling;
+
+/// How often the worker looks for new requests.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often `system.mutations` is checked while a request runs.
+const MUTATION_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Linked IDs are followed for at most this many hops.
+const MAX_RESOLVE_ROUNDS: usize = 10;
+
+/// Maximum number of DLQ messages scanned per request.
+pub const MAX_DLQ_SWEEP: usize = 10_000;
+
+/// DLQ messages scanned per receive round.
+const DLQ_SWEEP_CHUNK: usize = 500;
+
+/// How long scanned DLQ messages stay hidden. Kept messages are released as
+/// soon as the sweep finishes; this must outlast the sweep so none is
+/// scanned twice.
+const DLQ_SWEEP_VISIBILITY_SECS: i32 = 900;
+
+/// ClickHouse tables holding personal data, erased in this order.
+pub const ERASED_TABLES: &[&str] = &[
+    "events",
+    "user_profiles",
+    "user_profile_history",
+    "users_daily",
+    "user_first_seen",
+    "user_stats",
+    "identity_map",
+    "identity_graph",
+];
+
+/// Everything known about the user being erased.
+#[derive(Debug, Default)]
+struct Resolved {
+    /// User IDs, anonymous IDs and person IDs.
+    ids: BTreeSet<String>,
+    /// Lowercased emails.
+    emails: BTreeSet<String>,
+    mobile_numbers: BTreeSet<String>,
+}
+
+impl Resolved {
+    fn count(&self) -> usize {
+        self.ids.len() + self.emails.len() + self.mobile_numbers.len()
+    }
+
+    /// Every identifier with its identifier type; anonymous and person IDs
+    /// are hashed like user IDs.
+    fn identifiers(&self) -> impl Iterator<Item = (&'static str, &String)> {
+        self.ids
+            .iter()
+            .map(|id| ("user_id", id))
+            .chain(self.emails.iter().map(|email| ("email", email)))
+            .chain(self.mobile_numbers.iter().map(|m| ("mobile_number", m)))
+    }
+
+    /// Whether a serialized event of the project belongs to the user,
+    /// compared like the `events` delete mutation.
+    fn matches_event(&self, event: &serde_json::Value) -> bool {
+        let field = |name: &str| event.get(name).and_then(|v| v.as_str()).unwrap_or("");
+        self.ids.contains(field("user_id"))
+            || self.ids.contains(field("anonymous_id"))
+            || self.emails.contains(&field("email").to_lowercase())
+            || self.mobile_numbers.contains(field("mobile_number"))
+    }
+}
+
+#[derive(clickhouse::Row, Deserialize)]
+struct IdRow {
+    id: String,
+}
+
+#[derive(clickhouse::Row, Deserialize)]
+struct ContactRow {
+    email: String,
+    mobile_number: String,
+}
+
+#[derive(clickhouse::Row, Deserialize)]
+struct MutationRow {
+    table: String,
+    is_done: u8,
+    latest_fail_reason: String,
+}
+
+/// Spawns the worker. It runs until admin-api exits. `hash_key` is the
+/// `ERASURE_HASH_KEY` suppressed identifiers are hashed with; `dlq` is
+/// `None` when the DLQ tooling is not configured.
+pub fn spawn(
+    db_pool: DbPool,
+    clickhouse: Arc<clickhouse::Client>,
+    database: String,
+    hash_key: String,
+    dlq: Option<Arc<DlqTooling>>,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            loop {
+                let request = match db::claim_next(&db_pool) {
+                    Ok(Some(request)) => request,
+                    Ok(None) => break,
+                    Err(e) => {
+                        tracing::error!(error = %e, "failed to claim erasure request");
+                        break;
+                    }
+                };
+                let dlq = dlq.as_deref();
+                run(&db_pool, &clickhouse, &database, &hash_key, dlq, &request).await;
+            }
+        }
+    });
+}
+
+async fn run(
+    db_pool: &DbPool,
+    clickhouse: &clickhouse::Client,
+    database: &str,
+    hash_key: &str,
+    dlq: Option<&DlqTooling>,
+    request: &ErasureRequest,
+) {
+    tracing::info!(
+        erasure_id = %request.id,
+        project_id = %request.project_id,
+        identifier_type = %request.identifier_type,
+        "erasure started"
+    );
+
+    let result = erase(db_pool, clickhouse, database, hash_key, dlq, request).await;
+    let recorded = match &result {
+        Ok(()) => db::complete(db_pool, request.id),
+        Err(e) => db::fail(db_pool, request.id, &format!("{e:#}")),
+    };
+    if let Err(e) = recorded {
+        tracing::error!(erasure_id = %request.id, error = %e, "failed to record erasure outcome");
+    }
+
+    match result {
+        Ok(()) => tracing::info!(erasure_id = %request.id, "erasure completed"),
+        Err(e) => tracing::error!(erasure_id = %request.id, error = %e, "erasure failed"),
+    }
+}
+
+async fn erase(
+    db_pool: &DbPool,
+    clickhouse: &clickhouse::Client,
+    database: &str,
+    hash_key: &str,
+    dlq: Option<&DlqTooling>,
+    request: &ErasureRequest,
+) -> Result<()> {
+    let Some(identifier) = request.identifier.as_deref() else {
+        bail!("erasure request has no identifier");
+    };
+
+    let resolved = resolve(clickhouse, database, request, identifier).await?;
+    let resolved_ids = resolved.count() as i32;
+
+    // Suppress first, so events arriving while the mutations run are dropped.
+    let suppressions = std::iter::once((request.identifier_type.as_str(), identifier))
+        .chain(resolved.identifiers().map(|(kind, id)| (kind, id.as_str())))
+        .map(|(kind, id)| identifier_hash(hash_key, request.project_id, kind, id))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|hash| NewSuppressedIdentifier {
+            project_id: request.project_id,
+            identifier_hash: hash,
+            erasure_request_id: request.id,
+        })
+        .collect();
+    db::insert_suppressions(db_pool, suppressions)?;
+
+    // Archives exported for the user must not outlive the erasure.
+    let ids: Vec<String> = resolved.ids.iter().cloned().collect();
+    crate::db::data_exports::expire_for_users(db_pool, request.project_id, &ids)?;
+
+    let dead_letter_events =
+        crate::db::webhooks::erase_dead_letter_events(db_pool, request.project_id, |event| {
+            resolved.matches_event(event)
+        })?;
+    if dead_letter_events > 0 {
+        tracing::info!(
+            erasure_id = %request.id,
+            events = dead_letter_events,
+            "erased webhook dead letter events"
+        );
+    }
+
+    if let Some(dlq) = dlq {
+        let dlq_events = erase_dlq_messages(dlq, request, &resolved)
+            .await
+            .context("failed to erase DLQ messages")?;
+        if dlq_events > 0 {
+            tracing::info!(
+                erasure_id = %request.id,
+                events = dlq_events,
+                "erased DLQ events"
+            );
+        }
+    }
+
+    let mut progress: Vec<TableProgress> = ERASED_TABLES
+        .iter()
+        .map(|table| TableProgress {
+            table: table.to_string(),
+            done: false,
+        })
+        .collect();
+    db::update_progress(
+        db_pool,
+        request.id,
+        resolved_ids,
+        serde_json::to_value(&progress)?,
+    )?;
+
+    for entry in &mut progress {
+        let issued = delete_from(clickhouse, database, request, &entry.table, &resolved).await?;
+        // Nothing to match in this table, e.g. no IDs linked to an email.
+        entry.done = !issued;
+    }
+
+    loop {
+        db::update_progress(
+            db_pool,
+            request.id,
+            resolved_ids,
+            serde_json::to_value(&progress)?,
+        )?;
+        if progress.iter().all(|p| p.done) {
+            return Ok(());
+        }
+        tokio::time::sleep(MUTATION_POLL_INTERVAL).await;
+
+        let mutations = clickhouse
+            .query(
+                "SELECT table, is_done, latest_fail_reason FROM system.mutations \
+                 WHERE database = ? AND position(command, ?) > 0",
+            )
+            .bind(database)
+            .bind(request.id.to_string())
+            .fetch_all::<MutationRow>()
+            .await
+            .context("failed to read system.mutations")?;
+
+        for entry in progress.iter_mut().filter(|p| !p.done) {
+            let table_mutations: Vec<_> = mutations
+                .iter()
+                .filter(|m| m.table == entry.table)
+                .collect();
+            if let Some(failed) = table_mutations
+                .iter()
+                .find(|m| !m.latest_fail_reason.is_empty())
+            {
+                bail!(
+                    "deleting from {} failed: {}",
+                    entry.table,
+                    failed.latest_fail_reason
+                );
+            }
+            entry.done =
+                !table_mutations.is_empty() && table_mutations.iter().all(|m| m.is_done == 1);
+        }
+    }
+}
+
+/// Deletes the user's events from the SQS DLQ and returns how many were
+/// deleted. Every other scanned message stays hidden until the sweep ends, so
+/// each one is scanned once, and is then released.
+async fn erase_dlq_messages(
+    dlq: &DlqTooling,
+    request: &ErasureRequest,
+    resolved: &Resolved,
+) -> Result<usize> {
+    let project_id = request.project_id.to_string();
+    let mut kept: Vec<DlqMessage> = Vec::new();
+    let mut erased = 0;
+
+    let swept: Result<()> = async {
+        while kept.len() + erased < MAX_DLQ_SWEEP {
+            let chunk = DLQ_SWEEP_CHUNK.min(MAX_DLQ_SWEEP - kept.len() - erased);
+            let scanned = dlq
+                .client
+                .scan(&dlq.dlq_url, chunk, DLQ_SWEEP_VISIBILITY_SECS)
+                .await?;
+            let exhausted = scanned.len() < chunk;
+
+            let (matched, rest): (Vec<_>, Vec<_>) = scanned.into_iter().partition(|message| {
+                serde_json::from_str::<serde_json::Value>(&message.body).is_ok_and(|event| {
+                    event.get("project_id").and_then(|v| v.as_str()) == Some(&project_id)
+                        && resolved.matches_event(&event)
+                })
+            });
+            kept.extend(rest);
+            if !matched.is_empty() {
+                dlq.client.delete(&dlq.dlq_url, &matched).await?;
+                erased += matched.len();
+            }
+            if exhausted {
+                break;
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    if !kept.is_empty()
+        && let Err(e) = dlq.client.release(&dlq.dlq_url, &kept).await
+    {
+        tracing::error!(erasure_id = %request.id, error = %e, "failed to release DLQ messages");
+    }
+    swept.map(|()| erased)
+}
+
+/// Issues the delete mutation of one table. Returns `false` if there is
+/// nothing to match it by.
+async fn delete_from(
+    clickhouse: &clickhouse::Client,
+    database: &str,
+    request: &ErasureRequest,
+    table: &str,
+    resolved: &Resolved,
+) -> Result<bool> {
+    let ids: Vec<&String> = resolved.ids.iter().collect();
+    let emails: Vec<&String> = resolved.emails.iter().collect();
+    let mobile_numbers: Vec<&String> = resolved.mobile_numbers.iter().collect();
+
+    let mut conditions: Vec<(&str, &Vec<&String>)> = match table {
+        "events" => vec![
+            ("has(?, ifNull(user_id, ''))", &ids),
+            ("has(?, anonymous_id)", &ids),
+            ("has(?, lower(ifNull(email, '')))", &emails),
+            ("has(?, ifNull(mobile_number, ''))", &mobile_numbers),
+        ],
+        "user_profiles" => vec![
+            ("has(?, user_uid)", &ids),
+            ("has(?, lower(ifNull(email, '')))", &emails),
+            ("has(?, ifNull(mobile_number, ''))", &mobile_numbers),
+        ],
+        "identity_map" => vec![("has(?, anonymous_id)", &ids), ("has(?, user_id)", &ids)],
+        "identity_graph" => vec![("has(?, distinct_id)", &ids), ("has(?, person_id)", &ids)],
+        _ => vec![("has(?, user_uid)", &ids)],
+    };
+    conditions.retain(|(_, values)| !values.is_empty());
+    if conditions.is_empty() {
+        return Ok(false);
+    }
+
+    // The request ID tags the mutation so it can be found in system.mutations.
+    let predicate = conditions
+        .iter()
+        .map(|(condition, _)| *condition)
+        .collect::<Vec<_>>()
+        .join(" OR ");
+    let sql = format!(
+        "ALTER TABLE {database}.{table} DELETE \
+         WHERE project_id = ? AND ({predicate}) AND '{id}' = '{id}'",
+        id = request.id
+    );
+
+    let mut query = clickhouse.query(&sql).bind(request.project_id);
+    for (_, values) in &conditions {
+        query = query.bind(*values);
+    }
+    query
+        .execute()
+        .await
+        .with_context(|| format!("failed to delete from {table}"))?;
+    Ok(true)
+}
+
+/// Resolves the IDs, emails and mobile numbers linked to the identifier.
+async fn resolve(
+    clickhouse: &clickhouse::Client,
+    database: &str,
+    request: &ErasureRequest,
+    identifier: &str,
+) -> Result<Resolved> {
+    let project_id = request.project_id;
+    let identifier = identifier.trim();
+    let mut resolved = Resolved::default();
+
+    match request.identifier_type.as_str() {
+        "user_id" => {
+            resolved.ids.insert(identifier.to_string());
+        }
+        kind @ ("email" | "mobile_number") => {
+            let (column, value) = if kind == "email" {
+                resolved.emails.insert(identifier.to_lowercase());
+                ("lower(ifNull(email, ''))", identifier.to_lowercase())
+            } else {
+                resolved.mobile_numbers.insert(identifier.to_string());
+                ("ifNull(mobile_number, '')", identifier.to_string())
+            };
+            let rows = clickhouse
+                .query(&format!(
+                    "SELECT DISTINCT arrayJoin([ifNull(user_id, ''), anonymous_id]) AS id \
+                     FROM {database}.events WHERE project_id = ? AND {column} = ? \
+                     UNION DISTINCT \
+                     SELECT user_uid AS id FROM {database}.user_profiles \
+                     WHERE project_id = ? AND {column} = ?"
+                ))
+                .bind(project_id)
+                .bind(&value)
+                .bind(project_id)
+                .bind(&value)
+                .fetch_all::<IdRow>()
+                .await
+                .context("failed to find users by contact")?;
+            resolved.ids.extend(rows.into_iter().map(|r| r.id));
+        }
+        other => bail!("unknown identifier type {other}"),
+    }
+    resolved.ids.remove("");
+
+    // Follow identity links until no new IDs turn up.
+    for _ in 0..MAX_RESOLVE_ROUNDS {
+        if resolved.ids.is_empty() {
+            break;
+        }
+        let ids: Vec<&String> = resolved.ids.iter().collect();
+        let rows = clickhouse
+            .query(&format!(
+                "SELECT arrayJoin([anonymous_id, user_id]) AS id FROM {database}.identity_map \
+                 WHERE project_id = ? AND (has(?, anonymous_id) OR has(?, user_id)) \
+                 UNION DISTINCT \
+                 SELECT distinct_id AS id FROM {database}.identity_graph FINAL \
+                 WHERE project_id = ? AND person_id IN ( \
+                   SELECT person_id FROM {database}.identity_graph FINAL \
+                   WHERE project_id = ? AND (has(?, distinct_id) OR has(?, person_id))) \
+                 UNION DISTINCT \
+                 SELECT person_id AS id FROM {database}.identity_graph FINAL \
+                 WHERE project_id = ? AND has(?, distinct_id)"
+            ))
+            .bind(project_id)
+            .bind(&ids)
+            .bind(&ids)
+            .bind(project_id)
+            .bind(project_id)
+            .bind(&ids)
+            .bind(&ids)
+            .bind(project_id)
+            .bind(&ids)
+            .fetch_all::<IdRow>()
+            .await
+            .context("failed to resolve linked IDs")?;
+
+        let before = resolved.ids.len();
+        resolved
+            .ids
+            .extend(rows.into_iter().map(|r| r.id).filter(|id| !id.is_empty()));
+        if resolved.ids.len() == before {
+            break;
+        }
+    }
+
+    // Contacts seen for any of the IDs are erased and suppressed too.
+    if !resolved.ids.is_empty() {
+        let ids: Vec<&String> = resolved.ids.iter().collect();
+        let rows = clickhouse
+            .query(&format!(
+                "SELECT DISTINCT lower(ifNull(email, '')) AS email, \
+                   ifNull(mobile_number, '') AS mobile_number \
+                 FROM {database}.events \
+                 WHERE project_id = ? AND (has(?, ifNull(user_id, '')) OR has(?, anonymous_id)) \
+                 UNION DISTINCT \
+                 SELECT lower(ifNull(email, '')) AS email, \
+                   ifNull(mobile_number, '') AS mobile_number \
+                 FROM {database}.user_profiles \
+                 WHERE project_id = ? AND has(?, user_uid)"
+            ))
+            .bind(project_id)
+            .bind(&ids)
+            .bind(&ids)
+            .bind(project_id)
+            .bind(&ids)
+            .fetch_all::<ContactRow>()
+            .await
+            .context("failed to resolve contacts")?;
+        for row in rows {
+            if !row.email.is_empty() {
+                resolved.emails.insert(row.email);
+            }
+            if !row.mobile_number.is_empty() {
+                resolved.mobile_numbers.insert(row.mobile_number);
+            }
+        }
+    }
+
+    Ok(resolved)
+}
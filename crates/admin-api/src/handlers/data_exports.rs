@@ -0,0 +1,151 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use truesight_common::data_export::{FORMATS, NewDataExport, STATUS_COMPLETED};
+use truesight_common::error::AppError;
+use truesight_common::team::TeamRole;
+
+use crate::data_export::ExportStore;
+use crate::db::data_exports as db;
+use crate::handlers::rbac;
+use crate::middleware::admin_auth::AuthUser;
+use crate::state::AppState;
+
+// ── Types ───────────────────────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct CreateExportInput {
+    /// The user as identified by the users endpoints.
+    pub user_uid: String,
+    #[serde(default = "default_format")]
+    pub format: String,
+}
+
+fn default_format() -> String {
+    "json".to_string()
+}
+
+fn export_store(state: &AppState) -> Result<&ExportStore, AppError> {
+    state.exports.as_deref().ok_or_else(|| {
+        AppError::ServiceUnavailable("Data exports are not configured (set EXPORT_URL)".to_string())
+    })
+}
+
+// ── Validation ──────────────────────────────────────────────────────
+
+fn validate_input(input: &CreateExportInput) -> Result<(), AppError> {
+    if input.user_uid.trim().is_empty() {
+        return Err(AppError::Validation("user_uid must not be empty".into()));
+    }
+    if !FORMATS.contains(&input.format.as_str()) {
+        return Err(AppError::Validation(format!(
+            "format must be one of: {}",
+            FORMATS.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+// ── Handlers ────────────────────────────────────────────────────────
+
+/// Queues the export of a user's data. The archive is built in the
+/// background; poll the returned export until it completes, then download it.
+pub async fn create_export(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(project_id): Path<Uuid>,
+    Json(input): Json<CreateExportInput>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Admin)?;
+    export_store(&state)?;
+    validate_input(&input)?;
+
+    let requested_by = match auth.email {
+        Some(email) => email,
+        None => "admin-token".to_string(),
+    };
+    let export = db::insert_export(
+        &state.db_pool,
+        NewDataExport {
+            project_id,
+            user_uid: input.user_uid.trim().to_string(),
+            format: input.format,
+            requested_by,
+        },
+    )?;
+    tracing::info!(
+        project_id = %project_id,
+        export_id = %export.id,
+        "data export requested"
+    );
+    Ok((axum::http::StatusCode::ACCEPTED, Json(export)))
+}
+
+pub async fn list_exports(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(project_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Admin)?;
+    let exports = db::list_exports(&state.db_pool, project_id)?;
+    Ok(Json(exports))
+}
+
+pub async fn get_export(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((project_id, export_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Admin)?;
+    let export = db::find_export(&state.db_pool, project_id, export_id)?;
+    Ok(Json(export))
+}
+
+/// Downloads the archive of a completed export as a tar file.
+pub async fn download_export(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((project_id, export_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Admin)?;
+    let store = export_store(&state)?;
+    let export = db::find_export(&state.db_pool, project_id, export_id)?;
+    if export.status != STATUS_COMPLETED {
+        return Err(AppError::Validation(format!(
+            "export is {}; only completed exports can be downloaded",
+            export.status
+        )));
+    }
+
+    // Archives of erased users expire at once but are only deleted by the
+    // worker's next sweep.
+    let expired = export.expires_at.is_none_or(|at| at <= Utc::now());
+    let key = db::archive_key(&state.db_pool, project_id, export_id)?;
+    let Some(key) = key.filter(|_| !expired) else {
+        return Err(AppError::NotFound("Export archive has expired".into()));
+    };
+    let tar = store.download(&key).await.map_err(|e| match e {
+        object_store::Error::NotFound { .. } => {
+            AppError::NotFound("Export archive has expired".into())
+        }
+        e => AppError::Internal(format!("Failed to read export archive: {e}")),
+    })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-tar".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"truesight-export-{export_id}.tar\""),
+            ),
+        ],
+        tar,
+    ))
+}
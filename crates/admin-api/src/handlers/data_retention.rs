@@ -0,0 +1,192 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use truesight_common::error::AppError;
+use truesight_common::retention::{
+    ENVIRONMENTS, MAX_RETENTION_DAYS, MIN_RETENTION_DAYS, NewRetentionPolicy, RETAINED_TABLES,
+    RetentionPolicy,
+};
+use truesight_common::team::TeamRole;
+
+use crate::db::retention as db;
+use crate::handlers::rbac;
+use crate::middleware::admin_auth::AuthUser;
+use crate::state::AppState;
+
+// ── Types ───────────────────────────────────────────────────────────
+
+/// Retention in effect for one environment of a project.
+#[derive(Debug, Serialize)]
+pub struct EnvironmentRetention {
+    pub environment: String,
+    /// `None` when data is kept forever.
+    pub retention_days: Option<i32>,
+    /// Data older than this is purged.
+    pub cutoff: Option<DateTime<Utc>>,
+    pub last_purged_at: Option<DateTime<Utc>>,
+    /// When the next purge runs at the earliest; now if one is due.
+    pub next_purge_at: Option<DateTime<Utc>>,
+    /// Events older than the cutoff, removed by the next purge.
+    pub expired_events: u64,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionResponse {
+    pub project_id: Uuid,
+    pub environments: Vec<EnvironmentRetention>,
+    /// ClickHouse tables the policies apply to.
+    pub tables: Vec<&'static str>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetRetentionInput {
+    pub retention_days: i32,
+}
+
+#[derive(Debug, clickhouse::Row, Deserialize)]
+struct ExpiredRow {
+    expired: u64,
+}
+
+// ── Validation ──────────────────────────────────────────────────────
+
+fn validate_environment(environment: &str) -> Result<(), AppError> {
+    if !ENVIRONMENTS.contains(&environment) {
+        return Err(AppError::Validation(format!(
+            "environment must be one of: {}",
+            ENVIRONMENTS.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+fn validate_retention_days(days: i32) -> Result<(), AppError> {
+    if !(MIN_RETENTION_DAYS..=MAX_RETENTION_DAYS).contains(&days) {
+        return Err(AppError::Validation(format!(
+            "retention_days must be between {MIN_RETENTION_DAYS} and {MAX_RETENTION_DAYS}"
+        )));
+    }
+    Ok(())
+}
+
+// ── Helpers ─────────────────────────────────────────────────────────
+
+async fn environment_retention(
+    state: &AppState,
+    project_id: Uuid,
+    environment: &str,
+    policy: Option<&RetentionPolicy>,
+) -> Result<EnvironmentRetention, AppError> {
+    let Some(policy) = policy else {
+        return Ok(EnvironmentRetention {
+            environment: environment.to_string(),
+            retention_days: None,
+            cutoff: None,
+            last_purged_at: None,
+            next_purge_at: None,
+            expired_events: 0,
+            updated_at: None,
+        });
+    };
+
+    let now = Utc::now();
+    let cutoff = policy.cutoff(now);
+    let db = &state.config.clickhouse_database;
+    let expired = state
+        .clickhouse_client
+        .query(&format!(
+            "SELECT sum(count) AS expired FROM {db}.events_hourly \
+             WHERE project_id = ? AND environment = ? AND hour < toDateTime64(?, 3, 'UTC')"
+        ))
+        .bind(project_id)
+        .bind(environment)
+        .bind(cutoff.timestamp_millis() as f64 / 1000.0)
+        .fetch_one::<ExpiredRow>()
+        .await
+        .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
+
+    Ok(EnvironmentRetention {
+        environment: environment.to_string(),
+        retention_days: Some(policy.retention_days),
+        cutoff: Some(cutoff),
+        last_purged_at: policy.last_purged_at,
+        next_purge_at: Some(policy.next_purge_at(now).unwrap_or(now)),
+        expired_events: expired.expired,
+        updated_at: Some(policy.updated_at),
+    })
+}
+
+// ── Handlers ────────────────────────────────────────────────────────
+
+/// Retention in effect for each environment and what the next purge removes.
+pub async fn get_retention(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(project_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Viewer)?;
+    let policies = db::list_policies(&state.db_pool, project_id)?;
+
+    let mut environments = Vec::with_capacity(ENVIRONMENTS.len());
+    for environment in ENVIRONMENTS {
+        let policy = policies.iter().find(|p| p.environment == *environment);
+        environments.push(environment_retention(&state, project_id, environment, policy).await?);
+    }
+
+    Ok(Json(RetentionResponse {
+        project_id,
+        environments,
+        tables: RETAINED_TABLES
+            .iter()
+            .map(|(table, _)| *table)
+            .chain(["event_property_keys"])
+            .collect(),
+    }))
+}
+
+pub async fn set_retention(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((project_id, environment)): Path<(Uuid, String)>,
+    Json(input): Json<SetRetentionInput>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Admin)?;
+    validate_environment(&environment)?;
+    validate_retention_days(input.retention_days)?;
+
+    let policy = db::upsert_policy(
+        &state.db_pool,
+        NewRetentionPolicy {
+            project_id,
+            environment: environment.clone(),
+            retention_days: input.retention_days,
+        },
+    )?;
+    tracing::info!(
+        project_id = %project_id,
+        environment = %environment,
+        retention_days = input.retention_days,
+        "retention policy set"
+    );
+    let retention = environment_retention(&state, project_id, &environment, Some(&policy)).await?;
+    Ok(Json(retention))
+}
+
+/// Removes the policy, keeping the environment's data forever again.
+pub async fn delete_retention(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((project_id, environment)): Path<(Uuid, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Admin)?;
+    validate_environment(&environment)?;
+    db::delete_policy(&state.db_pool, project_id, &environment)?;
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+
+use axum::{Json, extract::Query, extract::State, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use truesight_common::erasure::{Suppression, SuppressionList};
+use truesight_common::error::AppError;
+use truesight_common::event::EnrichedEvent;
+use truesight_common::event_row::{EventRow, write_event_rows};
+use truesight_common::sqs::DlqMessage;
+
+use crate::handlers::rbac;
+use crate::middleware::admin_auth::AuthUser;
+use crate::state::{AppState, DlqTooling};
+
+/// How long scanned messages stay hidden from other DLQ readers. They are
+/// released as soon as the request finishes; this only bounds the damage if
+/// the request dies half-way.
+const SCAN_VISIBILITY_SECS: i32 = 60;
+
+/// Maximum number of DLQ messages scanned per request.
+const MAX_SCAN: usize = 1000;
+
+/// Number of body characters returned as a preview.
+const PREVIEW_CHARS: usize = 200;
+
+/// Number of sample message IDs listed per reason group.
+const GROUP_SAMPLE_IDS: usize = 10;
+
+fn dlq_tooling(state: &AppState) -> Result<&DlqTooling, AppError> {
+    state.dlq.as_deref().ok_or_else(|| {
+        AppError::ServiceUnavailable(
+            "DLQ tooling is not configured (set SQS_QUEUE_URL or SQS_DLQ_URL)".to_string(),
+        )
+    })
+}
+
+/// Returns `true` if the message passes the optional project and reason filters.
+fn matches_filters(
+    message: &DlqMessage,
+    event: Option<&EnrichedEvent>,
+    project_id: Option<Uuid>,
+    reason: Option<&str>,
+) -> bool {
+    if let Some(pid) = project_id
+        && event.map(|e| e.project_id) != Some(pid)
+    {
+        return false;
+    }
+    if let Some(reason) = reason.filter(|r| !r.is_empty())
+        && !message
+            .error_reason
+            .as_deref()
+            .is_some_and(|r| r.contains(reason))
+    {
+        return false;
+    }
+    true
+}
+
+/// Whether the message is an event of a user erased through admin-api.
+fn is_suppressed(suppression: Option<&SuppressionList>, event: Option<&EnrichedEvent>) -> bool {
+    matches!((suppression, event), (Some(list), Some(event)) if list.is_enriched_suppressed(event))
+}
+
+/// Deletes scanned messages of erased users from the DLQ, logging (not
+/// failing) on error; they are picked up again by a later scan.
+async fn delete_suppressed(dlq: &DlqTooling, messages: &[DlqMessage]) {
+    if messages.is_empty() {
+        return;
+    }
+    match dlq.client.delete(&dlq.dlq_url, messages).await {
+        Ok(()) => tracing::info!(
+            deleted = messages.len(),
+            "Deleted DLQ messages of erased users"
+        ),
+        Err(e) => tracing::error!(error = %e, "Failed to delete DLQ messages of erased users"),
+    }
+}
+
+/// Releases scanned messages back to the DLQ, logging (not failing) on error.
+async fn release(dlq: &DlqTooling, messages: &[DlqMessage]) {
+    if messages.is_empty() {
+        return;
+    }
+    if let Err(e) = dlq.client.release(&dlq.dlq_url, messages).await {
+        tracing::error!(error = %e, "Failed to release scanned DLQ messages");
+    }
+}
+
+// ── List / Group DLQ Messages ───────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct DlqListQuery {
+    /// Maximum number of messages to scan (default 100, max 1000).
+    pub limit: Option<usize>,
+    pub project_id: Option<Uuid>,
+    /// Substring filter on `error_reason`.
+    pub reason: Option<String>,
+    /// Return full bodies instead of previews.
+    #[serde(default)]
+    pub include_body: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DlqMessageView {
+    pub message_id: String,
+    pub error_reason: Option<String>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub receive_count: u32,
+    pub project_id: Option<Uuid>,
+    pub event_id: Option<Uuid>,
+    pub event_name: Option<String>,
+    /// `false` when the body is not a valid event (e.g. deserialisation failures);
+    /// such messages cannot be redriven.
+    pub parseable: bool,
+    pub body: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DlqReasonGroup {
+    pub error_reason: Option<String>,
+    pub count: usize,
+    pub first_sent_at: Option<DateTime<Utc>>,
+    pub last_sent_at: Option<DateTime<Utc>>,
+    pub message_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DlqListResponse {
+    pub dlq_url: String,
+    /// Approximate total number of messages in the DLQ (visible + delayed).
+    pub approximate_depth: Option<u64>,
+    pub scanned: usize,
+    pub matched: usize,
+    /// Messages of erased users, deleted instead of listed.
+    pub suppressed: usize,
+    pub groups: Vec<DlqReasonGroup>,
+    pub messages: Vec<DlqMessageView>,
+}
+
+/// Lists DLQ messages grouped by `error_reason`, with payload previews.
+///
+/// Scanned messages are hidden for the duration of the request and released
+/// immediately afterwards, except those of erased users, which are deleted.
+pub async fn list_dlq(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Query(params): Query<DlqListQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_operator(&auth)?;
+    let dlq = dlq_tooling(&state)?;
+
+    let limit = params.limit.unwrap_or(100).clamp(1, MAX_SCAN);
+
+    let approximate_depth = match dlq.producer.approximate_queue_depth(&dlq.dlq_url).await {
+        Ok(depth) => Some(depth),
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to read DLQ depth");
+            None
+        }
+    };
+
+    let scanned = dlq
+        .client
+        .scan(&dlq.dlq_url, limit, SCAN_VISIBILITY_SECS)
+        .await
+        .map_err(|e| AppError::Sqs(e.to_string()))?;
+
+    let suppression = state.suppression.as_ref().map(Suppression::list);
+    let mut suppressed: Vec<DlqMessage> = Vec::new();
+    let mut released: Vec<DlqMessage> = Vec::new();
+    let mut groups: HashMap<Option<String>, DlqReasonGroup> = HashMap::new();
+    let mut messages = Vec::new();
+
+    for message in &scanned {
+        let event = serde_json::from_str::<EnrichedEvent>(&message.body).ok();
+        if is_suppressed(suppression.as_deref(), event.as_ref()) {
+            suppressed.push(message.clone());
+            continue;
+        }
+        released.push(message.clone());
+        if !matches_filters(
+            message,
+            event.as_ref(),
+            params.project_id,
+            params.reason.as_deref(),
+        ) {
+            continue;
+        }
+
+        let group = groups
+            .entry(message.error_reason.clone())
+            .or_insert_with(|| DlqReasonGroup {
+                error_reason: message.error_reason.clone(),
+                count: 0,
+                first_sent_at: None,
+                last_sent_at: None,
+                message_ids: Vec::new(),
+            });
+        group.count += 1;
+        group.first_sent_at = match (group.first_sent_at, message.sent_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        group.last_sent_at = group.last_sent_at.max(message.sent_at);
+        if group.message_ids.len() < GROUP_SAMPLE_IDS {
+            group.message_ids.push(message.message_id.clone());
+        }
+
+        messages.push(DlqMessageView {
+            message_id: message.message_id.clone(),
+            error_reason: message.error_reason.clone(),
+            sent_at: message.sent_at,
+            receive_count: message.receive_count,
+            project_id: event.as_ref().map(|e| e.project_id),
+            event_id: event.as_ref().map(|e| e.event_id),
+            event_name: event.as_ref().map(|e| e.event_name.clone()),
+            parseable: event.is_some(),
+            body: if params.include_body {
+                message.body.clone()
+            } else {
+                message.body.chars().take(PREVIEW_CHARS).collect()
+            },
+        });
+    }
+
+    delete_suppressed(dlq, &suppressed).await;
+    release(dlq, &released).await;
+
+    let mut groups: Vec<DlqReasonGroup> = groups.into_values().collect();
+    groups.sort_by(|a, b| b.count.cmp(&a.count));
+
+    Ok(Json(DlqListResponse {
+        dlq_url: dlq.dlq_url.clone(),
+        approximate_depth,
+        scanned: scanned.len(),
+        matched: messages.len(),
+        suppressed: suppressed.len(),
+        groups,
+        messages,
+    }))
+}
+
+// ── Redrive DLQ Messages ────────────────────────────────────────────
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RedriveTarget {
+    /// Send back to the main SQS queue so ch-writer processes them normally.
+    #[default]
+    Queue,
+    /// Insert straight into the ClickHouse `events` table. Identity and
+    /// profile processing is skipped.
+    Clickhouse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RedriveRequest {
+    /// Message IDs to redrive.
+    #[serde(default)]
+    pub message_ids: Vec<String>,
+    /// Redrive every scanned message whose `error_reason` contains this.
+    pub reason: Option<String>,
+    pub project_id: Option<Uuid>,
+    #[serde(default)]
+    pub target: RedriveTarget,
+    /// Maximum number of messages to scan (default and max 1000).
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedriveFailure {
+    pub message_id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RedriveResponse {
+    pub target: RedriveTarget,
+    pub scanned: usize,
+    pub redriven: usize,
+    /// Messages of erased users, deleted instead of redriven.
+    pub suppressed: usize,
+    /// Requested message IDs that were not found within the scanned messages.
+    pub not_found: Vec<String>,
+    pub failed: Vec<RedriveFailure>,
+}
+
+/// Redrives selected DLQ messages to the main queue or directly into
+/// ClickHouse. Successfully redriven messages are deleted from the DLQ, as are
+/// scanned messages of erased users, which are never redriven; everything
+/// else scanned is released back untouched.
+pub async fn redrive_dlq(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Json(body): Json<RedriveRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_operator(&auth)?;
+    let dlq = dlq_tooling(&state)?;
+
+    let reason = body.reason.as_deref().filter(|r| !r.is_empty());
+    if body.message_ids.is_empty() && reason.is_none() {
+        return Err(AppError::Validation(
+            "Provide message_ids and/or reason to select messages to redrive".to_string(),
+        ));
+    }
+    if body.target == RedriveTarget::Queue && dlq.queue_url.is_none() {
+        return Err(AppError::Validation(
+            "SQS_QUEUE_URL is not configured; use target \"clickhouse\"".to_string(),
+        ));
+    }
+
+    let limit = body.limit.unwrap_or(MAX_SCAN).clamp(1, MAX_SCAN);
+    let scanned = dlq
+        .client
+        .scan(&dlq.dlq_url, limit, SCAN_VISIBILITY_SECS)
+        .await
+        .map_err(|e| AppError::Sqs(e.to_string()))?;
+
+    let suppression = state.suppression.as_ref().map(Suppression::list);
+    let mut selected: Vec<(DlqMessage, EnrichedEvent)> = Vec::new();
+    let mut suppressed: Vec<DlqMessage> = Vec::new();
+    let mut untouched: Vec<DlqMessage> = Vec::new();
+    let mut failed: Vec<RedriveFailure> = Vec::new();
+
+    for message in scanned.iter().cloned() {
+        let event = serde_json::from_str::<EnrichedEvent>(&message.body).ok();
+        if is_suppressed(suppression.as_deref(), event.as_ref()) {
+            suppressed.push(message);
+            continue;
+        }
+        let wanted = (body.message_ids.is_empty()
+            || body.message_ids.contains(&message.message_id))
+            && matches_filters(&message, event.as_ref(), body.project_id, reason);
+        match (wanted, event) {
+            (true, Some(event)) => selected.push((message, event)),
+            (true, None) => {
+                failed.push(RedriveFailure {
+                    message_id: message.message_id.clone(),
+                    error: "message body is not a valid event".to_string(),
+                });
+                untouched.push(message);
+            }
+            (false, _) => untouched.push(message),
+        }
+    }
+
+    let not_found: Vec<String> = body
+        .message_ids
+        .iter()
+        .filter(|id| !scanned.iter().any(|m| &m.message_id == *id))
+        .cloned()
+        .collect();
+
+    let (done, errored) = match body.target {
+        RedriveTarget::Queue => redrive_to_queue(dlq, selected).await,
+        RedriveTarget::Clickhouse => redrive_to_clickhouse(&state, selected).await,
+    };
+
+    for (message, error) in errored {
+        failed.push(RedriveFailure {
+            message_id: message.message_id.clone(),
+            error,
+        });
+        untouched.push(message);
+    }
+
+    if !done.is_empty()
+        && let Err(e) = dlq.client.delete(&dlq.dlq_url, &done).await
+    {
+        // The events were already redriven; leaving them on the DLQ only risks
+        // a duplicate (collapsed by ReplacingMergeTree) on a later redrive.
+        tracing::error!(error = %e, "Failed to delete redriven messages from DLQ");
+    }
+    delete_suppressed(dlq, &suppressed).await;
+    release(dlq, &untouched).await;
+
+    tracing::info!(
+        target = ?body.target,
+        scanned = scanned.len(),
+        redriven = done.len(),
+        suppressed = suppressed.len(),
+        failed = failed.len(),
+        "DLQ redrive completed"
+    );
+
+    Ok(Json(RedriveResponse {
+        target: body.target,
+        scanned: scanned.len(),
+        redriven: done.len(),
+        suppressed: suppressed.len(),
+        not_found,
+        failed,
+    }))
+}
+
+type RedriveOutcome = (Vec<DlqMessage>, Vec<(DlqMessage, String)>);
+
+/// Re-enqueues events on the main queue, one message at a time so a failure
+/// only affects the message it belongs to.
+async fn redrive_to_queue(
+    dlq: &DlqTooling,
+    selected: Vec<(DlqMessage, EnrichedEvent)>,
+) -> RedriveOutcome {
+    let Some(queue_url) = dlq.queue_url.as_deref() else {
+        return (Vec::new(), Vec::new());
+    };
+
+    let mut done = Vec::new();
+    let mut errored = Vec::new();
+    for (message, event) in selected {
+        match dlq
+            .producer
+            .send_batch(std::slice::from_ref(&event), queue_url)
+            .await
+        {
+            Ok(()) => done.push(message),
+            Err(e) => errored.push((message, e.to_string())),
+        }
+    }
+    (done, errored)
+}
+
+/// Inserts events straight into ClickHouse. Tries a single insert first and
+/// falls back to row-by-row inserts to pin failures on individual messages.
+async fn redrive_to_clickhouse(
+    state: &AppState,
+    selected: Vec<(DlqMessage, EnrichedEvent)>,
+) -> RedriveOutcome {
+    if selected.is_empty() {
+        return (Vec::new(), Vec::new());
+    }
+
+    let rows: Vec<EventRow> = selected
+        .iter()
+        .map(|(_, event)| EventRow::from_enriched(event))
+        .collect();
+
+    if write_event_rows(&state.clickhouse_client, &rows)
+        .await
+        .is_ok()
+    {
+        return (selected.into_iter().map(|(m, _)| m).collect(), Vec::new());
+    }
+
+    let mut done = Vec::new();
+    let mut errored = Vec::new();
+    for ((message, _), row) in selected.into_iter().zip(rows) {
+        match write_event_rows(&state.clickhouse_client, std::slice::from_ref(&row)).await {
+            Ok(()) => done.push(message),
+            Err(e) => errored.push((message, format!("{e:#}"))),
+        }
+    }
+    (done, errored)
+}
@@ -0,0 +1,114 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use truesight_common::erasure::{NewErasureRequest, identifier_hash};
+use truesight_common::error::AppError;
+use truesight_common::team::TeamRole;
+
+use crate::db::erasures as db;
+use crate::handlers::rbac;
+use crate::middleware::admin_auth::AuthUser;
+use crate::state::AppState;
+
+// ── Types ───────────────────────────────────────────────────────────
+
+/// Names the user to erase by exactly one identifier.
+#[derive(Debug, Deserialize)]
+pub struct CreateErasureInput {
+    pub user_id: Option<String>,
+    pub email: Option<String>,
+    pub mobile_number: Option<String>,
+}
+
+// ── Validation ──────────────────────────────────────────────────────
+
+/// Returns the identifier type and identifier of the request.
+fn validate_identifier(input: CreateErasureInput) -> Result<(&'static str, String), AppError> {
+    let given: Vec<(&'static str, String)> = [
+        ("user_id", input.user_id),
+        ("email", input.email),
+        ("mobile_number", input.mobile_number),
+    ]
+    .into_iter()
+    .filter_map(|(kind, value)| value.map(|v| (kind, v.trim().to_string())))
+    .collect();
+
+    match given.as_slice() {
+        [(kind, value)] if !value.is_empty() => Ok((kind, value.clone())),
+        [(kind, _)] => Err(AppError::Validation(format!("{kind} must not be empty"))),
+        _ => Err(AppError::Validation(
+            "exactly one of user_id, email or mobile_number is required".into(),
+        )),
+    }
+}
+
+// ── Handlers ────────────────────────────────────────────────────────
+
+/// Queues the erasure of a user's data. The erasure runs in the background;
+/// poll the returned request for its progress.
+pub async fn create_erasure(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(project_id): Path<Uuid>,
+    Json(input): Json<CreateErasureInput>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Admin)?;
+    let hash_key = state
+        .config
+        .erasure_hash_key
+        .as_deref()
+        .filter(|k| !k.is_empty())
+        .ok_or_else(|| {
+            AppError::ServiceUnavailable(
+                "Erasure is not configured (set ERASURE_HASH_KEY)".to_string(),
+            )
+        })?;
+    let (identifier_type, identifier) = validate_identifier(input)?;
+
+    let requested_by = match auth.email {
+        Some(email) => email,
+        None => "admin-token".to_string(),
+    };
+    let erasure = db::insert_erasure(
+        &state.db_pool,
+        NewErasureRequest {
+            project_id,
+            identifier_type: identifier_type.to_string(),
+            identifier_hash: identifier_hash(hash_key, project_id, identifier_type, &identifier),
+            identifier: Some(identifier),
+            requested_by,
+        },
+    )?;
+    tracing::info!(
+        project_id = %project_id,
+        erasure_id = %erasure.id,
+        identifier_type,
+        "erasure requested"
+    );
+    Ok((axum::http::StatusCode::ACCEPTED, Json(erasure)))
+}
+
+pub async fn list_erasures(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(project_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Admin)?;
+    let erasures = db::list_erasures(&state.db_pool, project_id)?;
+    Ok(Json(erasures))
+}
+
+pub async fn get_erasure(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((project_id, erasure_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Admin)?;
+    let erasure = db::find_erasure(&state.db_pool, project_id, erasure_id)?;
+    Ok(Json(erasure))
+}
@@ -1,8 +1,11 @@
+use std::collections::HashMap;
+
 use axum::{
     Json,
     extract::{Path, Query, State},
     response::IntoResponse,
 };
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -118,16 +121,41 @@ pub struct EventPropertiesQuery {
     pub environment: Option<String>,
 }
 
-#[derive(Debug, Serialize, clickhouse::Row, Deserialize)]
-pub struct PropertyKeyRow {
+#[derive(Debug, clickhouse::Row, Deserialize)]
+struct PropertyKeyRow {
+    property_key: String,
+    first_seen: String,
+}
+
+#[derive(Debug, clickhouse::Row, Deserialize)]
+struct PropertyTypeRow {
+    property_key: String,
+    value_type: String,
+    event_count: u64,
+    first_seen: String,
+    last_seen: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PropertyTypeCount {
+    pub value_type: String,
+    pub event_count: u64,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventProperty {
     pub property_key: String,
     pub first_seen: String,
+    /// Value types the property was sent with, first seen first.
+    pub types: Vec<PropertyTypeCount>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct EventPropertiesResponse {
     pub event_name: String,
-    pub properties: Vec<PropertyKeyRow>,
+    pub properties: Vec<EventProperty>,
 }
 
 pub async fn event_properties(
@@ -167,8 +195,234 @@ pub async fn event_properties(
         .await
         .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
 
+    let types_query = format!(
+        "SELECT property_key, value_type, \
+         sum(event_count) AS event_count, \
+         formatDateTime(min(first_seen), '%Y-%m-%dT%H:%i:%SZ', 'UTC') AS first_seen, \
+         formatDateTime(max(last_seen), '%Y-%m-%dT%H:%i:%SZ', 'UTC') AS last_seen \
+         FROM {db}.event_property_types \
+         WHERE project_id = ? AND event_name = ?{env_filter} \
+         GROUP BY property_key, value_type \
+         ORDER BY property_key, first_seen"
+    );
+
+    let mut q = state
+        .clickhouse_client
+        .query(&types_query)
+        .bind(project_id)
+        .bind(event_name.as_str());
+    if let Some(ref env) = params.environment {
+        q = q.bind(env.as_str());
+    }
+    let type_rows = q
+        .fetch_all::<PropertyTypeRow>()
+        .await
+        .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
+
+    let mut types: HashMap<String, Vec<PropertyTypeCount>> = HashMap::new();
+    for row in type_rows {
+        types
+            .entry(row.property_key)
+            .or_default()
+            .push(PropertyTypeCount {
+                value_type: row.value_type,
+                event_count: row.event_count,
+                first_seen: row.first_seen,
+                last_seen: row.last_seen,
+            });
+    }
+
+    let properties = rows
+        .into_iter()
+        .map(|row| EventProperty {
+            types: types.remove(&row.property_key).unwrap_or_default(),
+            property_key: row.property_key,
+            first_seen: row.first_seen,
+        })
+        .collect();
+
     Ok(Json(EventPropertiesResponse {
         event_name,
-        properties: rows,
+        properties,
     }))
 }
+
+// ── Property Type Drift ─────────────────────────────────────────────
+
+#[derive(Debug, Deserialize)]
+pub struct PropertyDriftQuery {
+    pub event_name: Option<String>,
+    pub environment: Option<String>,
+    /// Only report properties whose new type first appeared at or after this
+    /// time.
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, clickhouse::Row, Deserialize)]
+struct DriftRow {
+    event_name: String,
+    property_key: String,
+    value_type: String,
+    sdk_version: String,
+    event_count: u64,
+    first_seen: String,
+    last_seen: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SdkVersionCount {
+    pub sdk_version: String,
+    pub event_count: u64,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DriftedType {
+    pub value_type: String,
+    pub event_count: u64,
+    pub first_seen: String,
+    pub last_seen: String,
+    /// SDK version that first sent the property with this type.
+    pub introduced_in: String,
+    pub sdk_versions: Vec<SdkVersionCount>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PropertyDrift {
+    pub event_name: String,
+    pub property_key: String,
+    /// Type the property was first sent with.
+    pub original_type: String,
+    /// When a different type first appeared.
+    pub changed_at: String,
+    /// Every observed type, first seen first.
+    pub types: Vec<DriftedType>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PropertyDriftResponse {
+    pub drifts: Vec<PropertyDrift>,
+}
+
+/// Properties that have been sent with more than one value type, such as a
+/// `price` that was a number and is a string from some SDK version on. Most
+/// recent changes first.
+pub async fn property_drift(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(project_id): Path<Uuid>,
+    Query(params): Query<PropertyDriftQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Viewer)?;
+    let db = &state.config.clickhouse_database;
+
+    let mut filters = String::new();
+    if params.event_name.is_some() {
+        filters.push_str(" AND event_name = ?");
+    }
+    if params.environment.is_some() {
+        filters.push_str(" AND environment = ?");
+    }
+
+    let query = format!(
+        "SELECT event_name, property_key, value_type, sdk_version, \
+         sum(event_count) AS event_count, \
+         formatDateTime(min(first_seen), '%Y-%m-%dT%H:%i:%SZ', 'UTC') AS first_seen, \
+         formatDateTime(max(last_seen), '%Y-%m-%dT%H:%i:%SZ', 'UTC') AS last_seen \
+         FROM {db}.event_property_types \
+         WHERE project_id = ? AND NOT startsWith(event_name, '$'){filters} \
+         AND (event_name, property_key) IN ( \
+           SELECT event_name, property_key FROM {db}.event_property_types \
+           WHERE project_id = ?{filters} \
+           GROUP BY event_name, property_key \
+           HAVING uniqExact(value_type) > 1) \
+         GROUP BY event_name, property_key, value_type, sdk_version \
+         ORDER BY event_name, property_key, first_seen"
+    );
+
+    let mut q = state.clickhouse_client.query(&query);
+    for _ in 0..2 {
+        q = q.bind(project_id);
+        if let Some(ref name) = params.event_name {
+            q = q.bind(name.as_str());
+        }
+        if let Some(ref env) = params.environment {
+            q = q.bind(env.as_str());
+        }
+    }
+    let rows = q
+        .fetch_all::<DriftRow>()
+        .await
+        .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
+
+    let since = params
+        .since
+        .map(|s| s.format("%Y-%m-%dT%H:%M:%SZ").to_string());
+    let mut drifts = group_drifts(rows);
+    drifts.retain(|d| since.as_ref().is_none_or(|since| d.changed_at >= *since));
+    drifts.sort_by(|a, b| b.changed_at.cmp(&a.changed_at));
+
+    Ok(Json(PropertyDriftResponse { drifts }))
+}
+
+/// Groups per-SDK-version rows (ordered by event, property and first seen)
+/// into one drift per property.
+fn group_drifts(rows: Vec<DriftRow>) -> Vec<PropertyDrift> {
+    let mut drifts: Vec<PropertyDrift> = Vec::new();
+
+    for row in rows {
+        let version = SdkVersionCount {
+            sdk_version: row.sdk_version,
+            event_count: row.event_count,
+            first_seen: row.first_seen,
+            last_seen: row.last_seen,
+        };
+        let drift = match drifts.last_mut() {
+            Some(d) if d.event_name == row.event_name && d.property_key == row.property_key => d,
+            _ => {
+                drifts.push(PropertyDrift {
+                    event_name: row.event_name,
+                    property_key: row.property_key,
+                    original_type: String::new(),
+                    changed_at: String::new(),
+                    types: Vec::new(),
+                });
+                drifts.last_mut().expect("just pushed")
+            }
+        };
+
+        match drift
+            .types
+            .iter_mut()
+            .find(|t| t.value_type == row.value_type)
+        {
+            Some(t) => {
+                t.event_count += version.event_count;
+                if version.last_seen > t.last_seen {
+                    t.last_seen = version.last_seen.clone();
+                }
+                t.sdk_versions.push(version);
+            }
+            None => drift.types.push(DriftedType {
+                value_type: row.value_type,
+                event_count: version.event_count,
+                first_seen: version.first_seen.clone(),
+                last_seen: version.last_seen.clone(),
+                introduced_in: version.sdk_version.clone(),
+                sdk_versions: vec![version],
+            }),
+        }
+    }
+
+    for drift in &mut drifts {
+        drift.types.sort_by(|a, b| a.first_seen.cmp(&b.first_seen));
+        if let Some(original) = drift.types.first() {
+            drift.original_type = original.value_type.clone();
+        }
+        if let Some(changed) = drift.types.get(1) {
+            drift.changed_at = changed.first_seen.clone();
+        }
+    }
+    drifts
+}
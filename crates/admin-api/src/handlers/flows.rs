@@ -17,7 +17,7 @@ use crate::middleware::admin_auth::AuthUser;
 use crate::state::AppState;
 
 use super::query_builder::{
-    PropertyFilter, USER_UID_EXPR, build_property_filter_clauses, identity_join,
+    PropertyFilter, USER_UID_EXPR, build_property_filter_clauses, identity_join, profile_joins,
     validate_identifier,
 };
 
@@ -158,7 +158,8 @@ pub async fn flows(
     // ── Transition query ────────────────────────────────────────────
 
     let user_uid = USER_UID_EXPR;
-    let ij = identity_join(db);
+    let pj = profile_joins(db, req.filters.iter().map(|f| f.property.as_str()));
+    let ij = format!("{}{pj}", identity_join(db));
     let transition_query = format!(
         "WITH user_events AS ( \
             SELECT \
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use axum::{
     Json,
     extract::{Path, Query, State},
@@ -12,7 +14,11 @@ use truesight_common::team::TeamRole;
 
 use crate::db::funnels as db;
 use crate::db::segments as segments_db;
-use crate::handlers::query_builder::{self, build_property_filter_clauses, identity_join, USER_UID_EXPR};
+use crate::handlers::query_builder::{
+    self, PROPERTY_MAP_COLUMNS, USER_UID_EXPR, build_property_filter_clauses, column_expr,
+    identity_join, is_array_path, is_top_level, profile_joins, profile_property,
+    validate_identifier,
+};
 use crate::handlers::rbac;
 use crate::handlers::segments::SegmentFilter;
 use crate::middleware::admin_auth::AuthUser;
@@ -148,6 +154,69 @@ pub struct FunnelResultsQuery {
     pub to: DateTime<Utc>,
     pub environment: Option<String>,
     pub segment_id: Option<Uuid>,
+    /// Property to split users by: an event property, a `profile:` property
+    /// or a top-level column such as `platform`.
+    pub breakdown: Option<String>,
+    pub breakdown_segment_ids: Option<String>, // comma-separated UUIDs
+    /// Number of property values returned before "other".
+    pub breakdown_limit: Option<usize>,
+}
+
+const DEFAULT_BREAKDOWN_LIMIT: usize = 10;
+const MAX_BREAKDOWN_LIMIT: usize = 50;
+
+/// Label of the group holding users outside the top breakdown groups.
+const OTHER_GROUP: &str = "other";
+
+/// How funnel users are split. Users are attributed to the groups of their
+/// first step-1 event.
+#[derive(Debug)]
+pub enum FunnelBreakdown {
+    /// The top `limit` values of a property.
+    Property { key: String, limit: usize },
+    /// Membership of each segment; a user can be in several.
+    Segments(Vec<Uuid>),
+}
+
+impl FunnelResultsQuery {
+    fn breakdown(&self) -> Result<Option<FunnelBreakdown>, AppError> {
+        match (&self.breakdown, &self.breakdown_segment_ids) {
+            (Some(_), Some(_)) => Err(AppError::Validation(
+                "breakdown and breakdown_segment_ids are mutually exclusive".into(),
+            )),
+            (Some(key), None) => {
+                validate_identifier(key)?;
+                let limit = self.breakdown_limit.unwrap_or(DEFAULT_BREAKDOWN_LIMIT);
+                if limit == 0 || limit > MAX_BREAKDOWN_LIMIT {
+                    return Err(AppError::Validation(format!(
+                        "breakdown_limit must be between 1 and {}",
+                        MAX_BREAKDOWN_LIMIT
+                    )));
+                }
+                Ok(Some(FunnelBreakdown::Property {
+                    key: key.clone(),
+                    limit,
+                }))
+            }
+            (None, Some(ids)) => {
+                let mut ids = ids
+                    .split(',')
+                    .map(|s| s.trim().parse::<Uuid>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| AppError::Validation(format!("Invalid segment IDs: {}", e)))?;
+                let mut seen = HashSet::new();
+                ids.retain(|id| seen.insert(*id));
+                if ids.len() > MAX_BREAKDOWN_LIMIT {
+                    return Err(AppError::Validation(format!(
+                        "breakdown_segment_ids must list at most {} segments",
+                        MAX_BREAKDOWN_LIMIT
+                    )));
+                }
+                Ok(Some(FunnelBreakdown::Segments(ids)))
+            }
+            (None, None) => Ok(None),
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -156,6 +225,8 @@ pub struct FunnelStepResult {
     pub event_name: String,
     pub users: u64,
     pub conversion_rate: f64,
+    /// Time from the previous step; `None` for the first step.
+    pub time_to_convert: Option<TimeToConvert>,
 }
 
 #[derive(Debug, Serialize)]
@@ -165,6 +236,63 @@ pub struct FunnelResultsResponse {
     pub to: DateTime<Utc>,
     pub steps: Vec<FunnelStepResult>,
     pub overall_conversion: f64,
+    /// Time from the first step to the last.
+    pub overall_time_to_convert: Option<TimeToConvert>,
+    /// One step series per breakdown group, when a breakdown was requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub breakdown: Option<Vec<FunnelBreakdownGroup>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FunnelBreakdownGroup {
+    /// Property value or segment ID; `"other"` for the remaining users.
+    pub group: String,
+    pub other: bool,
+    pub segment_name: Option<String>,
+    pub steps: Vec<FunnelStepResult>,
+    pub overall_conversion: f64,
+    pub overall_time_to_convert: Option<TimeToConvert>,
+}
+
+/// Lower bounds, in seconds, of the time-to-convert histogram buckets; the
+/// last bucket is open-ended.
+const TIME_TO_CONVERT_BUCKETS: [u64; 9] = [0, 10, 60, 300, 1800, 3600, 21600, 86400, 604800];
+
+#[derive(Debug, Serialize)]
+pub struct TimeToConvert {
+    /// Users whose conversion was timed.
+    pub users: u64,
+    pub median_secs: f64,
+    pub p90_secs: f64,
+    pub histogram: Vec<TimeToConvertBucket>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimeToConvertBucket {
+    pub min_secs: u64,
+    /// Exclusive; `None` for the last bucket.
+    pub max_secs: Option<u64>,
+    pub users: u64,
+}
+
+impl From<TimeToConvertRow> for TimeToConvert {
+    fn from(row: TimeToConvertRow) -> Self {
+        let histogram = TIME_TO_CONVERT_BUCKETS
+            .iter()
+            .enumerate()
+            .map(|(i, &min_secs)| TimeToConvertBucket {
+                min_secs,
+                max_secs: TIME_TO_CONVERT_BUCKETS.get(i + 1).copied(),
+                users: row.histogram.get(i).copied().unwrap_or(0),
+            })
+            .collect();
+        Self {
+            users: row.users,
+            median_secs: row.median_secs,
+            p90_secs: row.p90_secs,
+            histogram,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -187,11 +315,378 @@ pub struct FunnelPropertyFilter {
 
 #[derive(Debug, clickhouse::Row, Deserialize)]
 pub struct WindowFunnelRow {
+    /// Breakdown group; `None` for "other".
+    pub grp: Option<String>,
     pub level: u8,
     pub users: u64,
 }
 
+#[derive(Debug, clickhouse::Row, Deserialize)]
+pub struct GroupRow {
+    pub grp: String,
+}
+
+/// Durations into `step` (2..=N) from the previous step, or through the
+/// whole funnel for step 0.
+#[derive(Debug, clickhouse::Row, Deserialize)]
+pub struct TimeToConvertRow {
+    pub grp: Option<String>,
+    pub step: u32,
+    pub users: u64,
+    pub median_secs: f64,
+    pub p90_secs: f64,
+    /// User counts per [`TIME_TO_CONVERT_BUCKETS`] bucket.
+    pub histogram: Vec<u64>,
+}
+
+/// SQL computing `times`, each user's timestamps (in seconds, 0 when not
+/// reached) of `steps` funnel steps, from [`step_events_expr`]'s
+/// `step_events`.
+///
+/// The steps are those of the chain windowFunnel counts, so a user is timed
+/// at every step their level reaches. Its algorithm is replayed over the
+/// user's events in time order, keeping a chain per level: a step-1 event
+/// starts a new level-1 chain, and an event matching step k extends the
+/// level k-1 chain into level k if it is within `window` seconds of that
+/// chain's start. A complete chain is kept once found.
+fn step_times_expr(steps: usize, window: i32) -> String {
+    let chains: Vec<String> = (1..=steps)
+        .map(|k| {
+            if k == 1 {
+                "if(flags[1], [ts], acc[1])".to_string()
+            } else {
+                let extended = format!(
+                    "if(flags[{k}] AND notEmpty(acc[{p}]) \
+                     AND floor(ts) <= floor(acc[{p}][1]) + {window}, \
+                     arrayPushBack(acc[{p}], ts), acc[{k}])",
+                    p = k - 1
+                );
+                if k == steps {
+                    format!("if(notEmpty(acc[{k}]), acc[{k}], {extended})")
+                } else {
+                    extended
+                }
+            }
+        })
+        .collect();
+    format!(
+        "arrayLast(c -> notEmpty(c), arrayFold( \
+            (acc, ts, flags) -> [{chains}], \
+            arrayMap(e -> e.1, step_events), \
+            arrayMap(e -> e.2, step_events), \
+            arrayMap(k -> CAST([], 'Array(Float64)'), range({steps})) \
+        )) AS times",
+        chains = chains.join(", "),
+    )
+}
+
+/// SQL computing `step_events`, each user's events as `(seconds, [matches
+/// step 1, ..., matches step N])` in time order.
+fn step_events_expr(conditions: &[String]) -> String {
+    format!(
+        "arraySort(e -> e.1, groupArray( \
+            (toUnixTimestamp64Milli(server_timestamp) / 1000, [{}]) \
+        )) AS step_events",
+        conditions
+            .iter()
+            .map(|cond| format!("toUInt8({cond})"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// Histogram bucket counts over `d` as an array.
+fn time_to_convert_histogram_expr() -> String {
+    let counts: Vec<String> = TIME_TO_CONVERT_BUCKETS
+        .iter()
+        .enumerate()
+        .map(|(i, min)| match TIME_TO_CONVERT_BUCKETS.get(i + 1) {
+            Some(max) => format!("countIf(d >= {min} AND d < {max})"),
+            None => format!("countIf(d >= {min})"),
+        })
+        .collect();
+    format!("[{}]", counts.join(", "))
+}
+
+/// Splits funnel users into groups by `event_groups`, an `Array(String)`
+/// expression over events evaluated at each user's first step-1 event. Only
+/// `top` groups are kept; users in none of them fall in the "other" group.
+struct Grouping {
+    event_groups: String,
+    /// Segment filters referenced by `event_groups`, in bind order.
+    segment_filters: Vec<SegmentFilter>,
+    top: Vec<String>,
+}
+
+impl Grouping {
+    /// A single group holding every user.
+    fn all() -> Self {
+        Self {
+            event_groups: "['']".to_string(),
+            segment_filters: Vec::new(),
+            top: vec![String::new()],
+        }
+    }
+}
+
+/// A funnel's step conditions and event source, shared by the funnel
+/// queries.
+struct FunnelQuery<'a> {
+    state: &'a AppState,
+    project_id: Uuid,
+    from_ts: f64,
+    to_ts: f64,
+    environment: &'a Option<String>,
+    segment_filter: Option<SegmentFilter>,
+    window: i32,
+    /// windowFunnel step conditions and the bind values of each.
+    conditions: Vec<String>,
+    condition_binds: Vec<Vec<String>>,
+    /// Inner SELECT columns, before the groups.
+    columns: String,
+    /// Inner FROM and WHERE clauses.
+    from_where: String,
+    segment_clause: String,
+}
+
+impl FunnelQuery<'_> {
+    /// The events of each user, grouped by `user_uid`.
+    fn source(&self, grouping: &Grouping) -> String {
+        format!(
+            "FROM ( \
+                SELECT {columns}, {event_groups} AS event_groups {from_where} \
+            ){segment_clause} GROUP BY user_uid",
+            columns = self.columns,
+            event_groups = grouping.event_groups,
+            from_where = self.from_where,
+            segment_clause = self.segment_clause,
+        )
+    }
+
+    /// Each user's groups at their first event matching step 1, property
+    /// filters included. Binds the first step's values
+    /// ([`Self::bind_first_condition`]).
+    fn user_groups(&self) -> String {
+        format!(
+            "argMinIf(event_groups, server_timestamp, {})",
+            self.conditions[0]
+        )
+    }
+
+    /// Each user's groups among `top`, `NULL` standing for "other".
+    fn mapped_user_groups(&self) -> String {
+        format!(
+            "arrayDistinct(arrayMap(g -> if(has(?, g), toNullable(g), NULL), {})) AS groups",
+            self.user_groups()
+        )
+    }
+
+    fn bind_conditions(&self, mut q: clickhouse::query::Query) -> clickhouse::query::Query {
+        for val in self.condition_binds.iter().flatten() {
+            q = q.bind(val.as_str());
+        }
+        q
+    }
+
+    fn bind_first_condition(&self, mut q: clickhouse::query::Query) -> clickhouse::query::Query {
+        for val in &self.condition_binds[0] {
+            q = q.bind(val.as_str());
+        }
+        q
+    }
+
+    // Bind order must match ? placeholder order in the source:
+    // 1. group segment params (inner SELECT)
+    // 2. project_id, from_ts, to_ts (inner WHERE)
+    // 3. environment (inner WHERE, optional)
+    // 4. segment params (outer WHERE, optional)
+    fn bind_source(
+        &self,
+        mut q: clickhouse::query::Query,
+        grouping: &Grouping,
+    ) -> clickhouse::query::Query {
+        for sf in &grouping.segment_filters {
+            q = sf.bind_params(q, self.project_id, self.environment);
+        }
+        q = q.bind(self.project_id).bind(self.from_ts).bind(self.to_ts);
+        if let Some(env) = self.environment {
+            q = q.bind(env.as_str());
+        }
+        if let Some(ref sf) = self.segment_filter {
+            q = sf.bind_params(q, self.project_id, self.environment);
+        }
+        q
+    }
+
+    /// The `limit` groups with the most users doing the first step.
+    async fn top_groups(&self, grouping: &Grouping, limit: usize) -> Result<Vec<String>, AppError> {
+        let query = format!(
+            "SELECT grp FROM ( \
+                SELECT arrayJoin(groups) AS grp FROM ( \
+                    SELECT {user_groups} AS groups {source} \
+                ) \
+            ) GROUP BY grp ORDER BY count() DESC, grp LIMIT {limit}",
+            user_groups = self.user_groups(),
+            source = self.source(grouping),
+        );
+        let q = self.bind_first_condition(self.state.clickhouse_client.query(&query));
+        let rows = self
+            .bind_source(q, grouping)
+            .fetch_all::<GroupRow>()
+            .await
+            .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
+        Ok(rows.into_iter().map(|r| r.grp).collect())
+    }
+
+    /// Step results of each group, `None` being "other".
+    async fn series(
+        &self,
+        steps: &[FunnelStep],
+        grouping: &Grouping,
+    ) -> Result<Vec<(Option<String>, StepSeries)>, AppError> {
+        let source = self.source(grouping);
+        let groups = self.mapped_user_groups();
+
+        let query = format!(
+            "SELECT arrayJoin(groups) AS grp, level, count() AS users FROM ( \
+                SELECT windowFunnel({window})(toDateTime(server_timestamp), {conditions}) AS level, \
+                {groups} {source} \
+            ) GROUP BY grp, level ORDER BY grp, level",
+            window = self.window,
+            conditions = self.conditions.join(", "),
+        );
+
+        // Step 0 holds the durations through the whole funnel.
+        let last = self.conditions.len();
+        let time_query = format!(
+            "SELECT grp, toUInt32(pair.1) AS step, count() AS users, \
+                quantile(0.5)(d) AS median_secs, quantile(0.9)(d) AS p90_secs, \
+                {histogram} AS histogram \
+            FROM ( \
+                SELECT arrayJoin(groups) AS grp, arrayJoin(arrayConcat( \
+                    arrayMap(k -> (k + 1, times[k + 1] - times[k]), \
+                        arrayFilter(k -> times[k + 1] > 0, range(1, {last}))), \
+                    if(times[{last}] > 0, [(0, times[{last}] - times[1])], []) \
+                )) AS pair, pair.2 AS d \
+                FROM (SELECT {times}, groups FROM (SELECT {events}, {groups} {source})) \
+            ) GROUP BY grp, step ORDER BY grp, step",
+            histogram = time_to_convert_histogram_expr(),
+            events = step_events_expr(&self.conditions),
+            times = step_times_expr(last, self.window),
+        );
+
+        // Both queries bind the step conditions, the top groups, the first
+        // step's condition, then the source.
+        let bind = |sql: &str| {
+            let q = self.bind_conditions(self.state.clickhouse_client.query(sql));
+            let q = self.bind_first_condition(q.bind(&grouping.top));
+            self.bind_source(q, grouping)
+        };
+        let rows = bind(&query)
+            .fetch_all::<WindowFunnelRow>()
+            .await
+            .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
+        let mut time_rows = bind(&time_query)
+            .fetch_all::<TimeToConvertRow>()
+            .await
+            .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
+
+        let mut groups: Vec<Option<String>> = grouping.top.iter().cloned().map(Some).collect();
+        if rows.iter().any(|r| r.grp.is_none()) {
+            groups.push(None);
+        }
+        Ok(groups
+            .into_iter()
+            .map(|group| {
+                let level_rows: Vec<&WindowFunnelRow> =
+                    rows.iter().filter(|r| r.grp == group).collect();
+                let (group_times, rest): (Vec<_>, Vec<_>) = std::mem::take(&mut time_rows)
+                    .into_iter()
+                    .partition(|r| r.grp == group);
+                time_rows = rest;
+                let series = step_series(steps, &level_rows, group_times);
+                (group, series)
+            })
+            .collect())
+    }
+}
+
+/// A funnel's step results for one group of users.
+struct StepSeries {
+    steps: Vec<FunnelStepResult>,
+    overall_conversion: f64,
+    overall_time_to_convert: Option<TimeToConvert>,
+}
+
+fn step_series(
+    steps: &[FunnelStep],
+    rows: &[&WindowFunnelRow],
+    mut time_rows: Vec<TimeToConvertRow>,
+) -> StepSeries {
+    let mut take_time = |step: u32| {
+        time_rows
+            .iter()
+            .position(|r| r.step == step)
+            .map(|i| TimeToConvert::from(time_rows.swap_remove(i)))
+    };
+
+    // windowFunnel returns the max step reached per user
+    // level 0 = didn't complete step 1, level 1 = completed step 1, etc.
+    let total_steps = steps.len();
+
+    // Build cumulative counts: users who reached at least step N
+    let mut level_counts = vec![0u64; total_steps + 1];
+    for row in rows {
+        if (row.level as usize) <= total_steps {
+            level_counts[row.level as usize] = row.users;
+        }
+    }
+
+    // Cumulative: users reaching step N = sum of users with level >= N
+    let mut cumulative = vec![0u64; total_steps + 1];
+    let mut running = 0u64;
+    for i in (0..=total_steps).rev() {
+        running += level_counts[i];
+        cumulative[i] = running;
+    }
+
+    // Total users who entered the funnel (reached at least step 1)
+    let total_entered = cumulative.get(1).copied().unwrap_or(0);
+
+    let step_results: Vec<FunnelStepResult> = steps
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let users = cumulative.get(i + 1).copied().unwrap_or(0);
+            let conversion_rate = if total_entered > 0 {
+                (users as f64 / total_entered as f64) * 100.0
+            } else {
+                0.0
+            };
+            FunnelStepResult {
+                step: i + 1,
+                event_name: s.event_name.clone(),
+                users,
+                conversion_rate: (conversion_rate * 100.0).round() / 100.0,
+                time_to_convert: take_time(i as u32 + 1).filter(|_| i > 0),
+            }
+        })
+        .collect();
+
+    let overall = step_results
+        .last()
+        .map(|s| s.conversion_rate)
+        .unwrap_or(0.0);
+
+    StepSeries {
+        steps: step_results,
+        overall_conversion: overall,
+        overall_time_to_convert: take_time(0),
+    }
+}
+
 /// Core computation for funnel results, shared by the handler and compare endpoints.
+#[allow(clippy::too_many_arguments)]
 async fn compute_funnel_results(
     state: &AppState,
     project_id: Uuid,
@@ -200,6 +695,7 @@ async fn compute_funnel_results(
     to: DateTime<Utc>,
     environment: Option<String>,
     segment_id: Option<Uuid>,
+    breakdown: Option<&FunnelBreakdown>,
 ) -> Result<FunnelResultsResponse, AppError> {
     let funnel = db::find_funnel(&state.db_pool, project_id, funnel_id)?;
 
@@ -226,13 +722,14 @@ async fn compute_funnel_results(
 
     // Build per-step windowFunnel conditions and collect bind values
     let mut wf_conditions: Vec<String> = Vec::new();
-    let mut filter_bind_values: Vec<String> = Vec::new();
+    let mut filter_bind_values: Vec<Vec<String>> = Vec::new();
 
     for step in &steps {
         let event_cond = format!("event_name = '{}'", step.event_name.replace('\'', "\\'"));
 
         if step.property_filters.is_empty() {
             wf_conditions.push(event_cond);
+            filter_bind_values.push(Vec::new());
         } else {
             let qb_filters: Vec<query_builder::PropertyFilter> = step
                 .property_filters
@@ -247,7 +744,7 @@ async fn compute_funnel_results(
             let mut all_conds = vec![event_cond];
             all_conds.extend(prop_conds);
             wf_conditions.push(all_conds.join(" AND "));
-            filter_bind_values.extend(prop_binds);
+            filter_bind_values.push(prop_binds);
         }
     }
 
@@ -267,104 +764,156 @@ async fn compute_funnel_results(
         .map(|f| format!(" WHERE {}", f.sql))
         .unwrap_or_default();
 
-    // Include properties_map in inner SELECT when any step has filters
-    let has_filters = steps.iter().any(|s| !s.property_filters.is_empty());
-    let extra_cols = if has_filters { ", properties_map" } else { "" };
+    // Include the property maps in inner SELECT when any step has filters, plus
+    // the as-of value of every `profile:` property they or the breakdown
+    // reference
+    let filter_keys = || {
+        steps
+            .iter()
+            .flat_map(|s| s.property_filters.iter().map(|f| f.property.as_str()))
+    };
+    let has_filters = filter_keys().next().is_some();
+    let mut extra_cols = if has_filters {
+        format!(", {PROPERTY_MAP_COLUMNS}")
+    } else {
+        String::new()
+    };
+    let breakdown_key = match breakdown {
+        Some(FunnelBreakdown::Property { key, .. }) => Some(key.as_str()),
+        _ => None,
+    };
+    let mut profile_keys: Vec<&str> = filter_keys()
+        .chain(breakdown_key)
+        .filter(|k| profile_property(k).is_some())
+        .collect();
+    profile_keys.sort_unstable();
+    profile_keys.dedup();
+    for key in &profile_keys {
+        extra_cols.push_str(&format!(", {}", column_expr(key)));
+    }
     let user_uid = USER_UID_EXPR;
-    let ij = identity_join(db_name);
-
-    let query = format!(
-        "SELECT level, count() AS users FROM ( \
-            SELECT user_uid, windowFunnel({window})(toDateTime(server_timestamp), {conditions}) AS level \
-            FROM ( \
-                SELECT {user_uid} AS user_uid, server_timestamp, event_name{extra_cols} \
-                FROM {db_name}.events AS e{ij} \
-                WHERE e.project_id = ? AND server_timestamp BETWEEN ? AND ? \
-                AND event_name IN ({event_names}){env_filter} \
-            ){segment_clause} GROUP BY user_uid \
-        ) GROUP BY level ORDER BY level",
-        window = funnel.window_seconds,
-        conditions = wf_conditions.join(", "),
-        event_names = event_names.join(", "),
+    let ij = format!(
+        "{}{}",
+        identity_join(db_name),
+        profile_joins(db_name, profile_keys.iter().copied())
     );
 
-    // Bind order must match ? placeholder order in the SQL:
-    // 1. windowFunnel step filter params (in the SELECT clause)
-    // 2. project_id, from_ts, to_ts (inner WHERE)
-    // 3. environment (inner WHERE, optional)
-    // 4. segment params (outer WHERE, optional)
-    let mut q = state.clickhouse_client.query(&query);
-    for val in &filter_bind_values {
-        q = q.bind(val.as_str());
-    }
-    q = q.bind(project_id).bind(from_ts).bind(to_ts);
-    if let Some(ref env) = environment {
-        q = q.bind(env.as_str());
-    }
-    if let Some(ref sf) = segment_filter {
-        q = sf.bind_params(q, project_id, &environment);
-    }
-    let rows = q
-        .fetch_all::<WindowFunnelRow>()
-        .await
-        .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
-
-    // windowFunnel returns the max step reached per user
-    // level 0 = didn't complete step 1, level 1 = completed step 1, etc.
-    let total_steps = steps.len();
-
-    // Build cumulative counts: users who reached at least step N
-    let mut level_counts = vec![0u64; total_steps + 1];
-    for row in &rows {
-        if (row.level as usize) <= total_steps {
-            level_counts[row.level as usize] = row.users;
-        }
-    }
+    let fq = FunnelQuery {
+        state,
+        project_id,
+        from_ts,
+        to_ts,
+        environment: &environment,
+        segment_filter,
+        window: funnel.window_seconds,
+        conditions: wf_conditions,
+        condition_binds: filter_bind_values,
+        columns: format!("{user_uid} AS user_uid, server_timestamp, event_name{extra_cols}"),
+        from_where: format!(
+            "FROM {db_name}.events AS e{ij} \
+            WHERE e.project_id = ? AND server_timestamp BETWEEN ? AND ? \
+            AND event_name IN ({event_names}){env_filter}",
+            event_names = event_names.join(", "),
+        ),
+        segment_clause,
+    };
 
-    // Cumulative: users reaching step N = sum of users with level >= N
-    let mut cumulative = vec![0u64; total_steps + 1];
-    let mut running = 0u64;
-    for i in (0..=total_steps).rev() {
-        running += level_counts[i];
-        cumulative[i] = running;
-    }
+    let overall = fq
+        .series(&steps, &Grouping::all())
+        .await?
+        .into_iter()
+        .next()
+        .map(|(_, series)| series)
+        .unwrap_or_else(|| step_series(&steps, &[], Vec::new()));
 
-    // Total users who entered the funnel (reached at least step 1)
-    let total_entered = cumulative.get(1).copied().unwrap_or(0);
-
-    let step_results: Vec<FunnelStepResult> = steps
-        .iter()
-        .enumerate()
-        .map(|(i, s)| {
-            let users = cumulative.get(i + 1).copied().unwrap_or(0);
-            let conversion_rate = if total_entered > 0 {
-                (users as f64 / total_entered as f64) * 100.0
-            } else {
-                0.0
-            };
-            FunnelStepResult {
-                step: i + 1,
-                event_name: s.event_name.clone(),
-                users,
-                conversion_rate: (conversion_rate * 100.0).round() / 100.0,
-            }
-        })
-        .collect();
-
-    let overall = step_results
-        .last()
-        .map(|s| s.conversion_rate)
-        .unwrap_or(0.0);
+    let breakdown = match breakdown {
+        Some(breakdown) => Some(compute_breakdown(state, &fq, &steps, breakdown).await?),
+        None => None,
+    };
 
     Ok(FunnelResultsResponse {
         funnel_id,
         from,
         to,
-        steps: step_results,
-        overall_conversion: overall,
+        steps: overall.steps,
+        overall_conversion: overall.overall_conversion,
+        overall_time_to_convert: overall.overall_time_to_convert,
+        breakdown,
     })
 }
 
+async fn compute_breakdown(
+    state: &AppState,
+    fq: &FunnelQuery<'_>,
+    steps: &[FunnelStep],
+    breakdown: &FunnelBreakdown,
+) -> Result<Vec<FunnelBreakdownGroup>, AppError> {
+    let mut segment_names = Vec::new();
+    let grouping = match breakdown {
+        FunnelBreakdown::Property { key, limit } => {
+            // Array paths put users under each of their values
+            let event_groups = if is_array_path(key) && !is_top_level(key) {
+                format!("if(empty(properties_arrays['{key}']), [''], properties_arrays['{key}'])")
+            } else {
+                format!("[toString({})]", column_expr(key))
+            };
+            let mut grouping = Grouping {
+                event_groups,
+                segment_filters: Vec::new(),
+                top: Vec::new(),
+            };
+            grouping.top = fq.top_groups(&grouping, *limit).await?;
+            grouping
+        }
+        FunnelBreakdown::Segments(ids) => {
+            let mut members = Vec::new();
+            let mut segment_filters = Vec::new();
+            for id in ids {
+                let segment = segments_db::find_segment(&state.db_pool, fq.project_id, *id)?;
+                match SegmentFilter::build(state, &segment.definition, fq.environment)? {
+                    Some(sf) => {
+                        members.push(format!("if({}, '{id}', '')", sf.sql));
+                        segment_filters.push(sf);
+                    }
+                    None => members.push(format!("'{id}'")),
+                }
+                segment_names.push(segment.name);
+            }
+            // Users outside every listed segment fall in "other"
+            Grouping {
+                event_groups: format!(
+                    "if(empty(arrayFilter(x -> x != '', [{members}]) AS segment_ids), [''], segment_ids)",
+                    members = members.join(", ")
+                ),
+                segment_filters,
+                top: ids.iter().map(Uuid::to_string).collect(),
+            }
+        }
+    };
+
+    let series = fq.series(steps, &grouping).await?;
+    Ok(series
+        .into_iter()
+        .map(|(group, series)| {
+            let segment_name = group.as_ref().and_then(|g| {
+                grouping
+                    .top
+                    .iter()
+                    .position(|t| t == g)
+                    .and_then(|i| segment_names.get(i).cloned())
+            });
+            FunnelBreakdownGroup {
+                other: group.is_none(),
+                group: group.unwrap_or_else(|| OTHER_GROUP.to_string()),
+                segment_name,
+                steps: series.steps,
+                overall_conversion: series.overall_conversion,
+                overall_time_to_convert: series.overall_time_to_convert,
+            }
+        })
+        .collect())
+}
+
 pub async fn funnel_results(
     State(state): State<AppState>,
     auth: AuthUser,
@@ -372,6 +921,7 @@ pub async fn funnel_results(
     Query(params): Query<FunnelResultsQuery>,
 ) -> Result<impl IntoResponse, AppError> {
     rbac::require_project_role(&state, &auth, project_id, TeamRole::Viewer)?;
+    let breakdown = params.breakdown()?;
     let result = compute_funnel_results(
         &state,
         project_id,
@@ -380,6 +930,7 @@ pub async fn funnel_results(
         params.to,
         params.environment,
         params.segment_id,
+        breakdown.as_ref(),
     )
     .await?;
     Ok(Json(result))
@@ -425,6 +976,7 @@ pub async fn compare_funnels(
             params.to,
             params.environment.clone(),
             params.segment_id,
+            None,
         )
         .await?;
         results.push(result);
@@ -458,6 +1010,7 @@ pub async fn compare_time_ranges(
         params.to_a,
         params.environment.clone(),
         params.segment_id,
+        None,
     )
     .await?;
     let result_b = compute_funnel_results(
@@ -468,6 +1021,7 @@ pub async fn compare_time_ranges(
         params.to_b,
         params.environment,
         params.segment_id,
+        None,
     )
     .await?;
 
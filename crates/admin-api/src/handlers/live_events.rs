@@ -1,18 +1,22 @@
 use std::convert::Infallible;
+use std::sync::Arc;
 use std::time::Duration;
 
 use axum::{
     extract::{Path, Query, State},
     response::sse::{Event, KeepAlive, Sse},
 };
-use futures::stream::Stream;
+use futures::stream::{BoxStream, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
 use truesight_common::error::AppError;
+use truesight_common::live::{LiveEvent, LiveMessage};
 use truesight_common::team::TeamRole;
 
 use crate::handlers::rbac;
+use crate::live::LiveHub;
 use crate::middleware::admin_auth::AuthUser;
 use crate::state::AppState;
 
@@ -90,6 +94,11 @@ fn validate_token(state: &AppState, token: &str) -> Result<AuthUser, AppError> {
 
 // ── SSE handler ─────────────────────────────────────────────────────
 
+type EventStream = BoxStream<'static, Result<Event, Infallible>>;
+
+/// Streams a project's new events. With `REDIS_URL` configured, events are
+/// pushed from the [`LiveHub`] and filtered here; otherwise ClickHouse is
+/// polled every 2 seconds.
 pub async fn live_events_stream(
     State(state): State<AppState>,
     Path(project_id): Path<Uuid>,
@@ -98,6 +107,107 @@ pub async fn live_events_stream(
     let auth = validate_token(&state, &params.token)?;
     rbac::require_project_role(&state, &auth, project_id, TeamRole::Viewer)?;
 
+    let stream = match state.live.clone() {
+        Some(live) => push_stream(live, project_id, params),
+        None => poll_stream(state, project_id, params),
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15))))
+}
+
+// ── Push stream ─────────────────────────────────────────────────────
+
+fn contains_ci(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+/// Applies the same filters as the polling query's WHERE clause.
+fn matches_filters(params: &LiveEventsQuery, event: &LiveEvent) -> bool {
+    params
+        .environment
+        .as_ref()
+        .is_none_or(|v| event.environment == *v)
+        && params
+            .event_type
+            .as_ref()
+            .is_none_or(|v| event.event_type == *v)
+        && params
+            .event_name
+            .as_ref()
+            .is_none_or(|v| contains_ci(&event.event_name, v))
+        && params
+            .user_id
+            .as_ref()
+            .is_none_or(|v| contains_ci(&event.user_id, v))
+        && params
+            .email
+            .as_ref()
+            .is_none_or(|v| contains_ci(&event.email, v))
+        && params
+            .mobile_number
+            .as_ref()
+            .is_none_or(|v| contains_ci(&event.mobile_number, v))
+        && params
+            .platform
+            .as_ref()
+            .is_none_or(|v| event.platform == *v)
+}
+
+fn events_message(events: &[LiveEvent]) -> Event {
+    Event::default()
+        .event("events")
+        .data(serde_json::to_string(events).unwrap_or_default())
+}
+
+fn push_stream(live: Arc<LiveHub>, project_id: Uuid, params: LiveEventsQuery) -> EventStream {
+    let (receiver, recent) = live.subscribe(project_id);
+    let recent: Vec<LiveEvent> = recent
+        .into_iter()
+        .filter(|e| matches_filters(&params, e))
+        .collect();
+
+    // Recent events first (or a heartbeat, as the polling stream sends),
+    // then every matching published batch.
+    let first = if recent.is_empty() {
+        Event::default().event("heartbeat").data("")
+    } else {
+        events_message(&recent)
+    };
+
+    let updates = futures::stream::unfold(
+        (receiver, params),
+        move |(mut receiver, params): (broadcast::Receiver<Arc<LiveMessage>>, LiveEventsQuery)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(message) if message.project_id == project_id => {
+                        let events: Vec<LiveEvent> = message
+                            .events
+                            .iter()
+                            .filter(|e| matches_filters(&params, e))
+                            .cloned()
+                            .collect();
+                        if !events.is_empty() {
+                            return Some((Ok(events_message(&events)), (receiver, params)));
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(%project_id, skipped, "Live events subscriber lagged");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+
+    futures::stream::once(async move { Ok(first) })
+        .chain(updates)
+        .boxed()
+}
+
+// ── Poll stream ─────────────────────────────────────────────────────
+
+fn poll_stream(state: AppState, project_id: Uuid, params: LiveEventsQuery) -> EventStream {
     // Start cursor 30s in the past to show recent events on connect.
     let initial_cursor = chrono::Utc::now().timestamp_millis() as f64 / 1000.0 - 30.0;
 
@@ -211,5 +321,5 @@ pub async fn live_events_stream(
         },
     );
 
-    Ok(Sse::new(stream).keep_alive(KeepAlive::new().interval(Duration::from_secs(15))))
+    stream.boxed()
 }
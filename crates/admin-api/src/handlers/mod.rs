@@ -3,6 +3,10 @@ pub mod pagination;
 pub mod auth;
 pub mod boards;
 pub mod cohorts;
+pub mod data_exports;
+pub mod data_retention;
+pub mod dlq;
+pub mod erasures;
 pub mod event_catalog;
 pub mod flows;
 pub mod funnels;
@@ -16,7 +20,9 @@ pub mod query_builder;
 pub mod rbac;
 pub mod retention;
 pub mod segments;
+pub mod sessions;
 pub mod stats;
 pub mod teams;
 pub mod trends;
 pub mod users_ch;
+pub mod webhooks;
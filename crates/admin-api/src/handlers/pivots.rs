@@ -17,8 +17,8 @@ use crate::middleware::admin_auth::AuthUser;
 use crate::state::AppState;
 
 use super::query_builder::{
-    PropertyFilter, build_property_filter_clauses, column_expr, identity_join, metric_expr,
-    validate_identifier,
+    PropertyFilter, build_property_filter_clauses, group_by_expr, identity_join, metric_expr,
+    profile_joins, validate_identifier,
 };
 
 // ── Request / Response ──────────────────────────────────────────────
@@ -89,12 +89,18 @@ pub async fn pivots(
         validate_identifier(&f.property)?;
     }
 
-    let row_expr = column_expr(&req.row_dimension);
-    let col_expr = column_expr(&req.column_dimension);
+    let row_expr = group_by_expr(&req.row_dimension);
+    let col_expr = group_by_expr(&req.column_dimension);
     let metric = metric_expr(&req.metric)?;
 
     // Build WHERE conditions
-    let ij = identity_join(db);
+    let pj = profile_joins(
+        db,
+        [req.row_dimension.as_str(), req.column_dimension.as_str()]
+            .into_iter()
+            .chain(req.filters.iter().map(|f| f.property.as_str())),
+    );
+    let ij = format!("{}{pj}", identity_join(db));
     let mut conditions = Vec::new();
     conditions.push("e.project_id = ?".to_string());
     conditions.push("server_timestamp BETWEEN ? AND ?".to_string());
@@ -16,8 +16,8 @@ use crate::state::AppState;
 
 use super::query_builder::{
     GroupedSeriesRow, GroupedTotalsRow, PropertyFilter, build_group_key,
-    build_property_filter_clauses, column_expr, group_series_rows, identity_join, metric_expr,
-    period_expr, validate_identifier,
+    build_property_filter_clauses, group_by_expr, group_series_rows, identity_join, is_array_path,
+    metric_expr, period_expr, profile_joins, validate_identifier,
 };
 
 // ── Constants ────────────────────────────────────────────────────────
@@ -68,7 +68,8 @@ pub async fn property_keys(
     };
 
     let query = format!(
-        "SELECT DISTINCT arrayJoin(mapKeys(properties_map)) AS key \
+        "SELECT DISTINCT \
+         arrayJoin(arrayConcat(mapKeys(properties_map), mapKeys(properties_arrays))) AS key \
          FROM {db}.events \
          WHERE project_id = ? AND server_timestamp BETWEEN ? AND ? \
          AND NOT startsWith(event_name, '$'){env_filter} \
@@ -129,11 +130,17 @@ pub async fn property_values(
         ""
     };
 
+    // `[]` array paths list each element as a value.
+    let (value_expr, map_column) = if is_array_path(&params.key) {
+        ("arrayJoin(properties_arrays[?])", "properties_arrays")
+    } else {
+        ("properties_map[?]", "properties_map")
+    };
     let query = format!(
-        "SELECT DISTINCT properties_map[?] AS value \
+        "SELECT DISTINCT {value_expr} AS value \
          FROM {db}.events \
          WHERE project_id = ? AND server_timestamp BETWEEN ? AND ? \
-         AND mapContains(properties_map, ?) \
+         AND mapContains({map_column}, ?) \
          AND NOT startsWith(event_name, '$'){env_filter} \
          ORDER BY value LIMIT 500"
     );
@@ -252,7 +259,7 @@ pub async fn insights(
     let mut group_by_aliases = Vec::new();
     for i in 0..MAX_GROUP_BY {
         if i < req.group_by.len() {
-            let expr = column_expr(&req.group_by[i]);
+            let expr = group_by_expr(&req.group_by[i]);
             group_select_parts.push(format!("{} AS g{}", expr, i));
             group_by_aliases.push(format!("g{}", i));
         } else {
@@ -268,7 +275,14 @@ pub async fn insights(
     };
 
     // Build WHERE conditions
-    let ij = identity_join(db);
+    let pj = profile_joins(
+        db,
+        req.group_by
+            .iter()
+            .chain(req.filters.iter().map(|f| &f.property))
+            .map(String::as_str),
+    );
+    let ij = format!("{}{pj}", identity_join(db));
     let mut conditions = Vec::new();
     conditions.push("e.project_id = ?".to_string());
     conditions.push("server_timestamp BETWEEN ? AND ?".to_string());
@@ -2,23 +2,77 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 use truesight_common::error::AppError;
+use truesight_common::event_row::parse_datetime;
 
 // ── Constants ────────────────────────────────────────────────────────
 
 /// Identity-resolved user UID expression.
 /// Requires events aliased as `e` and `identity_join()` appended to the FROM clause.
-/// Prefers the identity_map's resolved user_id, falls back to anonymous_id.
-pub const USER_UID_EXPR: &str = "COALESCE(NULLIF(_im.user_id, ''), e.anonymous_id)";
+/// Prefers the identity graph's canonical person_id, falls back to anonymous_id.
+pub const USER_UID_EXPR: &str = "COALESCE(NULLIF(_im.person_id, ''), e.anonymous_id)";
 
-/// Returns a LEFT JOIN clause that resolves anonymous_id → user_id via the
-/// identity_map table.  The events table **must** be aliased as `e`.
+/// Returns a LEFT JOIN clause that resolves anonymous_id → person_id via the
+/// identity_graph table.  The events table **must** be aliased as `e`.
 pub fn identity_join(db: &str) -> String {
     format!(
-        " LEFT JOIN (SELECT * FROM {db}.identity_map FINAL) AS _im \
-         ON _im.project_id = e.project_id AND _im.anonymous_id = e.anonymous_id"
+        " LEFT JOIN (SELECT project_id, distinct_id, person_id FROM {db}.identity_graph FINAL) AS _im \
+         ON _im.project_id = e.project_id AND _im.distinct_id = e.anonymous_id"
     )
 }
 
+/// Prefix marking a filter or group-by key as a user profile property, e.g.
+/// `profile:plan`. The value is the one the profile held at each event's
+/// `server_timestamp`, read from `user_profile_history`.
+pub const PROFILE_PROPERTY_PREFIX: &str = "profile:";
+
+/// Returns the profile property name if `key` is a `profile:` key.
+pub fn profile_property(key: &str) -> Option<&str> {
+    key.strip_prefix(PROFILE_PROPERTY_PREFIX)
+        .filter(|name| !name.is_empty())
+}
+
+/// Join alias for a profile property. Hex-encoded so that any property name
+/// yields a valid, unique identifier.
+fn profile_alias(name: &str) -> String {
+    let hex: String = name.bytes().map(|b| format!("{b:02x}")).collect();
+    format!("_ph_{hex}")
+}
+
+/// Returns one ASOF LEFT JOIN per distinct `profile:` key in `keys`, exposing
+/// the property's value as of each event. Must follow [`identity_join`],
+/// since rows are matched on [`USER_UID_EXPR`]. History rows are written
+/// under the raw user or anonymous ID, so they are resolved to their person
+/// through `identity_graph` too; changes made under any ID of a person form
+/// one timeline. Events before the first recorded value, or after an unset,
+/// read as `''`.
+pub fn profile_joins<'a>(db: &str, keys: impl IntoIterator<Item = &'a str>) -> String {
+    let mut names: Vec<&str> = keys.into_iter().filter_map(profile_property).collect();
+    names.sort_unstable();
+    names.dedup();
+
+    names
+        .into_iter()
+        .map(|name| {
+            let alias = profile_alias(name);
+            let property = escape_string_literal(name);
+            format!(
+                " ASOF LEFT JOIN (SELECT h.project_id AS {alias}_project_id, \
+                 h.environment AS {alias}_environment, \
+                 COALESCE(NULLIF(g.person_id, ''), h.user_uid) AS {alias}_user_uid, \
+                 h.valid_from AS {alias}_valid_from, h.value AS {alias}_value \
+                 FROM {db}.user_profile_history AS h \
+                 LEFT JOIN (SELECT project_id, distinct_id, person_id \
+                 FROM {db}.identity_graph FINAL) AS g \
+                 ON g.project_id = h.project_id AND g.distinct_id = h.user_uid \
+                 WHERE h.property = '{property}') AS {alias} \
+                 ON {alias}_project_id = e.project_id AND {alias}_environment = e.environment \
+                 AND {alias}_user_uid = {USER_UID_EXPR} \
+                 AND e.server_timestamp >= {alias}_valid_from"
+            )
+        })
+        .collect()
+}
+
 /// Merged superset of top-level columns from properties.rs and flows.rs.
 pub const TOP_LEVEL_COLUMNS: &[&str] = &[
     "anonymous_id",
@@ -71,16 +125,51 @@ pub fn is_top_level(col: &str) -> bool {
     TOP_LEVEL_COLUMNS.contains(&col)
 }
 
-/// Returns the SQL expression to read `key` — either the bare column name
-/// for top-level columns, or `properties_map['<key>']` otherwise.
+/// Returns `true` when `key` addresses fields of objects inside an array
+/// property (`items[].sku`), which are only stored in `properties_arrays`.
+pub fn is_array_path(key: &str) -> bool {
+    key.contains("[]")
+}
+
+/// Returns the SQL expression to read `key` — the bare column name for
+/// top-level columns, the as-of value joined by [`profile_joins`] for
+/// `profile:` keys, the comma-joined values for `[]` array paths, or
+/// `properties_map['<key>']` otherwise.
 pub fn column_expr(key: &str) -> String {
-    if is_top_level(key) {
+    if let Some(name) = profile_property(key) {
+        format!("{}_value", profile_alias(name))
+    } else if is_top_level(key) {
         key.to_string()
+    } else if is_array_path(key) {
+        format!("arrayStringConcat(properties_arrays['{}'], ',')", key)
     } else {
         format!("properties_map['{}']", key)
     }
 }
 
+/// Like [`column_expr`], but `[]` array paths expand to one row per value so
+/// that events are grouped under each of their values. Events without the
+/// path fall in the `''` group.
+pub fn group_by_expr(key: &str) -> String {
+    if is_array_path(key) && !is_top_level(key) && profile_property(key).is_none() {
+        format!(
+            "arrayJoin(if(empty(properties_arrays['{key}']), [''], properties_arrays['{key}']))"
+        )
+    } else {
+        column_expr(key)
+    }
+}
+
+/// Filter value as a string for binding: strings as-is, other scalars in
+/// their JSON form, and `""` when missing.
+fn scalar_string(value: &Option<serde_json::Value>) -> String {
+    match value {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Null) | None => String::new(),
+        Some(other) => other.to_string(),
+    }
+}
+
 /// Extract a `Vec<String>` from a `Some(Value::Array([...]))`, or return a
 /// validation error.  Used by the `in` / `not_in` filter operators.
 pub fn extract_string_array(value: &Option<serde_json::Value>) -> Result<Vec<String>, AppError> {
@@ -107,15 +196,40 @@ pub fn extract_string_array(value: &Option<serde_json::Value>) -> Result<Vec<Str
 
 // ── Metric & Period helpers ──────────────────────────────────────────
 
-/// SQL expression for the selected aggregation metric.
-pub fn metric_expr(metric: &str) -> Result<&'static str, AppError> {
+/// SQL expression for the selected aggregation metric: `total`,
+/// `unique_users`, `avg_per_user`, or `sum:<key>`, `avg:<key>`, `min:<key>`,
+/// `max:<key>` over a numeric event property (events without it are
+/// ignored).
+pub fn metric_expr(metric: &str) -> Result<String, AppError> {
     match metric {
-        "total" => Ok("toFloat64(count())"),
-        "unique_users" => Ok("toFloat64(uniqExact(COALESCE(NULLIF(_im.user_id, ''), e.anonymous_id)))"),
+        "total" => Ok("toFloat64(count())".to_string()),
+        "unique_users" => Ok("toFloat64(uniqExact(COALESCE(NULLIF(_im.person_id, ''), e.anonymous_id)))".to_string()),
         "avg_per_user" => Ok(
-            "toFloat64(count()) / greatest(1, uniqExact(COALESCE(NULLIF(_im.user_id, ''), e.anonymous_id)))",
+            "toFloat64(count()) / greatest(1, uniqExact(COALESCE(NULLIF(_im.person_id, ''), e.anonymous_id)))".to_string(),
         ),
-        other => Err(AppError::Validation(format!("Unknown metric: {}", other))),
+        other => {
+            let Some((aggregate, key)) = other.split_once(':') else {
+                return Err(AppError::Validation(format!("Unknown metric: {}", other)));
+            };
+            let function = match aggregate {
+                "sum" => "sumIf",
+                "avg" => "avgIf",
+                "min" => "minIf",
+                "max" => "maxIf",
+                _ => return Err(AppError::Validation(format!("Unknown metric: {}", other))),
+            };
+            if key.is_empty() || is_top_level(key) || profile_property(key).is_some() {
+                return Err(AppError::Validation(format!(
+                    "Metric '{}' needs a numeric event property",
+                    other
+                )));
+            }
+            validate_identifier(key)?;
+            let key = escape_string_literal(key);
+            Ok(format!(
+                "toFloat64(ifNotFinite({function}(properties_num['{key}'], mapContains(properties_num, '{key}')), 0))"
+            ))
+        }
     }
 }
 
@@ -212,6 +326,179 @@ pub fn group_series_rows(
 
 // ── Filter helpers ──────────────────────────────────────────────────
 
+/// Columns holding event properties: `properties_map` with every value as
+/// a string, and the typed and array maps written alongside it. Subqueries that
+/// filter on properties in an outer query must select all of them.
+pub const PROPERTY_MAP_COLUMNS: &str =
+    "properties_map, properties_num, properties_bool, properties_datetime, properties_arrays";
+
+/// Typed map a property value is compared in instead of `properties_map`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyType {
+    Number,
+    Boolean,
+    DateTime,
+}
+
+impl PropertyType {
+    pub fn map_column(self) -> &'static str {
+        match self {
+            PropertyType::Number => "properties_num",
+            PropertyType::Boolean => "properties_bool",
+            PropertyType::DateTime => "properties_datetime",
+        }
+    }
+}
+
+/// Infers the type to compare an event property as from the filter value and
+/// SQL operator, returning it with the value as a SQL literal. JSON numbers
+/// and booleans select their maps; for range operators, numeric strings
+/// compare as numbers and ISO-8601 strings as timestamps. Everything else,
+/// and top-level or `profile:` keys, compares as strings (`None`).
+pub fn infer_property_type(
+    key: &str,
+    sql_op: &str,
+    value: &serde_json::Value,
+) -> Option<(PropertyType, String)> {
+    if is_top_level(key) || profile_property(key).is_some() {
+        return None;
+    }
+    let range = matches!(sql_op, ">" | ">=" | "<" | "<=");
+
+    match value {
+        serde_json::Value::Number(n) => n
+            .as_f64()
+            .filter(|n| n.is_finite())
+            .map(|n| (PropertyType::Number, format!("{n}"))),
+        serde_json::Value::Bool(b) if !range => Some((PropertyType::Boolean, b.to_string())),
+        serde_json::Value::String(s) if range => {
+            if let Ok(n) = s.trim().parse::<f64>()
+                && n.is_finite()
+            {
+                Some((PropertyType::Number, format!("{n}")))
+            } else {
+                parse_datetime(s.trim()).map(|ts| {
+                    (
+                        PropertyType::DateTime,
+                        format!(
+                            "toDateTime64('{}', 3, 'UTC')",
+                            ts.format("%Y-%m-%d %H:%M:%S%.3f")
+                        ),
+                    )
+                })
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Condition comparing `key` in its typed map, for keys and values
+/// [`infer_property_type`] types. Events without the key never match `=`
+/// or range operators, and always match `!=`.
+pub fn typed_property_condition(
+    key: &str,
+    sql_op: &str,
+    value: &serde_json::Value,
+) -> Option<String> {
+    let (property_type, literal) = infer_property_type(key, sql_op, value)?;
+    let column = property_type.map_column();
+    let key = escape_string_literal(key);
+    let present = format!("mapContains({column}, '{key}')");
+    Some(if sql_op == "!=" {
+        format!("NOT ({present} AND {column}['{key}'] = {literal})")
+    } else {
+        format!("({present} AND {column}['{key}'] {sql_op} {literal})")
+    })
+}
+
+/// `length(properties_arrays[...])` comparisons for the `array_length`
+/// operator: a number for an exact length, or an object of `eq`, `neq`,
+/// `gt`, `gte`, `lt`, `lte` bounds such as `{"gte": 2}`.
+fn array_length_conditions(
+    key: &str,
+    value: &Option<serde_json::Value>,
+) -> Result<Vec<String>, AppError> {
+    let invalid = || {
+        AppError::Validation(
+            "Filter 'array_length' requires a length or an object of bounds such as {\"gte\": 2}"
+                .to_string(),
+        )
+    };
+    let length_expr = format!("length(properties_arrays['{key}'])");
+    let as_length = |v: &serde_json::Value| v.as_u64().ok_or_else(invalid);
+
+    match value {
+        Some(serde_json::Value::Object(bounds)) if !bounds.is_empty() => bounds
+            .iter()
+            .map(|(op, v)| {
+                let sql_op = match op.as_str() {
+                    "eq" => "=",
+                    "neq" => "!=",
+                    "gt" => ">",
+                    "gte" => ">=",
+                    "lt" => "<",
+                    "lte" => "<=",
+                    _ => return Err(invalid()),
+                };
+                Ok(format!("{length_expr} {sql_op} {}", as_length(v)?))
+            })
+            .collect(),
+        Some(v) => Ok(vec![format!("{length_expr} = {}", as_length(v)?)]),
+        None => Err(invalid()),
+    }
+}
+
+/// Condition for a filter on a `[]` array path, which matches when any of
+/// the event's values does (`neq` and `not_in`: when none does).
+fn array_path_condition(
+    f: &PropertyFilter,
+    bind_values: &mut Vec<String>,
+) -> Result<String, AppError> {
+    validate_identifier(&f.property)?;
+    let key = escape_string_literal(&f.property);
+    let array = format!("properties_arrays['{key}']");
+    let quoted = |values: Vec<String>| {
+        values
+            .iter()
+            .map(|v| format!("'{}'", escape_string_literal(v)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let condition = match f.operator.as_str() {
+        "eq" | "array_contains" => {
+            bind_values.push(scalar_string(&f.value));
+            format!("has({array}, ?)")
+        }
+        "neq" => {
+            bind_values.push(scalar_string(&f.value));
+            format!("NOT has({array}, ?)")
+        }
+        "contains" => {
+            bind_values.push(scalar_string(&f.value));
+            format!("arrayExists(v -> positionCaseInsensitive(v, ?) > 0, {array})")
+        }
+        "in" => format!(
+            "hasAny({array}, [{}])",
+            quoted(extract_string_array(&f.value)?)
+        ),
+        "not_in" => format!(
+            "NOT hasAny({array}, [{}])",
+            quoted(extract_string_array(&f.value)?)
+        ),
+        "exists" => format!("mapContains(properties_arrays, '{key}')"),
+        "not_exists" => format!("NOT mapContains(properties_arrays, '{key}')"),
+        "array_length" => array_length_conditions(&key, &f.value)?.join(" AND "),
+        other => {
+            return Err(AppError::Validation(format!(
+                "Filter operator '{}' is not supported on array path '{}'",
+                other, f.property
+            )));
+        }
+    };
+    Ok(condition)
+}
+
 pub fn build_property_filter_clauses(
     filters: &[PropertyFilter],
 ) -> Result<(Vec<String>, Vec<String>), AppError> {
@@ -219,27 +506,56 @@ pub fn build_property_filter_clauses(
     let mut bind_values: Vec<String> = Vec::new();
 
     for f in filters {
+        let event_property = !is_top_level(&f.property) && profile_property(&f.property).is_none();
+        if matches!(f.operator.as_str(), "array_contains" | "array_length") && !event_property {
+            return Err(AppError::Validation(format!(
+                "Filter '{}' needs an array event property",
+                f.operator
+            )));
+        }
+        if event_property && is_array_path(&f.property) {
+            conditions.push(array_path_condition(f, &mut bind_values)?);
+            continue;
+        }
+
         let col = column_expr(&f.property);
         match f.operator.as_str() {
+            "array_contains" => {
+                validate_identifier(&f.property)?;
+                conditions.push(format!(
+                    "has(properties_arrays['{}'], ?)",
+                    escape_string_literal(&f.property)
+                ));
+                bind_values.push(scalar_string(&f.value));
+            }
+            "array_length" => {
+                validate_identifier(&f.property)?;
+                let key = escape_string_literal(&f.property);
+                conditions.extend(array_length_conditions(&key, &f.value)?);
+            }
             "eq" => {
-                conditions.push(format!("{} = ?", col));
-                let val = f
+                if let Some(condition) = f
                     .value
                     .as_ref()
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                bind_values.push(val);
+                    .and_then(|v| typed_property_condition(&f.property, "=", v))
+                {
+                    conditions.push(condition);
+                    continue;
+                }
+                conditions.push(format!("{} = ?", col));
+                bind_values.push(scalar_string(&f.value));
             }
             "neq" => {
-                conditions.push(format!("{} != ?", col));
-                let val = f
+                if let Some(condition) = f
                     .value
                     .as_ref()
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                bind_values.push(val);
+                    .and_then(|v| typed_property_condition(&f.property, "!=", v))
+                {
+                    conditions.push(condition);
+                    continue;
+                }
+                conditions.push(format!("{} != ?", col));
+                bind_values.push(scalar_string(&f.value));
             }
             "contains" => {
                 conditions.push(format!("positionCaseInsensitive({}, ?) > 0", col));
@@ -268,44 +584,58 @@ pub fn build_property_filter_clauses(
                 conditions.push(format!("{} NOT IN ({})", col, escaped.join(", ")));
             }
             "gt" => {
-                conditions.push(format!("{} > ?", col));
-                let val = f
+                if let Some(condition) = f
                     .value
                     .as_ref()
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                bind_values.push(val);
+                    .and_then(|v| typed_property_condition(&f.property, ">", v))
+                {
+                    conditions.push(condition);
+                    continue;
+                }
+                conditions.push(format!("{} > ?", col));
+                bind_values.push(scalar_string(&f.value));
             }
             "gte" => {
-                conditions.push(format!("{} >= ?", col));
-                let val = f
+                if let Some(condition) = f
                     .value
                     .as_ref()
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                bind_values.push(val);
+                    .and_then(|v| typed_property_condition(&f.property, ">=", v))
+                {
+                    conditions.push(condition);
+                    continue;
+                }
+                conditions.push(format!("{} >= ?", col));
+                bind_values.push(scalar_string(&f.value));
             }
             "lt" => {
-                conditions.push(format!("{} < ?", col));
-                let val = f
+                if let Some(condition) = f
                     .value
                     .as_ref()
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                bind_values.push(val);
+                    .and_then(|v| typed_property_condition(&f.property, "<", v))
+                {
+                    conditions.push(condition);
+                    continue;
+                }
+                conditions.push(format!("{} < ?", col));
+                bind_values.push(scalar_string(&f.value));
             }
             "lte" => {
-                conditions.push(format!("{} <= ?", col));
-                let val = f
+                if let Some(condition) = f
                     .value
                     .as_ref()
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                bind_values.push(val);
+                    .and_then(|v| typed_property_condition(&f.property, "<=", v))
+                {
+                    conditions.push(condition);
+                    continue;
+                }
+                conditions.push(format!("{} <= ?", col));
+                bind_values.push(scalar_string(&f.value));
+            }
+            "exists" if profile_property(&f.property).is_some() => {
+                conditions.push(format!("{} != ''", col));
+            }
+            "not_exists" if profile_property(&f.property).is_some() => {
+                conditions.push(format!("{} = ''", col));
             }
             "exists" => {
                 validate_identifier(&f.property)?;
@@ -36,6 +36,18 @@ pub fn require_project_role(
     Ok(())
 }
 
+/// Check that the caller is an operator. Operator-only endpoints (e.g. the DLQ
+/// tooling) span every project, so only the static admin token may call them.
+pub fn require_operator(auth: &AuthUser) -> Result<(), AppError> {
+    if auth.is_static_token {
+        return Ok(());
+    }
+
+    Err(AppError::Forbidden(
+        "Requires the operator (static admin) token".to_string(),
+    ))
+}
+
 /// Get the list of project IDs a user has access to. Static token returns None (meaning all).
 pub fn accessible_project_ids(
     state: &AppState,
@@ -17,7 +17,7 @@ use crate::middleware::admin_auth::AuthUser;
 use crate::state::AppState;
 
 use super::query_builder::{
-    PropertyFilter, USER_UID_EXPR, build_property_filter_clauses, identity_join,
+    PropertyFilter, USER_UID_EXPR, build_property_filter_clauses, identity_join, profile_joins,
     validate_identifier,
 };
 
@@ -152,13 +152,17 @@ pub async fn retention(
 
     let user_uid = USER_UID_EXPR;
     let ij = identity_join(db);
+    let cohort_ij = format!(
+        "{ij}{}",
+        profile_joins(db, req.filters.iter().map(|f| f.property.as_str()))
+    );
     let query = format!(
         "WITH \
           cohort_users AS ( \
             SELECT user_uid, {pfn_alias} AS cohort_period \
             FROM ( \
               SELECT {user_uid} AS user_uid, server_timestamp \
-              FROM {db}.events AS e{ij} \
+              FROM {db}.events AS e{cohort_ij} \
               WHERE e.project_id = ? AND server_timestamp BETWEEN ? AND ? \
                 AND event_name = ?{cohort_env_filter}{cohort_filter_sql} \
             ) \
@@ -17,7 +17,7 @@ use crate::state::AppState;
 
 use super::query_builder::{
     build_property_filter_clauses, column_expr, escape_string_literal, identity_join, is_top_level,
-    validate_identifier, USER_UID_EXPR,
+    profile_joins, typed_property_condition, validate_identifier, USER_UID_EXPR,
 };
 
 // ── Types ───────────────────────────────────────────────────────────
@@ -410,7 +410,11 @@ fn build_segment_clauses(
                 };
 
                 let in_op = if action == "did_not" { "NOT IN" } else { "IN" };
-                let ij = identity_join(db_name);
+                let ij = format!(
+                    "{}{}",
+                    identity_join(db_name),
+                    profile_joins(db_name, property_filters.iter().map(|f| f.property.as_str()))
+                );
 
                 let subquery = format!(
                     "SELECT {USER_UID_EXPR} AS user_uid \
@@ -496,12 +500,21 @@ fn build_segment_clauses(
                         validate_identifier(value)?;
                         let sql_operator = sql_op(op)?;
                         let escaped_value = value.replace('\'', "\\'");
+                        let typed = (source == "event")
+                            .then(|| {
+                                typed_property_condition(
+                                    property,
+                                    sql_operator,
+                                    &serde_json::Value::String(value.clone()),
+                                )
+                            })
+                            .flatten();
                         let col = if source == "event" {
                             column_expr(property)
                         } else {
                             format!("properties['{escaped_property}']")
                         };
-                        format!("{col} {sql_operator} '{escaped_value}'")
+                        typed.unwrap_or_else(|| format!("{col} {sql_operator} '{escaped_value}'"))
                     }
                 };
 
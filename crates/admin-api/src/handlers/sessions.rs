@@ -0,0 +1,227 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use truesight_common::error::AppError;
+use truesight_common::team::TeamRole;
+
+use crate::handlers::rbac;
+use crate::middleware::admin_auth::AuthUser;
+use crate::state::AppState;
+
+use super::query_builder::{
+    PropertyFilter, build_property_filter_clauses, identity_join, period_expr, profile_joins,
+    validate_identifier,
+};
+
+// ── Constants ────────────────────────────────────────────────────────
+
+/// Entry and exit events returned, by number of sessions.
+const TOP_EVENTS_LIMIT: u32 = 10;
+
+/// Longest session assumed, in seconds. Events are read this far either side
+/// of the range so sessions starting in it are aggregated whole, and those
+/// that started before it are recognised.
+const MAX_SESSION_SECS: u32 = 86_400;
+
+// ── Request types ───────────────────────────────────────────────────
+
+fn default_granularity() -> String {
+    "day".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SessionsRequest {
+    #[serde(default = "default_granularity")]
+    pub granularity: String,
+    /// Keep sessions with at least one event matching every filter.
+    #[serde(default)]
+    pub filters: Vec<PropertyFilter>,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub environment: Option<String>,
+}
+
+// ── Response types ──────────────────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct SessionsResponse {
+    pub overview: SessionMetrics,
+    pub series: Vec<SessionMetrics>,
+    pub entry_events: Vec<SessionEventCount>,
+    pub exit_events: Vec<SessionEventCount>,
+}
+
+#[derive(Debug, Serialize, clickhouse::Row, Deserialize)]
+pub struct SessionMetrics {
+    pub period: String,
+    pub sessions: u64,
+    pub avg_duration_secs: f64,
+    pub median_duration_secs: f64,
+    pub events_per_session: f64,
+    /// Share of sessions with a single event.
+    pub bounce_rate: f64,
+}
+
+#[derive(Debug, Serialize, clickhouse::Row, Deserialize)]
+pub struct SessionEventCount {
+    pub event_name: String,
+    pub sessions: u64,
+}
+
+// ── Query building ──────────────────────────────────────────────────
+
+/// Aggregates each session that started in the range into one row, over
+/// all its events up to [`MAX_SESSION_SECS`] either side of the range; the
+/// filters match any of those events. Events are ordered by
+/// `client_timestamp`, since SDKs send events in batches and server
+/// timestamps of a session's events cluster at upload time; system
+/// (`$`-prefixed) events count towards neither the events nor the entry
+/// and exit events. Sessions start at, and are bucketed by, their first
+/// `server_timestamp`, exposed under that name so [`period_expr`] applies; the
+/// alias can't be set in the aggregating query, where it would shadow the
+/// column in `WHERE`.
+fn sessions_cte(
+    db: &str,
+    environment: bool,
+    filter_conditions: &[String],
+    filter_joins: &str,
+) -> String {
+    let env_condition = if environment {
+        " AND environment = ?"
+    } else {
+        ""
+    };
+
+    let filter_condition = if filter_conditions.is_empty() {
+        String::new()
+    } else {
+        format!(
+            " AND session_id IN ( \
+             SELECT e.session_id FROM {db}.events AS e{filter_joins} \
+             WHERE e.project_id = ? AND server_timestamp BETWEEN ? - {MAX_SESSION_SECS} AND ? + {MAX_SESSION_SECS}{env_condition} \
+             AND {})",
+            filter_conditions.join(" AND ")
+        )
+    };
+
+    format!(
+        "WITH session_rows AS ( \
+         SELECT session_id, \
+         min(server_timestamp) AS started_at, \
+         dateDiff('millisecond', min(client_timestamp), max(client_timestamp)) / 1000 AS duration, \
+         countIf(NOT startsWith(event_name, '$')) AS events, \
+         argMinIf(event_name, client_timestamp, NOT startsWith(event_name, '$')) AS entry_event, \
+         argMaxIf(event_name, client_timestamp, NOT startsWith(event_name, '$')) AS exit_event \
+         FROM {db}.events \
+         WHERE project_id = ? AND server_timestamp BETWEEN ? - {MAX_SESSION_SECS} AND ? + {MAX_SESSION_SECS}{env_condition} \
+         AND ifNull(session_id, '') != ''{filter_condition} \
+         GROUP BY session_id \
+         HAVING events > 0 AND started_at BETWEEN ? AND ?), \
+         sessions AS (SELECT *, started_at AS server_timestamp FROM session_rows) "
+    )
+}
+
+fn metrics_select(period: &str) -> String {
+    format!(
+        "SELECT {period} AS period, \
+         count() AS sessions, \
+         ifNotFinite(avg(duration), 0) AS avg_duration_secs, \
+         ifNotFinite(quantile(0.5)(duration), 0) AS median_duration_secs, \
+         ifNotFinite(avg(events), 0) AS events_per_session, \
+         ifNotFinite(countIf(events = 1) / count(), 0) AS bounce_rate \
+         FROM sessions"
+    )
+}
+
+// ── Handler ─────────────────────────────────────────────────────────
+
+/// Session counts, durations, events per session, bounce rate and the most
+/// common entry and exit events.
+pub async fn sessions(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(project_id): Path<Uuid>,
+    Json(req): Json<SessionsRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Viewer)?;
+
+    for f in &req.filters {
+        validate_identifier(&f.property)?;
+    }
+
+    let period = period_expr(&req.granularity)?;
+    let db = &state.config.clickhouse_database;
+    let from_ts = req.from.timestamp_millis() as f64 / 1000.0;
+    let to_ts = req.to.timestamp_millis() as f64 / 1000.0;
+    let environment = req.environment.as_deref();
+
+    let (filter_conditions, filter_values) = build_property_filter_clauses(&req.filters)?;
+    let filter_joins = format!(
+        "{}{}",
+        identity_join(db),
+        profile_joins(db, req.filters.iter().map(|f| f.property.as_str()))
+    );
+    let cte = sessions_cte(db, environment.is_some(), &filter_conditions, &filter_joins);
+
+    let query = |sql: String| {
+        let mut q = state
+            .clickhouse_client
+            .query(&format!("{cte}{sql}"))
+            .bind(project_id)
+            .bind(from_ts)
+            .bind(to_ts);
+        if let Some(env) = environment {
+            q = q.bind(env);
+        }
+        if !filter_conditions.is_empty() {
+            q = q.bind(project_id).bind(from_ts).bind(to_ts);
+            if let Some(env) = environment {
+                q = q.bind(env);
+            }
+            for val in &filter_values {
+                q = q.bind(val.as_str());
+            }
+        }
+        q.bind(from_ts).bind(to_ts)
+    };
+
+    let overview = query(metrics_select("'total'"))
+        .fetch_one::<SessionMetrics>()
+        .await
+        .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
+
+    let series = query(format!(
+        "{} GROUP BY period ORDER BY period",
+        metrics_select(period)
+    ))
+    .fetch_all::<SessionMetrics>()
+    .await
+    .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
+
+    let top_events = |column: &str| {
+        query(format!(
+            "SELECT {column} AS event_name, count() AS sessions FROM sessions \
+             GROUP BY event_name ORDER BY sessions DESC, event_name LIMIT {TOP_EVENTS_LIMIT}"
+        ))
+        .fetch_all::<SessionEventCount>()
+    };
+    let entry_events = top_events("entry_event")
+        .await
+        .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
+    let exit_events = top_events("exit_event")
+        .await
+        .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
+
+    Ok(Json(SessionsResponse {
+        overview,
+        series,
+        entry_events,
+        exit_events,
+    }))
+}
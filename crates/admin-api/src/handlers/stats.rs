@@ -560,10 +560,10 @@ pub async fn active_users(
     // Active users per period (resolve identity for pre-identify events)
     let active_query = format!(
         "SELECT {period_expr} AS period, \
-         uniqExact(COALESCE(NULLIF(m.user_id, ''), ud.user_uid)) AS active_users \
+         uniqExact(COALESCE(NULLIF(m.person_id, ''), ud.user_uid)) AS active_users \
          FROM {db}.users_daily AS ud \
-         LEFT JOIN (SELECT * FROM {db}.identity_map FINAL) AS m \
-           ON m.project_id = ud.project_id AND m.anonymous_id = ud.user_uid \
+         LEFT JOIN (SELECT * FROM {db}.identity_graph FINAL) AS m \
+           ON m.project_id = ud.project_id AND m.distinct_id = ud.user_uid \
          WHERE ud.project_id = ? AND event_date BETWEEN ? AND ?{env_filter} \
          GROUP BY period ORDER BY period"
     );
@@ -590,22 +590,27 @@ pub async fn active_users(
         _ => "toString(first_seen_date)".to_string(),
     };
 
+    // A person is new on the earliest first_seen_date across all their IDs,
+    // so the range filter applies after resolving identity.
     let new_query = format!(
         "SELECT {new_period_expr} AS period, count() AS new_users \
-         FROM {db}.user_first_seen \
-         WHERE project_id = ? AND first_seen_date BETWEEN ? AND ?{env_filter} \
+         FROM ( \
+           SELECT COALESCE(NULLIF(m.person_id, ''), fs.user_uid) AS person_uid, \
+           min(fs.first_seen_date) AS first_seen_date \
+           FROM {db}.user_first_seen AS fs \
+           LEFT JOIN (SELECT * FROM {db}.identity_graph FINAL) AS m \
+             ON m.project_id = fs.project_id AND m.distinct_id = fs.user_uid \
+           WHERE fs.project_id = ?{env_filter} \
+           GROUP BY person_uid) \
+         WHERE first_seen_date BETWEEN ? AND ? \
          GROUP BY period ORDER BY period"
     );
 
-    let mut q = state
-        .clickhouse_client
-        .query(&new_query)
-        .bind(project_id)
-        .bind(from_date.as_str())
-        .bind(to_date.as_str());
+    let mut q = state.clickhouse_client.query(&new_query).bind(project_id);
     if let Some(ref env) = params.environment {
         q = q.bind(env.as_str());
     }
+    let q = q.bind(from_date.as_str()).bind(to_date.as_str());
     let new_rows = q
         .fetch_all::<NewUsersRow>()
         .await
@@ -16,8 +16,8 @@ use crate::state::AppState;
 
 use super::query_builder::{
     GroupedSeriesRow, GroupedTotalsRow, PropertyFilter, build_group_key,
-    build_property_filter_clauses, column_expr, group_series_rows, identity_join, metric_expr,
-    period_expr, validate_identifier,
+    build_property_filter_clauses, group_by_expr, group_series_rows, identity_join, metric_expr,
+    period_expr, profile_joins, validate_identifier,
 };
 
 // ── Constants ────────────────────────────────────────────────────────
@@ -130,7 +130,7 @@ pub async fn trends(
     let mut group_by_aliases = Vec::new();
     for i in 0..MAX_GROUP_BY {
         if i < req.group_by.len() {
-            let expr = column_expr(&req.group_by[i]);
+            let expr = group_by_expr(&req.group_by[i]);
             group_select_parts.push(format!("{} AS g{}", expr, i));
             group_by_aliases.push(format!("g{}", i));
         } else {
@@ -148,6 +148,14 @@ pub async fn trends(
     let (global_filter_conditions, global_filter_values) =
         build_property_filter_clauses(&req.filters)?;
 
+    // Keys that may need a `profile:` as-of join in every event query
+    let shared_keys: Vec<String> = req
+        .group_by
+        .iter()
+        .cloned()
+        .chain(req.filters.iter().map(|f| f.property.clone()))
+        .collect();
+
     // Execute per-event queries concurrently
     let mut futures = Vec::new();
     for eq in &req.events {
@@ -163,6 +171,7 @@ pub async fn trends(
         let event_name = eq.event_name.clone();
         let metric_str = eq.metric.clone();
         let per_event_filters = eq.filters.clone();
+        let shared_keys_clone = shared_keys.clone();
 
         futures.push(tokio::spawn(async move {
             query_single_event(
@@ -182,6 +191,7 @@ pub async fn trends(
                 &event_name,
                 &metric_str,
                 &per_event_filters,
+                &shared_keys_clone,
             )
             .await
         }));
@@ -224,6 +234,7 @@ async fn query_single_event(
     event_name: &str,
     metric_str: &str,
     per_event_filters: &[PropertyFilter],
+    shared_keys: &[String],
 ) -> Result<(Vec<TrendSeries>, Vec<TrendTotal>), AppError> {
     let metric = metric_expr(metric_str)?;
 
@@ -251,7 +262,14 @@ async fn query_single_event(
     let where_clause = conditions.join(" AND ");
 
     // ── Series query ────────────────────────────────────────────────
-    let ij = identity_join(db);
+    let pj = profile_joins(
+        db,
+        shared_keys
+            .iter()
+            .map(String::as_str)
+            .chain(per_event_filters.iter().map(|f| f.property.as_str())),
+    );
+    let ij = format!("{}{pj}", identity_join(db));
     let series_query = format!(
         "SELECT {period} AS period, {group_select}, {metric} AS value \
          FROM {db}.events AS e{ij} \
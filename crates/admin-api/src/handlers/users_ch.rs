@@ -88,7 +88,7 @@ pub async fn list_users(
     };
 
     let query_str = format!(
-        "SELECT COALESCE(NULLIF(im.user_id, ''), s.user_uid) AS user_uid, \
+        "SELECT COALESCE(NULLIF(im.person_id, ''), s.user_uid) AS user_uid, \
          COALESCE(any(p.email), '') AS email, \
          COALESCE(any(p.name), '') AS name, \
          COALESCE(any(p.mobile_number), '') AS mobile_number, \
@@ -96,19 +96,34 @@ pub async fn list_users(
          formatDateTime(max(s.last_seen), '%Y-%m-%dT%H:%i:%SZ', 'UTC') AS last_seen, \
          sum(s.event_count) AS event_count \
          FROM {db}.user_stats AS s \
-         LEFT JOIN (SELECT * FROM {db}.identity_map FINAL) AS im \
-           ON im.project_id = s.project_id AND im.anonymous_id = s.user_uid \
-         LEFT JOIN (SELECT * FROM {db}.user_profiles FINAL) AS p \
+         LEFT JOIN (SELECT * FROM {db}.identity_graph FINAL) AS im \
+           ON im.project_id = s.project_id AND im.distinct_id = s.user_uid \
+         LEFT JOIN ( \
+           SELECT pr.project_id AS project_id, pr.environment AS environment, \
+           COALESCE(NULLIF(pg.person_id, ''), pr.user_uid) AS person_uid, \
+           argMaxIf(pr.email, pr.last_seen, isNotNull(pr.email)) AS email, \
+           argMaxIf(pr.name, pr.last_seen, isNotNull(pr.name)) AS name, \
+           argMaxIf(pr.mobile_number, pr.last_seen, isNotNull(pr.mobile_number)) AS mobile_number \
+           FROM (SELECT * FROM {db}.user_profiles FINAL WHERE project_id = ?) AS pr \
+           LEFT JOIN (SELECT * FROM {db}.identity_graph FINAL) AS pg \
+             ON pg.project_id = pr.project_id AND pg.distinct_id = pr.user_uid \
+           GROUP BY project_id, environment, person_uid) AS p \
            ON s.project_id = p.project_id \
-           AND COALESCE(NULLIF(im.user_id, ''), s.user_uid) = p.user_uid \
+           AND COALESCE(NULLIF(im.person_id, ''), s.user_uid) = p.person_uid \
            AND s.environment = p.environment \
          WHERE s.project_id = ?{env_filter} \
-         GROUP BY COALESCE(NULLIF(im.user_id, ''), s.user_uid){search_filter} \
+         GROUP BY COALESCE(NULLIF(im.person_id, ''), s.user_uid){search_filter} \
          ORDER BY {sort_col} {sort_dir} \
          LIMIT ? OFFSET ?"
     );
 
-    let mut q = state.clickhouse_client.query(&query_str).bind(project_id);
+    // Profiles are keyed by the raw user or anonymous ID they were written
+    // under; each person gets the latest contact fields across their IDs.
+    let mut q = state
+        .clickhouse_client
+        .query(&query_str)
+        .bind(project_id)
+        .bind(project_id);
 
     if let Some(ref env) = params.environment {
         q = q.bind(env.as_str());
@@ -168,44 +183,73 @@ pub async fn get_user(
     Query(params): Query<GetUserQuery>,
 ) -> Result<impl IntoResponse, AppError> {
     rbac::require_project_role(&state, &auth, project_id, TeamRole::Viewer)?;
-    let db = &state.config.clickhouse_database;
+    let user = fetch_user_detail(
+        &state.clickhouse_client,
+        &state.config.clickhouse_database,
+        project_id,
+        &user_uid,
+        params.environment.as_deref(),
+    )
+    .await?;
+
+    match user {
+        Some(user) => Ok(Json(user)),
+        None => Err(AppError::NotFound(format!("User '{}' not found", user_uid))),
+    }
+}
 
-    let env_filter = if params.environment.is_some() {
+/// Profile and activity of a user, counting events of every distinct ID
+/// linked to the person. `None` if the user is unknown.
+pub(crate) async fn fetch_user_detail(
+    clickhouse: &clickhouse::Client,
+    db: &str,
+    project_id: Uuid,
+    user_uid: &str,
+    environment: Option<&str>,
+) -> Result<Option<UserDetailRow>, AppError> {
+    let env_filter = if environment.is_some() {
         " AND environment = ?"
     } else {
         ""
     };
 
-    let stats_env_filter = if params.environment.is_some() {
+    let stats_env_filter = if environment.is_some() {
         " AND environment = ?"
     } else {
         ""
     };
 
-    // Get profile data from user_profiles
+    // Get profile data from user_profiles. Profiles are keyed by the raw ID
+    // they were written under, so a person's are looked up by every linked
+    // distinct ID, preferring the requested ID's own profile.
     let profile_query = format!(
-        "SELECT user_uid, \
-         COALESCE(email, '') AS email, \
+        "SELECT COALESCE(email, '') AS email, \
          COALESCE(name, '') AS name, \
          COALESCE(mobile_number, '') AS mobile_number, \
          toString(properties) AS properties \
          FROM {db}.user_profiles FINAL \
-         WHERE project_id = ? AND user_uid = ?{env_filter} \
+         WHERE project_id = ? AND (user_uid = ? \
+           OR user_uid IN ( \
+             SELECT distinct_id FROM {db}.identity_graph FINAL \
+             WHERE project_id = ? AND person_id = ? \
+           )){env_filter} \
+         ORDER BY user_uid = ? DESC, last_seen DESC \
          LIMIT 1"
     );
 
-    let mut pq = state
-        .clickhouse_client
+    let mut pq = clickhouse
         .query(&profile_query)
         .bind(project_id)
-        .bind(user_uid.as_str());
-    if let Some(ref env) = params.environment {
-        pq = pq.bind(env.as_str());
+        .bind(user_uid)
+        .bind(project_id)
+        .bind(user_uid);
+    if let Some(env) = environment {
+        pq = pq.bind(env);
     }
+    pq = pq.bind(user_uid);
 
     #[derive(clickhouse::Row, Deserialize)]
     struct ProfileOnly {
-        user_uid: String,
         email: String,
         name: String,
         mobile_number: String,
@@ -217,7 +261,7 @@ pub async fn get_user(
         .await
         .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
 
-    // Get accurate stats from user_stats, including any linked anonymous_ids
+    // Get accurate stats from user_stats, including every distinct ID linked to the person
     let stats_query = format!(
         "SELECT sum(event_count) AS event_count, \
          formatDateTime(min(first_seen), '%Y-%m-%dT%H:%i:%SZ', 'UTC') AS first_seen, \
@@ -225,8 +269,8 @@ pub async fn get_user(
          FROM {db}.user_stats \
          WHERE project_id = ? AND (user_uid = ? \
            OR user_uid IN ( \
-             SELECT anonymous_id FROM {db}.identity_map FINAL \
-             WHERE project_id = ? AND user_id = ? \
+             SELECT distinct_id FROM {db}.identity_graph FINAL \
+             WHERE project_id = ? AND person_id = ? \
            )){stats_env_filter}"
     );
 
@@ -237,15 +281,14 @@ pub async fn get_user(
         last_seen: String,
     }
 
-    let mut sq = state
-        .clickhouse_client
+    let mut sq = clickhouse
         .query(&stats_query)
         .bind(project_id)
-        .bind(user_uid.as_str())
+        .bind(user_uid)
         .bind(project_id)
-        .bind(user_uid.as_str());
-    if let Some(ref env) = params.environment {
-        sq = sq.bind(env.as_str());
+        .bind(user_uid);
+    if let Some(env) = environment {
+        sq = sq.bind(env);
     }
 
     let stats = sq
@@ -253,9 +296,9 @@ pub async fn get_user(
         .await
         .map_err(|e| AppError::Database(format!("ClickHouse error: {}", e)))?;
 
-    match (profile, stats) {
-        (Some(p), Some(s)) => Ok(Json(UserDetailRow {
-            user_uid: p.user_uid,
+    Ok(match (profile, stats) {
+        (Some(p), Some(s)) => Some(UserDetailRow {
+            user_uid: user_uid.to_string(),
             email: p.email,
             name: p.name,
             mobile_number: p.mobile_number,
@@ -263,9 +306,9 @@ pub async fn get_user(
             first_seen: s.first_seen,
             last_seen: s.last_seen,
             event_count: s.event_count,
-        })),
-        (Some(p), None) => Ok(Json(UserDetailRow {
-            user_uid: p.user_uid,
+        }),
+        (Some(p), None) => Some(UserDetailRow {
+            user_uid: user_uid.to_string(),
             email: p.email,
             name: p.name,
             mobile_number: p.mobile_number,
@@ -273,9 +316,9 @@ pub async fn get_user(
             first_seen: String::new(),
             last_seen: String::new(),
             event_count: 0,
-        })),
-        (None, Some(s)) => Ok(Json(UserDetailRow {
-            user_uid: user_uid.clone(),
+        }),
+        (None, Some(s)) => Some(UserDetailRow {
+            user_uid: user_uid.to_string(),
             email: String::new(),
             name: String::new(),
             mobile_number: String::new(),
@@ -283,9 +326,9 @@ pub async fn get_user(
             first_seen: s.first_seen,
             last_seen: s.last_seen,
             event_count: s.event_count,
-        })),
-        (None, None) => Err(AppError::NotFound(format!("User '{}' not found", user_uid))),
-    }
+        }),
+        (None, None) => None,
+    })
 }
 
 // ── User Events ─────────────────────────────────────────────────────
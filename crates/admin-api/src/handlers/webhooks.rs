@@ -0,0 +1,326 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use truesight_common::error::AppError;
+use truesight_common::team::TeamRole;
+use truesight_common::webhook::{
+    ANY_EVENT, MAX_BATCH_SIZE, NewWebhookDestination, UpdateWebhookDestination, WebhookDeadLetter,
+    WebhookDestination, check_destination_url, generate_secret, parse_filters,
+};
+
+use crate::db::webhooks as db;
+use crate::handlers::rbac;
+use crate::middleware::admin_auth::AuthUser;
+use crate::state::AppState;
+
+/// Maximum number of dead letters returned per request.
+const MAX_DEAD_LETTERS: i64 = 100;
+
+// ── Types ───────────────────────────────────────────────────────────
+
+/// A destination as returned by list/get/update. The signing secret is only
+/// returned on create and by `rotate-secret`.
+#[derive(Debug, Serialize)]
+pub struct WebhookResponse {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub name: String,
+    pub url: String,
+    pub event_name: String,
+    pub filters: serde_json::Value,
+    pub batch_size: i32,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<WebhookDestination> for WebhookResponse {
+    fn from(w: WebhookDestination) -> Self {
+        Self {
+            id: w.id,
+            project_id: w.project_id,
+            name: w.name,
+            url: w.url,
+            event_name: w.event_name,
+            filters: w.filters,
+            batch_size: w.batch_size,
+            enabled: w.enabled,
+            created_at: w.created_at,
+            updated_at: w.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebhookWithSecretResponse {
+    #[serde(flatten)]
+    pub webhook: WebhookResponse,
+    pub secret: String,
+}
+
+impl From<WebhookDestination> for WebhookWithSecretResponse {
+    fn from(w: WebhookDestination) -> Self {
+        let secret = w.secret.clone();
+        Self {
+            webhook: WebhookResponse::from(w),
+            secret,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookInput {
+    pub name: String,
+    pub url: String,
+    /// Event name to forward, or `*` for every event.
+    #[serde(default = "default_event_name")]
+    pub event_name: String,
+    #[serde(default = "default_filters")]
+    pub filters: serde_json::Value,
+    #[serde(default = "default_batch_size")]
+    pub batch_size: i32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_event_name() -> String {
+    ANY_EVENT.to_string()
+}
+
+fn default_filters() -> serde_json::Value {
+    serde_json::json!([])
+}
+
+fn default_batch_size() -> i32 {
+    100
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookInput {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub event_name: Option<String>,
+    pub filters: Option<serde_json::Value>,
+    pub batch_size: Option<i32>,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeadLetterQuery {
+    /// Maximum number of dead letters to return (default and max 100).
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteDeadLettersResponse {
+    pub deleted: usize,
+}
+
+// ── Validation ──────────────────────────────────────────────────────
+
+fn validate_name(name: &str) -> Result<(), AppError> {
+    if name.trim().is_empty() {
+        return Err(AppError::Validation("name must not be empty".into()));
+    }
+    Ok(())
+}
+
+fn validate_url(url: &str) -> Result<(), AppError> {
+    check_destination_url(url).map_err(AppError::Validation)
+}
+
+fn validate_event_name(event_name: &str) -> Result<(), AppError> {
+    if event_name.trim().is_empty() {
+        return Err(AppError::Validation(format!(
+            "event_name must not be empty (use '{ANY_EVENT}' for every event)"
+        )));
+    }
+    Ok(())
+}
+
+fn validate_filters(filters: &serde_json::Value) -> Result<(), AppError> {
+    parse_filters(filters)
+        .map(|_| ())
+        .map_err(AppError::Validation)
+}
+
+fn validate_batch_size(batch_size: i32) -> Result<(), AppError> {
+    if !(1..=MAX_BATCH_SIZE).contains(&batch_size) {
+        return Err(AppError::Validation(format!(
+            "batch_size must be between 1 and {MAX_BATCH_SIZE}"
+        )));
+    }
+    Ok(())
+}
+
+// ── CRUD Handlers ───────────────────────────────────────────────────
+
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(project_id): Path<Uuid>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Viewer)?;
+    let webhooks = db::list_webhooks(&state.db_pool, project_id)?;
+    let response: Vec<WebhookResponse> = webhooks.into_iter().map(WebhookResponse::from).collect();
+    Ok(Json(response))
+}
+
+pub async fn get_webhook(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((project_id, webhook_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Viewer)?;
+    let webhook = db::find_webhook(&state.db_pool, project_id, webhook_id)?;
+    Ok(Json(WebhookResponse::from(webhook)))
+}
+
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path(project_id): Path<Uuid>,
+    Json(input): Json<CreateWebhookInput>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Editor)?;
+    validate_name(&input.name)?;
+    validate_url(&input.url)?;
+    validate_event_name(&input.event_name)?;
+    validate_filters(&input.filters)?;
+    validate_batch_size(input.batch_size)?;
+
+    let webhook = db::insert_webhook(
+        &state.db_pool,
+        NewWebhookDestination {
+            project_id,
+            name: input.name,
+            url: input.url,
+            secret: generate_secret(),
+            event_name: input.event_name,
+            filters: input.filters,
+            batch_size: input.batch_size,
+            enabled: input.enabled,
+        },
+    )?;
+    Ok((
+        axum::http::StatusCode::CREATED,
+        Json(WebhookWithSecretResponse::from(webhook)),
+    ))
+}
+
+pub async fn update_webhook(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((project_id, webhook_id)): Path<(Uuid, Uuid)>,
+    Json(input): Json<UpdateWebhookInput>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Editor)?;
+    if let Some(ref name) = input.name {
+        validate_name(name)?;
+    }
+    if let Some(ref url) = input.url {
+        validate_url(url)?;
+    }
+    if let Some(ref event_name) = input.event_name {
+        validate_event_name(event_name)?;
+    }
+    if let Some(ref filters) = input.filters {
+        validate_filters(filters)?;
+    }
+    if let Some(batch_size) = input.batch_size {
+        validate_batch_size(batch_size)?;
+    }
+
+    let webhook = db::update_webhook(
+        &state.db_pool,
+        project_id,
+        webhook_id,
+        UpdateWebhookDestination {
+            name: input.name,
+            url: input.url,
+            secret: None,
+            event_name: input.event_name,
+            filters: input.filters,
+            batch_size: input.batch_size,
+            enabled: input.enabled,
+            updated_at: Utc::now(),
+        },
+    )?;
+    Ok(Json(WebhookResponse::from(webhook)))
+}
+
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((project_id, webhook_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Editor)?;
+    db::delete_webhook(&state.db_pool, project_id, webhook_id)?;
+    Ok(axum::http::StatusCode::NO_CONTENT)
+}
+
+/// Replaces the signing secret. ch-writer picks it up on its next refresh of
+/// the destination cache.
+pub async fn rotate_secret(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((project_id, webhook_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Editor)?;
+    let webhook = db::update_webhook(
+        &state.db_pool,
+        project_id,
+        webhook_id,
+        UpdateWebhookDestination {
+            name: None,
+            url: None,
+            secret: Some(generate_secret()),
+            event_name: None,
+            filters: None,
+            batch_size: None,
+            enabled: None,
+            updated_at: Utc::now(),
+        },
+    )?;
+    Ok(Json(WebhookWithSecretResponse::from(webhook)))
+}
+
+// ── Dead Letters ────────────────────────────────────────────────────
+
+pub async fn list_dead_letters(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((project_id, webhook_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<DeadLetterQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Viewer)?;
+    db::find_webhook(&state.db_pool, project_id, webhook_id)?;
+    let limit = query
+        .limit
+        .unwrap_or(MAX_DEAD_LETTERS)
+        .clamp(1, MAX_DEAD_LETTERS);
+    let dead_letters: Vec<WebhookDeadLetter> =
+        db::list_dead_letters(&state.db_pool, webhook_id, limit)?;
+    Ok(Json(dead_letters))
+}
+
+pub async fn delete_dead_letters(
+    State(state): State<AppState>,
+    auth: AuthUser,
+    Path((project_id, webhook_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, AppError> {
+    rbac::require_project_role(&state, &auth, project_id, TeamRole::Editor)?;
+    db::find_webhook(&state.db_pool, project_id, webhook_id)?;
+    let deleted = db::delete_dead_letters(&state.db_pool, webhook_id)?;
+    Ok(Json(DeleteDeadLettersResponse { deleted }))
+}
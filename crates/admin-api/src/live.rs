@@ -0,0 +1,133 @@
+//! Live event fan-out.
+//!
+//! [`LiveHub`] holds one Redis pub/sub subscription per admin-api instance to
+//! the events ch-writer publishes after each insert (see
+//! [`truesight_common::live`]) and re-broadcasts them to every live-events
+//! SSE connection. It also keeps each project's most recent events, so a new
+//! connection starts with recent history without querying ClickHouse.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::sync::broadcast;
+use truesight_common::live::{CHANNEL_PATTERN, LiveEvent, LiveMessage};
+use uuid::Uuid;
+
+/// Messages a slow SSE connection may fall behind before it skips ahead.
+const BROADCAST_CAPACITY: usize = 1024;
+
+/// Recent events kept per project for new connections.
+const RECENT_EVENTS: usize = 100;
+
+/// Age limit of the recent events, in seconds.
+const RECENT_WINDOW_SECS: f64 = 30.0;
+
+/// Longest wait between Redis reconnection attempts.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+pub struct LiveHub {
+    sender: broadcast::Sender<Arc<LiveMessage>>,
+    recent: Mutex<HashMap<Uuid, VecDeque<LiveEvent>>>,
+}
+
+impl LiveHub {
+    /// Creates the hub and spawns its Redis subscriber, which reconnects with
+    /// back-off whenever the subscription is lost.
+    pub fn start(redis_url: &str) -> anyhow::Result<Arc<Self>> {
+        let client = redis::Client::open(redis_url)?;
+        let (sender, _) = broadcast::channel(BROADCAST_CAPACITY);
+        let hub = Arc::new(Self {
+            sender,
+            recent: Mutex::new(HashMap::new()),
+        });
+        tokio::spawn(Arc::clone(&hub).run(client));
+        Ok(hub)
+    }
+
+    /// Subscribes to live messages and returns the project's recent events.
+    /// Both are taken under one lock, so no event is missed or repeated.
+    pub fn subscribe(
+        &self,
+        project_id: Uuid,
+    ) -> (broadcast::Receiver<Arc<LiveMessage>>, Vec<LiveEvent>) {
+        let mut recent = self.recent.lock().expect("live hub poisoned");
+        let receiver = self.sender.subscribe();
+        let events = match recent.get_mut(&project_id) {
+            Some(events) => {
+                prune(events, now_secs());
+                events.iter().cloned().collect()
+            }
+            None => Vec::new(),
+        };
+        (receiver, events)
+    }
+
+    fn publish(&self, message: LiveMessage) {
+        let now = now_secs();
+        let mut recent = self.recent.lock().expect("live hub poisoned");
+        recent
+            .entry(message.project_id)
+            .or_default()
+            .extend(message.events.iter().cloned());
+        recent.retain(|_, events| {
+            prune(events, now);
+            !events.is_empty()
+        });
+
+        // No receivers just means nobody is watching.
+        let _ = self.sender.send(Arc::new(message));
+    }
+
+    async fn run(self: Arc<Self>, client: redis::Client) {
+        let mut delay = Duration::from_secs(1);
+
+        loop {
+            match client.get_async_pubsub().await {
+                Ok(mut pubsub) => match pubsub.psubscribe(CHANNEL_PATTERN).await {
+                    Ok(()) => {
+                        tracing::info!("Subscribed to live events");
+                        delay = Duration::from_secs(1);
+
+                        let mut messages = pubsub.on_message();
+                        while let Some(msg) = messages.next().await {
+                            let parsed = msg
+                                .get_payload::<String>()
+                                .map_err(anyhow::Error::from)
+                                .and_then(|p| Ok(serde_json::from_str::<LiveMessage>(&p)?));
+                            match parsed {
+                                Ok(message) => self.publish(message),
+                                Err(e) => tracing::warn!(
+                                    channel = msg.get_channel_name(),
+                                    error = %e,
+                                    "Ignoring malformed live event message"
+                                ),
+                            }
+                        }
+                        tracing::warn!("Live events subscription lost");
+                    }
+                    Err(e) => tracing::error!(error = %e, "Failed to subscribe to live events"),
+                },
+                Err(e) => tracing::error!(error = %e, "Failed to connect to Redis for live events"),
+            }
+
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+}
+
+fn now_secs() -> f64 {
+    chrono::Utc::now().timestamp_millis() as f64 / 1000.0
+}
+
+fn prune(events: &mut VecDeque<LiveEvent>, now: f64) {
+    while events.len() > RECENT_EVENTS
+        || events
+            .front()
+            .is_some_and(|e| e.server_ts_raw < now - RECENT_WINDOW_SECS)
+    {
+        events.pop_front();
+    }
+}
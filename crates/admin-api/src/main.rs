@@ -1,27 +1,35 @@
+mod data_export;
 mod db;
+mod erasure;
 mod handlers;
+mod live;
 mod middleware;
+mod retention;
 mod routes;
 mod state;
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
 use tokio::net::TcpListener;
 use tokio::sync::RwLock;
 use tower_http::cors::{AllowOrigin, CorsLayer};
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{info, warn};
 
 use truesight_common::api_key::{NewApiKey, generate_api_key};
 use truesight_common::auth::hash_api_key;
 use truesight_common::config::AdminConfig;
 use truesight_common::db::create_pool;
+use truesight_common::erasure::Suppression;
 use truesight_common::project::NewProject;
 use truesight_common::shutdown::shutdown_signal;
+use truesight_common::sqs::{DlqClient, SqsProducer};
 use truesight_common::telemetry::init_telemetry;
 
-use crate::state::AppState;
+use crate::live::LiveHub;
+use crate::state::{AppState, DlqTooling};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("../../migrations");
 
@@ -42,6 +50,21 @@ fn build_clickhouse_client(config: &AdminConfig) -> clickhouse::Client {
         .with_database(&config.clickhouse_database)
 }
 
+async fn build_dlq_tooling(config: &AdminConfig) -> anyhow::Result<Option<DlqTooling>> {
+    let Some(dlq_url) = config.dlq_url() else {
+        info!("DLQ tooling disabled (no SQS_QUEUE_URL or SQS_DLQ_URL configured)");
+        return Ok(None);
+    };
+
+    let endpoint_url = config.sqs_endpoint_url.as_deref();
+    Ok(Some(DlqTooling {
+        client: DlqClient::new(&config.aws_region, endpoint_url).await?,
+        producer: SqsProducer::new(&config.aws_region, endpoint_url).await?,
+        queue_url: config.sqs_queue_url.clone().filter(|u| !u.is_empty()),
+        dlq_url,
+    }))
+}
+
 fn build_cors_layer(config: &AdminConfig) -> CorsLayer {
     use axum::http::{Method, header};
 
@@ -120,6 +143,7 @@ async fn main() -> anyhow::Result<()> {
     );
 
     info!("Starting admin-api");
+    truesight_common::event_row::set_flatten_depth(config.property_flatten_depth);
 
     // Create DB pool
     let db_pool = create_pool(&config.database_url)?;
@@ -137,6 +161,66 @@ async fn main() -> anyhow::Result<()> {
     // Create ClickHouse client
     let ch_client = build_clickhouse_client(&config);
 
+    // Create SQS clients for the DLQ tooling
+    let dlq = build_dlq_tooling(&config).await?.map(Arc::new);
+
+    // Subscribe to live events published by ch-writer
+    let live = match config.redis_url.as_deref().filter(|u| !u.is_empty()) {
+        Some(redis_url) => Some(LiveHub::start(redis_url)?),
+        None => {
+            info!("REDIS_URL not set, live events stream polls ClickHouse");
+            None
+        }
+    };
+
+    let ch_client = Arc::new(ch_client);
+
+    // Purge data past each project's retention policy
+    if config.retention_purge_interval_secs > 0 {
+        retention::spawn(
+            db_pool.clone(),
+            Arc::clone(&ch_client),
+            config.clickhouse_database.clone(),
+            Duration::from_secs(config.retention_purge_interval_secs),
+        );
+    } else {
+        info!("RETENTION_PURGE_INTERVAL_SECS is 0, retention purges disabled");
+    }
+
+    // Build data subject access exports
+    let exports = data_export::ExportStore::from_config(&config)?.map(Arc::new);
+    match &exports {
+        Some(store) => data_export::spawn(
+            db_pool.clone(),
+            Arc::clone(&ch_client),
+            config.clickhouse_database.clone(),
+            Arc::clone(store),
+        ),
+        None => info!("EXPORT_URL not set, data exports disabled"),
+    }
+
+    // Run right-to-erasure requests, and keep erased users out of DLQ redrives
+    let suppression = match config.erasure_hash_key.clone().filter(|k| !k.is_empty()) {
+        Some(hash_key) => {
+            erasure::spawn(
+                db_pool.clone(),
+                Arc::clone(&ch_client),
+                config.clickhouse_database.clone(),
+                hash_key.clone(),
+                dlq.clone(),
+            );
+            Some(Suppression::spawn(
+                db_pool.clone(),
+                hash_key,
+                Duration::from_secs(config.suppression_refresh_secs),
+            ))
+        }
+        None => {
+            warn!("ERASURE_HASH_KEY not set, erasure requests disabled");
+            None
+        }
+    };
+
     // Build CORS layer
     let cors = build_cors_layer(&config);
 
@@ -146,9 +230,13 @@ async fn main() -> anyhow::Result<()> {
     // Build app state
     let state = AppState {
         db_pool,
-        clickhouse_client: Arc::new(ch_client),
+        clickhouse_client: ch_client,
         config: Arc::new(config.clone()),
         google_jwks: Arc::new(RwLock::new(None)),
+        dlq,
+        live,
+        exports,
+        suppression,
     };
 
     // Build router
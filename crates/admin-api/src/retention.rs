@@ -0,0 +1,169 @@
+//! Retention purge scheduler.
+//!
+//! Every `RETENTION_PURGE_INTERVAL_SECS` the scheduler loads the retention
+//! policies (see [`truesight_common::retention`]) and purges each one that
+//! is due: it claims the policy in Postgres, so only one admin-api instance
+//! purges it per interval, then deletes the environment's rows older than
+//! the policy's cutoff from every retained ClickHouse table. A purge that
+//! fails releases its claim and is retried on the next tick.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, SubsecRound, Utc};
+use truesight_common::db::DbPool;
+use truesight_common::retention::{RETAINED_TABLES, RetentionPolicy};
+
+use crate::db::retention as db;
+
+/// Spawns the scheduler. It runs until admin-api exits.
+pub fn spawn(
+    db_pool: DbPool,
+    clickhouse: Arc<clickhouse::Client>,
+    database: String,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = purge_due(&db_pool, &clickhouse, &database).await {
+                tracing::error!(error = %e, "retention purge failed");
+            }
+        }
+    });
+}
+
+async fn purge_due(
+    db_pool: &DbPool,
+    clickhouse: &clickhouse::Client,
+    database: &str,
+) -> Result<()> {
+    // Postgres keeps microseconds; truncating lets the claim be matched when
+    // it is released.
+    let now = Utc::now().trunc_subsecs(6);
+    for policy in db::list_all_policies(db_pool)? {
+        if policy.next_purge_at(now).is_some() || !db::claim_purge(db_pool, policy.id, now)? {
+            continue;
+        }
+
+        let cutoff = policy.cutoff(now);
+        if let Err(e) = purge(clickhouse, database, &policy, cutoff).await {
+            tracing::error!(
+                project_id = %policy.project_id,
+                environment = %policy.environment,
+                error = %e,
+                "failed to purge expired data"
+            );
+            // Give the claim back so the purge is retried on the next tick.
+            if let Err(e) = db::release_purge(db_pool, policy.id, now, policy.last_purged_at) {
+                tracing::error!(
+                    project_id = %policy.project_id,
+                    environment = %policy.environment,
+                    error = %e,
+                    "failed to release retention purge claim"
+                );
+            }
+            continue;
+        }
+        tracing::info!(
+            project_id = %policy.project_id,
+            environment = %policy.environment,
+            retention_days = policy.retention_days,
+            cutoff = %cutoff,
+            "purged expired data"
+        );
+    }
+    Ok(())
+}
+
+/// Deletes the policy's rows older than `cutoff` from the retained tables.
+async fn purge(
+    clickhouse: &clickhouse::Client,
+    database: &str,
+    policy: &RetentionPolicy,
+    cutoff: DateTime<Utc>,
+) -> Result<()> {
+    let cutoff_ts = cutoff.timestamp_millis() as f64 / 1000.0;
+
+    for (table, column) in RETAINED_TABLES {
+        // Rows kept despite being older than the cutoff, with the number of
+        // (project, environment, cutoff) parameter sets the condition takes.
+        let (keep, keep_binds) = match *table {
+            // Users still active are returning users, not new ones.
+            "user_first_seen" => (
+                format!(
+                    " AND user_uid NOT IN ( \
+                       SELECT user_uid FROM {database}.user_stats \
+                       WHERE project_id = ? AND environment = ? \
+                       AND last_seen >= toDateTime64(?, 3, 'UTC'))"
+                ),
+                1,
+            ),
+            // The value each property held at the cutoff still applies to
+            // profiles that are kept.
+            "user_profile_history" => (
+                format!(
+                    " AND (user_uid, property, valid_from) NOT IN ( \
+                       SELECT user_uid, property, max(valid_from) \
+                       FROM {database}.user_profile_history \
+                       WHERE project_id = ? AND environment = ? \
+                       AND valid_from < toDateTime64(?, 3, 'UTC') \
+                       AND user_uid IN ( \
+                         SELECT user_uid FROM {database}.user_profiles \
+                         WHERE project_id = ? AND environment = ? \
+                         AND last_seen >= toDateTime64(?, 3, 'UTC')) \
+                       GROUP BY user_uid, property)"
+                ),
+                2,
+            ),
+            _ => (String::new(), 0),
+        };
+
+        let mut query = clickhouse
+            .query(&format!(
+                "DELETE FROM {database}.{table} \
+                 WHERE project_id = ? AND environment = ? \
+                 AND {column} < toDateTime64(?, 3, 'UTC'){keep}"
+            ))
+            .bind(policy.project_id)
+            .bind(policy.environment.as_str())
+            .bind(cutoff_ts);
+        for _ in 0..keep_binds {
+            query = query
+                .bind(policy.project_id)
+                .bind(policy.environment.as_str())
+                .bind(cutoff_ts);
+        }
+        query
+            .execute()
+            .await
+            .with_context(|| format!("failed to purge {table}"))?;
+    }
+
+    // Property keys only record when they were first seen; keep those the
+    // type counts show are still in use.
+    clickhouse
+        .query(&format!(
+            "DELETE FROM {database}.event_property_keys \
+             WHERE project_id = ? AND environment = ? \
+             AND first_seen < toDateTime64(?, 3, 'UTC') \
+             AND (event_name, property_key) NOT IN ( \
+               SELECT event_name, property_key FROM {database}.event_property_types \
+               WHERE project_id = ? AND environment = ? \
+               AND last_seen >= toDateTime64(?, 3, 'UTC'))"
+        ))
+        .bind(policy.project_id)
+        .bind(policy.environment.as_str())
+        .bind(cutoff_ts)
+        .bind(policy.project_id)
+        .bind(policy.environment.as_str())
+        .bind(cutoff_ts)
+        .execute()
+        .await
+        .context("failed to purge event_property_keys")?;
+
+    Ok(())
+}
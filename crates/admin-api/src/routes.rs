@@ -1,6 +1,6 @@
 use axum::{
     Router, middleware,
-    routing::{delete, get, patch, post},
+    routing::{delete, get, patch, post, put},
 };
 
 use crate::handlers;
@@ -12,7 +12,10 @@ pub fn create_router(state: AppState) -> Router {
     let api_routes = Router::new()
         // Auth (requires JWT/token)
         .route("/v1/auth/me", get(handlers::auth::me))
-        .route("/v1/auth/me/onboarding-complete", post(handlers::auth::complete_onboarding))
+        .route(
+            "/v1/auth/me/onboarding-complete",
+            post(handlers::auth::complete_onboarding),
+        )
         // Projects
         .route("/v1/projects", get(handlers::projects::list_projects))
         .route("/v1/projects/{id}", get(handlers::projects::get_project))
@@ -43,6 +46,10 @@ pub fn create_router(state: AppState) -> Router {
             "/v1/stats/projects/{pid}/event-catalog",
             get(handlers::event_catalog::event_catalog),
         )
+        .route(
+            "/v1/stats/projects/{pid}/event-catalog/drift",
+            get(handlers::event_catalog::property_drift),
+        )
         .route(
             "/v1/stats/projects/{pid}/event-catalog/{event_name}/properties",
             get(handlers::event_catalog::event_properties),
@@ -127,6 +134,10 @@ pub fn create_router(state: AppState) -> Router {
             "/v1/stats/projects/{pid}/flows",
             post(handlers::flows::flows),
         )
+        .route(
+            "/v1/stats/projects/{pid}/sessions",
+            post(handlers::sessions::sessions),
+        )
         // Cohorts
         .route(
             "/v1/projects/{pid}/cohorts",
@@ -259,6 +270,82 @@ pub fn create_router(state: AppState) -> Router {
             "/v1/projects/{pid}/funnels/{fid}/compare",
             get(handlers::funnels::compare_time_ranges),
         )
+        // Webhooks
+        .route(
+            "/v1/projects/{pid}/webhooks",
+            get(handlers::webhooks::list_webhooks),
+        )
+        .route(
+            "/v1/projects/{pid}/webhooks",
+            post(handlers::webhooks::create_webhook),
+        )
+        .route(
+            "/v1/projects/{pid}/webhooks/{wid}",
+            get(handlers::webhooks::get_webhook),
+        )
+        .route(
+            "/v1/projects/{pid}/webhooks/{wid}",
+            patch(handlers::webhooks::update_webhook),
+        )
+        .route(
+            "/v1/projects/{pid}/webhooks/{wid}",
+            delete(handlers::webhooks::delete_webhook),
+        )
+        .route(
+            "/v1/projects/{pid}/webhooks/{wid}/rotate-secret",
+            post(handlers::webhooks::rotate_secret),
+        )
+        .route(
+            "/v1/projects/{pid}/webhooks/{wid}/dead-letters",
+            get(handlers::webhooks::list_dead_letters),
+        )
+        .route(
+            "/v1/projects/{pid}/webhooks/{wid}/dead-letters",
+            delete(handlers::webhooks::delete_dead_letters),
+        )
+        // Data retention
+        .route(
+            "/v1/projects/{pid}/data-retention",
+            get(handlers::data_retention::get_retention),
+        )
+        .route(
+            "/v1/projects/{pid}/data-retention/{environment}",
+            put(handlers::data_retention::set_retention),
+        )
+        .route(
+            "/v1/projects/{pid}/data-retention/{environment}",
+            delete(handlers::data_retention::delete_retention),
+        )
+        // Data subject access exports
+        .route(
+            "/v1/projects/{pid}/data-exports",
+            get(handlers::data_exports::list_exports),
+        )
+        .route(
+            "/v1/projects/{pid}/data-exports",
+            post(handlers::data_exports::create_export),
+        )
+        .route(
+            "/v1/projects/{pid}/data-exports/{xid}",
+            get(handlers::data_exports::get_export),
+        )
+        .route(
+            "/v1/projects/{pid}/data-exports/{xid}/download",
+            get(handlers::data_exports::download_export),
+        )
+        // Right to erasure
+        .route(
+            "/v1/projects/{pid}/erasures",
+            get(handlers::erasures::list_erasures),
+        )
+        .route(
+            "/v1/projects/{pid}/erasures",
+            post(handlers::erasures::create_erasure),
+        )
+        .route(
+            "/v1/projects/{pid}/erasures/{eid}",
+            get(handlers::erasures::get_erasure),
+        )
         // Teams
         .route("/v1/teams", get(handlers::teams::list_teams))
         .route("/v1/teams", post(handlers::teams::create_team))
@@ -321,6 +408,9 @@ pub fn create_router(state: AppState) -> Router {
             "/v1/teams/{tid}/allowed-domains/{did}",
             delete(handlers::teams::remove_allowed_domain),
         )
+        // Dead-letter queue (operator only)
+        .route("/v1/dlq", get(handlers::dlq::list_dlq))
+        .route("/v1/dlq/redrive", post(handlers::dlq::redrive_dlq))
         .route_layer(middleware::from_fn_with_state(state.clone(), admin_auth))
         .with_state(state.clone());
 
@@ -4,6 +4,11 @@ use chrono::{DateTime, Utc};
 use tokio::sync::RwLock;
 use truesight_common::config::AdminConfig;
 use truesight_common::db::DbPool;
+use truesight_common::erasure::Suppression;
+use truesight_common::sqs::{DlqClient, SqsProducer};
+
+use crate::data_export::ExportStore;
+use crate::live::LiveHub;
 
 /// Cached Google JWKS key entry.
 #[derive(Clone, Debug)]
@@ -20,10 +25,28 @@ pub struct CachedJwks {
     pub fetched_at: DateTime<Utc>,
 }
 
+/// SQS clients backing the DLQ inspection and redrive endpoints.
+pub struct DlqTooling {
+    pub client: DlqClient,
+    pub producer: SqsProducer,
+    /// Main event queue (redrive target); `None` if only the DLQ is configured.
+    pub queue_url: Option<String>,
+    pub dlq_url: String,
+}
+
 #[derive(Clone)]
 pub struct AppState {
     pub db_pool: DbPool,
     pub clickhouse_client: Arc<clickhouse::Client>,
     pub config: Arc<AdminConfig>,
     pub google_jwks: Arc<RwLock<Option<CachedJwks>>>,
+    /// `None` when neither `SQS_QUEUE_URL` nor `SQS_DLQ_URL` is configured.
+    pub dlq: Option<Arc<DlqTooling>>,
+    /// Pushed live events; `None` when `REDIS_URL` is not configured.
+    pub live: Option<Arc<LiveHub>>,
+    /// Data export archives; `None` when `EXPORT_URL` is not configured.
+    pub exports: Option<Arc<ExportStore>>,
+    /// Erased users, whose DLQ messages are deleted instead of listed or
+    /// redriven; `None` when `ERASURE_HASH_KEY` is not configured.
+    pub suppression: Option<Suppression>,
 }
@@ -0,0 +1,181 @@
+//! Adaptive batch size and insert concurrency.
+//!
+//! The [`Batcher`](crate::batcher::Batcher) reads its flush threshold and
+//! in-flight limit from [`AdaptiveLimits`], which adjusts both from the
+//! outcome of each `events` insert, within the configured bounds:
+//!
+//! - a batch that fails as a whole halves the batch size and the in-flight
+//!   limit;
+//! - an insert slower than the target latency shrinks the batch size by a
+//!   quarter and drops one in-flight slot;
+//! - a size-triggered batch inserted in under half the target latency grows
+//!   the batch size by a quarter, and once it is at its maximum, adds an
+//!   in-flight slot.
+//!
+//! Batches flushed by the timeout never grow the limits, so quiet periods
+//! keep whatever size the last busy period settled on. Partially rejected
+//! batches are ignored: their latency includes bisection.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use truesight_common::config::WriterConfig;
+
+use crate::config::DEFAULT_IN_FLIGHT;
+
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    batch_size: usize,
+    in_flight: usize,
+}
+
+/// Batch size and in-flight limits shared by the batcher and its flush tasks.
+pub struct AdaptiveLimits {
+    enabled: bool,
+    min_batch_size: usize,
+    max_batch_size: usize,
+    max_in_flight: usize,
+    target_latency: Duration,
+    limits: Mutex<Limits>,
+    active: AtomicUsize,
+    released: Notify,
+}
+
+/// Held by a flush task while its insert is in flight.
+pub struct InFlightPermit {
+    limits: Arc<AdaptiveLimits>,
+}
+
+impl Drop for InFlightPermit {
+    fn drop(&mut self) {
+        self.limits.active.fetch_sub(1, Ordering::SeqCst);
+        self.limits.released.notify_one();
+    }
+}
+
+impl AdaptiveLimits {
+    /// Starts at `CH_BATCH_SIZE` and [`DEFAULT_IN_FLIGHT`], clamped to the
+    /// configured bounds. With `CH_ADAPTIVE_BATCHING=false` the limits stay
+    /// at their starting values.
+    pub fn from_config(config: &WriterConfig) -> Self {
+        let min_batch_size = config.ch_min_batch_size.max(1);
+        let max_batch_size = config.ch_max_batch_size.max(min_batch_size);
+        let max_in_flight = config.ch_max_in_flight.max(1);
+
+        Self {
+            enabled: config.ch_adaptive_batching,
+            min_batch_size,
+            max_batch_size,
+            max_in_flight,
+            target_latency: Duration::from_millis(config.ch_target_insert_latency_ms),
+            limits: Mutex::new(Limits {
+                batch_size: config.batch_size().clamp(min_batch_size, max_batch_size),
+                in_flight: DEFAULT_IN_FLIGHT.min(max_in_flight),
+            }),
+            active: AtomicUsize::new(0),
+            released: Notify::new(),
+        }
+    }
+
+    fn current(&self) -> Limits {
+        *self.limits.lock().expect("adaptive limits poisoned")
+    }
+
+    /// Current flush threshold.
+    pub fn batch_size(&self) -> usize {
+        self.current().batch_size
+    }
+
+    /// Current maximum number of concurrent inserts.
+    pub fn in_flight(&self) -> usize {
+        self.current().in_flight
+    }
+
+    pub fn max_batch_size(&self) -> usize {
+        self.max_batch_size
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Takes a slot if fewer than [`Self::in_flight`] inserts are running.
+    /// Only the batcher task acquires, so check-then-increment cannot race.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<InFlightPermit> {
+        if self.active.load(Ordering::SeqCst) >= self.in_flight() {
+            return None;
+        }
+        self.active.fetch_add(1, Ordering::SeqCst);
+        Some(InFlightPermit {
+            limits: Arc::clone(self),
+        })
+    }
+
+    /// Completes when an in-flight insert finishes.
+    pub async fn released(&self) {
+        self.released.notified().await;
+    }
+
+    /// Waits until every in-flight insert has finished.
+    pub async fn drain(&self) {
+        while self.active.load(Ordering::SeqCst) > 0 {
+            self.released.notified().await;
+        }
+    }
+
+    /// Records a fully successful insert. `full` is `true` when the batch was
+    /// flushed because it reached the batch size.
+    pub fn record_success(&self, latency: Duration, full: bool) {
+        if !self.enabled {
+            return;
+        }
+        self.adjust("insert latency", latency, |l| {
+            if latency > self.target_latency {
+                l.batch_size = (l.batch_size * 3 / 4).max(self.min_batch_size);
+                l.in_flight = l.in_flight.saturating_sub(1).max(1);
+            } else if full && latency < self.target_latency / 2 {
+                if l.batch_size < self.max_batch_size {
+                    l.batch_size =
+                        (l.batch_size + (l.batch_size / 4).max(1)).min(self.max_batch_size);
+                } else if l.in_flight < self.max_in_flight {
+                    l.in_flight += 1;
+                }
+            }
+        });
+    }
+
+    /// Records an insert that failed for the whole batch.
+    pub fn record_failure(&self, latency: Duration) {
+        if !self.enabled {
+            return;
+        }
+        self.adjust("insert failure", latency, |l| {
+            l.batch_size = (l.batch_size / 2).max(self.min_batch_size);
+            l.in_flight = (l.in_flight / 2).max(1);
+        });
+    }
+
+    fn adjust(&self, cause: &str, latency: Duration, update: impl FnOnce(&mut Limits)) {
+        let mut limits = self.limits.lock().expect("adaptive limits poisoned");
+        let before = *limits;
+        update(&mut limits);
+
+        if limits.batch_size != before.batch_size || limits.in_flight != before.in_flight {
+            tracing::info!(
+                cause,
+                latency_ms = latency.as_millis() as u64,
+                batch_size = limits.batch_size,
+                previous_batch_size = before.batch_size,
+                in_flight = limits.in_flight,
+                previous_in_flight = before.in_flight,
+                "adjusted batch limits"
+            );
+        }
+    }
+}
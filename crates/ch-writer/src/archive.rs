@@ -0,0 +1,475 @@
+//! Raw event archival to Parquet.
+//!
+//! When `ARCHIVE_URL` is set, every flushed batch is also written as Parquet
+//! to a local directory or an S3-compatible bucket, so raw history survives
+//! ClickHouse retention. Files are partitioned Hive-style:
+//!
+//! ```text
+//! <archive>/project_id=<uuid>/environment=<env>/date=<YYYY-MM-DD>/<millis>-<uuid>.parquet
+//! ```
+//!
+//! The date is the UTC date of `server_timestamp`. Each file holds one
+//! [`EnrichedEvent`] per row with its device context flattened and its
+//! properties as a JSON string, so [`Archiver::reimport`] rebuilds the same
+//! events and inserts them through the normal `events` row mapping.
+//!
+//! Events of users erased through admin-api are skipped on re-import, and
+//! [`Archiver::purge`] rewrites a project's files without them (see
+//! [`crate::erasure`]).
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use arrow::array::{Array, ArrayRef, RecordBatch, StringArray, TimestampMillisecondArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::TryStreamExt;
+use object_store::ObjectStore;
+use object_store::path::Path;
+use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use truesight_common::config::WriterConfig;
+use truesight_common::erasure::SuppressionList;
+use truesight_common::event::{DeviceContext, EnrichedEvent, EventType};
+use uuid::Uuid;
+
+use crate::inserter::ClickHouseInserter;
+
+/// Upload attempts per file before the partition is reported as not archived.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Base delay for exponential back-off between upload attempts.
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Columns of an archive file, in order.
+fn schema() -> SchemaRef {
+    let text = |name: &str| Field::new(name, DataType::Utf8, false);
+    let nullable_text = |name: &str| Field::new(name, DataType::Utf8, true);
+    let timestamp = |name: &str| {
+        Field::new(
+            name,
+            DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            false,
+        )
+    };
+
+    Arc::new(Schema::new(vec![
+        text("event_id"),
+        text("event_name"),
+        text("event_type"),
+        nullable_text("user_id"),
+        text("anonymous_id"),
+        nullable_text("mobile_number"),
+        nullable_text("email"),
+        nullable_text("session_id"),
+        timestamp("client_timestamp"),
+        timestamp("server_timestamp"),
+        nullable_text("properties"),
+        text("project_id"),
+        text("environment"),
+        nullable_text("app_version"),
+        text("os_name"),
+        text("os_version"),
+        text("device_model"),
+        text("device_id"),
+        nullable_text("network_type"),
+        text("locale"),
+        text("timezone"),
+        text("sdk_version"),
+        nullable_text("platform"),
+    ]))
+}
+
+/// Writes flushed batches to the archive and loads them back.
+pub struct Archiver {
+    store: Arc<dyn ObjectStore>,
+    root: Path,
+}
+
+impl Archiver {
+    /// Builds the archiver from `ARCHIVE_URL`, or returns `None` when
+    /// archival is disabled.
+    ///
+    /// `s3://bucket/prefix` uses the standard `AWS_*` credentials and
+    /// `AWS_REGION`; `ARCHIVE_S3_ENDPOINT_URL` points it at an S3-compatible
+    /// store such as MinIO or LocalStack. Anything else is a local directory
+    /// (`file:///path` or a plain path), created if missing.
+    pub fn from_config(config: &WriterConfig) -> Result<Option<Self>> {
+        let Some(url) = config.archive_url.as_deref().filter(|u| !u.is_empty()) else {
+            return Ok(None);
+        };
+
+        let (store, root) = truesight_common::storage::open(
+            url,
+            config.archive_s3_endpoint_url.as_deref(),
+            &config.aws_region,
+        )
+        .context("failed to open archive")?;
+        let archiver = Self { store, root };
+
+        tracing::info!(archive_url = url, "event archival enabled");
+        Ok(Some(archiver))
+    }
+
+    /// Archives `events`, one file per project, environment and date.
+    /// Upload failures are retried, then logged; they never fail the batch,
+    /// since the events are already in ClickHouse.
+    pub async fn archive(&self, events: &[EnrichedEvent]) {
+        let mut partitions: BTreeMap<(Uuid, &str, NaiveDate), Vec<&EnrichedEvent>> =
+            BTreeMap::new();
+        for event in events {
+            partitions
+                .entry((
+                    event.project_id,
+                    event.environment.as_str(),
+                    event.server_timestamp.date_naive(),
+                ))
+                .or_default()
+                .push(event);
+        }
+
+        for ((project_id, environment, date), events) in partitions {
+            let location = self
+                .root
+                .child(format!("project_id={project_id}"))
+                .child(format!("environment={environment}"))
+                .child(format!("date={date}"))
+                .child(format!(
+                    "{}-{}.parquet",
+                    Utc::now().timestamp_millis(),
+                    Uuid::new_v4()
+                ));
+
+            if let Err(e) = self.write_file(&location, &events).await {
+                tracing::error!(
+                    location = %location,
+                    count = events.len(),
+                    error = %format!("{e:#}"),
+                    "failed to archive events"
+                );
+            }
+        }
+    }
+
+    async fn write_file(&self, location: &Path, events: &[&EnrichedEvent]) -> Result<()> {
+        let batch = to_record_batch(events)?;
+        let bytes = tokio::task::spawn_blocking(move || encode(&batch)).await??;
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match self.store.put(location, bytes.clone().into()).await {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt < MAX_ATTEMPTS => {
+                    tracing::warn!(location = %location, attempt, error = %e, "archive upload failed, retrying");
+                    tokio::time::sleep(BASE_DELAY * 2u32.pow(attempt - 1)).await;
+                }
+                Err(e) => return Err(e).context("failed to upload archive file"),
+            }
+        }
+    }
+
+    /// Loads archived events under `prefix` (relative to the archive root,
+    /// e.g. `project_id=<uuid>/environment=live`) back into the `events`
+    /// table, except those of users in `suppression`. Files outside
+    /// `[from, to]` by their `date=` partition are skipped. Returns
+    /// `(inserted, failed)` event counts.
+    pub async fn reimport(
+        &self,
+        inserter: &ClickHouseInserter,
+        suppression: &SuppressionList,
+        prefix: &str,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        batch_size: usize,
+    ) -> Result<(usize, usize)> {
+        let mut location = self.root.clone();
+        for part in prefix.split('/').filter(|p| !p.is_empty()) {
+            location = location.child(part);
+        }
+
+        let files = self.list_files(&location, from, to).await?;
+        tracing::info!(prefix, files = files.len(), "re-importing archived events");
+
+        let (mut inserted, mut failed) = (0, 0);
+        for file in files {
+            let mut events = self.read_file(&file).await?;
+            let total = events.len();
+            events.retain(|e| !suppression.is_enriched_suppressed(e));
+            if events.len() < total {
+                tracing::info!(
+                    file = %file,
+                    skipped = total - events.len(),
+                    "skipping archived events of erased users"
+                );
+            }
+
+            for chunk in events.chunks(batch_size.max(1)) {
+                let failures = inserter
+                    .insert_batch(chunk)
+                    .await
+                    .with_context(|| format!("failed to re-import {file}"))?;
+                for (idx, reason) in &failures {
+                    tracing::error!(
+                        file = %file,
+                        event_id = %chunk[*idx].event_id,
+                        error = %reason,
+                        "failed to re-import event"
+                    );
+                }
+                failed += failures.len();
+                inserted += chunk.len() - failures.len();
+            }
+            tracing::info!(file = %file, events = events.len(), "re-imported archive file");
+        }
+
+        Ok((inserted, failed))
+    }
+
+    /// Rewrites the project's archive files without the events of users in
+    /// `suppression`, deleting files left empty. Returns the number of
+    /// events removed.
+    pub async fn purge(&self, project_id: Uuid, suppression: &SuppressionList) -> Result<usize> {
+        let location = self.root.child(format!("project_id={project_id}"));
+        let files = self.list_files(&location, None, None).await?;
+
+        let mut removed = 0;
+        for file in files {
+            let events = self.read_file(&file).await?;
+            let kept: Vec<&EnrichedEvent> = events
+                .iter()
+                .filter(|e| !suppression.is_enriched_suppressed(e))
+                .collect();
+            if kept.len() == events.len() {
+                continue;
+            }
+
+            // Files are overwritten in place, so a purge interrupted or run
+            // twice leaves no duplicates behind.
+            if kept.is_empty() {
+                self.store
+                    .delete(&file)
+                    .await
+                    .with_context(|| format!("failed to delete {file}"))?;
+            } else {
+                self.write_file(&file, &kept).await?;
+            }
+            removed += events.len() - kept.len();
+            tracing::info!(
+                file = %file,
+                removed = events.len() - kept.len(),
+                "purged events of erased users from archive file"
+            );
+        }
+        Ok(removed)
+    }
+
+    /// Parquet files under `location`, in path order, skipping those outside
+    /// `[from, to]` by their `date=` partition.
+    async fn list_files(
+        &self,
+        location: &Path,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<Path>> {
+        let mut files: Vec<Path> = self
+            .store
+            .list(Some(location))
+            .map_ok(|meta| meta.location)
+            .try_filter(|path| {
+                let keep = path.extension() == Some("parquet") && in_range(path, from, to);
+                futures::future::ready(keep)
+            })
+            .try_collect()
+            .await
+            .context("failed to list archive files")?;
+        files.sort();
+        Ok(files)
+    }
+
+    async fn read_file(&self, file: &Path) -> Result<Vec<EnrichedEvent>> {
+        let bytes = self
+            .store
+            .get(file)
+            .await
+            .with_context(|| format!("failed to open {file}"))?
+            .bytes()
+            .await
+            .with_context(|| format!("failed to read {file}"))?;
+        tokio::task::spawn_blocking(move || decode(bytes))
+            .await?
+            .with_context(|| format!("failed to decode {file}"))
+    }
+}
+
+/// Returns `true` if the path's `date=` partition is within `[from, to]`.
+/// Paths without a parseable date are kept.
+fn in_range(path: &Path, from: Option<NaiveDate>, to: Option<NaiveDate>) -> bool {
+    let date = path
+        .parts()
+        .find_map(|p| p.as_ref().strip_prefix("date=").map(str::to_string))
+        .and_then(|d| d.parse::<NaiveDate>().ok());
+    match date {
+        Some(date) => from.is_none_or(|f| date >= f) && to.is_none_or(|t| date <= t),
+        None => true,
+    }
+}
+
+fn to_record_batch(events: &[&EnrichedEvent]) -> Result<RecordBatch> {
+    let text = |f: fn(&EnrichedEvent) -> String| -> ArrayRef {
+        Arc::new(StringArray::from_iter_values(events.iter().map(|e| f(e))))
+    };
+    let nullable_text = |f: fn(&EnrichedEvent) -> Option<String>| -> ArrayRef {
+        Arc::new(events.iter().map(|e| f(e)).collect::<StringArray>())
+    };
+    let timestamp = |f: fn(&EnrichedEvent) -> DateTime<Utc>| -> ArrayRef {
+        Arc::new(
+            TimestampMillisecondArray::from_iter_values(
+                events.iter().map(|e| f(e).timestamp_millis()),
+            )
+            .with_timezone("UTC"),
+        )
+    };
+
+    let columns: Vec<ArrayRef> = vec![
+        text(|e| e.event_id.to_string()),
+        text(|e| e.event_name.clone()),
+        text(|e| event_type_str(&e.event_type)),
+        nullable_text(|e| e.user_id.clone()),
+        text(|e| e.anonymous_id.clone()),
+        nullable_text(|e| e.mobile_number.clone()),
+        nullable_text(|e| e.email.clone()),
+        nullable_text(|e| e.session_id.clone()),
+        timestamp(|e| e.client_timestamp),
+        timestamp(|e| e.server_timestamp),
+        nullable_text(|e| e.properties.as_ref().map(|p| p.to_string())),
+        text(|e| e.project_id.to_string()),
+        text(|e| e.environment.clone()),
+        nullable_text(|e| e.context.app_version.clone()),
+        text(|e| e.context.os_name.clone()),
+        text(|e| e.context.os_version.clone()),
+        text(|e| e.context.device_model.clone()),
+        text(|e| e.context.device_id.clone()),
+        nullable_text(|e| e.context.network_type.clone()),
+        text(|e| e.context.locale.clone()),
+        text(|e| e.context.timezone.clone()),
+        text(|e| e.context.sdk_version.clone()),
+        nullable_text(|e| e.context.platform.clone()),
+    ];
+
+    RecordBatch::try_new(schema(), columns).context("failed to build archive record batch")
+}
+
+fn event_type_str(event_type: &EventType) -> String {
+    serde_json::to_value(event_type)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn encode(batch: &RecordBatch) -> Result<bytes::Bytes> {
+    let props = WriterProperties::builder()
+        .set_compression(Compression::ZSTD(ZstdLevel::default()))
+        .build();
+    let mut buf = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut buf, batch.schema(), Some(props))?;
+    writer.write(batch)?;
+    writer.close()?;
+    Ok(buf.into())
+}
+
+fn decode(bytes: bytes::Bytes) -> Result<Vec<EnrichedEvent>> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(bytes)?.build()?;
+    let mut events = Vec::new();
+    for batch in reader {
+        let batch = batch?;
+        let text = |name: &str| -> Result<&StringArray> {
+            batch
+                .column_by_name(name)
+                .and_then(|c| c.as_any().downcast_ref::<StringArray>())
+                .with_context(|| format!("missing or mistyped column {name}"))
+        };
+        let timestamp = |name: &str| -> Result<&TimestampMillisecondArray> {
+            batch
+                .column_by_name(name)
+                .and_then(|c| c.as_any().downcast_ref::<TimestampMillisecondArray>())
+                .with_context(|| format!("missing or mistyped column {name}"))
+        };
+        let opt = |col: &StringArray, i: usize| (!col.is_null(i)).then(|| col.value(i).to_string());
+        let millis = |col: &TimestampMillisecondArray, i: usize| -> Result<DateTime<Utc>> {
+            DateTime::from_timestamp_millis(col.value(i)).context("timestamp out of range")
+        };
+
+        let (event_id, event_name, event_type) =
+            (text("event_id")?, text("event_name")?, text("event_type")?);
+        let (user_id, anonymous_id, mobile_number, email, session_id) = (
+            text("user_id")?,
+            text("anonymous_id")?,
+            text("mobile_number")?,
+            text("email")?,
+            text("session_id")?,
+        );
+        let (client_timestamp, server_timestamp) = (
+            timestamp("client_timestamp")?,
+            timestamp("server_timestamp")?,
+        );
+        let (properties, project_id, environment) = (
+            text("properties")?,
+            text("project_id")?,
+            text("environment")?,
+        );
+        let (app_version, os_name, os_version, device_model, device_id) = (
+            text("app_version")?,
+            text("os_name")?,
+            text("os_version")?,
+            text("device_model")?,
+            text("device_id")?,
+        );
+        let (network_type, locale, timezone, sdk_version, platform) = (
+            text("network_type")?,
+            text("locale")?,
+            text("timezone")?,
+            text("sdk_version")?,
+            text("platform")?,
+        );
+
+        for i in 0..batch.num_rows() {
+            events.push(EnrichedEvent {
+                event_id: event_id.value(i).parse()?,
+                event_name: event_name.value(i).to_string(),
+                event_type: serde_json::from_value(serde_json::Value::String(
+                    event_type.value(i).to_string(),
+                ))?,
+                user_id: opt(user_id, i),
+                anonymous_id: anonymous_id.value(i).to_string(),
+                mobile_number: opt(mobile_number, i),
+                email: opt(email, i),
+                session_id: opt(session_id, i),
+                client_timestamp: millis(client_timestamp, i)?,
+                properties: opt(properties, i)
+                    .map(|p| serde_json::from_str(&p))
+                    .transpose()?,
+                context: DeviceContext {
+                    app_version: opt(app_version, i),
+                    os_name: os_name.value(i).to_string(),
+                    os_version: os_version.value(i).to_string(),
+                    device_model: device_model.value(i).to_string(),
+                    device_id: device_id.value(i).to_string(),
+                    network_type: opt(network_type, i),
+                    locale: locale.value(i).to_string(),
+                    timezone: timezone.value(i).to_string(),
+                    sdk_version: sdk_version.value(i).to_string(),
+                    platform: opt(platform, i),
+                },
+                project_id: project_id.value(i).parse()?,
+                server_timestamp: millis(server_timestamp, i)?,
+                environment: environment.value(i).to_string(),
+            });
+        }
+    }
+    Ok(events)
+}
@@ -1,33 +1,66 @@
 //! Event batcher.
 //!
-//! Accumulates [`IncomingEvent`]s received from the consumer loops and flushes
-//! them to the [`ClickHouseInserter`] when either the batch-size threshold or
-//! the timeout interval is reached. After a successful insert the corresponding
-//! SQS messages are acknowledged (deleted). Failed batches are routed to the DLQ.
+//! Accumulates [`IncomingEvent`]s received from the consumer loops in a
+//! per-project [`FairQueue`] and flushes them to the [`ClickHouseInserter`]
+//! when either the batch-size threshold is reached or the oldest buffered
+//! event has waited for the batch timeout. Each batch is composed fairly
+//! across projects (see [`crate::fairness`]), so one tenant's backfill does
+//! not delay another's events. After the insert the corresponding SQS
+//! messages are acknowledged (deleted). Rows that ClickHouse rejected are
+//! isolated by the inserter and only those are routed to the DLQ, so a single
+//! poison event no longer dead-letters the rest of its batch; a batch that
+//! fails for any other reason is left unacknowledged for SQS to redeliver.
+//! Events of users erased through admin-api, e.g. redriven from the DLQ after
+//! the erasure, are acknowledged without being written.
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
-use tokio::sync::{Semaphore, mpsc};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
 use tracing::Instrument;
+use truesight_common::erasure::Suppression;
 use truesight_common::sqs::SqsConsumer;
 
-use crate::config::{DEFAULT_BATCH_SIZE, DEFAULT_BATCH_TIMEOUT_MS, MAX_IN_FLIGHT};
+use crate::adaptive::{AdaptiveLimits, InFlightPermit};
+use crate::archive::Archiver;
+use crate::config::DEFAULT_BATCH_TIMEOUT_MS;
 use crate::consumer::IncomingEvent;
 use crate::dlq::DlqSender;
-use crate::identity::process_identify_event;
+use crate::fairness::{FairQueue, ProjectStats};
+use crate::heartbeat::InFlightMessages;
+use crate::identity::{IdentityGraph, insert_identity_mappings};
 use crate::inserter::ClickHouseInserter;
-use crate::profiles;
+use crate::live::LivePublisher;
+use crate::profiles::ProfileWriter;
+use crate::webhooks::WebhookDispatcher;
+
+/// How often per-project buffer depth is refreshed and backlogs are logged.
+const PROJECT_STATS_INTERVAL: Duration = Duration::from_secs(10);
 
 /// Receives events from consumer loops, batches them, and flushes to ClickHouse.
 pub struct Batcher {
     receiver: mpsc::Receiver<IncomingEvent>,
+    queue: FairQueue,
+    project_stats: Arc<ProjectStats>,
+    max_buffered_events: usize,
     inserter: Arc<ClickHouseInserter>,
     sqs_consumer: Arc<SqsConsumer>,
     dlq_sender: Arc<DlqSender>,
+    identity_graph: Arc<IdentityGraph>,
+    profiles: Arc<ProfileWriter>,
+    webhooks: Option<WebhookDispatcher>,
+    suppression: Option<Suppression>,
+    archiver: Option<Arc<Archiver>>,
+    live: Option<LivePublisher>,
+    in_flight_messages: Arc<InFlightMessages>,
     queue_url: String,
     dlq_url: Option<String>,
-    batch_size: usize,
+    limits: Arc<AdaptiveLimits>,
+    /// Running flush tasks. Dropping the batcher aborts them.
+    flush_tasks: JoinSet<()>,
     batch_timeout_ms: u64,
 }
 
@@ -40,7 +73,15 @@ impl Batcher {
     /// * `dlq_sender`    - Shared DLQ sender for failed batches.
     /// * `queue_url`     - Source SQS queue URL (for message deletion).
     /// * `dlq_url`       - Dead-letter queue URL (if configured).
-    /// * `batch_size`    - Optional override of [`DEFAULT_BATCH_SIZE`].
+    /// * `webhooks`      - Webhook fan-out for inserted events (if configured).
+    /// * `suppression`   - Erased users whose events are dropped (if configured).
+    /// * `archiver`      - Parquet archive for inserted events (if configured).
+    /// * `live`          - Live-view publisher for inserted events (if configured).
+    /// * `in_flight_messages` - Registry of unacknowledged messages; acked ones are removed.
+    /// * `limits`        - Adaptive batch size and in-flight limits.
+    /// * `queue`         - Per-project buffer, with the configured project weights.
+    /// * `project_stats` - Per-project lag metrics, also served by the health endpoint.
+    /// * `max_buffered_events` - Buffered events at which reading from `receiver` pauses.
     /// * `batch_timeout_ms` - Optional override of [`DEFAULT_BATCH_TIMEOUT_MS`].
     #[allow(clippy::too_many_arguments)]
     pub fn new(
@@ -50,197 +91,378 @@ impl Batcher {
         dlq_sender: Arc<DlqSender>,
         queue_url: String,
         dlq_url: Option<String>,
-        batch_size: Option<usize>,
+        webhooks: Option<WebhookDispatcher>,
+        suppression: Option<Suppression>,
+        archiver: Option<Arc<Archiver>>,
+        live: Option<LivePublisher>,
+        in_flight_messages: Arc<InFlightMessages>,
+        limits: Arc<AdaptiveLimits>,
+        queue: FairQueue,
+        project_stats: Arc<ProjectStats>,
+        max_buffered_events: usize,
         batch_timeout_ms: Option<u64>,
     ) -> Self {
         Self {
             receiver,
+            queue,
+            project_stats,
+            max_buffered_events: max_buffered_events.max(1),
             inserter,
             sqs_consumer,
             dlq_sender,
+            identity_graph: Arc::new(IdentityGraph::new()),
+            profiles: Arc::new(ProfileWriter::new()),
+            webhooks,
+            suppression,
+            archiver,
+            live,
+            in_flight_messages,
             queue_url,
             dlq_url,
-            batch_size: batch_size.unwrap_or(DEFAULT_BATCH_SIZE),
+            limits,
+            flush_tasks: JoinSet::new(),
             batch_timeout_ms: batch_timeout_ms.unwrap_or(DEFAULT_BATCH_TIMEOUT_MS),
         }
     }
 
     /// Runs the batcher loop.
     ///
-    /// Events are accumulated in a local buffer. A flush is triggered when:
-    /// - The buffer reaches [`Self::batch_size`] events, OR
-    /// - The timeout interval ([`Self::batch_timeout_ms`]) elapses with a
-    ///   non-empty buffer.
+    /// Events are buffered per project. A batch is taken when:
+    /// - The buffer holds at least the current adaptive batch size, OR
+    /// - The oldest buffered event has waited [`Self::batch_timeout_ms`].
     ///
-    /// At most [`MAX_IN_FLIGHT`] insert tasks run concurrently. When the limit
-    /// is reached the batcher blocks until an in-flight task completes.
+    /// At most the current adaptive in-flight limit of insert tasks run
+    /// concurrently. While the limit is reached events keep being buffered,
+    /// up to `max_buffered_events`, after which the consumers are held back
+    /// by the channel.
     pub async fn run(mut self) -> Result<()> {
         tracing::info!(
-            batch_size = self.batch_size,
+            batch_size = self.limits.batch_size(),
+            max_batch_size = self.limits.max_batch_size(),
             batch_timeout_ms = self.batch_timeout_ms,
-            max_in_flight = MAX_IN_FLIGHT,
+            in_flight = self.limits.in_flight(),
+            max_in_flight = self.limits.max_in_flight(),
+            adaptive = self.limits.is_enabled(),
+            max_buffered_events = self.max_buffered_events,
             "batcher started"
         );
 
-        let in_flight = Arc::new(Semaphore::new(MAX_IN_FLIGHT));
-        let mut buffer: Vec<IncomingEvent> = Vec::with_capacity(self.batch_size);
-        let mut interval =
-            tokio::time::interval(std::time::Duration::from_millis(self.batch_timeout_ms));
+        let timeout = Duration::from_millis(self.batch_timeout_ms);
+        let mut received: Vec<IncomingEvent> = Vec::new();
+        let mut closed = false;
 
-        // The first tick completes immediately; consume it so we don't flush an
-        // empty buffer right away.
-        interval.tick().await;
+        let mut stats_interval = tokio::time::interval(PROJECT_STATS_INTERVAL);
+        stats_interval.tick().await;
 
         loop {
-            tokio::select! {
-                maybe_event = self.receiver.recv() => {
-                    match maybe_event {
-                        Some(event) => {
-                            buffer.push(event);
-                            if buffer.len() >= self.batch_size {
-                                let batch = std::mem::replace(
-                                    &mut buffer,
-                                    Vec::with_capacity(self.batch_size),
-                                );
-                                self.flush_batch(batch, &in_flight).await;
-                                // Reset the interval so we get a full timeout
-                                // window after a size-triggered flush.
-                                interval.reset();
-                            }
-                        }
-                        None => {
-                            // All senders dropped -- drain remaining buffer.
-                            tracing::info!("all consumer senders dropped, flushing remaining buffer");
-                            if !buffer.is_empty() {
-                                let batch = std::mem::take(&mut buffer);
-                                self.flush_batch(batch, &in_flight).await;
-                            }
-                            break;
-                        }
-                    }
+            while self.flush_tasks.try_join_next().is_some() {}
+
+            // Start every batch that is ready while in-flight slots are free.
+            let mut waiting_for_slot = false;
+            while !self.queue.is_empty() {
+                let batch_size = self.limits.batch_size();
+                let due = closed
+                    || self.queue.len() >= batch_size
+                    || self
+                        .queue
+                        .oldest()
+                        .is_some_and(|at| at.elapsed() >= timeout);
+                if !due {
+                    break;
                 }
-                _ = interval.tick() => {
-                    if !buffer.is_empty() {
-                        let batch = std::mem::replace(
-                            &mut buffer,
-                            Vec::with_capacity(self.batch_size),
+                let Some(permit) = self.limits.try_acquire() else {
+                    waiting_for_slot = true;
+                    break;
+                };
+                let batch = self.queue.take_batch(batch_size, &self.project_stats);
+                let full = batch.len() >= batch_size;
+                self.flush_batch(batch, full, permit);
+            }
+
+            if closed && self.queue.is_empty() {
+                break;
+            }
+
+            // With a ready batch waiting for a slot, only a released slot
+            // (or more events) can make progress, so the timer is not armed.
+            let deadline = self
+                .queue
+                .oldest()
+                .filter(|_| !waiting_for_slot)
+                .map(|at| tokio::time::Instant::from_std(at + timeout));
+            let room = self.max_buffered_events.saturating_sub(self.queue.len());
+
+            tokio::select! {
+                count = self.receiver.recv_many(&mut received, room), if !closed && room > 0 => {
+                    if count == 0 {
+                        // All senders dropped -- flush what is left.
+                        tracing::info!(
+                            buffered = self.queue.len(),
+                            "all consumer senders dropped, flushing remaining buffer"
                         );
-                        self.flush_batch(batch, &in_flight).await;
+                        closed = true;
                     }
+                    for event in received.drain(..) {
+                        self.queue.push(event);
+                    }
+                }
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)), if deadline.is_some() => {}
+                _ = self.limits.released() => {}
+                _ = stats_interval.tick() => {
+                    self.project_stats.observe_queue(&self.queue);
+                    self.project_stats.log_backlog();
                 }
             }
         }
 
         // Wait for all in-flight tasks to finish before returning.
-        let _ = in_flight.acquire_many(MAX_IN_FLIGHT as u32).await;
+        while self.flush_tasks.join_next().await.is_some() {}
+        self.project_stats.observe_queue(&self.queue);
         tracing::info!("batcher shut down");
         Ok(())
     }
 
-    /// Acquires an in-flight permit and spawns a task that inserts the batch,
-    /// handles identity events, deletes SQS messages on success, or routes to
-    /// the DLQ on failure.
-    async fn flush_batch(&self, batch: Vec<IncomingEvent>, in_flight: &Arc<Semaphore>) {
-        let permit = in_flight
-            .clone()
-            .acquire_owned()
-            .await
-            .expect("semaphore closed");
+    /// Spawns a task, holding `permit`, that inserts the batch, handles
+    /// identity events for the inserted rows, routes rejected rows to the
+    /// DLQ, records per-project lag, and deletes the SQS messages. `full`
+    /// marks a size-triggered flush, which lets the adaptive limits grow.
+    fn flush_batch(&mut self, batch: Vec<IncomingEvent>, full: bool, permit: InFlightPermit) {
+        let limits = Arc::clone(&self.limits);
+        let project_stats = Arc::clone(&self.project_stats);
 
         let inserter = Arc::clone(&self.inserter);
         let sqs_consumer = Arc::clone(&self.sqs_consumer);
         let dlq_sender = Arc::clone(&self.dlq_sender);
+        let identity_graph = Arc::clone(&self.identity_graph);
+        let profiles = Arc::clone(&self.profiles);
+        let webhooks = self.webhooks.clone();
+        let suppression = self.suppression.clone();
+        let archiver = self.archiver.clone();
+        let live = self.live.clone();
+        let in_flight_messages = Arc::clone(&self.in_flight_messages);
         let queue_url = self.queue_url.clone();
         let dlq_url = self.dlq_url.clone();
 
         let event_count = batch.len();
         let span = tracing::info_span!("flush_batch", event_count);
-        tokio::spawn(
+        self.flush_tasks.spawn(
             async move {
                 tracing::info!(count = event_count, "flushing batch");
 
+                let (batch, suppressed): (Vec<_>, Vec<_>) = match suppression {
+                    Some(ref suppression) => {
+                        let list = suppression.list();
+                        batch
+                            .into_iter()
+                            .partition(|ie| !list.is_enriched_suppressed(&ie.event))
+                    }
+                    None => (batch, Vec::new()),
+                };
+                if !suppressed.is_empty() {
+                    tracing::info!(count = suppressed.len(), "dropping events of erased users");
+                }
+
                 let events: Vec<_> = batch.iter().map(|ie| ie.event.clone()).collect();
 
-                match inserter.insert_batch(&events).await {
-                    Ok(()) => {
-                        tracing::info!(count = event_count, "batch inserted successfully");
+                let started = Instant::now();
+                let failures = match inserter.insert_batch(&events).await {
+                    Ok(failures) => failures,
+                    Err(e) => {
+                        // Not caused by the data: leave the messages for SQS
+                        // to redeliver once their visibility timeout expires,
+                        // instead of dead-lettering rows that may be fine.
+                        limits.record_failure(started.elapsed());
+                        tracing::error!(
+                            count = event_count,
+                            error = %format!("{e:#}"),
+                            "batch insert failed, leaving messages for redelivery"
+                        );
+                        leave_for_redelivery(
+                            &sqs_consumer,
+                            &queue_url,
+                            &in_flight_messages,
+                            &batch,
+                            &suppressed,
+                        )
+                        .await;
+                        drop(permit);
+                        return;
+                    }
+                };
+                if failures.is_empty() {
+                    limits.record_success(started.elapsed(), full);
+                } else if failures.len() == events.len() {
+                    limits.record_failure(started.elapsed());
+                }
+
+                // Projects with rows rejected from an otherwise accepted batch
+                // are isolated; a batch failing as a whole is more likely an
+                // outage than poison data.
+                let failed_projects: Vec<_> = failures
+                    .iter()
+                    .map(|(idx, _)| events[*idx].project_id)
+                    .collect();
+                project_stats.record_insert(
+                    &batch,
+                    &failed_projects,
+                    failures.len() < events.len(),
+                );
 
-                        // Process identify events for identity resolution.
-                        for event in &events {
-                            if let Err(e) = process_identify_event(inserter.client(), event).await {
+                // Final DLQ reason per batch index. An event can fail in more
+                // than one table; the reasons are joined.
+                let mut dlq_reasons: BTreeMap<usize, String> = failures
+                    .iter()
+                    .map(|(idx, reason)| (*idx, format!("insert failure: {reason}")))
+                    .collect();
+
+                let inserted_idx: Vec<usize> = (0..events.len())
+                    .filter(|i| !dlq_reasons.contains_key(i))
+                    .collect();
+                let inserted: Vec<_> = inserted_idx.iter().map(|&i| events[i].clone()).collect();
+
+                if failures.is_empty() {
+                    tracing::info!(count = event_count, "batch inserted successfully");
+                } else {
+                    tracing::error!(
+                        count = event_count,
+                        inserted = inserted.len(),
+                        failed = failures.len(),
+                        "batch insert failed for some events"
+                    );
+                }
+
+                if !inserted.is_empty() {
+                    // Upsert identity mappings, the identity graph and user
+                    // profiles for the inserted events.
+                    let identity_failures =
+                        match insert_identity_mappings(&inserter, &inserted).await {
+                            Ok(failures) => failures,
+                            Err(e) => {
+                                // As above. The events are already written; their
+                                // rows collapse with the redelivered ones on merge,
+                                // and the graph and profiles are updated then.
                                 tracing::error!(
-                                    error = %e,
-                                    event_id = %event.event_id,
-                                    "failed to process identify event"
+                                    count = event_count,
+                                    error = %format!("{e:#}"),
+                                    "identity_map insert failed, leaving messages for redelivery"
                                 );
+                                leave_for_redelivery(
+                                    &sqs_consumer,
+                                    &queue_url,
+                                    &in_flight_messages,
+                                    &batch,
+                                    &suppressed,
+                                )
+                                .await;
+                                drop(permit);
+                                return;
                             }
-                        }
-
-                        // Upsert user profiles.
-                        if let Err(e) = profiles::upsert_profiles(inserter.client(), &events).await
-                        {
-                            tracing::error!(error = %e, "failed to upsert user profiles");
-                        }
-
-                        // Delete successfully processed messages from SQS.
-                        let entries: Vec<(String, String)> = batch
-                            .iter()
-                            .enumerate()
-                            .map(|(i, ie)| (format!("del_{i}"), ie.receipt_handle.clone()))
-                            .collect();
+                        };
+                    let graph_failures = identity_graph.update(&inserter, &inserted).await;
+                    let profile_failures = profiles.upsert(&inserter, &inserted).await;
 
-                        if let Err(e) = sqs_consumer.delete_message_batch(&queue_url, entries).await
-                        {
+                    for (table, table_failures) in [
+                        ("identity_map", identity_failures),
+                        ("identity_graph", graph_failures),
+                        ("user_profiles", profile_failures),
+                    ] {
+                        if !table_failures.is_empty() {
                             tracing::error!(
-                                error = %e,
-                                "failed to delete SQS messages after successful insert"
+                                table,
+                                failed = table_failures.len(),
+                                "failed to write rows derived from inserted events"
                             );
                         }
+                        for (idx, reason) in table_failures {
+                            let reason = format!("{table} insert failure: {reason}");
+                            dlq_reasons
+                                .entry(inserted_idx[idx])
+                                .and_modify(|r| {
+                                    r.push_str("; ");
+                                    r.push_str(&reason);
+                                })
+                                .or_insert(reason);
+                        }
                     }
-                    Err(e) => {
-                        tracing::error!(
-                            error = %e,
-                            count = event_count,
-                            "batch insert failed after retries"
-                        );
 
-                        // Route each event to DLQ if configured.
-                        if let Some(ref dlq_url) = dlq_url {
-                            for incoming in &batch {
-                                if let Err(dlq_err) = dlq_sender
-                                    .send_to_dlq(
-                                        dlq_url,
-                                        &incoming.raw_body,
-                                        &format!("insert failure: {e}"),
-                                    )
-                                    .await
-                                {
-                                    tracing::error!(error = %dlq_err, "failed to send to DLQ");
-                                }
-                            }
-                        }
+                    if let Some(ref archiver) = archiver {
+                        archiver.archive(&inserted).await;
+                    }
 
-                        // Delete from source queue to avoid infinite reprocessing.
-                        let entries: Vec<(String, String)> = batch
-                            .iter()
-                            .enumerate()
-                            .map(|(i, ie)| (format!("del_{i}"), ie.receipt_handle.clone()))
-                            .collect();
+                    if let Some(ref live) = live {
+                        live.publish(&inserted);
+                    }
+
+                    // Fan out the stored events to matching webhook
+                    // destinations.
+                    if let Some(ref webhooks) = webhooks {
+                        webhooks.dispatch(&inserted);
+                    }
+                }
 
-                        if let Err(del_err) =
-                            sqs_consumer.delete_message_batch(&queue_url, entries).await
+                // Route each rejected event to the DLQ if configured, with the
+                // ClickHouse error that rejected it. Redriving an event whose
+                // `events` row was already written is harmless: the re-inserted
+                // row has the same sorting key and collapses on merge.
+                if let Some(ref dlq_url) = dlq_url {
+                    for (idx, reason) in &dlq_reasons {
+                        let incoming = &batch[*idx];
+                        tracing::warn!(
+                            event_id = %incoming.event.event_id,
+                            error = %reason,
+                            "routing rejected event to DLQ"
+                        );
+                        if let Err(dlq_err) = dlq_sender
+                            .send_to_dlq(dlq_url, &incoming.raw_body, reason)
+                            .await
                         {
-                            tracing::error!(
-                                error = %del_err,
-                                "failed to delete SQS messages after DLQ routing"
-                            );
+                            tracing::error!(error = %dlq_err, "failed to send to DLQ");
                         }
                     }
                 }
 
+                // Delete every message from the source queue: inserted ones are
+                // done, rejected ones are either in the DLQ or would only fail
+                // again if reprocessed, and suppressed ones must not be written.
+                let entries: Vec<(String, String)> = batch
+                    .iter()
+                    .chain(&suppressed)
+                    .enumerate()
+                    .map(|(i, ie)| (format!("del_{i}"), ie.receipt_handle.clone()))
+                    .collect();
+
+                if let Err(e) = sqs_consumer.delete_message_batch(&queue_url, entries).await {
+                    tracing::error!(error = %e, "failed to delete SQS messages after insert");
+                }
+                in_flight_messages
+                    .untrack(batch.iter().chain(&suppressed).map(|ie| &ie.receipt_handle));
+
                 drop(permit);
             }
             .instrument(span),
         );
     }
 }
+
+/// Leaves a batch that failed for reasons other than its data for SQS to
+/// redeliver once the visibility timeout expires. Only the messages of erased
+/// users are deleted, as they must not be written.
+async fn leave_for_redelivery(
+    sqs_consumer: &SqsConsumer,
+    queue_url: &str,
+    in_flight_messages: &InFlightMessages,
+    batch: &[IncomingEvent],
+    suppressed: &[IncomingEvent],
+) {
+    if !suppressed.is_empty() {
+        let entries: Vec<(String, String)> = suppressed
+            .iter()
+            .enumerate()
+            .map(|(i, ie)| (format!("del_{i}"), ie.receipt_handle.clone()))
+            .collect();
+        if let Err(e) = sqs_consumer.delete_message_batch(queue_url, entries).await {
+            tracing::error!(error = %e, "failed to delete SQS messages");
+        }
+    }
+    in_flight_messages.untrack(batch.iter().chain(suppressed).map(|ie| &ie.receipt_handle));
+}
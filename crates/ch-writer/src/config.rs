@@ -6,13 +6,11 @@
 
 pub use truesight_common::config::WriterConfig;
 
-/// Default maximum number of events accumulated before a batch flush is triggered.
-pub const DEFAULT_BATCH_SIZE: usize = 5000;
-
 /// Default timeout in milliseconds before a partial batch is flushed regardless of size.
 pub const DEFAULT_BATCH_TIMEOUT_MS: u64 = 2000;
 
-/// Maximum number of concurrent in-flight insert batches. When this limit is
-/// reached the batcher will back-pressure, waiting for an outstanding insert to
-/// complete before sending the next batch to ClickHouse.
-pub const MAX_IN_FLIGHT: usize = 3;
+/// Starting number of concurrent in-flight insert batches. The adaptive limits
+/// move it between 1 and `CH_MAX_IN_FLIGHT`; when it is reached the batcher
+/// back-pressures, waiting for an outstanding insert to complete before
+/// sending the next batch to ClickHouse.
+pub const DEFAULT_IN_FLIGHT: usize = 3;
@@ -4,12 +4,15 @@
 //! [`EnrichedEvent`]s, and forwards them through a `tokio::mpsc` channel to the
 //! batcher. On deserialisation failure the raw message body is sent to the DLQ.
 
+use std::sync::Arc;
+
 use anyhow::Result;
 use tokio::sync::mpsc;
 use truesight_common::event::EnrichedEvent;
 use truesight_common::sqs::SqsConsumer;
 
 use crate::dlq::DlqSender;
+use crate::heartbeat::InFlightMessages;
 
 /// A message that has been successfully deserialised, carrying the original SQS
 /// receipt handle so that the batcher can acknowledge it after a successful
@@ -33,6 +36,8 @@ pub struct ConsumerLoop {
     dlq_sender: DlqSender,
     dlq_url: Option<String>,
     receive_batch_size: i32,
+    visibility_timeout_secs: i32,
+    in_flight_messages: Arc<InFlightMessages>,
 }
 
 impl ConsumerLoop {
@@ -44,6 +49,11 @@ impl ConsumerLoop {
     /// * `dlq_sender`         - Client for sending failed messages to the DLQ.
     /// * `dlq_url`            - URL of the dead-letter queue (if configured).
     /// * `receive_batch_size` - Maximum number of messages per `ReceiveMessage` call.
+    /// * `visibility_timeout_secs` - How long received messages stay invisible
+    ///   before the heartbeat first extends them.
+    /// * `in_flight_messages` - Registry of unacknowledged messages kept invisible
+    ///   by the visibility heartbeat.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         consumer: SqsConsumer,
         queue_url: String,
@@ -51,6 +61,8 @@ impl ConsumerLoop {
         dlq_sender: DlqSender,
         dlq_url: Option<String>,
         receive_batch_size: i32,
+        visibility_timeout_secs: i32,
+        in_flight_messages: Arc<InFlightMessages>,
     ) -> Self {
         Self {
             consumer,
@@ -59,6 +71,8 @@ impl ConsumerLoop {
             dlq_sender,
             dlq_url,
             receive_batch_size,
+            visibility_timeout_secs,
+            in_flight_messages,
         }
     }
 
@@ -80,7 +94,12 @@ impl ConsumerLoop {
 
             let messages = match self
                 .consumer
-                .receive_messages(&self.queue_url, self.receive_batch_size, 20)
+                .receive_messages(
+                    &self.queue_url,
+                    self.receive_batch_size,
+                    20,
+                    self.visibility_timeout_secs,
+                )
                 .await
             {
                 Ok(msgs) => msgs,
@@ -116,6 +135,7 @@ impl ConsumerLoop {
 
                 match serde_json::from_str::<EnrichedEvent>(&body) {
                     Ok(event) => {
+                        self.in_flight_messages.track(&receipt_handle);
                         let incoming = IncomingEvent {
                             event,
                             receipt_handle,
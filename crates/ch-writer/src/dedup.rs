@@ -15,6 +15,10 @@
 //! **no runtime deduplication logic is required in the writer**. The writer simply
 //! inserts every event it receives; duplicates (e.g. from SQS at-least-once
 //! delivery) are naturally collapsed by ClickHouse.
+//!
+//! Most duplicates never reach the writer: ingestion-api drops SDK retries of
+//! an already-accepted `(project_id, event_id)` within a short window before
+//! enqueueing. Merge-time dedup remains the safety net for the rest.
 
 /// Returns a human-readable explanation of the deduplication strategy.
 ///
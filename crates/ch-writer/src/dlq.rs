@@ -2,7 +2,7 @@
 //!
 //! When an event cannot be inserted into ClickHouse after exhausting retries the
 //! original SQS message body is forwarded to a DLQ so it can be investigated and
-//! replayed later.
+//! replayed later (admin-api `/v1/dlq`, or `truesight dlq` from the CLI).
 
 use anyhow::{Context, Result};
 use aws_sdk_sqs::Client;
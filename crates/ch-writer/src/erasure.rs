@@ -0,0 +1,116 @@
+//! Purging erased users from the Parquet archive.
+//!
+//! admin-api erases a user's rows from ClickHouse and Postgres but has no
+//! access to the archive. When archival is enabled, the [`ArchivePurger`]
+//! polls for completed erasure requests whose `archive_purged_at` is unset,
+//! rewrites the archive files of their projects without the events of any
+//! suppressed user (see [`Archiver::purge`]) and marks the requests purged.
+//!
+//! Several writers may purge the same project at once. Files are overwritten
+//! in place, so that only costs duplicate work.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use diesel::prelude::*;
+use truesight_common::db::{DbPool, get_conn};
+use truesight_common::erasure::{STATUS_COMPLETED, SuppressionList};
+use truesight_common::schema::erasure_requests;
+use uuid::Uuid;
+
+use crate::archive::Archiver;
+
+/// How often completed erasures are checked for a pending archive purge.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Background task removing erased users' events from the archive.
+pub struct ArchivePurger {
+    archiver: Arc<Archiver>,
+    pool: DbPool,
+    hash_key: String,
+}
+
+impl ArchivePurger {
+    pub fn new(archiver: Arc<Archiver>, pool: DbPool, hash_key: String) -> Self {
+        Self {
+            archiver,
+            pool,
+            hash_key,
+        }
+    }
+
+    /// Runs until the task is aborted. An interrupted purge is redone on the
+    /// next start, as requests are only marked once their project is purged.
+    pub async fn run(self) {
+        let mut ticker = tokio::time::interval(POLL_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.purge_pending().await {
+                tracing::error!(error = %format!("{e:#}"), "failed to purge erased users from archive");
+            }
+        }
+    }
+
+    async fn purge_pending(&self) -> Result<()> {
+        let (pool, hash_key) = (self.pool.clone(), self.hash_key.clone());
+        let pending = tokio::task::spawn_blocking(move || -> Result<_> {
+            let mut conn = get_conn(&pool)?;
+            let pending = erasure_requests::table
+                .filter(erasure_requests::status.eq(STATUS_COMPLETED))
+                .filter(erasure_requests::archive_purged_at.is_null())
+                .select((erasure_requests::project_id, erasure_requests::id))
+                .load::<(Uuid, Uuid)>(&mut conn)?;
+            if pending.is_empty() {
+                return Ok(None);
+            }
+            // Loaded after the requests, so it holds every identifier they
+            // suppressed.
+            Ok(Some((pending, SuppressionList::load(&pool, &hash_key)?)))
+        })
+        .await??;
+        let Some((pending, suppression)) = pending else {
+            return Ok(());
+        };
+
+        let mut by_project: BTreeMap<Uuid, Vec<Uuid>> = BTreeMap::new();
+        for (project_id, erasure_id) in pending {
+            by_project.entry(project_id).or_default().push(erasure_id);
+        }
+
+        for (project_id, erasure_ids) in by_project {
+            let removed = match self.archiver.purge(project_id, &suppression).await {
+                Ok(removed) => removed,
+                Err(e) => {
+                    tracing::error!(
+                        project_id = %project_id,
+                        error = %format!("{e:#}"),
+                        "failed to purge archive of project"
+                    );
+                    continue;
+                }
+            };
+
+            let pool = self.pool.clone();
+            let ids = erasure_ids.clone();
+            tokio::task::spawn_blocking(move || -> Result<()> {
+                let mut conn = get_conn(&pool)?;
+                diesel::update(erasure_requests::table.filter(erasure_requests::id.eq_any(ids)))
+                    .set(erasure_requests::archive_purged_at.eq(Utc::now()))
+                    .execute(&mut conn)?;
+                Ok(())
+            })
+            .await??;
+            tracing::info!(
+                project_id = %project_id,
+                erasures = erasure_ids.len(),
+                removed,
+                "purged erased users from archive"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,337 @@
+//! Per-project fair batching.
+//!
+//! The batcher buffers events per project in a [`FairQueue`] and composes
+//! each batch by deficit round-robin: every backlogged project in turn may
+//! add up to [`QUANTUM`] × its weight events, so a project with a large
+//! backlog fills the remaining space but cannot push a small project's
+//! events out of the next batch. Weights default to 1 and are set with
+//! `CH_PROJECT_WEIGHTS=<project_id>=<weight>,...`.
+//!
+//! A project whose rows ClickHouse rejects is isolated for
+//! [`ISOLATION_PERIOD`]: its events are flushed in batches of their own, so
+//! bisecting its poison rows does not hold back other projects.
+//!
+//! [`ProjectStats`] tracks per-project buffer depth and insert lag, served as
+//! JSON on the health port at `GET /metrics/projects`.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::consumer::IncomingEvent;
+
+/// Events a project of weight 1 may add to a batch per round.
+const QUANTUM: usize = 100;
+
+/// How long a project stays isolated after one of its rows was rejected.
+const ISOLATION_PERIOD: Duration = Duration::from_secs(300);
+
+/// Parses `CH_PROJECT_WEIGHTS` (`<project_id>=<weight>,...`).
+pub fn parse_project_weights(spec: &str) -> Result<HashMap<Uuid, usize>> {
+    let mut weights = HashMap::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (project, weight) = entry
+            .split_once('=')
+            .with_context(|| format!("invalid CH_PROJECT_WEIGHTS entry '{entry}'"))?;
+        let project: Uuid = project
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid project ID in CH_PROJECT_WEIGHTS entry '{entry}'"))?;
+        let weight: usize = weight
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid weight in CH_PROJECT_WEIGHTS entry '{entry}'"))?;
+        if weight == 0 {
+            bail!("CH_PROJECT_WEIGHTS weight must be at least 1 in '{entry}'");
+        }
+        weights.insert(project, weight);
+    }
+    Ok(weights)
+}
+
+struct ProjectQueue {
+    events: VecDeque<(Instant, IncomingEvent)>,
+    deficit: usize,
+}
+
+/// Per-project event buffers drained by deficit round-robin.
+pub struct FairQueue {
+    projects: HashMap<Uuid, ProjectQueue>,
+    /// Backlogged projects in round-robin order.
+    order: VecDeque<Uuid>,
+    weights: HashMap<Uuid, usize>,
+    len: usize,
+}
+
+impl FairQueue {
+    pub fn new(weights: HashMap<Uuid, usize>) -> Self {
+        Self {
+            projects: HashMap::new(),
+            order: VecDeque::new(),
+            weights,
+            len: 0,
+        }
+    }
+
+    /// Total number of buffered events.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, event: IncomingEvent) {
+        let project_id = event.event.project_id;
+        let queue = self.projects.entry(project_id).or_insert_with(|| {
+            self.order.push_back(project_id);
+            ProjectQueue {
+                events: VecDeque::new(),
+                deficit: 0,
+            }
+        });
+        queue.events.push_back((Instant::now(), event));
+        self.len += 1;
+    }
+
+    /// When the longest-waiting buffered event was received.
+    pub fn oldest(&self) -> Option<Instant> {
+        self.projects
+            .values()
+            .filter_map(|q| q.events.front().map(|(at, _)| *at))
+            .min()
+    }
+
+    /// Takes up to `max` events. If the next project in turn is isolated the
+    /// batch holds only its events; otherwise isolated projects are skipped.
+    pub fn take_batch(&mut self, max: usize, stats: &ProjectStats) -> Vec<IncomingEvent> {
+        let mut batch = Vec::with_capacity(max.min(self.len));
+
+        if let Some(&first) = self.order.front()
+            && stats.is_isolated(first)
+        {
+            self.order.pop_front();
+            self.take_from(first, usize::MAX, max, &mut batch);
+            return batch;
+        }
+
+        let mut remaining_turns = self.order.len();
+        while batch.len() < max && remaining_turns > 0 {
+            let Some(project_id) = self.order.pop_front() else {
+                break;
+            };
+            remaining_turns -= 1;
+
+            if stats.is_isolated(project_id) {
+                self.order.push_back(project_id);
+                continue;
+            }
+
+            let weight = self.weights.get(&project_id).copied().unwrap_or(1);
+            let Some(queue) = self.projects.get_mut(&project_id) else {
+                continue;
+            };
+            queue.deficit += QUANTUM * weight;
+            let allowance = queue.deficit;
+            self.take_from(project_id, allowance, max, &mut batch);
+
+            // Another round if everyone had their turn and there is room.
+            if remaining_turns == 0 && batch.len() < max {
+                remaining_turns = self
+                    .order
+                    .iter()
+                    .filter(|p| !stats.is_isolated(**p))
+                    .count();
+            }
+        }
+
+        batch
+    }
+
+    /// Moves up to `allowance` events of `project_id` into `batch` and
+    /// re-queues the project if it still has events. An emptied project
+    /// leaves the rotation and forfeits its unused deficit.
+    fn take_from(
+        &mut self,
+        project_id: Uuid,
+        allowance: usize,
+        max: usize,
+        batch: &mut Vec<IncomingEvent>,
+    ) {
+        let Some(queue) = self.projects.get_mut(&project_id) else {
+            return;
+        };
+
+        let take = allowance.min(queue.events.len()).min(max - batch.len());
+        batch.extend(queue.events.drain(..take).map(|(_, e)| e));
+        queue.deficit = queue.deficit.saturating_sub(take);
+        self.len -= take;
+
+        if queue.events.is_empty() {
+            self.projects.remove(&project_id);
+        } else {
+            self.order.push_back(project_id);
+        }
+    }
+
+    /// Buffered event count and oldest receive time per project.
+    fn depths(&self) -> Vec<(Uuid, usize, Option<Instant>)> {
+        self.projects
+            .iter()
+            .map(|(id, q)| (*id, q.events.len(), q.events.front().map(|(at, _)| *at)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProjectLag {
+    pub project_id: Uuid,
+    /// Events waiting in the batcher.
+    pub buffered: usize,
+    /// How long the oldest buffered event has waited.
+    pub oldest_buffered_ms: u64,
+    /// Server-receive to insert lag of the oldest event in the last batch.
+    pub last_insert_lag_ms: u64,
+    pub last_insert_at: Option<DateTime<Utc>>,
+    pub inserted: u64,
+    /// Events not inserted, rejected or lost to failed inserts.
+    pub failed: u64,
+    /// Set while the project is flushed in batches of its own.
+    pub isolated_until: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    isolated_deadline: Option<Instant>,
+}
+
+/// Per-project lag metrics shared by the batcher, its flush tasks and the
+/// health endpoint.
+#[derive(Default)]
+pub struct ProjectStats {
+    projects: Mutex<HashMap<Uuid, ProjectLag>>,
+}
+
+impl ProjectStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, ProjectLag>> {
+        self.projects.lock().expect("project stats poisoned")
+    }
+
+    fn entry(projects: &mut HashMap<Uuid, ProjectLag>, project_id: Uuid) -> &mut ProjectLag {
+        projects.entry(project_id).or_insert_with(|| ProjectLag {
+            project_id,
+            ..ProjectLag::default()
+        })
+    }
+
+    /// Refreshes buffer depth and age from the batcher's queue.
+    pub fn observe_queue(&self, queue: &FairQueue) {
+        let now = Instant::now();
+        let mut projects = self.lock();
+        for lag in projects.values_mut() {
+            lag.buffered = 0;
+            lag.oldest_buffered_ms = 0;
+        }
+        for (project_id, buffered, oldest) in queue.depths() {
+            let lag = Self::entry(&mut projects, project_id);
+            lag.buffered = buffered;
+            lag.oldest_buffered_ms = oldest
+                .map(|at| now.duration_since(at).as_millis() as u64)
+                .unwrap_or(0);
+        }
+    }
+
+    /// Records the outcome of a flush for each project in it. `failed` holds
+    /// the project of every row that was not inserted; with `isolate`, those
+    /// projects are flushed on their own for [`ISOLATION_PERIOD`].
+    pub fn record_insert(&self, batch: &[IncomingEvent], failed: &[Uuid], isolate: bool) {
+        let now = Utc::now();
+        let mut oldest: HashMap<Uuid, (DateTime<Utc>, u64)> = HashMap::new();
+        for incoming in batch {
+            let entry = oldest
+                .entry(incoming.event.project_id)
+                .or_insert((incoming.event.server_timestamp, 0));
+            entry.0 = entry.0.min(incoming.event.server_timestamp);
+            entry.1 += 1;
+        }
+
+        let mut projects = self.lock();
+        for (project_id, (server_timestamp, count)) in oldest {
+            let failed = failed.iter().filter(|p| **p == project_id).count() as u64;
+            let lag = Self::entry(&mut projects, project_id);
+            if count > failed {
+                lag.last_insert_lag_ms = (now - server_timestamp).num_milliseconds().max(0) as u64;
+                lag.last_insert_at = Some(now);
+            }
+            lag.inserted += count - failed;
+            lag.failed += failed;
+            if isolate && failed > 0 {
+                if lag.isolated_deadline.is_none() {
+                    tracing::warn!(
+                        project_id = %project_id,
+                        rejected = failed,
+                        "isolating project after rejected rows"
+                    );
+                }
+                lag.isolated_deadline = Some(Instant::now() + ISOLATION_PERIOD);
+                lag.isolated_until = Some(now + ISOLATION_PERIOD);
+            }
+        }
+    }
+
+    /// Whether the project is currently flushed in batches of its own.
+    pub fn is_isolated(&self, project_id: Uuid) -> bool {
+        let mut projects = self.lock();
+        let Some(lag) = projects.get_mut(&project_id) else {
+            return false;
+        };
+        match lag.isolated_deadline {
+            Some(deadline) if deadline > Instant::now() => true,
+            Some(_) => {
+                lag.isolated_deadline = None;
+                lag.isolated_until = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Logs projects with buffered events, most delayed first.
+    pub fn log_backlog(&self) {
+        let mut backlogged: Vec<ProjectLag> = self
+            .lock()
+            .values()
+            .filter(|l| l.buffered > 0)
+            .cloned()
+            .collect();
+        backlogged.sort_by(|a, b| b.oldest_buffered_ms.cmp(&a.oldest_buffered_ms));
+        for lag in backlogged {
+            tracing::info!(
+                project_id = %lag.project_id,
+                buffered = lag.buffered,
+                oldest_buffered_ms = lag.oldest_buffered_ms,
+                last_insert_lag_ms = lag.last_insert_lag_ms,
+                isolated = lag.isolated_until.is_some(),
+                "project backlog"
+            );
+        }
+    }
+
+    /// All tracked projects, most delayed first.
+    pub fn snapshot(&self) -> Vec<ProjectLag> {
+        let mut lags: Vec<ProjectLag> = self.lock().values().cloned().collect();
+        lags.sort_by(|a, b| {
+            b.oldest_buffered_ms
+                .cmp(&a.oldest_buffered_ms)
+                .then(b.last_insert_lag_ms.cmp(&a.last_insert_lag_ms))
+        });
+        lags
+    }
+}
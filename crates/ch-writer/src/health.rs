@@ -1,18 +1,43 @@
 //! Minimal HTTP health-check endpoint.
 //!
 //! Exposes `GET /health` on port 9090 so that container orchestrators (ECS,
-//! Kubernetes) can probe liveness.
+//! Kubernetes) can probe liveness, along with the number of events dropped
+//! from webhook fan-out, and `GET /metrics/projects` with the per-project
+//! buffer depth and insert lag.
 
-use axum::{Json, Router, routing::get};
+use std::sync::Arc;
+
+use axum::{Json, Router, extract::State, routing::get};
 use serde_json::{Value, json};
 
-/// Returns a configured [`Router`] with the health endpoint.
-pub fn health_router() -> Router {
-    Router::new().route("/health", get(health_handler))
+use crate::fairness::{ProjectLag, ProjectStats};
+use crate::webhooks::WebhookStats;
+
+#[derive(Clone)]
+struct HealthState {
+    project_stats: Arc<ProjectStats>,
+    /// `None` when webhook fan-out is disabled.
+    webhooks: Option<WebhookStats>,
+}
+
+/// Returns a configured [`Router`] with the health and lag endpoints.
+pub fn health_router(project_stats: Arc<ProjectStats>, webhooks: Option<WebhookStats>) -> Router {
+    Router::new()
+        .route("/health", get(health_handler))
+        .route("/metrics/projects", get(project_metrics_handler))
+        .with_state(HealthState {
+            project_stats,
+            webhooks,
+        })
+}
+
+async fn health_handler(State(state): State<HealthState>) -> Json<Value> {
+    let webhooks = state.webhooks.as_ref().map(WebhookStats::snapshot);
+    Json(json!({ "status": "healthy", "webhooks": webhooks }))
 }
 
-async fn health_handler() -> Json<Value> {
-    Json(json!({ "status": "healthy" }))
+async fn project_metrics_handler(State(state): State<HealthState>) -> Json<Vec<ProjectLag>> {
+    Json(state.project_stats.snapshot())
 }
 
 /// Starts the health HTTP server on the given port.
@@ -21,9 +46,11 @@ async fn health_handler() -> Json<Value> {
 /// the caller to tie it into the global graceful-shutdown mechanism.
 pub async fn serve_health(
     port: u16,
+    project_stats: Arc<ProjectStats>,
+    webhooks: Option<WebhookStats>,
     shutdown: impl std::future::Future<Output = ()> + Send + 'static,
 ) {
-    let app = health_router();
+    let app = health_router(project_stats, webhooks);
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{port}"))
         .await
         .expect("failed to bind health endpoint");
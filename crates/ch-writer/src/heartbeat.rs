@@ -0,0 +1,167 @@
+//! SQS visibility heartbeats for in-flight messages.
+//!
+//! A message stays invisible to other consumers only for the queue's
+//! visibility timeout. Between being received and being acknowledged it can
+//! wait in the batcher buffer, behind the in-flight limit, and through insert
+//! retries, which together can outlast that timeout and get the message
+//! consumed twice.
+//!
+//! Consumer loops register each received message in [`InFlightMessages`] and
+//! the batcher removes it once the batch is acknowledged. The [`Heartbeat`]
+//! task extends the visibility of every registered message to
+//! `SQS_VISIBILITY_TIMEOUT_SECS` every third of that timeout. On shutdown it
+//! makes whatever is still registered visible again immediately, so another
+//! writer picks it up without waiting for the timeout.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::watch;
+use truesight_common::sqs::SqsConsumer;
+
+/// Receipt handles of messages received but not yet acknowledged.
+#[derive(Default)]
+pub struct InFlightMessages {
+    handles: Mutex<HashSet<String>>,
+}
+
+impl InFlightMessages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a received message.
+    pub fn track(&self, receipt_handle: &str) {
+        self.lock().insert(receipt_handle.to_string());
+    }
+
+    /// Unregisters messages once they are deleted, or once deleting them was
+    /// attempted: a message whose delete failed must be allowed to reappear.
+    pub fn untrack<'a>(&self, receipt_handles: impl IntoIterator<Item = &'a String>) {
+        let mut handles = self.lock();
+        for handle in receipt_handles {
+            handles.remove(handle);
+        }
+    }
+
+    /// Number of messages registered.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn snapshot(&self) -> Vec<String> {
+        self.lock().iter().cloned().collect()
+    }
+
+    fn take_all(&self) -> Vec<String> {
+        self.lock().drain().collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.handles.lock().expect("in-flight messages poisoned")
+    }
+}
+
+/// Background task that keeps in-flight messages invisible.
+pub struct Heartbeat {
+    sqs: Arc<SqsConsumer>,
+    queue_url: String,
+    messages: Arc<InFlightMessages>,
+    visibility_timeout_secs: i32,
+}
+
+impl Heartbeat {
+    pub fn new(
+        sqs: Arc<SqsConsumer>,
+        queue_url: String,
+        messages: Arc<InFlightMessages>,
+        visibility_timeout_secs: i32,
+    ) -> Self {
+        Self {
+            sqs,
+            queue_url,
+            messages,
+            visibility_timeout_secs: visibility_timeout_secs.max(3),
+        }
+    }
+
+    /// Timeout messages are kept invisible for, from receipt and on every
+    /// extension.
+    pub fn visibility_timeout_secs(&self) -> i32 {
+        self.visibility_timeout_secs
+    }
+
+    /// Extends visibility until `shutdown` flips to `true`, then releases
+    /// every message still registered.
+    pub async fn run(self, mut shutdown: watch::Receiver<bool>) {
+        let period = Duration::from_secs((self.visibility_timeout_secs / 3) as u64);
+        tracing::info!(
+            visibility_timeout_secs = self.visibility_timeout_secs,
+            period_secs = period.as_secs(),
+            "visibility heartbeat started"
+        );
+
+        let mut interval = tokio::time::interval(period);
+        interval.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => self.extend().await,
+                _ = shutdown.changed() => break,
+            }
+        }
+
+        self.release().await;
+        tracing::info!("visibility heartbeat shut down");
+    }
+
+    async fn extend(&self) {
+        let handles = self.messages.snapshot();
+        if handles.is_empty() {
+            return;
+        }
+
+        match self
+            .sqs
+            .change_visibility(&self.queue_url, &handles, self.visibility_timeout_secs)
+            .await
+        {
+            Ok(rejected) => {
+                tracing::debug!(count = handles.len(), "extended message visibility");
+                if !rejected.is_empty() {
+                    // Deleted in the meantime, or the receipt already expired
+                    // and the message was redelivered: stop extending it.
+                    tracing::warn!(
+                        count = rejected.len(),
+                        "SQS rejected visibility extension, no longer tracking"
+                    );
+                    self.messages.untrack(&rejected);
+                }
+            }
+            Err(e) => {
+                tracing::error!(count = handles.len(), error = %format!("{e:#}"), "failed to extend message visibility");
+            }
+        }
+    }
+
+    async fn release(&self) {
+        let handles = self.messages.take_all();
+        if handles.is_empty() {
+            return;
+        }
+
+        match self
+            .sqs
+            .change_visibility(&self.queue_url, &handles, 0)
+            .await
+        {
+            Ok(_) => tracing::info!(count = handles.len(), "released unprocessed messages"),
+            Err(e) => tracing::error!(
+                count = handles.len(),
+                error = %format!("{e:#}"),
+                "failed to release unprocessed messages"
+            ),
+        }
+    }
+}
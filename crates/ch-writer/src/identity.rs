@@ -4,80 +4,415 @@
 //! between `anonymous_id` and `user_id` in the ClickHouse `identity_map`
 //! table. This allows downstream queries to stitch sessions across identified
 //! and anonymous activity.
+//!
+//! Mappings for a whole batch are written as typed rows in a single insert,
+//! with the same retry and poison-row isolation as the events insert.
+//!
+//! On top of the raw mappings, [`IdentityGraph`] maintains the
+//! `identity_graph` table: every distinct ID (anonymous or known) linked by
+//! `Identify` or `Alias` events is assigned the canonical `person_id` of its
+//! connected component. Admin queries resolve users through this table.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use anyhow::{Context, Result};
-use truesight_common::event::{EnrichedEvent, EventType};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use truesight_common::event::{EnrichedEvent, EventType, alias_previous_id};
+use uuid::Uuid;
 
-/// If the given event is an `Identify` event with a `user_id`, upsert a row
-/// into the `identity_map` table.
-///
-/// The table is expected to exist with the following schema (or compatible):
+use crate::inserter::ClickHouseInserter;
+
+/// Row in the `identity_map` table:
 ///
 /// ```sql
 /// CREATE TABLE IF NOT EXISTS identity_map (
 ///     project_id   UUID,
 ///     anonymous_id String,
 ///     user_id      String,
-///     first_seen   DateTime64(3, 'UTC'),
-///     last_seen    DateTime64(3, 'UTC')
+///     first_seen   DateTime64(3),
+///     last_seen    DateTime64(3)
 /// ) ENGINE = ReplacingMergeTree(last_seen)
-/// ORDER BY (project_id, anonymous_id, user_id);
+/// ORDER BY (project_id, anonymous_id);
 /// ```
 ///
 /// Because we use `ReplacingMergeTree(last_seen)`, repeated inserts for the
-/// same `(project_id, anonymous_id, user_id)` triple naturally resolve to the
-/// row with the latest `last_seen` after a merge.
-pub async fn process_identify_event(
-    client: &clickhouse::Client,
-    event: &EnrichedEvent,
-) -> Result<()> {
-    if event.event_type != EventType::Identify {
-        return Ok(());
+/// same `(project_id, anonymous_id)` naturally resolve to the row with the
+/// latest `last_seen` after a merge.
+#[derive(Debug, Serialize, clickhouse::Row)]
+struct IdentityRow {
+    #[serde(with = "clickhouse::serde::uuid")]
+    project_id: Uuid,
+    anonymous_id: String,
+    user_id: String,
+    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
+    first_seen: DateTime<Utc>,
+    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
+    last_seen: DateTime<Utc>,
+}
+
+impl IdentityRow {
+    /// Builds the mapping for an `Identify` event with a non-empty `user_id`.
+    fn from_event(event: &EnrichedEvent) -> Option<Self> {
+        if event.event_type != EventType::Identify {
+            return None;
+        }
+
+        let user_id = event.user_id.as_deref().filter(|uid| !uid.is_empty())?;
+
+        Some(Self {
+            project_id: event.project_id,
+            anonymous_id: event.anonymous_id.clone(),
+            user_id: user_id.to_string(),
+            first_seen: event.server_timestamp,
+            last_seen: event.server_timestamp,
+        })
     }
+}
 
-    let user_id = match &event.user_id {
-        Some(uid) if !uid.is_empty() => uid,
-        _ => return Ok(()),
-    };
-
-    let project_id = event.project_id.to_string();
-    let anonymous_id = &event.anonymous_id;
-    let timestamp = event
-        .server_timestamp
-        .format("%Y-%m-%d %H:%M:%S%.3f")
-        .to_string();
-
-    let query = format!(
-        "INSERT INTO identity_map (project_id, anonymous_id, user_id, first_seen, last_seen) VALUES ('{}', '{}', '{}', '{}', '{}')",
-        project_id,
-        escape_ch_string(anonymous_id),
-        escape_ch_string(user_id),
-        timestamp,
-        timestamp,
-    );
+/// Upserts `identity_map` rows for every `Identify` event (with a `user_id`)
+/// in `events`.
+///
+/// Returns the indices (into `events`) of events whose mapping ClickHouse
+/// rejected, each paired with the ClickHouse error. Failures not caused by
+/// the rows themselves are returned as an error.
+pub async fn insert_identity_mappings(
+    inserter: &ClickHouseInserter,
+    events: &[EnrichedEvent],
+) -> Result<Vec<(usize, String)>> {
+    let (sources, rows): (Vec<usize>, Vec<IdentityRow>) = events
+        .iter()
+        .enumerate()
+        .filter_map(|(i, event)| IdentityRow::from_event(event).map(|row| (i, row)))
+        .unzip();
 
-    client
-        .query(&query)
-        .execute()
-        .await
-        .context("failed to upsert identity_map")?;
+    if rows.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let failures = inserter.insert_rows("identity_map", &rows).await?;
 
     tracing::debug!(
-        project_id = %event.project_id,
-        anonymous_id = %event.anonymous_id,
-        user_id = %user_id,
-        "upserted identity mapping"
+        count = rows.len(),
+        failed = failures.len(),
+        "upserted identity mappings"
     );
 
-    Ok(())
+    Ok(failures
+        .into_iter()
+        .map(|(row_idx, reason)| (sources[row_idx], reason))
+        .collect())
 }
 
-/// Escapes a string value for safe inclusion in a ClickHouse SQL literal.
+// ---------------------------------------------------------------------------
+// Identity graph
+// ---------------------------------------------------------------------------
+
+/// Row in the `identity_graph` table. `ReplacingMergeTree(version)` keeps the
+/// latest assignment per `(project_id, distinct_id)`.
+#[derive(Debug, Clone, Serialize, Deserialize, clickhouse::Row)]
+struct GraphRow {
+    #[serde(with = "clickhouse::serde::uuid")]
+    project_id: Uuid,
+    distinct_id: String,
+    person_id: String,
+    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
+    person_created_at: DateTime<Utc>,
+    version: u64,
+}
+
+/// Current assignment of a distinct ID, as read back from ClickHouse.
+#[derive(Debug, Deserialize, clickhouse::Row)]
+struct Assignment {
+    distinct_id: String,
+    person_id: String,
+    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
+    person_created_at: DateTime<Utc>,
+}
+
+/// An edge between two distinct IDs asserted by an event. `primary` is the
+/// ID that becomes the person ID if neither side belongs to a person yet.
+struct Link {
+    other: String,
+    primary: String,
+    at: DateTime<Utc>,
+    source: usize,
+}
+
+impl Link {
+    fn from_event(event: &EnrichedEvent, source: usize) -> Option<Self> {
+        let user_id = event.user_id.as_deref().filter(|uid| !uid.is_empty())?;
+        let other = match event.event_type {
+            EventType::Identify => event.anonymous_id.as_str(),
+            EventType::Alias => alias_previous_id(&event.properties)?,
+            EventType::Track | EventType::Screen => return None,
+        };
+        if other == user_id {
+            return None;
+        }
+
+        Some(Self {
+            other: other.to_string(),
+            primary: user_id.to_string(),
+            at: event.server_timestamp,
+            source,
+        })
+    }
+}
+
+/// Maintains the connected-component identity graph.
+///
+/// Each `Identify` (`anonymous_id` ↔ `user_id`) and `Alias`
+/// (`properties.previous_id` ↔ `user_id`) event adds an edge. When an edge
+/// joins two components, the person created first keeps its ID and every
+/// member of the other component is re-pointed to it. A brand-new component
+/// takes the `user_id` of its earliest edge as the person ID, so a person ID
+/// is always a real, known user ID. IDs that were never linked have no row
+/// and resolve to themselves at query time.
 ///
-/// Handles backslashes, single quotes, and `?` (which the `clickhouse` crate
-/// interprets as bind-parameter placeholders).
-fn escape_ch_string(s: &str) -> String {
-    s.replace('\\', "\\\\")
-        .replace('\'', "\\'")
-        .replace('?', "??")
+/// Updates are read-modify-write, so they are serialised within this process.
+/// Concurrent writers on other instances can race on the same component; the
+/// next edge touching it reconciles the assignment.
+pub struct IdentityGraph {
+    lock: Mutex<()>,
+}
+
+impl IdentityGraph {
+    pub fn new() -> Self {
+        Self {
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Applies the `Identify`/`Alias` edges in `events` to the graph.
+    ///
+    /// Returns the indices (into `events`) of events whose edges could not be
+    /// applied, each paired with the error.
+    pub async fn update(
+        &self,
+        inserter: &ClickHouseInserter,
+        events: &[EnrichedEvent],
+    ) -> Vec<(usize, String)> {
+        let mut by_project: BTreeMap<Uuid, Vec<Link>> = BTreeMap::new();
+        for (i, event) in events.iter().enumerate() {
+            if let Some(link) = Link::from_event(event, i) {
+                by_project.entry(event.project_id).or_default().push(link);
+            }
+        }
+
+        if by_project.is_empty() {
+            return Vec::new();
+        }
+
+        let _guard = self.lock.lock().await;
+        let mut failures = Vec::new();
+
+        for (project_id, links) in by_project {
+            match self.update_project(inserter, project_id, &links).await {
+                Ok(project_failures) => failures.extend(project_failures),
+                Err(e) => {
+                    let reason = format!("{e:#}");
+                    failures.extend(links.iter().map(|l| (l.source, reason.clone())));
+                }
+            }
+        }
+
+        failures
+    }
+
+    async fn update_project(
+        &self,
+        inserter: &ClickHouseInserter,
+        project_id: Uuid,
+        links: &[Link],
+    ) -> Result<Vec<(usize, String)>> {
+        let client = inserter.client();
+
+        let ids: Vec<String> = links
+            .iter()
+            .flat_map(|l| [l.other.clone(), l.primary.clone()])
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+
+        let current: HashMap<String, Assignment> = client
+            .query(
+                "SELECT distinct_id, person_id, person_created_at \
+                 FROM identity_graph FINAL \
+                 WHERE project_id = ? AND has(?, distinct_id)",
+            )
+            .bind(project_id)
+            .bind(&ids)
+            .fetch_all::<Assignment>()
+            .await
+            .context("failed to read identity_graph")?
+            .into_iter()
+            .map(|a| (a.distinct_id.clone(), a))
+            .collect();
+
+        // Union-find over "root keys": an ID's current person, or the ID
+        // itself if it is not part of any person yet.
+        let root_of = |id: &str| -> String {
+            current
+                .get(id)
+                .map(|a| a.person_id.clone())
+                .unwrap_or_else(|| id.to_string())
+        };
+        let mut uf = UnionFind::default();
+        for link in links {
+            uf.union(&root_of(&link.other), &root_of(&link.primary));
+        }
+
+        // Existing persons, with their creation time.
+        let persons: HashMap<&str, DateTime<Utc>> = current
+            .values()
+            .map(|a| (a.person_id.as_str(), a.person_created_at))
+            .collect();
+
+        // Group links by component.
+        let mut components: BTreeMap<String, Vec<&Link>> = BTreeMap::new();
+        for link in links {
+            let root = uf.find(&root_of(&link.primary));
+            components.entry(root).or_default().push(link);
+        }
+
+        let version = Utc::now().timestamp_millis() as u64;
+        let mut rows: Vec<GraphRow> = Vec::new();
+        let mut row_component: Vec<usize> = Vec::new();
+        let mut component_sources: Vec<Vec<usize>> = Vec::new();
+
+        for component_links in components.values() {
+            let component = component_sources.len();
+            component_sources.push(component_links.iter().map(|l| l.source).collect());
+
+            let mut keys: HashSet<String> = HashSet::new();
+            for link in component_links {
+                keys.insert(root_of(&link.other));
+                keys.insert(root_of(&link.primary));
+            }
+
+            // The oldest existing person wins; otherwise the earliest edge's
+            // user_id founds a new person.
+            let (person_id, created_at) = keys
+                .iter()
+                .filter_map(|k| persons.get(k.as_str()).map(|at| (k.clone(), *at)))
+                .min_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)))
+                .unwrap_or_else(|| {
+                    let first = component_links
+                        .iter()
+                        .min_by_key(|l| (l.at, l.source))
+                        .expect("component has at least one link");
+                    (first.primary.clone(), first.at)
+                });
+
+            // Members of persons being merged away must be re-pointed too.
+            let merged: Vec<String> = keys
+                .iter()
+                .filter(|k| persons.contains_key(k.as_str()) && **k != person_id)
+                .cloned()
+                .collect();
+            let mut members: HashSet<String> = if merged.is_empty() {
+                HashSet::new()
+            } else {
+                client
+                    .query(
+                        "SELECT distinct_id FROM identity_graph FINAL \
+                         WHERE project_id = ? AND has(?, person_id)",
+                    )
+                    .bind(project_id)
+                    .bind(&merged)
+                    .fetch_all::<String>()
+                    .await
+                    .context("failed to read merged identity_graph components")?
+                    .into_iter()
+                    .collect()
+            };
+            members.insert(person_id.clone());
+            for link in component_links {
+                members.insert(link.other.clone());
+                members.insert(link.primary.clone());
+            }
+
+            for distinct_id in members {
+                let unchanged = current
+                    .get(&distinct_id)
+                    .is_some_and(|a| a.person_id == person_id);
+                if unchanged {
+                    continue;
+                }
+                rows.push(GraphRow {
+                    project_id,
+                    distinct_id,
+                    person_id: person_id.clone(),
+                    person_created_at: created_at,
+                    version,
+                });
+                row_component.push(component);
+            }
+
+            if !merged.is_empty() {
+                tracing::info!(
+                    project_id = %project_id,
+                    person_id = %person_id,
+                    merged = ?merged,
+                    "merged identity graph components"
+                );
+            }
+        }
+
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let failures = inserter.insert_rows("identity_graph", &rows).await?;
+
+        tracing::debug!(
+            project_id = %project_id,
+            count = rows.len(),
+            failed = failures.len(),
+            "updated identity graph"
+        );
+
+        let mut failed_components: BTreeMap<usize, String> = BTreeMap::new();
+        for (row_idx, reason) in failures {
+            failed_components
+                .entry(row_component[row_idx])
+                .or_insert(reason);
+        }
+
+        Ok(failed_components
+            .into_iter()
+            .flat_map(|(component, reason)| {
+                component_sources[component]
+                    .iter()
+                    .map(move |&source| (source, reason.clone()))
+            })
+            .collect())
+    }
+}
+
+/// Minimal union-find over string keys.
+#[derive(Default)]
+struct UnionFind {
+    parent: HashMap<String, String>,
+}
+
+impl UnionFind {
+    fn find(&mut self, key: &str) -> String {
+        let parent = match self.parent.get(key) {
+            Some(p) if p != key => p.clone(),
+            _ => return key.to_string(),
+        };
+        let root = self.find(&parent);
+        self.parent.insert(key.to_string(), root.clone());
+        root
+    }
+
+    fn union(&mut self, a: &str, b: &str) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            self.parent.insert(ra, rb);
+        }
+    }
 }
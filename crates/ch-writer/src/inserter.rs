@@ -1,199 +1,127 @@
 //! ClickHouse batch inserter with retry logic.
 //!
 //! Accepts slices of [`EnrichedEvent`] and inserts them into the `events` table
-//! using `INSERT ... FORMAT JSONEachRow`. Failed inserts are retried up to 3
-//! times with exponential back-off before the error is propagated to the caller.
+//! as typed [`EventRow`]s through the `clickhouse` crate's row insert API. A
+//! failed insert is attempted up to 3 times with exponential back-off,
+//! whatever the error.
+//!
+//! When a batch is still rejected because of its data, i.e. a ClickHouse
+//! exception whose code is in `DATA_ERROR_CODES` (parse, type, range and
+//! constraint errors) or a row that fails to serialize,
+//! [`ClickHouseInserter::insert_batch`] then bisects it to find the offending
+//! rows so that only those need to be dead-lettered. Any other failure is
+//! returned as an error, and the caller leaves the batch for SQS to redeliver.
+//!
+//! The same retry/bisect path is available for any typed [`clickhouse::Row`]
+//! via [`ClickHouseInserter::insert_rows`], which the `identity_map` and
+//! `user_profiles` writers use.
 
 use anyhow::Result;
-use chrono::{DateTime, Utc};
-use regex::Regex;
 use serde::Serialize;
-use std::sync::LazyLock;
+use std::ops::Range;
 use truesight_common::event::EnrichedEvent;
-use uuid::Uuid;
-
-/// Regex to strip Swift `AnyDecodable("...")` wrappers from KMM SDK property values.
-static ANY_DECODABLE_RE: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r#"^AnyDecodable\("(.*)"\)$"#).unwrap());
+use truesight_common::event_row::{EventRow, write_rows};
 
 /// Wraps a [`clickhouse::Client`] and provides batch-insert functionality.
 pub struct ClickHouseInserter {
     client: clickhouse::Client,
 }
 
-/// Flat row representation that maps [`EnrichedEvent`] fields (including a
-/// flattened [`DeviceContext`](truesight_common::event::DeviceContext)) to the
-/// ClickHouse `events` table columns.
-///
-/// Types must match the ClickHouse schema exactly for RowBinary serialization:
-/// - `UUID` → `Uuid` with `serde(with = "clickhouse::serde::uuid")`
-/// - `Nullable(T)` → `Option<T>`
-/// - `DateTime64(3)` → `DateTime<Utc>` with `serde(with = ...millis)`
-/// - `LowCardinality(String)` → `String` (transparent)
-#[derive(Debug, Serialize, clickhouse::Row)]
-struct EventRow {
-    #[serde(with = "clickhouse::serde::uuid")]
-    event_id: Uuid,
-    event_name: String,
-    event_type: String,
-    user_id: Option<String>,
-    anonymous_id: String,
-    mobile_number: Option<String>,
-    email: Option<String>,
-    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
-    client_timestamp: DateTime<Utc>,
-    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
-    server_timestamp: DateTime<Utc>,
-    properties: String,
-    properties_map: Vec<(String, String)>,
-    #[serde(with = "clickhouse::serde::uuid")]
-    project_id: Uuid,
-    environment: String,
-    session_id: Option<String>,
-    // Flattened DeviceContext fields
-    app_version: Option<String>,
-    os_name: String,
-    os_version: String,
-    device_model: String,
-    device_id: String,
-    network_type: Option<String>,
-    locale: String,
-    timezone: String,
-    sdk_version: String,
-    platform: String,
-}
+/// Maximum number of retry attempts for a failed insert.
+const MAX_RETRIES: u32 = 3;
 
-impl EventRow {
-    fn from_enriched(event: &EnrichedEvent) -> Self {
-        let event_type_str = serde_json::to_string(&event.event_type)
-            .unwrap_or_default()
-            .trim_matches('"')
-            .to_string();
-
-        let sanitized_props = sanitize_properties(&event.properties);
-
-        let properties_json = sanitized_props
-            .as_ref()
-            .map(|v| serde_json::to_string(v).unwrap_or_default())
-            .unwrap_or_default();
-
-        let properties_map = flatten_properties(&sanitized_props);
-
-        Self {
-            event_id: event.event_id,
-            event_name: event.event_name.clone(),
-            event_type: event_type_str,
-            user_id: event.user_id.clone().filter(|s| !s.is_empty()),
-            anonymous_id: event.anonymous_id.clone(),
-            mobile_number: event.mobile_number.clone().filter(|s| !s.is_empty()),
-            email: event.email.clone().filter(|s| !s.is_empty()),
-            client_timestamp: event.client_timestamp,
-            server_timestamp: event.server_timestamp,
-            properties: properties_json,
-            properties_map,
-            project_id: event.project_id,
-            environment: event.environment.clone(),
-            session_id: event.session_id.clone(),
-            app_version: event.context.app_version.clone(),
-            os_name: event.context.os_name.clone(),
-            os_version: event.context.os_version.clone(),
-            device_model: event.context.device_model.clone(),
-            device_id: event.context.device_id.clone(),
-            network_type: event.context.network_type.clone(),
-            locale: event.context.locale.clone(),
-            timezone: event.context.timezone.clone(),
-            sdk_version: event.context.sdk_version.clone(),
-            platform: event
-                .context
-                .platform
-                .clone()
-                .filter(|p| !p.is_empty())
-                .unwrap_or_else(|| infer_platform(&event.context.os_name)),
-        }
-    }
-}
+/// Base delay in milliseconds for exponential back-off (500ms, 1s, 2s).
+const BASE_DELAY_MS: u64 = 500;
 
-fn infer_platform(os_name: &str) -> String {
-    match os_name.to_lowercase().as_str() {
-        "web" => "web",
-        "android" => "android",
-        "ios" => "ios",
-        "macos" | "mac os" | "mac os x" => "macos",
-        "windows" => "windows",
-        "linux" => "linux",
-        _ => "unknown",
-    }
-    .to_string()
-}
+/// Upper bound on single-attempt sub-batch inserts while bisecting a failed
+/// batch. Isolating `k` bad rows in a batch of `n` takes roughly
+/// `2k * log2(n)` inserts; once the budget is spent, the remaining suspect
+/// sub-batches are reported as failed as a whole.
+const MAX_BISECT_INSERTS: usize = 64;
 
-/// Strip `AnyDecodable("...")` wrappers from property values.
-///
-/// The KMM/Swift SDK sometimes serialises values wrapped in `AnyDecodable(...)`.
-/// This function recursively walks the JSON and unwraps them to clean strings.
-fn sanitize_properties(props: &Option<serde_json::Value>) -> Option<serde_json::Value> {
-    props.as_ref().map(sanitize_value)
-}
+/// ClickHouse exception codes raised when the inserted data itself is bad:
+/// parse failures, type mismatches, out-of-range values and constraint
+/// violations. Resending the same rows fails the same way, so only these are
+/// worth bisecting.
+const DATA_ERROR_CODES: &[u32] = &[
+    6,   // CANNOT_PARSE_TEXT
+    25,  // CANNOT_PARSE_ESCAPE_SEQUENCE
+    26,  // CANNOT_PARSE_QUOTED_STRING
+    27,  // CANNOT_PARSE_INPUT_ASSERTION_FAILED
+    32,  // ATTEMPT_TO_READ_AFTER_EOF
+    33,  // CANNOT_READ_ALL_DATA
+    38,  // CANNOT_PARSE_DATE
+    41,  // CANNOT_PARSE_DATETIME
+    53,  // TYPE_MISMATCH
+    69,  // ARGUMENT_OUT_OF_BOUND
+    70,  // CANNOT_CONVERT_TYPE
+    72,  // CANNOT_PARSE_NUMBER
+    117, // INCORRECT_DATA
+    131, // TOO_LARGE_STRING_SIZE
+    190, // SIZES_OF_ARRAYS_DONT_MATCH
+    321, // VALUE_IS_OUT_OF_RANGE_OF_DATA_TYPE
+    349, // CANNOT_INSERT_NULL_IN_ORDINARY_COLUMN
+    376, // CANNOT_PARSE_UUID
+    467, // CANNOT_PARSE_BOOL
+    469, // VIOLATED_CONSTRAINT
+];
 
-fn sanitize_value(value: &serde_json::Value) -> serde_json::Value {
-    match value {
-        serde_json::Value::String(s) => {
-            if let Some(caps) = ANY_DECODABLE_RE.captures(s) {
-                serde_json::Value::String(caps[1].to_string())
-            } else {
-                value.clone()
-            }
-        }
-        serde_json::Value::Object(map) => {
-            let cleaned: serde_json::Map<String, serde_json::Value> = map
-                .iter()
-                .map(|(k, v)| (k.clone(), sanitize_value(v)))
-                .collect();
-            serde_json::Value::Object(cleaned)
-        }
-        serde_json::Value::Array(arr) => {
-            serde_json::Value::Array(arr.iter().map(sanitize_value).collect())
+/// Returns `true` if the error indicates ClickHouse rejected the data itself,
+/// as opposed to a transport failure, timeout or server-side condition
+/// (overload, memory limit, read-only replica, missing table, ...) where
+/// every row would fail the same way and bisecting would only multiply the
+/// load.
+fn is_data_error(err: &anyhow::Error) -> bool {
+    use clickhouse::error::Error;
+
+    match err.downcast_ref::<Error>() {
+        Some(Error::BadResponse(reason)) => {
+            exception_code(reason).is_some_and(|code| DATA_ERROR_CODES.contains(&code))
         }
-        other => other.clone(),
+        // Raised client-side while serializing a row.
+        Some(
+            Error::Custom(_)
+            | Error::SequenceMustHaveLength
+            | Error::VariantDiscriminatorIsOutOfBound(_),
+        ) => true,
+        _ => false,
     }
 }
 
-/// Flatten a JSON Value (expected to be an object) into a `Map<String, String>`.
-///
-/// Primitives are converted with `to_string()`, nested objects/arrays are
-/// serialised as JSON strings so nothing is lost.
-fn flatten_properties(props: &Option<serde_json::Value>) -> Vec<(String, String)> {
-    let Some(serde_json::Value::Object(map)) = props else {
-        return Vec::new();
-    };
-    let mut out = Vec::with_capacity(map.len());
-    for (k, v) in map {
-        let s = match v {
-            serde_json::Value::String(s) => s.clone(),
-            serde_json::Value::Bool(b) => b.to_string(),
-            serde_json::Value::Number(n) => n.to_string(),
-            serde_json::Value::Null => continue,
-            // Nested objects / arrays — keep as JSON
-            other => serde_json::to_string(other).unwrap_or_default(),
-        };
-        out.push((k.clone(), s));
-    }
-    out
+/// Extracts the code from a ClickHouse exception message of the form
+/// `Code: 27. DB::Exception: ...`.
+fn exception_code(reason: &str) -> Option<u32> {
+    let rest = &reason[reason.find("Code: ")? + "Code: ".len()..];
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
 }
 
-/// Maximum number of retry attempts for a failed insert.
-const MAX_RETRIES: u32 = 3;
-
-/// Base delay in milliseconds for exponential back-off (500ms, 1s, 2s).
-const BASE_DELAY_MS: u64 = 500;
+/// Formats an insert error including its full cause chain, so the ClickHouse
+/// exception text survives into DLQ `error_reason` attributes.
+fn describe_error(err: &anyhow::Error) -> String {
+    format!("{err:#}")
+}
 
 impl ClickHouseInserter {
     /// Creates a new inserter connected to the given ClickHouse instance.
-    pub fn new(url: &str, database: &str, user: &str, password: &str) -> Self {
-        let client = clickhouse::Client::default()
+    ///
+    /// With `async_insert`, ClickHouse buffers inserts server-side and merges
+    /// small ones into larger parts. `wait_for_async_insert` stays on so an
+    /// insert only returns once its data is written and the SQS messages can
+    /// be deleted safely.
+    pub fn new(url: &str, database: &str, user: &str, password: &str, async_insert: bool) -> Self {
+        let mut client = clickhouse::Client::default()
             .with_url(url)
             .with_database(database)
             .with_user(user)
             .with_password(password);
+        if async_insert {
+            client = client
+                .with_option("async_insert", "1")
+                .with_option("wait_for_async_insert", "1");
+        }
 
         Self { client }
     }
@@ -206,15 +134,12 @@ impl ClickHouseInserter {
         &self.client
     }
 
-    /// Writes rows using the native `INSERT` API (avoids SQL string formatting
-    /// which can misinterpret `?` in data as bind parameters).
-    async fn try_insert_rows(&self, rows: &[EventRow]) -> Result<()> {
-        let mut insert = self.client.insert("events")?;
-        for row in rows {
-            insert.write(row).await?;
-        }
-        insert.end().await?;
-        Ok(())
+    /// Writes rows to `table` with a single insert attempt.
+    async fn try_insert_rows<T>(&self, table: &str, rows: &[T]) -> Result<()>
+    where
+        T: clickhouse::Row + Serialize,
+    {
+        write_rows(&self.client, table, rows).await
     }
 
     /// Inserts a batch of enriched events into the `events` table.
@@ -223,27 +148,139 @@ impl ClickHouseInserter {
     /// On failure it retries up to
     /// [`MAX_RETRIES`] times with exponential back-off (500 ms, 1 s, 2 s).
     ///
-    /// Returns `Ok(())` on success, or the last encountered error after all
-    /// retries are exhausted.
+    /// If the batch is still rejected because of its data, it is bisected to
+    /// isolate the offending rows. Returns the indices (into `events`) of rows
+    /// that could not be inserted, each paired with the ClickHouse error that
+    /// rejected it; an empty vector means every row was inserted. Transport
+    /// failures, timeouts and server-side exceptions are returned as an error
+    /// without bisecting: the batch may succeed once ClickHouse recovers.
     #[tracing::instrument(name = "ch.insert_batch", skip(self, events), fields(event_count = events.len()))]
-    pub async fn insert_batch(&self, events: &[EnrichedEvent]) -> Result<()> {
+    pub async fn insert_batch(&self, events: &[EnrichedEvent]) -> Result<Vec<(usize, String)>> {
         if events.is_empty() {
-            return Ok(());
+            return Ok(Vec::new());
         }
 
         let rows: Vec<EventRow> = events.iter().map(EventRow::from_enriched).collect();
+        self.insert_rows("events", &rows).await
+    }
 
+    /// Inserts typed rows into `table` with the same retry and bisection
+    /// behaviour as [`Self::insert_batch`]. Returned indices refer to `rows`.
+    #[tracing::instrument(name = "ch.insert_rows", skip(self, rows), fields(row_count = rows.len()))]
+    pub async fn insert_rows<T>(&self, table: &str, rows: &[T]) -> Result<Vec<(usize, String)>>
+    where
+        T: clickhouse::Row + Serialize,
+    {
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let err = match self.insert_rows_with_retry(table, rows).await {
+            Ok(()) => return Ok(Vec::new()),
+            Err(e) => e,
+        };
+
+        if !is_data_error(&err) {
+            return Err(err);
+        }
+
+        if rows.len() == 1 {
+            return Ok(vec![(0, describe_error(&err))]);
+        }
+
+        tracing::warn!(
+            table,
+            count = rows.len(),
+            error = %describe_error(&err),
+            "batch rejected by ClickHouse, bisecting to isolate bad rows"
+        );
+
+        let failed = self.bisect(table, rows, describe_error(&err)).await?;
+
+        tracing::warn!(
+            table,
+            count = rows.len(),
+            failed = failed.len(),
+            "bisection finished"
+        );
+
+        Ok(failed)
+    }
+
+    /// Splits `rows` in halves and inserts each with a single attempt,
+    /// recursing into halves that fail until single rows are isolated or
+    /// [`MAX_BISECT_INSERTS`] is reached. A failure that is not caused by the
+    /// data stops the bisection and is returned as an error.
+    async fn bisect<T>(
+        &self,
+        table: &str,
+        rows: &[T],
+        batch_error: String,
+    ) -> Result<Vec<(usize, String)>>
+    where
+        T: clickhouse::Row + Serialize,
+    {
+        let mut failed: Vec<(usize, String)> = Vec::new();
+        let mut pending: Vec<(Range<usize>, String)> = split_range(0..rows.len())
+            .into_iter()
+            .rev()
+            .map(|r| (r, batch_error.clone()))
+            .collect();
+        let mut attempts = 0;
+
+        while let Some((range, parent_error)) = pending.pop() {
+            if attempts >= MAX_BISECT_INSERTS {
+                failed.extend(range.map(|i| (i, parent_error.clone())));
+                continue;
+            }
+            attempts += 1;
+
+            match self.try_insert_rows(table, &rows[range.clone()]).await {
+                Ok(()) => {}
+                Err(e) if !is_data_error(&e) => {
+                    return Err(e.context(format!(
+                        "sub-batch insert failed after {attempts} bisection attempts"
+                    )));
+                }
+                Err(e) if range.len() == 1 => {
+                    failed.push((range.start, describe_error(&e)));
+                }
+                Err(e) => {
+                    let reason = describe_error(&e);
+                    for half in split_range(range).into_iter().rev() {
+                        pending.push((half, reason.clone()));
+                    }
+                }
+            }
+        }
+
+        failed.sort_by_key(|(i, _)| *i);
+        Ok(failed)
+    }
+
+    /// Inserts pre-converted rows, retrying up to [`MAX_RETRIES`] times with
+    /// exponential back-off.
+    async fn insert_rows_with_retry<T>(&self, table: &str, rows: &[T]) -> Result<()>
+    where
+        T: clickhouse::Row + Serialize,
+    {
         let mut last_err: Option<anyhow::Error> = None;
 
         for attempt in 0..MAX_RETRIES {
-            match self.try_insert_rows(&rows).await {
+            match self.try_insert_rows(table, rows).await {
                 Ok(()) => {
-                    tracing::debug!(count = events.len(), attempt, "batch inserted successfully");
+                    tracing::debug!(
+                        table,
+                        count = rows.len(),
+                        attempt,
+                        "batch inserted successfully"
+                    );
                     return Ok(());
                 }
                 Err(e) => {
                     let delay_ms = BASE_DELAY_MS * 2u64.pow(attempt);
                     tracing::warn!(
+                        table,
                         attempt,
                         delay_ms,
                         error = %e,
@@ -255,7 +292,14 @@ impl ClickHouseInserter {
             }
         }
 
-        Err(last_err
-            .unwrap_or_else(|| anyhow::anyhow!("insert_batch failed with no error captured")))
+        Err(last_err.unwrap_or_else(|| {
+            anyhow::anyhow!("insert into {table} failed with no error captured")
+        }))
     }
 }
+
+/// Splits a range into two halves (the first half gets the smaller share).
+fn split_range(range: Range<usize>) -> [Range<usize>; 2] {
+    let mid = range.start + range.len() / 2;
+    [range.start..mid, mid..range.end]
+}
@@ -0,0 +1,123 @@
+//! Live event publishing.
+//!
+//! After a batch is inserted, the batcher hands the inserted events to the
+//! [`LivePublisher`]. A background [`LivePublisherTask`] groups them by
+//! project and publishes them to Redis (see [`truesight_common::live`]),
+//! where admin-api's live-events stream picks them up.
+//!
+//! The live view is best-effort: when the task falls behind, batches are
+//! dropped instead of holding up the batcher, and Redis errors are logged.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use redis::aio::ConnectionManager;
+use tokio::sync::mpsc;
+use truesight_common::event::EnrichedEvent;
+use truesight_common::live::{LiveEvent, LiveMessage, channel, is_live_event};
+use uuid::Uuid;
+
+/// Capacity of the channel between the batcher and the task, in batches.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Maximum events per published message.
+const MAX_MESSAGE_EVENTS: usize = 500;
+
+/// Cloneable handle used by the batcher to submit inserted events.
+#[derive(Clone)]
+pub struct LivePublisher {
+    sender: mpsc::Sender<Vec<EnrichedEvent>>,
+}
+
+impl LivePublisher {
+    /// Connects to Redis and creates the publisher with the task serving it.
+    pub async fn connect(redis_url: &str) -> Result<(Self, LivePublisherTask)> {
+        let client = redis::Client::open(redis_url).context("invalid REDIS_URL")?;
+        let conn = ConnectionManager::new(client)
+            .await
+            .context("failed to connect to Redis")?;
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+
+        Ok((Self { sender }, LivePublisherTask { receiver, conn }))
+    }
+
+    /// Submits inserted events without waiting; drops them if the task is
+    /// saturated.
+    pub fn publish(&self, events: &[EnrichedEvent]) {
+        let events: Vec<EnrichedEvent> = events
+            .iter()
+            .filter(|e| is_live_event(&e.event_name))
+            .cloned()
+            .collect();
+        if events.is_empty() {
+            return;
+        }
+
+        if let Err(e) = self.sender.try_send(events) {
+            let count = match &e {
+                mpsc::error::TrySendError::Full(events)
+                | mpsc::error::TrySendError::Closed(events) => events.len(),
+            };
+            tracing::warn!(count, "live publisher unavailable, events not published");
+        }
+    }
+}
+
+/// Background task that publishes submitted events to Redis.
+pub struct LivePublisherTask {
+    receiver: mpsc::Receiver<Vec<EnrichedEvent>>,
+    conn: ConnectionManager,
+}
+
+impl LivePublisherTask {
+    /// Runs until every [`LivePublisher`] is dropped.
+    pub async fn run(mut self) {
+        tracing::info!("live publisher started");
+
+        while let Some(events) = self.receiver.recv().await {
+            let mut by_project: HashMap<Uuid, Vec<LiveEvent>> = HashMap::new();
+            for event in &events {
+                by_project
+                    .entry(event.project_id)
+                    .or_default()
+                    .push(LiveEvent::from_enriched(event));
+            }
+
+            for (project_id, events) in by_project {
+                for chunk in events.chunks(MAX_MESSAGE_EVENTS) {
+                    let message = LiveMessage {
+                        project_id,
+                        events: chunk.to_vec(),
+                    };
+                    self.send(project_id, &message).await;
+                }
+            }
+        }
+
+        tracing::info!("live publisher shut down");
+    }
+
+    async fn send(&mut self, project_id: Uuid, message: &LiveMessage) {
+        let payload = match serde_json::to_string(message) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!(error = %e, "failed to serialize live events");
+                return;
+            }
+        };
+
+        let result: redis::RedisResult<i64> = redis::cmd("PUBLISH")
+            .arg(channel(project_id))
+            .arg(payload)
+            .query_async(&mut self.conn)
+            .await;
+        if let Err(e) = result {
+            tracing::warn!(
+                project_id = %project_id,
+                count = message.events.len(),
+                error = %e,
+                "failed to publish live events"
+            );
+        }
+    }
+}
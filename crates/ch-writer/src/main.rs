@@ -3,29 +3,48 @@
 //! Consumes enriched events from SQS and inserts them into ClickHouse in
 //! batches. Designed to run as a long-lived service with multiple concurrent
 //! consumer tasks, a batching layer, and a health-check endpoint.
+//!
+//! `ch-writer reimport [PREFIX] [--from YYYY-MM-DD] [--to YYYY-MM-DD]` loads
+//! events from the Parquet archive (`ARCHIVE_URL`) back into ClickHouse and
+//! exits instead. It needs `DATABASE_URL` to skip the events of erased users.
 
+mod adaptive;
+mod archive;
 mod batcher;
 mod config;
 mod consumer;
 mod dedup;
 mod dlq;
+mod erasure;
+mod fairness;
 mod health;
+mod heartbeat;
 mod identity;
 mod inserter;
+mod live;
 mod profiles;
+mod webhooks;
 
 use std::sync::Arc;
 
 use anyhow::Result;
 use tokio::sync::{mpsc, watch};
+use truesight_common::erasure::{Suppression, SuppressionList};
 use truesight_common::sqs::SqsConsumer;
 use truesight_common::telemetry::init_telemetry;
 
+use crate::adaptive::AdaptiveLimits;
+use crate::archive::Archiver;
 use crate::batcher::Batcher;
 use crate::config::WriterConfig;
 use crate::consumer::ConsumerLoop;
 use crate::dlq::DlqSender;
+use crate::erasure::ArchivePurger;
+use crate::fairness::{FairQueue, ProjectStats, parse_project_weights};
+use crate::heartbeat::{Heartbeat, InFlightMessages};
 use crate::inserter::ClickHouseInserter;
+use crate::live::LivePublisher;
+use crate::webhooks::WebhookDispatcher;
 
 /// Number of concurrent SQS consumer tasks.
 const NUM_CONSUMERS: usize = 3;
@@ -55,6 +74,7 @@ async fn main() -> Result<()> {
 
     tracing::info!("ch-writer starting");
     tracing::info!(dedup = crate::dedup::dedup_note(), "dedup strategy");
+    truesight_common::event_row::set_flatten_depth(config.property_flatten_depth);
 
     // --- Build shared resources ---
 
@@ -63,8 +83,45 @@ async fn main() -> Result<()> {
         &config.clickhouse_database,
         &config.clickhouse_user,
         &config.clickhouse_password,
+        config.ch_async_insert,
     ));
 
+    let archiver = Archiver::from_config(&config)?.map(Arc::new);
+
+    // Erased users are matched by hashes keyed with ERASURE_HASH_KEY.
+    let erasure_hash_key = config.erasure_hash_key.clone().filter(|k| !k.is_empty());
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("reimport") {
+        let Some(archiver) = archiver else {
+            anyhow::bail!("ARCHIVE_URL must be set to re-import archived events");
+        };
+        let Some(database_url) = config.database_url.as_deref() else {
+            anyhow::bail!("DATABASE_URL must be set to skip erased users when re-importing");
+        };
+        let Some(erasure_hash_key) = erasure_hash_key else {
+            anyhow::bail!("ERASURE_HASH_KEY must be set to skip erased users when re-importing");
+        };
+        let pool = truesight_common::db::create_pool_with_size(database_url, 1)?;
+        let suppression =
+            tokio::task::spawn_blocking(move || SuppressionList::load(&pool, &erasure_hash_key))
+                .await??;
+        return reimport(
+            &archiver,
+            &inserter,
+            &suppression,
+            &args[1..],
+            config.batch_size(),
+        )
+        .await;
+    }
+
+    let db_pool = config
+        .database_url
+        .as_deref()
+        .map(|url| truesight_common::db::create_pool_with_size(url, 4))
+        .transpose()?;
+
     let sqs_consumer =
         Arc::new(SqsConsumer::new(&config.aws_region, config.sqs_endpoint_url.as_deref()).await?);
 
@@ -75,13 +132,30 @@ async fn main() -> Result<()> {
     // Derive a DLQ URL by convention: source queue URL + "-dlq" suffix.
     // In production this would typically be configured explicitly; this is a
     // sensible default.
-    let dlq_url: Option<String> = Some(format!("{}-dlq", &config.sqs_queue_url));
+    let dlq_url: Option<String> = Some(truesight_common::sqs::default_dlq_url(
+        &config.sqs_queue_url,
+    ));
 
     // --- Shutdown signal ---
 
     let (shutdown_tx, _shutdown_rx) = watch::channel(false);
     let (health_shutdown_tx, health_shutdown_rx) = tokio::sync::oneshot::channel::<()>();
 
+    // --- Visibility heartbeat ---
+
+    let in_flight_messages = Arc::new(InFlightMessages::new());
+    let heartbeat = Heartbeat::new(
+        Arc::clone(&sqs_consumer),
+        config.sqs_queue_url.clone(),
+        Arc::clone(&in_flight_messages),
+        config.sqs_visibility_timeout_secs,
+    );
+    let visibility_timeout_secs = heartbeat.visibility_timeout_secs();
+    // Stopped after the batcher, so messages are kept invisible until their
+    // final batches are acknowledged and the rest are released.
+    let (heartbeat_shutdown_tx, heartbeat_shutdown_rx) = watch::channel(false);
+    let heartbeat_handle = tokio::spawn(heartbeat.run(heartbeat_shutdown_rx));
+
     // --- Channel between consumers and batcher ---
 
     let (event_tx, event_rx) = mpsc::channel(CHANNEL_BUFFER);
@@ -106,6 +180,8 @@ async fn main() -> Result<()> {
             dlq_client,
             dlq_url.clone(),
             config.sqs_receive_batch_size,
+            visibility_timeout_secs,
+            Arc::clone(&in_flight_messages),
         );
 
         let cancel_rx = shutdown_tx.subscribe();
@@ -123,8 +199,81 @@ async fn main() -> Result<()> {
     // consumer tasks finish.
     drop(event_tx);
 
+    // --- Spawn webhook worker ---
+
+    let (webhooks, webhook_handle) = match db_pool.clone() {
+        Some(pool) => {
+            let (dispatcher, worker) = WebhookDispatcher::new(
+                pool,
+                std::time::Duration::from_secs(config.webhook_refresh_secs),
+            )?;
+            (Some(dispatcher), Some(tokio::spawn(worker.run())))
+        }
+        None => {
+            tracing::info!("DATABASE_URL not set, webhook fan-out disabled");
+            (None, None)
+        }
+    };
+
+    // --- Erased users ---
+
+    let suppression = match (db_pool.clone(), erasure_hash_key.clone()) {
+        (Some(pool), Some(key)) => Some(Suppression::spawn(
+            pool,
+            key,
+            std::time::Duration::from_secs(config.suppression_refresh_secs),
+        )),
+        _ => {
+            tracing::warn!(
+                "DATABASE_URL or ERASURE_HASH_KEY not set, events of erased users are not suppressed"
+            );
+            None
+        }
+    };
+
+    let purge_handle = match (archiver.clone(), db_pool, erasure_hash_key) {
+        (Some(archiver), Some(pool), Some(key)) => {
+            Some(tokio::spawn(ArchivePurger::new(archiver, pool, key).run()))
+        }
+        (Some(_), _, _) => {
+            tracing::warn!(
+                "DATABASE_URL or ERASURE_HASH_KEY not set, erased users are not purged from the archive"
+            );
+            None
+        }
+        (None, _, _) => None,
+    };
+
+    // --- Spawn live publisher ---
+
+    let (live, live_handle) = match config.redis_url.as_deref().filter(|u| !u.is_empty()) {
+        Some(redis_url) => match LivePublisher::connect(redis_url).await {
+            Ok((publisher, task)) => (Some(publisher), Some(tokio::spawn(task.run()))),
+            Err(e) => {
+                tracing::error!(error = %format!("{e:#}"), "live event publishing disabled");
+                (None, None)
+            }
+        },
+        None => {
+            tracing::info!("REDIS_URL not set, live event publishing disabled");
+            (None, None)
+        }
+    };
+
     // --- Spawn batcher ---
 
+    let project_weights =
+        parse_project_weights(config.ch_project_weights.as_deref().unwrap_or(""))?;
+    if !project_weights.is_empty() {
+        tracing::info!(
+            projects = project_weights.len(),
+            "project weights configured"
+        );
+    }
+    let project_stats = Arc::new(ProjectStats::new());
+    let webhook_stats = webhooks.as_ref().map(WebhookDispatcher::stats);
+    let limits = Arc::new(AdaptiveLimits::from_config(&config));
+
     let batcher = Batcher::new(
         event_rx,
         Arc::clone(&inserter),
@@ -132,11 +281,19 @@ async fn main() -> Result<()> {
         Arc::clone(&dlq_sender),
         config.sqs_queue_url.clone(),
         dlq_url.clone(),
-        Some(config.batch_size()),
+        webhooks,
+        suppression,
+        archiver,
+        live,
+        Arc::clone(&in_flight_messages),
+        Arc::clone(&limits),
+        FairQueue::new(project_weights),
+        Arc::clone(&project_stats),
+        config.ch_max_buffered_events,
         Some(config.flush_interval_secs() * 1000), // convert seconds to ms
     );
 
-    let batcher_handle = tokio::spawn(async move {
+    let mut batcher_handle = tokio::spawn(async move {
         if let Err(e) = batcher.run().await {
             tracing::error!(error = %e, "batcher exited with error");
         }
@@ -145,7 +302,7 @@ async fn main() -> Result<()> {
     // --- Spawn health endpoint ---
 
     let health_handle = tokio::spawn(async move {
-        health::serve_health(HEALTH_PORT, async {
+        health::serve_health(HEALTH_PORT, project_stats, webhook_stats, async {
             let _ = health_shutdown_rx.await;
         })
         .await;
@@ -165,8 +322,44 @@ async fn main() -> Result<()> {
         let _ = handle.await;
     }
 
-    // Batcher will drain the channel and flush remaining events.
-    let _ = batcher_handle.await;
+    // Batcher will drain the channel and flush remaining events. Past the
+    // grace period it is aborted along with its inserts, and only once they
+    // have stopped does the heartbeat release whatever is still
+    // unacknowledged, so another writer can pick it up right away without
+    // racing an insert of the same batch.
+    let grace = std::time::Duration::from_secs(config.shutdown_grace_secs);
+    if tokio::time::timeout(grace, &mut batcher_handle)
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            grace_secs = config.shutdown_grace_secs,
+            unacknowledged = in_flight_messages.len(),
+            "batcher did not finish within the shutdown grace period, abandoning its batches"
+        );
+        batcher_handle.abort();
+        let _ = batcher_handle.await;
+        limits.drain().await;
+    }
+    let _ = heartbeat_shutdown_tx.send(true);
+    let _ = heartbeat_handle.await;
+
+    // The batcher dropped its dispatcher; the webhook worker delivers what it
+    // still buffers, then exits.
+    if let Some(handle) = webhook_handle {
+        let _ = handle.await;
+    }
+
+    // A purge cut short is redone from scratch by the next writer to start.
+    if let Some(handle) = purge_handle {
+        handle.abort();
+    }
+
+    // Live publishing is best-effort; events still queued are not worth
+    // delaying shutdown for.
+    if let Some(handle) = live_handle {
+        handle.abort();
+    }
 
     // Shut down health endpoint.
     let _ = health_shutdown_tx.send(());
@@ -175,3 +368,44 @@ async fn main() -> Result<()> {
     tracing::info!("ch-writer shut down complete");
     Ok(())
 }
+
+/// Runs `ch-writer reimport [PREFIX] [--from YYYY-MM-DD] [--to YYYY-MM-DD]`.
+async fn reimport(
+    archiver: &Archiver,
+    inserter: &ClickHouseInserter,
+    suppression: &SuppressionList,
+    args: &[String],
+    batch_size: usize,
+) -> Result<()> {
+    let mut prefix = String::new();
+    let (mut from, mut to) = (None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" | "--to" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("{arg} needs a YYYY-MM-DD date"))?;
+                let date = value
+                    .parse::<chrono::NaiveDate>()
+                    .map_err(|e| anyhow::anyhow!("invalid {arg} date {value}: {e}"))?;
+                if arg == "--from" {
+                    from = Some(date);
+                } else {
+                    to = Some(date);
+                }
+            }
+            other if other.starts_with("--") => anyhow::bail!("unknown reimport flag {other}"),
+            other => prefix = other.to_string(),
+        }
+    }
+
+    let (inserted, failed) = archiver
+        .reimport(inserter, suppression, &prefix, from, to, batch_size)
+        .await?;
+    tracing::info!(inserted, failed, "re-import finished");
+    if failed > 0 {
+        anyhow::bail!("{failed} archived events could not be re-imported");
+    }
+    Ok(())
+}
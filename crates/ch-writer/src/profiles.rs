@@ -1,191 +1,556 @@
 //! User profile upserts into ClickHouse.
 //!
-//! On every event batch, upserts `user_profiles` rows to track last_seen and
-//! event counts. On `Identify` events, merges traits into profile properties
-//! and promotes `email`, `name`, and `mobile_number` to top-level columns.
+//! `Identify` events update profile properties through explicit operations
+//! (`$set`, `$set_once`, `$unset`, `$increment`, `$append`); any other
+//! top-level trait is an implicit `$set`. `email`, `name`, and
+//! `mobile_number` are promoted to top-level columns. Other events only
+//! update those promoted columns when they carry them.
+//!
+//! Because `user_profiles` is a `ReplacingMergeTree(last_seen)`, each write
+//! replaces the whole row. [`ProfileWriter`] therefore reads the current
+//! profile, applies the batch's operations in order, and writes back one
+//! merged row per user, so traits accumulate across identify calls. Only
+//! updates within one ch-writer instance are serialised; see
+//! [`ProfileWriter`] for what concurrent instances can lose.
+//!
+//! Every change to a property (including the promoted columns) is also
+//! appended to `user_profile_history` with the event's `server_timestamp`,
+//! so admin queries can read a profile property as of any event.
+//!
+//! Rows are written as typed [`clickhouse::Row`]s in a single batch insert,
+//! with the same retry and poison-row isolation as the events insert.
+
+use std::collections::{BTreeMap, HashMap};
 
 use anyhow::{Context, Result};
-use truesight_common::event::{EnrichedEvent, EventType};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use truesight_common::event::{EnrichedEvent, EventType, ProfileOperation};
+use uuid::Uuid;
+
+use crate::inserter::ClickHouseInserter;
 
-/// Upserts user profiles for a batch of enriched events.
+/// Row in the `user_profiles` table.
 ///
-/// For each event, inserts a row with `last_seen` updated. Because the table
-/// uses `ReplacingMergeTree(last_seen)`, the most recent row wins after merge.
-pub async fn upsert_profiles(client: &clickhouse::Client, events: &[EnrichedEvent]) -> Result<()> {
-    if events.is_empty() {
-        return Ok(());
-    }
+/// `properties` maps to `Map(String, String)`, the nullable profile fields to
+/// `Nullable(String)` and `environment` to `LowCardinality(String)`.
+#[derive(Debug, Serialize, Deserialize, clickhouse::Row)]
+struct ProfileRow {
+    #[serde(with = "clickhouse::serde::uuid")]
+    project_id: Uuid,
+    user_uid: String,
+    properties: Vec<(String, String)>,
+    email: Option<String>,
+    name: Option<String>,
+    mobile_number: Option<String>,
+    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
+    first_seen: DateTime<Utc>,
+    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
+    last_seen: DateTime<Utc>,
+    event_count: u64,
+    environment: String,
+}
 
-    // Only upsert profiles for identify events (or events that carry profile fields).
-    // Non-identify events with no profile data would overwrite existing name/email
-    // with NULL via ReplacingMergeTree(last_seen), so we skip them.
-    // Event counts and timestamps are handled by the user_stats materialized view.
-    let mut rows = Vec::new();
+/// Row in the `user_profile_history` table: `property` took `value` from
+/// `valid_from` until the next row for the same property. An unset is
+/// recorded as an empty `value`.
+#[derive(Debug, Serialize, clickhouse::Row)]
+struct HistoryRow {
+    #[serde(with = "clickhouse::serde::uuid")]
+    project_id: Uuid,
+    user_uid: String,
+    environment: String,
+    property: String,
+    value: String,
+    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
+    valid_from: DateTime<Utc>,
+}
 
-    for event in events {
-        let user_uid = match &event.user_id {
-            Some(uid) if !uid.is_empty() => uid.clone(),
-            _ => event.anonymous_id.clone(),
-        };
+const EMAIL_KEYS: [&str; 2] = ["email", "$email"];
+const NAME_KEYS: [&str; 6] = [
+    "name",
+    "$name",
+    "first name",
+    "first_name",
+    "full_name",
+    "fullname",
+];
+const MOBILE_KEYS: [&str; 5] = ["mobile_number", "$phone", "phone", "mobile", "phone_number"];
 
-        let (email, name, mobile_number, props_map) = extract_profile_fields(event);
+/// Top-level `user_profiles` column a trait is promoted to.
+#[derive(Debug, Clone, Copy)]
+enum Column {
+    Email,
+    Name,
+    MobileNumber,
+}
 
-        // Skip events that carry no profile information
-        if email.is_none() && name.is_none() && mobile_number.is_none() && props_map.is_empty() {
-            continue;
+impl Column {
+    /// Case-insensitive match of a trait key against the promoted columns.
+    fn for_key(key: &str) -> Option<Self> {
+        let lower = key.to_lowercase();
+        if EMAIL_KEYS.contains(&lower.as_str()) {
+            Some(Self::Email)
+        } else if NAME_KEYS.contains(&lower.as_str()) {
+            Some(Self::Name)
+        } else if MOBILE_KEYS.contains(&lower.as_str()) {
+            Some(Self::MobileNumber)
+        } else {
+            None
         }
+    }
+}
 
-        let timestamp = event
-            .server_timestamp
-            .format("%Y-%m-%d %H:%M:%S%.3f")
-            .to_string();
-
-        let email_val = nullable_str(&email);
-        let name_val = nullable_str(&name);
-        let mobile_val = nullable_str(&mobile_number);
-
-        rows.push(format!(
-            "('{}', '{}', {}, {}, {}, {}, '{}', '{}', 1, '{}')",
-            event.project_id,
-            escape(user_uid.as_str()),
-            if props_map.is_empty() {
-                "map()".to_string()
-            } else {
-                props_map
-            },
-            email_val,
-            name_val,
-            mobile_val,
-            timestamp,
-            timestamp,
-            escape(&event.environment),
-        ));
+/// A single profile mutation extracted from an event.
+#[derive(Debug)]
+enum Op {
+    Set(String, String),
+    SetOnce(String, String),
+    Unset(String),
+    Increment(String, f64),
+    Append(String, Vec<serde_json::Value>),
+}
+
+/// Mutable profile state that operations are applied to.
+struct Profile {
+    properties: BTreeMap<String, String>,
+    email: Option<String>,
+    name: Option<String>,
+    mobile_number: Option<String>,
+}
+
+impl Profile {
+    /// Flat view of every property, with the promoted columns under their
+    /// column names.
+    fn snapshot(&self) -> BTreeMap<String, String> {
+        let mut all = self.properties.clone();
+        for (key, value) in [
+            ("email", &self.email),
+            ("name", &self.name),
+            ("mobile_number", &self.mobile_number),
+        ] {
+            if let Some(value) = value {
+                all.insert(key.to_string(), value.clone());
+            }
+        }
+        all
     }
 
-    if rows.is_empty() {
-        return Ok(());
+    fn column(&mut self, column: Column) -> &mut Option<String> {
+        match column {
+            Column::Email => &mut self.email,
+            Column::Name => &mut self.name,
+            Column::MobileNumber => &mut self.mobile_number,
+        }
     }
 
-    let query = format!(
-        "INSERT INTO user_profiles \
-         (project_id, user_uid, properties, email, name, mobile_number, first_seen, last_seen, event_count, environment) \
-         VALUES {}",
-        rows.join(", ")
-    );
-
-    client
-        .query(&query)
-        .execute()
-        .await
-        .context("failed to upsert user_profiles")?;
-
-    tracing::debug!(count = rows.len(), "upserted user profiles");
-    Ok(())
+    fn apply(&mut self, op: &Op) {
+        match op {
+            Op::Set(key, value) => match Column::for_key(key) {
+                Some(column) => *self.column(column) = Some(value.clone()),
+                None => {
+                    self.properties.insert(key.clone(), value.clone());
+                }
+            },
+            Op::SetOnce(key, value) => match Column::for_key(key) {
+                Some(column) => {
+                    self.column(column).get_or_insert_with(|| value.clone());
+                }
+                None => {
+                    self.properties
+                        .entry(key.clone())
+                        .or_insert_with(|| value.clone());
+                }
+            },
+            Op::Unset(key) => match Column::for_key(key) {
+                Some(column) => *self.column(column) = None,
+                None => {
+                    self.properties.remove(key);
+                }
+            },
+            Op::Increment(key, by) => {
+                let current = match self.properties.get(key) {
+                    None => 0.0,
+                    Some(raw) => match raw.parse::<f64>() {
+                        Ok(n) => n,
+                        Err(_) => {
+                            tracing::debug!(key = %key, "skipping $increment of non-numeric property");
+                            return;
+                        }
+                    },
+                };
+                self.properties
+                    .insert(key.clone(), format_number(current + by));
+            }
+            Op::Append(key, values) => {
+                let mut list = match self.properties.get(key) {
+                    None => Vec::new(),
+                    Some(raw) => match serde_json::from_str::<serde_json::Value>(raw) {
+                        Ok(serde_json::Value::Array(items)) => items,
+                        _ => vec![serde_json::Value::String(raw.clone())],
+                    },
+                };
+                list.extend(values.iter().cloned());
+                let encoded = serde_json::Value::Array(list).to_string();
+                self.properties.insert(key.clone(), encoded);
+            }
+        }
+    }
 }
 
-/// Case-insensitive lookup: find the first value whose key matches any of
-/// the given candidates (compared lowercased).
-fn get_ci<'a>(
-    props: &'a serde_json::Map<String, serde_json::Value>,
-    candidates: &[&str],
-) -> Option<&'a str> {
-    for (k, v) in props {
-        let lower = k.to_lowercase();
-        if candidates.iter().any(|c| *c == lower) {
-            return v.as_str();
-        }
+/// Renders an incremented value, keeping whole numbers free of a fraction.
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        (n as i64).to_string()
+    } else {
+        n.to_string()
     }
-    None
 }
 
-/// Returns true if `key` (lowercased) matches any candidate.
-fn is_profile_key(key: &str, candidates: &[&str]) -> bool {
-    let lower = key.to_lowercase();
-    candidates.iter().any(|c| *c == lower)
+/// Stringifies a trait value the way `Map(String, String)` stores it.
+/// Returns `None` for nulls, which are skipped.
+fn stringify(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Null => None,
+        other => Some(other.to_string()),
+    }
 }
 
-/// Extract profile fields (email, name, mobile_number) from any event type.
-/// For identify events, also builds a properties Map for the profile.
-fn extract_profile_fields(
-    event: &EnrichedEvent,
-) -> (Option<String>, Option<String>, Option<String>, String) {
-    let is_identify = event.event_type == EventType::Identify;
-
-    let Some(serde_json::Value::Object(props)) = &event.properties else {
-        return (
-            event.email.clone(),
-            None,
-            event.mobile_number.clone(),
-            String::new(),
-        );
+/// Extracts the profile operations carried by a single event, in the order
+/// they apply.
+fn extract_operations(event: &EnrichedEvent) -> Vec<Op> {
+    let mut ops = Vec::new();
+    let props = match &event.properties {
+        Some(serde_json::Value::Object(props)) => Some(props),
+        _ => None,
     };
 
-    let email_keys = ["email", "$email"];
-    let name_keys = [
-        "name",
-        "$name",
-        "first name",
-        "first_name",
-        "full_name",
-        "fullname",
-    ];
-    let mobile_keys = ["mobile_number", "$phone", "phone", "mobile", "phone_number"];
-
-    let email = get_ci(props, &email_keys)
-        .map(String::from)
-        .or_else(|| event.email.clone());
-
-    let name = get_ci(props, &name_keys).map(String::from);
-
-    let mobile = get_ci(props, &mobile_keys)
-        .map(String::from)
-        .or_else(|| event.mobile_number.clone());
-
-    // Only build the properties map for identify events
-    let all_skip_keys: Vec<&str> = email_keys
-        .iter()
-        .chain(name_keys.iter())
-        .chain(mobile_keys.iter())
-        .copied()
-        .collect();
-
-    let props_map = if is_identify {
-        let map_entries: Vec<String> = props
-            .iter()
-            .filter(|(k, _)| !is_profile_key(k, &all_skip_keys))
-            .filter_map(|(k, v)| {
-                let val = match v {
-                    serde_json::Value::String(s) => s.clone(),
-                    serde_json::Value::Bool(b) => b.to_string(),
-                    serde_json::Value::Number(n) => n.to_string(),
-                    serde_json::Value::Null => return None,
-                    other => serde_json::to_string(other).unwrap_or_default(),
-                };
-                Some(format!("'{}', '{}'", escape(k), escape(&val)))
+    if event.event_type != EventType::Identify {
+        // Only the promoted columns are taken from non-identify events.
+        let lookup = |keys: &[&str]| {
+            props.and_then(|p| {
+                p.iter()
+                    .find(|(k, _)| keys.contains(&k.to_lowercase().as_str()))
+                    .and_then(|(_, v)| v.as_str())
+                    .map(String::from)
             })
-            .collect();
-
-        if map_entries.is_empty() {
-            String::new()
-        } else {
-            format!("map({})", map_entries.join(", "))
+        };
+        let email = lookup(&EMAIL_KEYS).or_else(|| event.email.clone());
+        let name = lookup(&NAME_KEYS);
+        let mobile = lookup(&MOBILE_KEYS).or_else(|| event.mobile_number.clone());
+        for (key, value) in [("email", email), ("name", name), ("mobile_number", mobile)] {
+            if let Some(value) = value {
+                ops.push(Op::Set(key.to_string(), value));
+            }
         }
-    } else {
-        String::new()
+        return ops;
+    }
+
+    if let Some(email) = &event.email {
+        ops.push(Op::Set("email".to_string(), email.clone()));
+    }
+    if let Some(mobile) = &event.mobile_number {
+        ops.push(Op::Set("mobile_number".to_string(), mobile.clone()));
+    }
+
+    let Some(props) = props else {
+        return ops;
     };
 
-    (email, name, mobile, props_map)
-}
+    // Implicit `$set` traits first, then the explicit operations in a fixed
+    // order so a payload's result doesn't depend on key order.
+    for (key, value) in props {
+        if ProfileOperation::from_key(key).is_none()
+            && let Some(value) = stringify(value)
+        {
+            ops.push(Op::Set(key.clone(), value));
+        }
+    }
 
-fn nullable_str(opt: &Option<String>) -> String {
-    match opt {
-        Some(s) => format!("'{}'", escape(s)),
-        None => "NULL".to_string(),
+    for op in ProfileOperation::ALL {
+        let Some(value) = props.get(op.key()) else {
+            continue;
+        };
+        // `$unset` takes a list of keys; every other operation takes an
+        // object of key/value pairs.
+        if op == ProfileOperation::Unset {
+            for key in value.as_array().into_iter().flatten() {
+                if let Some(key) = key.as_str() {
+                    ops.push(Op::Unset(key.to_string()));
+                }
+            }
+            continue;
+        }
+        for (key, value) in value.as_object().into_iter().flatten() {
+            let key = key.clone();
+            match op {
+                ProfileOperation::Set => {
+                    if let Some(value) = stringify(value) {
+                        ops.push(Op::Set(key, value));
+                    }
+                }
+                ProfileOperation::SetOnce => {
+                    if let Some(value) = stringify(value) {
+                        ops.push(Op::SetOnce(key, value));
+                    }
+                }
+                ProfileOperation::Increment => {
+                    if let Some(by) = value.as_f64() {
+                        ops.push(Op::Increment(key, by));
+                    }
+                }
+                ProfileOperation::Append => {
+                    let values = match value {
+                        serde_json::Value::Array(items) => items.clone(),
+                        other => vec![other.clone()],
+                    };
+                    ops.push(Op::Append(key, values));
+                }
+                // Handled above; `$unset` has no key/value pairs.
+                ProfileOperation::Unset => {}
+            }
+        }
     }
+
+    ops
+}
+
+/// Events of one batch that touch the same profile.
+struct PendingProfile {
+    environment: String,
+    user_uid: String,
+    /// `(index into events, operations)`, in application order.
+    updates: Vec<(usize, Vec<Op>)>,
+}
+
+/// Applies profile operations with read-modify-write semantics.
+///
+/// Updates are serialised within this process so two in-flight batches do
+/// not overwrite each other's changes to the same profile. Writers on other
+/// instances are not: if two instances update the same profile at once, the
+/// row with the later `last_seen` replaces the other on merge, and the other
+/// instance's `$set_once`, `$increment` and `$append` changes are lost. The
+/// next update of the profile does not restore them.
+pub struct ProfileWriter {
+    lock: Mutex<()>,
 }
 
-fn escape(s: &str) -> String {
-    s.replace('\\', "\\\\")
-        .replace('\'', "\\'")
-        .replace('?', "??")
+impl ProfileWriter {
+    pub fn new() -> Self {
+        Self {
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Upserts user profiles for a batch of enriched events.
+    ///
+    /// Events with no profile operations are skipped, so plain track events
+    /// never touch a profile. Event counts and timestamps for analytics are
+    /// handled by the `user_stats` materialized view.
+    ///
+    /// Returns the indices (into `events`) of events whose profile could not
+    /// be written, each paired with the error.
+    pub async fn upsert(
+        &self,
+        inserter: &ClickHouseInserter,
+        events: &[EnrichedEvent],
+    ) -> Vec<(usize, String)> {
+        // project -> (environment, user_uid) -> pending updates
+        let mut by_project: BTreeMap<Uuid, BTreeMap<(String, String), PendingProfile>> =
+            BTreeMap::new();
+
+        for (i, event) in events.iter().enumerate() {
+            let ops = extract_operations(event);
+            if ops.is_empty() {
+                continue;
+            }
+
+            let user_uid = match &event.user_id {
+                Some(uid) if !uid.is_empty() => uid.clone(),
+                _ => event.anonymous_id.clone(),
+            };
+
+            by_project
+                .entry(event.project_id)
+                .or_default()
+                .entry((event.environment.clone(), user_uid.clone()))
+                .or_insert_with(|| PendingProfile {
+                    environment: event.environment.clone(),
+                    user_uid,
+                    updates: Vec::new(),
+                })
+                .updates
+                .push((i, ops));
+        }
+
+        if by_project.is_empty() {
+            return Vec::new();
+        }
+
+        let _guard = self.lock.lock().await;
+        let mut failures = Vec::new();
+
+        for (project_id, pending) in by_project {
+            let sources = || {
+                pending
+                    .values()
+                    .flat_map(|p| p.updates.iter().map(|(i, _)| *i))
+            };
+            match self
+                .upsert_project(inserter, events, project_id, &pending)
+                .await
+            {
+                Ok(project_failures) => failures.extend(project_failures),
+                Err(e) => {
+                    let reason = format!("{e:#}");
+                    failures.extend(sources().map(|i| (i, reason.clone())));
+                }
+            }
+        }
+
+        failures
+    }
+
+    async fn upsert_project(
+        &self,
+        inserter: &ClickHouseInserter,
+        events: &[EnrichedEvent],
+        project_id: Uuid,
+        pending: &BTreeMap<(String, String), PendingProfile>,
+    ) -> Result<Vec<(usize, String)>> {
+        let user_uids: Vec<&str> = pending.values().map(|p| p.user_uid.as_str()).collect();
+
+        let mut existing: HashMap<(String, String), ProfileRow> = inserter
+            .client()
+            .query(
+                "SELECT ?fields FROM user_profiles FINAL \
+                 WHERE project_id = ? AND has(?, user_uid)",
+            )
+            .bind(project_id)
+            .bind(&user_uids)
+            .fetch_all::<ProfileRow>()
+            .await
+            .context("failed to read user_profiles")?
+            .into_iter()
+            .map(|row| ((row.environment.clone(), row.user_uid.clone()), row))
+            .collect();
+
+        let mut rows = Vec::with_capacity(pending.len());
+        let mut row_sources: Vec<Vec<usize>> = Vec::with_capacity(pending.len());
+        let mut history = Vec::new();
+        let mut history_sources = Vec::new();
+
+        for (key, profile) in pending {
+            let current = existing.remove(key);
+
+            let mut updates: Vec<&(usize, Vec<Op>)> = profile.updates.iter().collect();
+            updates.sort_by_key(|(i, _)| events[*i].server_timestamp);
+
+            let first_ts = events[updates[0].0].server_timestamp;
+            let last_ts = events[updates[updates.len() - 1].0].server_timestamp;
+
+            let (mut state, first_seen, last_seen, event_count) = match current {
+                Some(row) => (
+                    Profile {
+                        properties: row.properties.into_iter().collect(),
+                        email: row.email,
+                        name: row.name,
+                        mobile_number: row.mobile_number,
+                    },
+                    row.first_seen.min(first_ts),
+                    // Never move last_seen backwards, or the merged row would
+                    // lose to the one it replaces.
+                    row.last_seen.max(last_ts),
+                    row.event_count,
+                ),
+                None => (
+                    Profile {
+                        properties: BTreeMap::new(),
+                        email: None,
+                        name: None,
+                        mobile_number: None,
+                    },
+                    first_ts,
+                    last_ts,
+                    0,
+                ),
+            };
+
+            let mut before = state.snapshot();
+            for (i, ops) in &updates {
+                for op in ops {
+                    state.apply(op);
+                }
+
+                let after = state.snapshot();
+                let removed = before
+                    .keys()
+                    .filter(|k| !after.contains_key(*k))
+                    .map(|k| (k.clone(), String::new()));
+                let changed = after
+                    .iter()
+                    .filter(|(k, v)| before.get(*k) != Some(*v))
+                    .map(|(k, v)| (k.clone(), v.clone()));
+                for (property, value) in removed.chain(changed) {
+                    history.push(HistoryRow {
+                        project_id,
+                        user_uid: profile.user_uid.clone(),
+                        environment: profile.environment.clone(),
+                        property,
+                        value,
+                        valid_from: events[*i].server_timestamp,
+                    });
+                    history_sources.push(*i);
+                }
+                before = after;
+            }
+
+            row_sources.push(updates.iter().map(|(i, _)| *i).collect());
+            rows.push(ProfileRow {
+                project_id,
+                user_uid: profile.user_uid.clone(),
+                properties: state.properties.into_iter().collect(),
+                email: state.email,
+                name: state.name,
+                mobile_number: state.mobile_number,
+                first_seen,
+                last_seen,
+                event_count: event_count + updates.len() as u64,
+                environment: profile.environment.clone(),
+            });
+        }
+
+        let failures = inserter.insert_rows("user_profiles", &rows).await?;
+        let history_failures = inserter
+            .insert_rows("user_profile_history", &history)
+            .await?;
+
+        tracing::debug!(
+            project_id = %project_id,
+            count = rows.len(),
+            failed = failures.len(),
+            history = history.len(),
+            history_failed = history_failures.len(),
+            "upserted user profiles"
+        );
+
+        Ok(failures
+            .into_iter()
+            .flat_map(|(row_idx, reason)| {
+                row_sources[row_idx]
+                    .iter()
+                    .map(move |&i| (i, reason.clone()))
+            })
+            .chain(history_failures.into_iter().map(|(row_idx, reason)| {
+                (
+                    history_sources[row_idx],
+                    format!("user_profile_history: {reason}"),
+                )
+            }))
+            .collect())
+    }
 }
@@ -0,0 +1,411 @@
+//! Event fan-out to HTTP webhook destinations.
+//!
+//! After a batch is inserted, the batcher hands the inserted events to the
+//! [`WebhookDispatcher`]. A single background [`WebhookWorker`] matches them
+//! against the enabled destinations (cached from Postgres and refreshed
+//! periodically), buffers matches per destination, and delivers each buffer
+//! once it reaches the destination's `batch_size` or [`FLUSH_INTERVAL`]
+//! elapses.
+//!
+//! Deliveries are signed (see [`truesight_common::webhook`]) and retried with
+//! exponential back-off on transport errors, `429` and `5xx`. A delivery that
+//! still fails is stored in `webhook_dead_letters` for that destination.
+//!
+//! Destination hosts are resolved by [`PublicResolver`], which refuses
+//! non-public addresses, and redirects are not followed, so a destination
+//! cannot reach internal services however its hostname resolves.
+//!
+//! Fan-out never holds up ingestion: when the worker falls behind, e.g. on a
+//! slow endpoint, and its channel is full, the batcher's events are dropped
+//! from fan-out and counted in [`WebhookStats`] instead of waiting for room.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use diesel::prelude::*;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::Serialize;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{Semaphore, mpsc};
+use truesight_common::db::{DbPool, get_conn};
+use truesight_common::event::EnrichedEvent;
+use truesight_common::schema::{webhook_dead_letters, webhook_destinations};
+use truesight_common::webhook::{
+    NewWebhookDeadLetter, SIGNATURE_HEADER, TIMESTAMP_HEADER, WebhookDestination, WebhookFilter,
+    check_destination_url, is_public_ip, parse_filters, sign,
+};
+use uuid::Uuid;
+
+/// Maximum delay before a partially filled destination buffer is delivered.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Delivery attempts before a batch is dead-lettered.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Base delay for exponential back-off between attempts (1s, 2s, 4s, 8s).
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Per-request timeout.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum number of deliveries in flight across all destinations. When
+/// reached, the worker stops draining its channel, and once the channel is
+/// full further events are dropped instead of buffered without bound.
+const MAX_CONCURRENT_DELIVERIES: usize = 16;
+
+/// Capacity of the channel between the batcher and the worker, in batches.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Cloneable handle used by the batcher to submit inserted events.
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    sender: mpsc::Sender<Vec<EnrichedEvent>>,
+    stats: WebhookStats,
+}
+
+/// Fan-out counters shared with the health endpoint. Unlike a
+/// [`WebhookDispatcher`], holding one does not keep the worker running.
+#[derive(Clone, Default)]
+pub struct WebhookStats {
+    /// Events dropped because the worker's channel was full.
+    dropped: Arc<AtomicU64>,
+}
+
+/// Point-in-time view of [`WebhookStats`], exposed via `/health`.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookSnapshot {
+    pub events_dropped: u64,
+}
+
+impl WebhookStats {
+    pub fn snapshot(&self) -> WebhookSnapshot {
+        WebhookSnapshot {
+            events_dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl WebhookDispatcher {
+    /// Creates a dispatcher and the worker that serves it.
+    pub fn new(pool: DbPool, refresh_interval: Duration) -> Result<(Self, WebhookWorker)> {
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .dns_resolver(Arc::new(PublicResolver))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .context("failed to build webhook HTTP client")?;
+
+        Ok((
+            Self {
+                sender,
+                stats: WebhookStats::default(),
+            },
+            WebhookWorker {
+                receiver,
+                pool,
+                http,
+                refresh_interval,
+            },
+        ))
+    }
+
+    /// Counters of this dispatcher and its clones.
+    pub fn stats(&self) -> WebhookStats {
+        self.stats.clone()
+    }
+
+    /// Submits inserted events for fan-out. Drops them if the worker is
+    /// saturated, so a slow endpoint cannot back-pressure ingestion.
+    pub fn dispatch(&self, events: &[EnrichedEvent]) {
+        if events.is_empty() {
+            return;
+        }
+        match self.sender.try_send(events.to_vec()) {
+            Ok(()) => {}
+            Err(TrySendError::Full(events)) => {
+                let dropped = self
+                    .stats
+                    .dropped
+                    .fetch_add(events.len() as u64, Ordering::Relaxed)
+                    + events.len() as u64;
+                tracing::error!(
+                    count = events.len(),
+                    total_dropped = dropped,
+                    "webhook worker saturated, events not forwarded"
+                );
+            }
+            Err(TrySendError::Closed(events)) => {
+                tracing::error!(
+                    count = events.len(),
+                    "webhook worker stopped, events not forwarded"
+                );
+            }
+        }
+    }
+}
+
+/// Resolves destination hosts to their public addresses only, failing the
+/// delivery if there are none.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{host} does not resolve to a public address").into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// A destination with its parsed filters.
+struct Route {
+    destination: Arc<WebhookDestination>,
+    filters: Vec<WebhookFilter>,
+}
+
+/// Background task that matches, buffers and delivers events.
+pub struct WebhookWorker {
+    receiver: mpsc::Receiver<Vec<EnrichedEvent>>,
+    pool: DbPool,
+    http: reqwest::Client,
+    refresh_interval: Duration,
+}
+
+impl WebhookWorker {
+    /// Runs until every [`WebhookDispatcher`] is dropped, then delivers what
+    /// is still buffered and waits for in-flight deliveries.
+    pub async fn run(mut self) {
+        tracing::info!(
+            refresh_secs = self.refresh_interval.as_secs(),
+            "webhook worker started"
+        );
+
+        let in_flight = Arc::new(Semaphore::new(MAX_CONCURRENT_DELIVERIES));
+        let mut routes = self.load_routes().await.unwrap_or_else(|e| {
+            tracing::error!(error = %format!("{e:#}"), "failed to load webhook destinations");
+            Vec::new()
+        });
+        let mut buffers: HashMap<Uuid, (Arc<WebhookDestination>, Vec<EnrichedEvent>)> =
+            HashMap::new();
+
+        let mut refresh = tokio::time::interval(self.refresh_interval);
+        refresh.tick().await;
+        let mut flush = tokio::time::interval(FLUSH_INTERVAL);
+        flush.tick().await;
+
+        loop {
+            tokio::select! {
+                maybe_events = self.receiver.recv() => {
+                    let Some(events) = maybe_events else { break };
+                    for event in events {
+                        for route in routes.iter().filter(|r| r.destination.matches(&event, &r.filters)) {
+                            let (destination, buffer) = buffers
+                                .entry(route.destination.id)
+                                .or_insert_with(|| (Arc::clone(&route.destination), Vec::new()));
+                            // Deliver with the latest secret and URL after a refresh.
+                            *destination = Arc::clone(&route.destination);
+                            buffer.push(event.clone());
+                            if buffer.len() >= destination.batch_size.max(1) as usize {
+                                let batch = std::mem::take(buffer);
+                                self.spawn_delivery(Arc::clone(destination), batch, &in_flight).await;
+                            }
+                        }
+                    }
+                }
+                _ = flush.tick() => {
+                    for (destination, buffer) in buffers.values_mut() {
+                        if !buffer.is_empty() {
+                            let batch = std::mem::take(buffer);
+                            self.spawn_delivery(Arc::clone(destination), batch, &in_flight).await;
+                        }
+                    }
+                }
+                _ = refresh.tick() => {
+                    match self.load_routes().await {
+                        Ok(loaded) => routes = loaded,
+                        Err(e) => tracing::error!(
+                            error = %format!("{e:#}"),
+                            "failed to refresh webhook destinations, keeping previous set"
+                        ),
+                    }
+                }
+            }
+        }
+
+        for (destination, buffer) in buffers.into_values() {
+            if !buffer.is_empty() {
+                self.spawn_delivery(destination, buffer, &in_flight).await;
+            }
+        }
+        let _ = in_flight
+            .acquire_many(MAX_CONCURRENT_DELIVERIES as u32)
+            .await;
+        tracing::info!("webhook worker shut down");
+    }
+
+    /// Loads enabled destinations. Destinations with invalid filters are
+    /// skipped rather than matching everything.
+    async fn load_routes(&self) -> Result<Vec<Route>> {
+        let pool = self.pool.clone();
+        let destinations = tokio::task::spawn_blocking(move || -> Result<_> {
+            let mut conn = get_conn(&pool)?;
+            webhook_destinations::table
+                .filter(webhook_destinations::enabled.eq(true))
+                .select(WebhookDestination::as_select())
+                .load::<WebhookDestination>(&mut conn)
+                .context("failed to query webhook_destinations")
+        })
+        .await??;
+
+        Ok(destinations
+            .into_iter()
+            .filter_map(|d| match parse_filters(&d.filters) {
+                Ok(filters) => Some(Route {
+                    destination: Arc::new(d),
+                    filters,
+                }),
+                Err(e) => {
+                    tracing::warn!(destination_id = %d.id, error = %e, "skipping webhook destination");
+                    None
+                }
+            })
+            .collect())
+    }
+
+    async fn spawn_delivery(
+        &self,
+        destination: Arc<WebhookDestination>,
+        events: Vec<EnrichedEvent>,
+        in_flight: &Arc<Semaphore>,
+    ) {
+        let permit = Arc::clone(in_flight)
+            .acquire_owned()
+            .await
+            .expect("semaphore closed");
+        let http = self.http.clone();
+        let pool = self.pool.clone();
+
+        tokio::spawn(async move {
+            deliver(&http, &pool, &destination, events).await;
+            drop(permit);
+        });
+    }
+}
+
+/// Delivers one batch, retrying transient failures, and dead-letters it if
+/// every attempt fails.
+async fn deliver(
+    http: &reqwest::Client,
+    pool: &DbPool,
+    destination: &WebhookDestination,
+    events: Vec<EnrichedEvent>,
+) {
+    let payload = serde_json::json!({
+        "destination_id": destination.id,
+        "events": events,
+    });
+    let body = payload.to_string();
+    let mut last_error = String::new();
+    let mut attempts = 0;
+
+    // Destinations saved before URLs were checked, or pointing at a literal
+    // address the resolver never sees, are refused here.
+    let mut max_attempts = MAX_ATTEMPTS;
+    if let Err(e) = check_destination_url(&destination.url) {
+        last_error = e;
+        max_attempts = 0;
+    }
+
+    while attempts < max_attempts {
+        if attempts > 0 {
+            tokio::time::sleep(BASE_DELAY * 2u32.pow(attempts - 1)).await;
+        }
+        attempts += 1;
+
+        let timestamp = chrono::Utc::now().timestamp();
+        let result = http
+            .post(&destination.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(
+                SIGNATURE_HEADER,
+                sign(&destination.secret, timestamp, body.as_bytes()),
+            )
+            .body(body.clone())
+            .send()
+            .await;
+
+        match result {
+            Ok(resp) if resp.status().is_success() => {
+                tracing::debug!(
+                    destination_id = %destination.id,
+                    count = events.len(),
+                    attempts,
+                    "webhook delivered"
+                );
+                return;
+            }
+            Ok(resp) => {
+                let status = resp.status();
+                last_error = format!("HTTP {status}");
+                let retryable = status.is_server_error() || status.as_u16() == 429;
+                if !retryable {
+                    break;
+                }
+            }
+            Err(e) => last_error = format!("{e}"),
+        }
+
+        tracing::warn!(
+            destination_id = %destination.id,
+            attempt = attempts,
+            error = %last_error,
+            "webhook delivery failed"
+        );
+    }
+
+    tracing::error!(
+        destination_id = %destination.id,
+        count = events.len(),
+        attempts,
+        error = %last_error,
+        "webhook delivery dead-lettered"
+    );
+
+    let dead_letter = NewWebhookDeadLetter {
+        destination_id: destination.id,
+        events: payload["events"].clone(),
+        error: last_error,
+        attempts: attempts as i32,
+    };
+    let pool = pool.clone();
+    let stored = tokio::task::spawn_blocking(move || -> Result<()> {
+        let mut conn = get_conn(&pool)?;
+        diesel::insert_into(webhook_dead_letters::table)
+            .values(&dead_letter)
+            .execute(&mut conn)
+            .context("failed to insert webhook dead letter")?;
+        Ok(())
+    })
+    .await;
+
+    match stored {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            tracing::error!(error = %format!("{e:#}"), "failed to store webhook dead letter")
+        }
+        Err(e) => tracing::error!(error = %e, "webhook dead letter task panicked"),
+    }
+}
@@ -101,6 +101,11 @@ pub enum Command {
         #[command(subcommand)]
         command: FlowsCommand,
     },
+    /// Run a session analytics query
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
     /// Query user profiles and events
     Users {
         #[command(subcommand)]
@@ -126,6 +131,33 @@ pub enum Command {
         #[command(subcommand)]
         command: FunnelsCommand,
     },
+    /// Inspect and redrive dead-lettered events (requires the admin token)
+    Dlq {
+        #[command(subcommand)]
+        command: DlqCommand,
+    },
+    /// Manage webhook destinations and their dead letters
+    Webhooks {
+        #[command(subcommand)]
+        command: WebhooksCommand,
+    },
+    /// Show and set how long project data is kept
+    #[command(name = "data-retention")]
+    DataRetention {
+        #[command(subcommand)]
+        command: DataRetentionCommand,
+    },
+    /// Export a user's data (data subject access request)
+    #[command(name = "data-exports")]
+    DataExports {
+        #[command(subcommand)]
+        command: DataExportsCommand,
+    },
+    /// Erase a user's data (right to erasure)
+    Erasures {
+        #[command(subcommand)]
+        command: ErasuresCommand,
+    },
 }
 
 // -- Auth --
@@ -500,11 +532,20 @@ pub enum EventCatalogCommand {
         #[arg(long)]
         per_page: Option<u32>,
     },
-    /// Get properties for a specific event name
+    /// Get properties for a specific event name, with their value types
     Properties {
         /// Event name to get properties for
         event_name: String,
     },
+    /// List properties whose value type changed, and the SDK versions that sent each type
+    Drift {
+        /// Only check this event name
+        #[arg(long)]
+        event_name: Option<String>,
+        /// Only report type changes first seen on or after this date
+        #[arg(long)]
+        since: Option<String>,
+    },
 }
 
 // -- Properties --
@@ -597,6 +638,21 @@ pub enum FlowsCommand {
     },
 }
 
+// -- Sessions --
+
+#[derive(Subcommand)]
+pub enum SessionsCommand {
+    /// Run a sessions query (POST with JSON body)
+    Query {
+        /// Inline JSON body
+        #[arg(long)]
+        body: Option<String>,
+        /// Path to JSON file
+        #[arg(long)]
+        body_file: Option<String>,
+    },
+}
+
 // -- Users --
 
 #[derive(Subcommand)]
@@ -893,6 +949,15 @@ pub enum FunnelsCommand {
         from: Option<String>,
         #[arg(long)]
         to: Option<String>,
+        /// Split users by a property (e.g. `platform`, `app_version`, `profile:plan`)
+        #[arg(long, conflicts_with = "breakdown_segments")]
+        breakdown: Option<String>,
+        /// Split users by membership of these comma-separated segment IDs
+        #[arg(long)]
+        breakdown_segments: Option<String>,
+        /// Property values to show before grouping the rest as "other"
+        #[arg(long, requires = "breakdown")]
+        breakdown_limit: Option<usize>,
     },
     /// Compare funnel results across segments
     Compare {
@@ -919,3 +984,188 @@ pub enum FunnelsCommand {
         compare_to: String,
     },
 }
+
+// -- DLQ --
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum RedriveTarget {
+    /// Send back to the main SQS queue for normal processing
+    Queue,
+    /// Insert directly into ClickHouse (skips identity/profile processing)
+    Clickhouse,
+}
+
+#[derive(Subcommand)]
+pub enum DlqCommand {
+    /// List DLQ messages grouped by error reason, with payload previews
+    List {
+        /// Maximum number of messages to scan (max 1000)
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Only show messages for this project ID
+        #[arg(long)]
+        project_id: Option<String>,
+        /// Only show messages whose error reason contains this text
+        #[arg(long)]
+        reason: Option<String>,
+        /// Show full message bodies instead of previews
+        #[arg(long)]
+        include_body: bool,
+    },
+    /// Redrive selected DLQ messages
+    Redrive {
+        /// Message ID to redrive (repeatable)
+        #[arg(long = "id")]
+        ids: Vec<String>,
+        /// Redrive every message whose error reason contains this text
+        #[arg(long)]
+        reason: Option<String>,
+        /// Only redrive messages for this project ID
+        #[arg(long)]
+        project_id: Option<String>,
+        /// Where to send the messages
+        #[arg(long, value_enum, default_value = "queue")]
+        target: RedriveTarget,
+        /// Maximum number of messages to scan (max 1000)
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+}
+
+// -- Webhooks --
+
+#[derive(Subcommand)]
+pub enum WebhooksCommand {
+    /// List webhook destinations
+    List,
+    /// Get a webhook destination by ID
+    Get {
+        /// Webhook ID
+        id: String,
+    },
+    /// Create a webhook destination (POST with JSON body); prints the signing secret
+    Create {
+        /// Inline JSON body
+        #[arg(long)]
+        body: Option<String>,
+        /// Path to JSON file
+        #[arg(long)]
+        body_file: Option<String>,
+    },
+    /// Update a webhook destination (PATCH with JSON body)
+    Update {
+        /// Webhook ID
+        id: String,
+        /// Inline JSON body
+        #[arg(long)]
+        body: Option<String>,
+        /// Path to JSON file
+        #[arg(long)]
+        body_file: Option<String>,
+    },
+    /// Delete a webhook destination
+    Delete {
+        /// Webhook ID
+        id: String,
+    },
+    /// Replace the signing secret and print the new one
+    #[command(name = "rotate-secret")]
+    RotateSecret {
+        /// Webhook ID
+        id: String,
+    },
+    /// List deliveries that exhausted their retries
+    #[command(name = "dead-letters")]
+    DeadLetters {
+        /// Webhook ID
+        id: String,
+        /// Maximum number of dead letters (max 100)
+        #[arg(long)]
+        limit: Option<u32>,
+    },
+    /// Delete every dead letter of a webhook destination
+    #[command(name = "clear-dead-letters")]
+    ClearDeadLetters {
+        /// Webhook ID
+        id: String,
+    },
+}
+
+// -- Data Retention --
+
+#[derive(Subcommand)]
+pub enum DataRetentionCommand {
+    /// Show the retention in effect per environment and the next purges
+    Show,
+    /// Keep an environment's data for a number of days (requires project admin)
+    Set {
+        /// Environment: live or test
+        #[arg(long)]
+        environment: String,
+        /// Days to keep data for (1-3650)
+        #[arg(long)]
+        days: u32,
+    },
+    /// Remove an environment's policy so its data is kept forever (requires project admin)
+    Clear {
+        /// Environment: live or test
+        #[arg(long)]
+        environment: String,
+    },
+}
+
+// -- Data Exports --
+
+#[derive(Subcommand)]
+pub enum DataExportsCommand {
+    /// Export a user's profile, identities and events (requires project admin)
+    Create {
+        /// User UID as shown by `users list`
+        #[arg(long)]
+        user: String,
+        /// Format of the identity and event files: json or csv
+        #[arg(long, default_value = "json")]
+        format: String,
+    },
+    /// List data exports (requires project admin)
+    List,
+    /// Show a data export and its status (requires project admin)
+    Get {
+        /// Data export ID
+        id: String,
+    },
+    /// Download a completed export as a tar archive (requires project admin)
+    Download {
+        /// Data export ID
+        id: String,
+        /// File to write (default: truesight-export-<ID>.tar)
+        #[arg(long, short)]
+        output: Option<String>,
+    },
+}
+
+// -- Erasures --
+
+#[derive(Subcommand)]
+pub enum ErasuresCommand {
+    /// Erase a user's data and drop their future events (requires project admin)
+    #[command(group = clap::ArgGroup::new("identifier").required(true))]
+    Create {
+        /// User ID of the user to erase
+        #[arg(long, group = "identifier")]
+        user_id: Option<String>,
+        /// Email of the user to erase
+        #[arg(long, group = "identifier")]
+        email: Option<String>,
+        /// Mobile number of the user to erase
+        #[arg(long, group = "identifier")]
+        mobile_number: Option<String>,
+    },
+    /// List erasure requests (requires project admin)
+    List,
+    /// Show an erasure request and its progress per table (requires project admin)
+    Get {
+        /// Erasure request ID
+        id: String,
+    },
+}
@@ -34,6 +34,25 @@ impl TrueSightClient {
         self.handle_response(resp).await
     }
 
+    /// GET a non-JSON response, e.g. a file download.
+    pub async fn get_bytes(&self, path: &str) -> Result<Vec<u8>> {
+        let url = format!("{}{path}", self.base_url);
+        let resp = self
+            .http
+            .get(&url)
+            .header(AUTHORIZATION, self.auth_header())
+            .send()
+            .await
+            .context("HTTP request failed")?;
+
+        if !resp.status().is_success() {
+            self.handle_response(resp).await?;
+            bail!("Unexpected response");
+        }
+        let bytes = resp.bytes().await.context("Failed to read response body")?;
+        Ok(bytes.to_vec())
+    }
+
     pub async fn post(&self, path: &str, body: Option<Value>) -> Result<Value> {
         let url = format!("{}{path}", self.base_url);
         let mut req = self
@@ -65,6 +84,21 @@ impl TrueSightClient {
         self.handle_response(resp).await
     }
 
+    pub async fn put(&self, path: &str, body: Value) -> Result<Value> {
+        let url = format!("{}{path}", self.base_url);
+        let resp = self
+            .http
+            .put(&url)
+            .header(AUTHORIZATION, self.auth_header())
+            .header(CONTENT_TYPE, "application/json")
+            .json(&body)
+            .send()
+            .await
+            .context("HTTP request failed")?;
+
+        self.handle_response(resp).await
+    }
+
     pub async fn delete(&self, path: &str) -> Result<Value> {
         let url = format!("{}{path}", self.base_url);
         let resp = self
@@ -0,0 +1,42 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+
+use crate::cli::{DataExportsCommand, OutputFormat};
+use crate::client::TrueSightClient;
+use crate::output::render;
+
+pub async fn run(
+    command: &DataExportsCommand,
+    client: &TrueSightClient,
+    project: &str,
+    format: OutputFormat,
+) -> Result<()> {
+    let base = format!("/v1/projects/{project}/data-exports");
+    match command {
+        DataExportsCommand::Create {
+            user,
+            format: file_format,
+        } => {
+            let body = json!({ "user_uid": user, "format": file_format });
+            let resp = client.post(&base, Some(body)).await?;
+            render(format, &resp);
+        }
+        DataExportsCommand::List => {
+            let resp = client.get(&base).await?;
+            render(format, &resp);
+        }
+        DataExportsCommand::Get { id } => {
+            let resp = client.get(&format!("{base}/{id}")).await?;
+            render(format, &resp);
+        }
+        DataExportsCommand::Download { id, output } => {
+            let archive = client.get_bytes(&format!("{base}/{id}/download")).await?;
+            let path = output
+                .clone()
+                .unwrap_or_else(|| format!("truesight-export-{id}.tar"));
+            std::fs::write(&path, &archive).with_context(|| format!("Cannot write {path}"))?;
+            render(format, &json!({ "path": path, "bytes": archive.len() }));
+        }
+    }
+    Ok(())
+}
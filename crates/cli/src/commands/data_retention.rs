@@ -0,0 +1,34 @@
+use anyhow::Result;
+use serde_json::json;
+
+use crate::cli::{DataRetentionCommand, OutputFormat};
+use crate::client::TrueSightClient;
+use crate::output::render;
+
+pub async fn run(
+    command: &DataRetentionCommand,
+    client: &TrueSightClient,
+    project: &str,
+    format: OutputFormat,
+) -> Result<()> {
+    let base = format!("/v1/projects/{project}/data-retention");
+    match command {
+        DataRetentionCommand::Show => {
+            let resp = client.get(&base).await?;
+            render(format, &resp);
+        }
+        DataRetentionCommand::Set { environment, days } => {
+            let resp = client
+                .put(
+                    &format!("{base}/{environment}"),
+                    json!({ "retention_days": days }),
+                )
+                .await?;
+            render(format, &resp);
+        }
+        DataRetentionCommand::Clear { environment } => {
+            client.delete(&format!("{base}/{environment}")).await?;
+        }
+    }
+    Ok(())
+}
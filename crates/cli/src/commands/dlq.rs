@@ -0,0 +1,59 @@
+use anyhow::Result;
+use serde_json::json;
+
+use crate::cli::{DlqCommand, OutputFormat, RedriveTarget};
+use crate::client::TrueSightClient;
+use crate::output::render;
+
+pub async fn run(
+    command: &DlqCommand,
+    client: &TrueSightClient,
+    format: OutputFormat,
+) -> Result<()> {
+    match command {
+        DlqCommand::List {
+            limit,
+            project_id,
+            reason,
+            include_body,
+        } => {
+            let mut url = "/v1/dlq?".to_string();
+            if let Some(l) = limit {
+                url.push_str(&format!("limit={l}&"));
+            }
+            if let Some(pid) = project_id {
+                url.push_str(&format!("project_id={pid}&"));
+            }
+            if let Some(r) = reason {
+                url.push_str(&format!("reason={}&", super::urlencoding(r)));
+            }
+            if *include_body {
+                url.push_str("include_body=true&");
+            }
+            let resp = client.get(url.trim_end_matches(['&', '?'])).await?;
+            render(format, &resp);
+        }
+        DlqCommand::Redrive {
+            ids,
+            reason,
+            project_id,
+            target,
+            limit,
+        } => {
+            let target = match target {
+                RedriveTarget::Queue => "queue",
+                RedriveTarget::Clickhouse => "clickhouse",
+            };
+            let body = json!({
+                "message_ids": ids,
+                "reason": reason,
+                "project_id": project_id,
+                "target": target,
+                "limit": limit,
+            });
+            let resp = client.post("/v1/dlq/redrive", Some(body)).await?;
+            render(format, &resp);
+        }
+    }
+    Ok(())
+}
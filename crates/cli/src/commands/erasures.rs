@@ -0,0 +1,39 @@
+use anyhow::Result;
+use serde_json::json;
+
+use crate::cli::{ErasuresCommand, OutputFormat};
+use crate::client::TrueSightClient;
+use crate::output::render;
+
+pub async fn run(
+    command: &ErasuresCommand,
+    client: &TrueSightClient,
+    project: &str,
+    format: OutputFormat,
+) -> Result<()> {
+    let base = format!("/v1/projects/{project}/erasures");
+    match command {
+        ErasuresCommand::Create {
+            user_id,
+            email,
+            mobile_number,
+        } => {
+            let body = json!({
+                "user_id": user_id,
+                "email": email,
+                "mobile_number": mobile_number,
+            });
+            let resp = client.post(&base, Some(body)).await?;
+            render(format, &resp);
+        }
+        ErasuresCommand::List => {
+            let resp = client.get(&base).await?;
+            render(format, &resp);
+        }
+        ErasuresCommand::Get { id } => {
+            let resp = client.get(&format!("{base}/{id}")).await?;
+            render(format, &resp);
+        }
+    }
+    Ok(())
+}
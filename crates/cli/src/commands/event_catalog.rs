@@ -33,19 +33,24 @@ pub async fn run(
             render(format, &resp);
         }
         EventCatalogCommand::Properties { event_name } => {
-            let encoded = urlencoding(event_name);
+            let encoded = super::urlencoding(event_name);
             let resp = client
                 .get(&format!("{base}/event-catalog/{encoded}/properties"))
                 .await?;
             render(format, &resp);
         }
+        EventCatalogCommand::Drift { event_name, since } => {
+            let mut url = format!("{base}/event-catalog/drift?");
+            if let Some(name) = event_name {
+                url.push_str(&format!("event_name={}&", super::urlencoding(name)));
+            }
+            if let Some(since) = since {
+                let since = super::stats::normalize_dt(since, false);
+                url.push_str(&format!("since={}&", super::urlencoding(&since)));
+            }
+            let resp = client.get(url.trim_end_matches(['&', '?'])).await?;
+            render(format, &resp);
+        }
     }
     Ok(())
 }
-
-fn urlencoding(s: &str) -> String {
-    s.replace(' ', "%20")
-        .replace('#', "%23")
-        .replace('&', "%26")
-        .replace('?', "%3F")
-}
@@ -37,7 +37,14 @@ pub async fn run(
         FunnelsCommand::Delete { id } => {
             client.delete(&format!("{base}/{id}")).await?;
         }
-        FunnelsCommand::Results { id, from, to } => {
+        FunnelsCommand::Results {
+            id,
+            from,
+            to,
+            breakdown,
+            breakdown_segments,
+            breakdown_limit,
+        } => {
             let mut url = format!("{base}/{id}/results?");
             if let Some(f) = from {
                 url.push_str(&format!("from={f}&"));
@@ -45,6 +52,15 @@ pub async fn run(
             if let Some(t) = to {
                 url.push_str(&format!("to={t}&"));
             }
+            if let Some(key) = breakdown {
+                url.push_str(&format!("breakdown={}&", super::urlencoding(key)));
+            }
+            if let Some(ids) = breakdown_segments {
+                url.push_str(&format!("breakdown_segment_ids={ids}&"));
+            }
+            if let Some(limit) = breakdown_limit {
+                url.push_str(&format!("breakdown_limit={limit}&"));
+            }
             let resp = client.get(url.trim_end_matches('&')).await?;
             render(format, &resp);
         }
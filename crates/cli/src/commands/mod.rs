@@ -2,6 +2,10 @@ pub mod boards;
 pub mod update;
 pub mod cohorts;
 pub mod config;
+pub mod data_exports;
+pub mod data_retention;
+pub mod dlq;
+pub mod erasures;
 pub mod event_catalog;
 pub mod flows;
 pub mod funnels;
@@ -10,11 +14,13 @@ pub mod projects;
 pub mod properties;
 pub mod retention;
 pub mod segments;
+pub mod sessions;
 pub mod skill;
 pub mod stats;
 pub mod teams;
 pub mod trends;
 pub mod users;
+pub mod webhooks;
 
 use anyhow::{Result, bail};
 use serde_json::Value;
@@ -43,6 +49,17 @@ pub fn read_body(body: &Option<String>, body_file: &Option<String>) -> Result<Va
     bail!("No request body provided. Use --body, --body-file, or pipe JSON to stdin.")
 }
 
+/// Percent-encode the characters that would break a path segment or query value.
+pub fn urlencoding(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace(' ', "%20")
+        .replace('#', "%23")
+        .replace('&', "%26")
+        .replace('+', "%2B")
+        .replace('=', "%3D")
+        .replace('?', "%3F")
+}
+
 fn atty_check() -> bool {
     use std::io::IsTerminal;
     std::io::stdin().is_terminal()
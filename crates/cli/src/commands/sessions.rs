@@ -0,0 +1,23 @@
+use anyhow::Result;
+
+use crate::cli::{OutputFormat, SessionsCommand};
+use crate::client::TrueSightClient;
+use crate::output::render;
+
+pub async fn run(
+    command: &SessionsCommand,
+    client: &TrueSightClient,
+    project: &str,
+    format: OutputFormat,
+) -> Result<()> {
+    match command {
+        SessionsCommand::Query { body, body_file } => {
+            let body = super::read_body(body, body_file)?;
+            let resp = client
+                .post(&format!("/v1/stats/projects/{project}/sessions"), Some(body))
+                .await?;
+            render(format, &resp);
+        }
+    }
+    Ok(())
+}
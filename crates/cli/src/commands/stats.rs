@@ -7,7 +7,7 @@ use crate::output::render;
 /// Normalize a date string for the API.
 /// Accepts DD-MM-YYYY, YYYY-MM-DD, or full ISO datetime.
 /// Appends T00:00:00Z for `from` or T23:59:59Z for `to`.
-fn normalize_dt(s: &str, is_end: bool) -> String {
+pub(crate) fn normalize_dt(s: &str, is_end: bool) -> String {
     if s.contains('T') || s.contains(' ') {
         return s.to_string();
     }
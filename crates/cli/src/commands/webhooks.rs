@@ -0,0 +1,60 @@
+use anyhow::Result;
+
+use crate::cli::{OutputFormat, WebhooksCommand};
+use crate::client::TrueSightClient;
+use crate::output::render;
+
+pub async fn run(
+    command: &WebhooksCommand,
+    client: &TrueSightClient,
+    project: &str,
+    format: OutputFormat,
+) -> Result<()> {
+    let base = format!("/v1/projects/{project}/webhooks");
+    match command {
+        WebhooksCommand::List => {
+            let resp = client.get(&base).await?;
+            render(format, &resp);
+        }
+        WebhooksCommand::Get { id } => {
+            let resp = client.get(&format!("{base}/{id}")).await?;
+            render(format, &resp);
+        }
+        WebhooksCommand::Create { body, body_file } => {
+            let body = super::read_body(body, body_file)?;
+            let resp = client.post(&base, Some(body)).await?;
+            render(format, &resp);
+        }
+        WebhooksCommand::Update {
+            id,
+            body,
+            body_file,
+        } => {
+            let body = super::read_body(body, body_file)?;
+            let resp = client.patch(&format!("{base}/{id}"), body).await?;
+            render(format, &resp);
+        }
+        WebhooksCommand::Delete { id } => {
+            client.delete(&format!("{base}/{id}")).await?;
+        }
+        WebhooksCommand::RotateSecret { id } => {
+            let resp = client
+                .post(&format!("{base}/{id}/rotate-secret"), None)
+                .await?;
+            render(format, &resp);
+        }
+        WebhooksCommand::DeadLetters { id, limit } => {
+            let mut url = format!("{base}/{id}/dead-letters");
+            if let Some(l) = limit {
+                url.push_str(&format!("?limit={l}"));
+            }
+            let resp = client.get(&url).await?;
+            render(format, &resp);
+        }
+        WebhooksCommand::ClearDeadLetters { id } => {
+            let resp = client.delete(&format!("{base}/{id}/dead-letters")).await?;
+            render(format, &resp);
+        }
+    }
+    Ok(())
+}
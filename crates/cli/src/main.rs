@@ -106,6 +106,11 @@ async fn main() -> Result<()> {
             let project = resolve_project(&cli)?;
             commands::flows::run(command, &client, &project, cli.format).await
         }
+        Command::Sessions { command } => {
+            let client = build_client(&cli)?;
+            let project = resolve_project(&cli)?;
+            commands::sessions::run(command, &client, &project, cli.format).await
+        }
         Command::Users { command } => {
             let client = build_client(&cli)?;
             let project = resolve_project(&cli)?;
@@ -131,5 +136,29 @@ async fn main() -> Result<()> {
             let project = resolve_project(&cli)?;
             commands::funnels::run(command, &client, &project, cli.format).await
         }
+        Command::Dlq { command } => {
+            let client = build_client(&cli)?;
+            commands::dlq::run(command, &client, cli.format).await
+        }
+        Command::Webhooks { command } => {
+            let client = build_client(&cli)?;
+            let project = resolve_project(&cli)?;
+            commands::webhooks::run(command, &client, &project, cli.format).await
+        }
+        Command::DataRetention { command } => {
+            let client = build_client(&cli)?;
+            let project = resolve_project(&cli)?;
+            commands::data_retention::run(command, &client, &project, cli.format).await
+        }
+        Command::DataExports { command } => {
+            let client = build_client(&cli)?;
+            let project = resolve_project(&cli)?;
+            commands::data_exports::run(command, &client, &project, cli.format).await
+        }
+        Command::Erasures { command } => {
+            let client = build_client(&cli)?;
+            let project = resolve_project(&cli)?;
+            commands::erasures::run(command, &client, &project, cli.format).await
+        }
     }
 }
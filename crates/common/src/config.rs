@@ -53,12 +53,62 @@ pub struct IngestionConfig {
 
     #[serde(default = "default_dd_env")]
     pub dd_env: String,
+
+    #[serde(default)]
+    pub backpressure_soft_limit: Option<u64>,
+
+    #[serde(default)]
+    pub backpressure_hard_limit: Option<u64>,
+
+    #[serde(default = "default_backpressure_poll_interval_secs")]
+    pub backpressure_poll_interval_secs: u64,
+
+    #[serde(default = "default_backpressure_retry_after_secs")]
+    pub backpressure_retry_after_secs: u64,
+
+    #[serde(default = "default_dedup_window_secs")]
+    pub dedup_window_secs: u64,
+
+    #[serde(default = "default_dedup_max_entries")]
+    pub dedup_max_entries: usize,
+
+    #[serde(default)]
+    pub redis_url: Option<String>,
+
+    /// How often the erased-user suppression list is reloaded from Postgres.
+    #[serde(default = "default_suppression_refresh_secs")]
+    pub suppression_refresh_secs: u64,
+
+    /// Secret key of the erased-identifier hashes; must match admin-api's.
+    /// Events of erased users are not dropped when unset.
+    #[serde(default)]
+    pub erasure_hash_key: Option<String>,
 }
 
 fn default_ingestion_port() -> u16 {
     8080
 }
 
+fn default_backpressure_poll_interval_secs() -> u64 {
+    10
+}
+
+fn default_backpressure_retry_after_secs() -> u64 {
+    30
+}
+
+fn default_dedup_window_secs() -> u64 {
+    600
+}
+
+fn default_dedup_max_entries() -> usize {
+    500_000
+}
+
+fn default_suppression_refresh_secs() -> u64 {
+    60
+}
+
 impl IngestionConfig {
     pub fn from_env() -> anyhow::Result<Self> {
         dotenvy::dotenv().ok();
@@ -93,6 +143,11 @@ pub struct AdminConfig {
 
     pub admin_api_token: String,
 
+    /// Secret key of the erased-identifier hashes, shared with
+    /// ingestion-api and ch-writer. Erasure requests are refused when unset.
+    #[serde(default)]
+    pub erasure_hash_key: Option<String>,
+
     #[serde(default = "default_cors_origins")]
     pub cors_allowed_origins: String,
 
@@ -119,6 +174,47 @@ pub struct AdminConfig {
 
     #[serde(default = "default_dd_env")]
     pub dd_env: String,
+
+    #[serde(default)]
+    pub sqs_queue_url: Option<String>,
+
+    #[serde(default)]
+    pub sqs_dlq_url: Option<String>,
+
+    #[serde(default = "default_aws_region")]
+    pub aws_region: String,
+
+    #[serde(default)]
+    pub sqs_endpoint_url: Option<String>,
+
+    /// Redis the live-events stream subscribes to. The stream polls
+    /// ClickHouse when unset.
+    #[serde(default)]
+    pub redis_url: Option<String>,
+
+    /// Nesting depth of flattened property paths for DLQ replays; must match
+    /// ch-writer.
+    #[serde(default = "default_property_flatten_depth")]
+    pub property_flatten_depth: usize,
+
+    /// How often the erased-user suppression list the DLQ tooling checks is
+    /// reloaded from Postgres.
+    #[serde(default = "default_suppression_refresh_secs")]
+    pub suppression_refresh_secs: u64,
+
+    /// How often retention policies are checked for due purges. 0 disables
+    /// purging on this instance.
+    #[serde(default = "default_retention_purge_interval_secs")]
+    pub retention_purge_interval_secs: u64,
+
+    /// Where data export archives are stored: `s3://bucket/prefix` or a
+    /// local directory. Data exports are disabled when unset.
+    #[serde(default)]
+    pub export_url: Option<String>,
+
+    /// Endpoint for an S3-compatible export store (MinIO, LocalStack).
+    #[serde(default)]
+    pub export_s3_endpoint_url: Option<String>,
 }
 
 fn default_admin_port() -> u16 {
@@ -129,6 +225,14 @@ fn default_cors_origins() -> String {
     "*".to_string()
 }
 
+fn default_retention_purge_interval_secs() -> u64 {
+    3600
+}
+
+fn default_property_flatten_depth() -> usize {
+    crate::event_row::DEFAULT_FLATTEN_DEPTH
+}
+
 impl AdminConfig {
     pub fn from_env() -> anyhow::Result<Self> {
         dotenvy::dotenv().ok();
@@ -138,6 +242,21 @@ impl AdminConfig {
     pub fn port(&self) -> u16 {
         self.admin_api_port
     }
+
+    /// DLQ URL for the DLQ tooling: `SQS_DLQ_URL` if set, otherwise derived
+    /// from `SQS_QUEUE_URL` the same way ch-writer does. `None` when neither
+    /// is configured.
+    pub fn dlq_url(&self) -> Option<String> {
+        self.sqs_dlq_url
+            .clone()
+            .filter(|u| !u.is_empty())
+            .or_else(|| {
+                self.sqs_queue_url
+                    .as_deref()
+                    .filter(|u| !u.is_empty())
+                    .map(crate::sqs::default_dlq_url)
+            })
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -164,9 +283,91 @@ pub struct WriterConfig {
     #[serde(default = "default_flush_interval_secs")]
     pub ch_flush_interval_secs: u64,
 
+    /// Whether the batch size and insert concurrency adapt to ClickHouse
+    /// insert latency and errors. `CH_BATCH_SIZE` is the starting size.
+    #[serde(default = "default_adaptive_batching")]
+    pub ch_adaptive_batching: bool,
+
+    #[serde(default = "default_min_batch_size")]
+    pub ch_min_batch_size: usize,
+
+    #[serde(default = "default_max_batch_size")]
+    pub ch_max_batch_size: usize,
+
+    /// Upper bound on concurrent `events` inserts.
+    #[serde(default = "default_max_in_flight")]
+    pub ch_max_in_flight: usize,
+
+    /// Insert latency the adaptive limits aim to stay under.
+    #[serde(default = "default_target_insert_latency_ms")]
+    pub ch_target_insert_latency_ms: u64,
+
+    /// Insert with ClickHouse `async_insert=1` (and `wait_for_async_insert=1`,
+    /// so messages are still only acknowledged once the data is durable).
+    #[serde(default)]
+    pub ch_async_insert: bool,
+
+    /// Relative share of each batch for listed projects, as
+    /// `<project_id>=<weight>,...`. Unlisted projects have weight 1.
+    #[serde(default)]
+    pub ch_project_weights: Option<String>,
+
+    /// Events buffered across all projects before the batcher stops reading
+    /// from the consumers.
+    #[serde(default = "default_max_buffered_events")]
+    pub ch_max_buffered_events: usize,
+
     #[serde(default = "default_sqs_receive_batch_size")]
     pub sqs_receive_batch_size: i32,
 
+    /// Visibility timeout that received messages are extended to, every
+    /// third of it, until their batch is acknowledged.
+    #[serde(default = "default_sqs_visibility_timeout_secs")]
+    pub sqs_visibility_timeout_secs: i32,
+
+    /// How long shutdown waits for buffered batches to be written before
+    /// releasing their messages back to the queue.
+    #[serde(default = "default_shutdown_grace_secs")]
+    pub shutdown_grace_secs: u64,
+
+    /// Postgres URL for webhook destinations and the erased-user
+    /// suppression list. Webhook fan-out and suppression are disabled when
+    /// unset, and `reimport` refuses to run.
+    #[serde(default)]
+    pub database_url: Option<String>,
+
+    /// How often the webhook destination cache is reloaded from Postgres.
+    #[serde(default = "default_webhook_refresh_secs")]
+    pub webhook_refresh_secs: u64,
+
+    /// How often the erased-user suppression list is reloaded from Postgres.
+    #[serde(default = "default_suppression_refresh_secs")]
+    pub suppression_refresh_secs: u64,
+
+    /// Secret key of the erased-identifier hashes; must match admin-api's.
+    /// Suppression and archive purges are disabled when unset.
+    #[serde(default)]
+    pub erasure_hash_key: Option<String>,
+
+    /// Where flushed batches are archived as Parquet: `s3://bucket/prefix`
+    /// or a local directory. Archival is disabled when unset.
+    #[serde(default)]
+    pub archive_url: Option<String>,
+
+    /// Endpoint for an S3-compatible archive store (MinIO, LocalStack).
+    #[serde(default)]
+    pub archive_s3_endpoint_url: Option<String>,
+
+    /// Redis that inserted events are published to for the live view.
+    /// Publishing is disabled when unset.
+    #[serde(default)]
+    pub redis_url: Option<String>,
+
+    /// How many levels of nested property objects are flattened into
+    /// dotted paths (`address.city`). 1 keeps only top-level properties.
+    #[serde(default = "default_property_flatten_depth")]
+    pub property_flatten_depth: usize,
+
     #[serde(default = "default_aws_region")]
     pub aws_region: String,
 
@@ -197,10 +398,46 @@ fn default_flush_interval_secs() -> u64 {
     5
 }
 
+fn default_adaptive_batching() -> bool {
+    true
+}
+
+fn default_min_batch_size() -> usize {
+    100
+}
+
+fn default_max_batch_size() -> usize {
+    20_000
+}
+
+fn default_max_in_flight() -> usize {
+    8
+}
+
+fn default_max_buffered_events() -> usize {
+    50_000
+}
+
+fn default_target_insert_latency_ms() -> u64 {
+    1000
+}
+
 fn default_sqs_receive_batch_size() -> i32 {
     10
 }
 
+fn default_sqs_visibility_timeout_secs() -> i32 {
+    300
+}
+
+fn default_shutdown_grace_secs() -> u64 {
+    20
+}
+
+fn default_webhook_refresh_secs() -> u64 {
+    30
+}
+
 impl WriterConfig {
     pub fn from_env() -> anyhow::Result<Self> {
         dotenvy::dotenv().ok();
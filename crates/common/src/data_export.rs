@@ -0,0 +1,61 @@
+//! Data subject access exports.
+//!
+//! An export gathers everything stored about one user — their profile, the
+//! identity mappings linking their anonymous and known IDs, and their full
+//! event history — into a tar archive the project admin downloads and hands
+//! to the user. Exports are queued in Postgres and built by admin-api's
+//! export worker into object storage; archives are kept for
+//! [`EXPORT_TTL_DAYS`].
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::schema::data_exports;
+
+/// File formats of the identity and event listings in an archive. The
+/// profile is always JSON.
+pub const FORMATS: &[&str] = &["json", "csv"];
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_RUNNING: &str = "running";
+pub const STATUS_COMPLETED: &str = "completed";
+pub const STATUS_FAILED: &str = "failed";
+
+/// Days a completed archive can be downloaded before it is deleted.
+pub const EXPORT_TTL_DAYS: i64 = 7;
+
+/// An export. Its archive lives in the export store (`EXPORT_URL`); the key
+/// is only loaded for downloads.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = data_exports)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DataExport {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub user_uid: String,
+    pub format: String,
+    pub status: String,
+    /// Email of the user who requested the export, or `admin-token`.
+    pub requested_by: String,
+    pub event_count: i64,
+    /// Size of the uncompressed archive in bytes.
+    pub archive_size: Option<i64>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// When the archive is deleted; `None` until the export completes.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = data_exports)]
+pub struct NewDataExport {
+    pub project_id: Uuid,
+    pub user_uid: String,
+    pub format: String,
+    pub requested_by: String,
+}
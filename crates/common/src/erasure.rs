@@ -0,0 +1,268 @@
+//! Right-to-erasure requests and the suppression list.
+//!
+//! An erasure request names a user by user ID, email or mobile number.
+//! admin-api resolves every ID linked to it, deletes the user's rows from
+//! ClickHouse, webhook dead letters and the SQS DLQ, and records the linked
+//! IDs in `suppressed_identifiers`. ingestion-api drops the user's future
+//! events, ch-writer drops the user's events redriven from the DLQ or
+//! re-imported from the archive and rewrites the user's archived Parquet
+//! files, and admin-api's DLQ tooling deletes the user's dead-lettered events
+//! instead of listing or redriving them.
+//! Identifiers are stored as [`identifier_hash`]es, keyed with the
+//! `ERASURE_HASH_KEY` shared by the services, so they cannot be recovered by
+//! hashing candidate emails or phone numbers without it; the request's plain
+//! identifier is cleared once the erasure completes, leaving an audit record
+//! without the personal data.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::db::{DbPool, get_conn};
+use crate::event::{EnrichedEvent, IngestEvent};
+use crate::schema::{erasure_requests, suppressed_identifiers};
+
+/// Kinds of identifier an erasure can be requested by.
+pub const IDENTIFIER_TYPES: &[&str] = &["user_id", "email", "mobile_number"];
+
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_RUNNING: &str = "running";
+pub const STATUS_COMPLETED: &str = "completed";
+pub const STATUS_FAILED: &str = "failed";
+
+/// HMAC-SHA256 under `key` of an identifier of the given
+/// [`IDENTIFIER_TYPES`] kind within a project. Emails are compared
+/// case-insensitively; user IDs, anonymous IDs and mobile numbers exactly.
+pub fn identifier_hash(
+    key: &str,
+    project_id: Uuid,
+    identifier_type: &str,
+    identifier: &str,
+) -> String {
+    let identifier = identifier.trim();
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(project_id.as_bytes());
+    if identifier_type == "email" {
+        mac.update(identifier.to_lowercase().as_bytes());
+    } else {
+        mac.update(identifier.as_bytes());
+    }
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = erasure_requests)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ErasureRequest {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub identifier_type: String,
+    #[serde(skip_serializing)]
+    pub identifier: Option<String>,
+    pub identifier_hash: String,
+    pub status: String,
+    /// Email of the user who requested the erasure, or `admin-token`.
+    pub requested_by: String,
+    /// Linked user IDs, anonymous IDs, emails and mobile numbers erased.
+    pub resolved_ids: i32,
+    /// Per-table mutation progress, as [`TableProgress`] entries.
+    pub tables: serde_json::Value,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// When ch-writer removed the user's events from the Parquet archive;
+    /// unset while pending or when archival is disabled.
+    pub archive_purged_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = erasure_requests)]
+pub struct NewErasureRequest {
+    pub project_id: Uuid,
+    pub identifier_type: String,
+    pub identifier: Option<String>,
+    pub identifier_hash: String,
+    pub requested_by: String,
+}
+
+/// Deletion progress of one ClickHouse table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableProgress {
+    pub table: String,
+    /// Whether the table's delete mutation has finished.
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = suppressed_identifiers)]
+pub struct NewSuppressedIdentifier {
+    pub project_id: Uuid,
+    pub identifier_hash: String,
+    pub erasure_request_id: Uuid,
+}
+
+/// Snapshot of `suppressed_identifiers`, grouped by project.
+#[derive(Debug, Clone, Default)]
+pub struct SuppressionList {
+    key: String,
+    hashes: HashMap<Uuid, HashSet<String>>,
+}
+
+impl SuppressionList {
+    /// Loads every suppressed identifier, hashed under `key`. Blocking; call
+    /// from `spawn_blocking` in async code.
+    pub fn load(pool: &DbPool, key: &str) -> anyhow::Result<Self> {
+        let mut conn = get_conn(pool)?;
+        let rows = suppressed_identifiers::table
+            .select((
+                suppressed_identifiers::project_id,
+                suppressed_identifiers::identifier_hash,
+            ))
+            .load::<(Uuid, String)>(&mut conn)?;
+
+        let mut hashes: HashMap<Uuid, HashSet<String>> = HashMap::new();
+        for (project_id, hash) in rows {
+            hashes.entry(project_id).or_default().insert(hash);
+        }
+        Ok(Self {
+            key: key.to_string(),
+            hashes,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.values().map(HashSet::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Whether any of the event's user ID, anonymous ID, email or mobile
+    /// number belongs to an erased user of the project.
+    pub fn is_suppressed(&self, project_id: Uuid, event: &IngestEvent) -> bool {
+        self.matches(
+            project_id,
+            event.user_id.as_deref(),
+            &event.anonymous_id,
+            event.email.as_deref(),
+            event.mobile_number.as_deref(),
+        )
+    }
+
+    /// [`Self::is_suppressed`] for an event past ingestion.
+    pub fn is_enriched_suppressed(&self, event: &EnrichedEvent) -> bool {
+        self.matches(
+            event.project_id,
+            event.user_id.as_deref(),
+            &event.anonymous_id,
+            event.email.as_deref(),
+            event.mobile_number.as_deref(),
+        )
+    }
+
+    fn matches(
+        &self,
+        project_id: Uuid,
+        user_id: Option<&str>,
+        anonymous_id: &str,
+        email: Option<&str>,
+        mobile_number: Option<&str>,
+    ) -> bool {
+        let Some(hashes) = self.hashes.get(&project_id) else {
+            return false;
+        };
+        [
+            ("user_id", user_id),
+            ("user_id", Some(anonymous_id)),
+            ("email", email),
+            ("mobile_number", mobile_number),
+        ]
+        .into_iter()
+        .filter_map(|(kind, id)| Some((kind, id?)))
+        .filter(|(_, id)| !id.trim().is_empty())
+        .any(|(kind, id)| hashes.contains(&identifier_hash(&self.key, project_id, kind, id)))
+    }
+}
+
+/// Shared, periodically reloaded [`SuppressionList`].
+///
+/// The list is loaded from Postgres at startup and reloaded every
+/// `SUPPRESSION_REFRESH_SECS`, so a new erasure takes effect on every
+/// instance within one refresh. If a reload fails the previous list is kept.
+#[derive(Clone)]
+pub struct Suppression {
+    list: Arc<RwLock<Arc<SuppressionList>>>,
+}
+
+impl Suppression {
+    /// Creates the handle and spawns its refresh task. `key` is the
+    /// `ERASURE_HASH_KEY` the identifiers were hashed with.
+    pub fn spawn(pool: DbPool, key: String, refresh_interval: Duration) -> Self {
+        let this = Self {
+            list: Arc::new(RwLock::new(Arc::new(SuppressionList::default()))),
+        };
+
+        let task = this.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(refresh_interval);
+            loop {
+                interval.tick().await;
+                let (pool, key) = (pool.clone(), key.clone());
+                match tokio::task::spawn_blocking(move || SuppressionList::load(&pool, &key)).await
+                {
+                    Ok(Ok(list)) => {
+                        tracing::debug!(identifiers = list.len(), "Suppression list refreshed");
+                        *task
+                            .list
+                            .write()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(list);
+                    }
+                    Ok(Err(e)) => {
+                        tracing::warn!(error = %e, "Failed to refresh suppression list")
+                    }
+                    Err(e) => tracing::warn!(error = %e, "Suppression list refresh panicked"),
+                }
+            }
+        });
+
+        this
+    }
+
+    /// The current list.
+    pub fn list(&self) -> Arc<SuppressionList> {
+        Arc::clone(
+            &self
+                .list
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        )
+    }
+
+    /// Removes events of suppressed users and returns the rest with the
+    /// number removed.
+    pub fn filter(&self, project_id: Uuid, events: Vec<IngestEvent>) -> (Vec<IngestEvent>, usize) {
+        let list = self.list();
+        if list.is_empty() {
+            return (events, 0);
+        }
+
+        let total = events.len();
+        let kept: Vec<IngestEvent> = events
+            .into_iter()
+            .filter(|e| !list.is_suppressed(project_id, e))
+            .collect();
+        let suppressed = total - kept.len();
+        (kept, suppressed)
+    }
+}
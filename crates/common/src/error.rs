@@ -33,6 +33,9 @@ pub enum AppError {
 
     #[error("SQS error: {0}")]
     Sqs(String),
+
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
 }
 
 impl IntoResponse for AppError {
@@ -50,12 +53,16 @@ impl IntoResponse for AppError {
             AppError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR"),
             AppError::Database(_) => (StatusCode::INTERNAL_SERVER_ERROR, "DATABASE_ERROR"),
             AppError::Sqs(_) => (StatusCode::INTERNAL_SERVER_ERROR, "SQS_ERROR"),
+            AppError::ServiceUnavailable(_) => {
+                (StatusCode::SERVICE_UNAVAILABLE, "SERVICE_UNAVAILABLE")
+            }
         };
 
         let message = self.to_string();
 
-        // Report 5xx errors to Sentry
-        if status.is_server_error() {
+        // Report 5xx errors to Sentry. Load shedding is expected behaviour and
+        // would flood Sentry during an incident, so it is not reported.
+        if status.is_server_error() && !matches!(self, AppError::ServiceUnavailable(_)) {
             sentry::capture_message(&message, sentry::Level::Error);
         }
 
@@ -8,6 +8,101 @@ pub enum EventType {
     Track,
     Identify,
     Screen,
+    /// Declares that `properties.previous_id` and `user_id` belong to the same
+    /// person, merging their identity graph components.
+    Alias,
+}
+
+/// Property carrying the other distinct ID of an `alias` event.
+pub const ALIAS_PREVIOUS_ID_PROPERTY: &str = "previous_id";
+
+/// Returns the non-empty `previous_id` property of an `alias` event, if any.
+pub fn alias_previous_id(properties: &Option<serde_json::Value>) -> Option<&str> {
+    properties
+        .as_ref()?
+        .get(ALIAS_PREVIOUS_ID_PROPERTY)?
+        .as_str()
+        .filter(|id| !id.is_empty())
+}
+
+/// Profile operation an `identify` event can carry as a top-level property,
+/// e.g. `{"$set_once": {"signup_source": "ads"}}`. Any other top-level
+/// property of an `identify` event is an implicit `$set`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileOperation {
+    /// `{"$set": {key: value}}` overwrites each key.
+    Set,
+    /// `{"$set_once": {key: value}}` sets each key only if it has no value yet.
+    SetOnce,
+    /// `{"$unset": [key, ...]}` removes each key.
+    Unset,
+    /// `{"$increment": {key: number}}` adds to each numeric key (missing = 0).
+    Increment,
+    /// `{"$append": {key: value}}` appends to each list key; array values
+    /// append every element.
+    Append,
+}
+
+impl ProfileOperation {
+    pub const ALL: [Self; 5] = [
+        Self::Set,
+        Self::SetOnce,
+        Self::Unset,
+        Self::Increment,
+        Self::Append,
+    ];
+
+    /// The property key that carries this operation.
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::Set => "$set",
+            Self::SetOnce => "$set_once",
+            Self::Unset => "$unset",
+            Self::Increment => "$increment",
+            Self::Append => "$append",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.key() == key)
+    }
+}
+
+/// Checks the shape of the profile operations in an `identify` event's
+/// properties.
+fn validate_profile_operations(properties: &Option<serde_json::Value>, errors: &mut Vec<String>) {
+    let Some(serde_json::Value::Object(props)) = properties else {
+        return;
+    };
+
+    for (key, value) in props {
+        let Some(op) = ProfileOperation::from_key(key) else {
+            continue;
+        };
+        match op {
+            ProfileOperation::Unset => {
+                let valid = value
+                    .as_array()
+                    .is_some_and(|keys| keys.iter().all(|k| k.is_string()));
+                if !valid {
+                    errors.push(format!("properties.{key} must be an array of strings"));
+                }
+            }
+            ProfileOperation::Increment => {
+                let valid = value
+                    .as_object()
+                    .is_some_and(|ops| ops.values().all(|v| v.is_number()));
+                if !valid {
+                    errors.push(format!("properties.{key} must map keys to numbers"));
+                }
+            }
+            ProfileOperation::Set | ProfileOperation::SetOnce | ProfileOperation::Append => {
+                if !value.is_object() {
+                    errors.push(format!("properties.{key} must be an object"));
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -93,7 +188,7 @@ pub fn validate_ingest_event(event: &IngestEvent) -> Result<(), Vec<String>> {
 
     // event_type check (already guaranteed by deserialization, but be explicit)
     match event.event_type {
-        EventType::Track | EventType::Identify | EventType::Screen => {}
+        EventType::Track | EventType::Identify | EventType::Screen | EventType::Alias => {}
     }
 
     // If Identify then user_id is required
@@ -101,6 +196,25 @@ pub fn validate_ingest_event(event: &IngestEvent) -> Result<(), Vec<String>> {
         errors.push("user_id is required for identify events".to_string());
     }
 
+    if event.event_type == EventType::Identify {
+        validate_profile_operations(&event.properties, &mut errors);
+    }
+
+    // Alias needs both IDs, and they must differ
+    if event.event_type == EventType::Alias {
+        let user_id = event.user_id.as_deref().filter(|id| !id.is_empty());
+        match (user_id, alias_previous_id(&event.properties)) {
+            (None, _) => errors.push("user_id is required for alias events".to_string()),
+            (_, None) => errors.push(format!(
+                "properties.{ALIAS_PREVIOUS_ID_PROPERTY} is required for alias events"
+            )),
+            (Some(user_id), Some(previous_id)) if user_id == previous_id => errors.push(format!(
+                "properties.{ALIAS_PREVIOUS_ID_PROPERTY} must differ from user_id"
+            )),
+            _ => {}
+        }
+    }
+
     // mobile_number must be exactly 10 digits when present
     if let Some(ref mobile) = event.mobile_number
         && (mobile.len() != 10 || !mobile.chars().all(|c| c.is_ascii_digit()))
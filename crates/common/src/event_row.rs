@@ -0,0 +1,454 @@
+//! Row mapping for the ClickHouse `events` table.
+//!
+//! Shared by ch-writer (the normal insert path) and admin-api (direct DLQ
+//! re-inserts) so both write identical rows for the same [`EnrichedEvent`].
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use uuid::Uuid;
+
+use crate::event::EnrichedEvent;
+use crate::live::LiveEvent;
+
+/// Regex to strip Swift `AnyDecodable("...")` wrappers from KMM SDK property values.
+static ANY_DECODABLE_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^AnyDecodable\("(.*)"\)$"#).unwrap());
+
+/// Flat row representation that maps [`EnrichedEvent`] fields (including a
+/// flattened [`DeviceContext`](crate::event::DeviceContext)) to the
+/// ClickHouse `events` table columns.
+///
+/// Types must match the ClickHouse schema exactly for RowBinary serialization:
+/// - `UUID` → `Uuid` with `serde(with = "clickhouse::serde::uuid")`
+/// - `Nullable(T)` → `Option<T>`
+/// - `DateTime64(3)` → `DateTime<Utc>` with `serde(with = ...millis)`
+/// - `LowCardinality(String)` → `String` (transparent)
+#[derive(Debug, Serialize, clickhouse::Row)]
+pub struct EventRow {
+    #[serde(with = "clickhouse::serde::uuid")]
+    event_id: Uuid,
+    event_name: String,
+    event_type: String,
+    user_id: Option<String>,
+    anonymous_id: String,
+    mobile_number: Option<String>,
+    email: Option<String>,
+    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
+    client_timestamp: DateTime<Utc>,
+    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
+    server_timestamp: DateTime<Utc>,
+    properties: String,
+    properties_map: Vec<(String, String)>,
+    properties_num: Vec<(String, f64)>,
+    properties_bool: Vec<(String, bool)>,
+    /// `DateTime64(3)` values as Unix milliseconds.
+    properties_datetime: Vec<(String, i64)>,
+    properties_arrays: Vec<(String, Vec<String>)>,
+    /// JSON type of each property path, for the event catalog.
+    property_types: Vec<(String, String)>,
+    #[serde(with = "clickhouse::serde::uuid")]
+    project_id: Uuid,
+    environment: String,
+    session_id: Option<String>,
+    // Flattened DeviceContext fields
+    app_version: Option<String>,
+    os_name: String,
+    os_version: String,
+    device_model: String,
+    device_id: String,
+    network_type: Option<String>,
+    locale: String,
+    timezone: String,
+    sdk_version: String,
+    platform: String,
+    /// When the row was written. Rows written before the column existed read
+    /// as 1970-01-01, which keeps them apart in the `event_property_types`
+    /// backfill.
+    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
+    inserted_at: DateTime<Utc>,
+}
+
+impl EventRow {
+    /// Builds the row for an event, sanitising and flattening its properties.
+    pub fn from_enriched(event: &EnrichedEvent) -> Self {
+        let event_type_str = serde_json::to_string(&event.event_type)
+            .unwrap_or_default()
+            .trim_matches('"')
+            .to_string();
+
+        let sanitized_props = sanitize_properties(&event.properties);
+
+        let properties_json = sanitized_props
+            .as_ref()
+            .map(|v| serde_json::to_string(v).unwrap_or_default())
+            .unwrap_or_default();
+
+        let Flattened {
+            map: properties_map,
+            arrays: properties_arrays,
+            types: property_types,
+        } = flatten_properties(&sanitized_props);
+        let typed = TypedProperties::from_flattened(&properties_map);
+
+        Self {
+            event_id: event.event_id,
+            event_name: event.event_name.clone(),
+            event_type: event_type_str,
+            user_id: event.user_id.clone().filter(|s| !s.is_empty()),
+            anonymous_id: event.anonymous_id.clone(),
+            mobile_number: event.mobile_number.clone().filter(|s| !s.is_empty()),
+            email: event.email.clone().filter(|s| !s.is_empty()),
+            client_timestamp: event.client_timestamp,
+            server_timestamp: event.server_timestamp,
+            properties: properties_json,
+            properties_map,
+            properties_num: typed.num,
+            properties_bool: typed.bool,
+            properties_datetime: typed.datetime,
+            properties_arrays,
+            property_types,
+            project_id: event.project_id,
+            environment: event.environment.clone(),
+            session_id: event.session_id.clone(),
+            app_version: event.context.app_version.clone(),
+            os_name: event.context.os_name.clone(),
+            os_version: event.context.os_version.clone(),
+            device_model: event.context.device_model.clone(),
+            device_id: event.context.device_id.clone(),
+            network_type: event.context.network_type.clone(),
+            locale: event.context.locale.clone(),
+            timezone: event.context.timezone.clone(),
+            sdk_version: event.context.sdk_version.clone(),
+            platform: event
+                .context
+                .platform
+                .clone()
+                .filter(|p| !p.is_empty())
+                .unwrap_or_else(|| infer_platform(&event.context.os_name)),
+            inserted_at: Utc::now(),
+        }
+    }
+}
+
+impl From<&EventRow> for LiveEvent {
+    fn from(row: &EventRow) -> Self {
+        const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+        Self {
+            event_id: row.event_id.to_string(),
+            project_id: row.project_id.to_string(),
+            event_name: row.event_name.clone(),
+            event_type: row.event_type.clone(),
+            user_id: row.user_id.clone().unwrap_or_default(),
+            anonymous_id: row.anonymous_id.clone(),
+            email: row.email.clone().unwrap_or_default(),
+            mobile_number: row.mobile_number.clone().unwrap_or_default(),
+            client_timestamp: row.client_timestamp.format(TIMESTAMP_FORMAT).to_string(),
+            server_timestamp: row.server_timestamp.format(TIMESTAMP_FORMAT).to_string(),
+            server_ts_raw: row.server_timestamp.timestamp_millis() as f64 / 1000.0,
+            properties: row.properties.clone(),
+            os_name: row.os_name.clone(),
+            device_model: row.device_model.clone(),
+            sdk_version: row.sdk_version.clone(),
+            platform: row.platform.clone(),
+            environment: row.environment.clone(),
+        }
+    }
+}
+
+fn infer_platform(os_name: &str) -> String {
+    match os_name.to_lowercase().as_str() {
+        "web" => "web",
+        "android" => "android",
+        "ios" => "ios",
+        "macos" | "mac os" | "mac os x" => "macos",
+        "windows" => "windows",
+        "linux" => "linux",
+        _ => "unknown",
+    }
+    .to_string()
+}
+
+/// Strip `AnyDecodable("...")` wrappers from property values.
+///
+/// The KMM/Swift SDK sometimes serialises values wrapped in `AnyDecodable(...)`.
+/// This function recursively walks the JSON and unwraps them to clean strings.
+fn sanitize_properties(props: &Option<serde_json::Value>) -> Option<serde_json::Value> {
+    props.as_ref().map(sanitize_value)
+}
+
+fn sanitize_value(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(s) => {
+            if let Some(caps) = ANY_DECODABLE_RE.captures(s) {
+                serde_json::Value::String(caps[1].to_string())
+            } else {
+                value.clone()
+            }
+        }
+        serde_json::Value::Object(map) => {
+            let cleaned: serde_json::Map<String, serde_json::Value> = map
+                .iter()
+                .map(|(k, v)| (k.clone(), sanitize_value(v)))
+                .collect();
+            serde_json::Value::Object(cleaned)
+        }
+        serde_json::Value::Array(arr) => {
+            serde_json::Value::Array(arr.iter().map(sanitize_value).collect())
+        }
+        other => other.clone(),
+    }
+}
+
+/// Default for [`set_flatten_depth`].
+pub const DEFAULT_FLATTEN_DEPTH: usize = 3;
+
+/// Values kept per array-valued property path.
+const MAX_ARRAY_VALUES: usize = 1000;
+
+static FLATTEN_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_FLATTEN_DEPTH);
+
+/// Sets how many levels of nested objects are flattened into dotted paths
+/// (`PROPERTY_FLATTEN_DEPTH`). 1 keeps only top-level properties.
+pub fn set_flatten_depth(depth: usize) {
+    FLATTEN_DEPTH.store(depth.max(1), Ordering::Relaxed);
+}
+
+/// Properties flattened for the `properties_map`, `properties_arrays` and
+/// `property_types` columns.
+#[derive(Default)]
+struct Flattened {
+    map: Vec<(String, String)>,
+    arrays: Vec<(String, Vec<String>)>,
+    types: Vec<(String, String)>,
+}
+
+/// Flatten a JSON Value (expected to be an object) into `properties_map`
+/// entries and `properties_arrays` entries.
+///
+/// Top-level primitives are converted with `to_string()` and top-level
+/// objects/arrays are kept as JSON strings, as before nested flattening.
+/// In addition, nested object fields are added as dotted paths
+/// (`address.city`) down to the configured depth, below which they stay JSON.
+/// Every array is stored in `properties_arrays` under its path with its
+/// elements as strings, and fields of object elements under
+/// `<path>[].<field>` (`items[].sku`), one value per element that has it.
+/// Each path's JSON type is recorded from its first value.
+fn flatten_properties(props: &Option<serde_json::Value>) -> Flattened {
+    let Some(serde_json::Value::Object(map)) = props else {
+        return Flattened::default();
+    };
+    let mut flattener = Flattener {
+        depth: FLATTEN_DEPTH.load(Ordering::Relaxed),
+        map: Vec::with_capacity(map.len()),
+        arrays: BTreeMap::new(),
+        types: BTreeMap::new(),
+    };
+    for (k, v) in map {
+        if v.is_null() {
+            continue;
+        }
+        flattener.map.push((k.clone(), value_string(v)));
+        flattener.record_type(k, v);
+        match v {
+            serde_json::Value::Object(obj) if flattener.depth > 1 => {
+                flattener.object(k, obj, 2, false)
+            }
+            serde_json::Value::Array(items) => flattener.array(k, items, 1),
+            _ => {}
+        }
+    }
+    Flattened {
+        map: flattener.map,
+        arrays: flattener.arrays.into_iter().collect(),
+        types: flattener
+            .types
+            .into_iter()
+            .map(|(path, value_type)| (path, value_type.to_string()))
+            .collect(),
+    }
+}
+
+struct Flattener {
+    depth: usize,
+    map: Vec<(String, String)>,
+    arrays: BTreeMap<String, Vec<String>>,
+    types: BTreeMap<String, &'static str>,
+}
+
+impl Flattener {
+    /// Adds the fields of `obj` at `prefix`, which sit at nesting `level`.
+    /// Inside array elements (`in_array`) values are collected per path.
+    fn object(
+        &mut self,
+        prefix: &str,
+        obj: &serde_json::Map<String, serde_json::Value>,
+        level: usize,
+        in_array: bool,
+    ) {
+        for (k, v) in obj {
+            let path = format!("{prefix}.{k}");
+            match v {
+                serde_json::Value::Null => {}
+                serde_json::Value::Object(inner) if level < self.depth => {
+                    self.object(&path, inner, level + 1, in_array)
+                }
+                serde_json::Value::Array(items) if !in_array => {
+                    self.map.push((path.clone(), value_string(v)));
+                    self.record_type(&path, v);
+                    self.array(&path, items, level);
+                }
+                other => {
+                    self.record_type(&path, other);
+                    self.leaf(path, value_string(other), in_array)
+                }
+            }
+        }
+    }
+
+    fn array(&mut self, path: &str, items: &[serde_json::Value], level: usize) {
+        let values = self.arrays.entry(path.to_string()).or_default();
+        values.extend(
+            items
+                .iter()
+                .filter(|v| !v.is_null())
+                .take(MAX_ARRAY_VALUES)
+                .map(value_string),
+        );
+
+        if level < self.depth {
+            let element_prefix = format!("{path}[]");
+            for item in items.iter().take(MAX_ARRAY_VALUES) {
+                if let serde_json::Value::Object(obj) = item {
+                    self.object(&element_prefix, obj, level + 1, true);
+                }
+            }
+        }
+    }
+
+    fn record_type(&mut self, path: &str, value: &serde_json::Value) {
+        if !self.types.contains_key(path) {
+            self.types.insert(path.to_string(), value_type(value));
+        }
+    }
+
+    fn leaf(&mut self, path: String, value: String, in_array: bool) {
+        if in_array {
+            let values = self.arrays.entry(path).or_default();
+            if values.len() < MAX_ARRAY_VALUES {
+                values.push(value);
+            }
+        } else {
+            self.map.push((path, value));
+        }
+    }
+}
+
+/// JSON type name of a property value, as tracked by the event catalog.
+fn value_type(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
+/// Strings as-is, other primitives with `to_string()`, nested objects and
+/// arrays as JSON.
+fn value_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Bool(b) => b.to_string(),
+        serde_json::Value::Number(n) => n.to_string(),
+        other => serde_json::to_string(other).unwrap_or_default(),
+    }
+}
+
+/// Flattened properties whose string form reads as a number, boolean or
+/// ISO-8601 date/timestamp, for the `properties_num`, `properties_bool` and
+/// `properties_datetime` columns. Numeric and boolean strings count, since
+/// some SDKs send every value as a string. Must match the column DEFAULTs in
+/// `021_add_typed_properties.sql`.
+#[derive(Default)]
+struct TypedProperties {
+    num: Vec<(String, f64)>,
+    bool: Vec<(String, bool)>,
+    datetime: Vec<(String, i64)>,
+}
+
+/// JSON number syntax, the only numeric strings accepted.
+static NUMBER_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^-?[0-9]+(\.[0-9]+)?([eE][+-]?[0-9]+)?$").unwrap());
+
+impl TypedProperties {
+    fn from_flattened(flattened: &[(String, String)]) -> Self {
+        let mut typed = Self::default();
+        for (key, value) in flattened {
+            if NUMBER_RE.is_match(value) {
+                if let Ok(n) = value.parse::<f64>()
+                    && n.is_finite()
+                {
+                    typed.num.push((key.clone(), n));
+                }
+            } else if value == "true" || value == "false" {
+                typed.bool.push((key.clone(), value == "true"));
+            } else if let Some(ts) = parse_datetime(value) {
+                typed.datetime.push((key.clone(), ts.timestamp_millis()));
+            }
+        }
+        typed
+    }
+}
+
+/// Parses an RFC 3339 timestamp or a `YYYY-MM-DD` date (as UTC midnight).
+pub fn parse_datetime(value: &str) -> Option<DateTime<Utc>> {
+    if value.len() < 10 || !value.as_bytes()[0].is_ascii_digit() {
+        return None;
+    }
+    if value.len() > 10 {
+        return DateTime::parse_from_rfc3339(value)
+            .ok()
+            .filter(|_| value.as_bytes()[10] == b'T')
+            .map(|ts| ts.with_timezone(&Utc));
+    }
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| dt.and_utc())
+}
+
+/// Writes typed rows to `table` using the native `INSERT` API (avoids SQL
+/// string formatting which can misinterpret `?` in data as bind parameters).
+///
+/// ClickHouse errors are preserved in the returned error so callers can
+/// downcast to [`clickhouse::error::Error`].
+pub async fn write_rows<T>(
+    client: &clickhouse::Client,
+    table: &str,
+    rows: &[T],
+) -> anyhow::Result<()>
+where
+    T: clickhouse::Row + Serialize,
+{
+    let mut insert = client.insert(table)?;
+    for row in rows {
+        insert.write(row).await?;
+    }
+    insert.end().await?;
+    Ok(())
+}
+
+/// Writes rows to the `events` table.
+pub async fn write_event_rows(
+    client: &clickhouse::Client,
+    rows: &[EventRow],
+) -> anyhow::Result<()> {
+    write_rows(client, "events", rows).await
+}
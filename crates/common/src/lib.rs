@@ -1,16 +1,23 @@
 pub mod api_key;
 pub mod auth;
 pub mod config;
+pub mod data_export;
 pub mod db;
+pub mod erasure;
 pub mod error;
 pub mod event;
+pub mod event_row;
 pub mod health;
 pub mod identity;
 pub mod jwt;
+pub mod live;
 pub mod project;
+pub mod retention;
 pub mod schema;
 pub mod shutdown;
 pub mod sqs;
+pub mod storage;
 pub mod team;
 pub mod telemetry;
 pub mod user;
+pub mod webhook;
@@ -0,0 +1,68 @@
+//! Live event feed.
+//!
+//! ch-writer publishes each inserted batch to Redis pub/sub, one
+//! [`LiveMessage`] per project on [`channel`]`(project_id)`. admin-api
+//! subscribes to [`CHANNEL_PATTERN`] once per instance and fans messages out
+//! to the live-events SSE connections, so viewers never query ClickHouse.
+//!
+//! [`LiveEvent`] has the same JSON shape as the rows the polling stream
+//! returns, so the dashboard handles both alike.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::event::EnrichedEvent;
+use crate::event_row::EventRow;
+
+/// Prefix of the per-project Redis channels.
+pub const CHANNEL_PREFIX: &str = "truesight:live";
+
+/// Pattern matching every project's channel.
+pub const CHANNEL_PATTERN: &str = "truesight:live:*";
+
+/// Redis channel carrying the live events of `project_id`.
+pub fn channel(project_id: Uuid) -> String {
+    format!("{CHANNEL_PREFIX}:{project_id}")
+}
+
+/// Whether an event is shown in the live view. System events (`$identify`,
+/// `$screen`, ...) are not.
+pub fn is_live_event(event_name: &str) -> bool {
+    !event_name.starts_with('$')
+}
+
+/// Events of one project published together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveMessage {
+    pub project_id: Uuid,
+    pub events: Vec<LiveEvent>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveEvent {
+    pub event_id: String,
+    pub project_id: String,
+    pub event_name: String,
+    pub event_type: String,
+    pub user_id: String,
+    pub anonymous_id: String,
+    pub email: String,
+    pub mobile_number: String,
+    pub client_timestamp: String,
+    pub server_timestamp: String,
+    pub server_ts_raw: f64,
+    pub properties: String,
+    pub os_name: String,
+    pub device_model: String,
+    pub sdk_version: String,
+    pub platform: String,
+    pub environment: String,
+}
+
+impl LiveEvent {
+    /// Builds the live view of an event from the row written to ClickHouse,
+    /// so properties and platform match what queries return.
+    pub fn from_enriched(event: &EnrichedEvent) -> Self {
+        Self::from(&EventRow::from_enriched(event))
+    }
+}
@@ -0,0 +1,77 @@
+//! Per-project data retention policies.
+//!
+//! A policy keeps a project's data in one environment (`live` or `test`) for
+//! `retention_days`; environments without a policy are kept forever. Policies
+//! are stored in Postgres and enforced by admin-api's purge scheduler, which
+//! deletes expired rows from the ClickHouse tables in [`RETAINED_TABLES`] at
+//! most once per [`PURGE_INTERVAL_HOURS`] per policy.
+
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::schema::retention_policies;
+
+/// Environments a policy can apply to.
+pub const ENVIRONMENTS: &[&str] = &["live", "test"];
+
+/// Bounds of `retention_days`.
+pub const MIN_RETENTION_DAYS: i32 = 1;
+pub const MAX_RETENTION_DAYS: i32 = 3650;
+
+/// Minimum time between two purges of the same policy.
+pub const PURGE_INTERVAL_HOURS: i64 = 24;
+
+/// ClickHouse tables a policy applies to, with the time column rows expire
+/// by. Aggregated tables expire by their last activity, so a user or event
+/// name seen within the retention period keeps its row. `user_first_seen`
+/// and `user_profile_history` are purged after `user_stats` and
+/// `user_profiles`: a user still active keeps their first-seen date, and
+/// each property keeps the value it held at the cutoff.
+pub const RETAINED_TABLES: &[(&str, &str)] = &[
+    ("events", "server_timestamp"),
+    ("events_hourly", "hour"),
+    ("users_daily", "event_date"),
+    ("user_stats", "last_seen"),
+    ("user_first_seen", "first_seen_date"),
+    ("user_profiles", "last_seen"),
+    ("user_profile_history", "valid_from"),
+    ("event_catalog", "last_seen"),
+    ("event_property_types", "last_seen"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = retention_policies)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct RetentionPolicy {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub environment: String,
+    pub retention_days: i32,
+    pub last_purged_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = retention_policies)]
+pub struct NewRetentionPolicy {
+    pub project_id: Uuid,
+    pub environment: String,
+    pub retention_days: i32,
+}
+
+impl RetentionPolicy {
+    /// Rows older than this are expired.
+    pub fn cutoff(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now - Duration::days(self.retention_days as i64)
+    }
+
+    /// Earliest time the policy will be purged again; `None` if it is due.
+    pub fn next_purge_at(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.last_purged_at
+            .map(|at| at + Duration::hours(PURGE_INTERVAL_HOURS))
+            .filter(|next| *next > now)
+    }
+}
@@ -66,6 +66,19 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    retention_policies (id) {
+        id -> Uuid,
+        project_id -> Uuid,
+        #[max_length = 4]
+        environment -> Varchar,
+        retention_days -> Int4,
+        last_purged_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     segments (id) {
         id -> Uuid,
@@ -81,6 +94,53 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    data_exports (id) {
+        id -> Uuid,
+        project_id -> Uuid,
+        user_uid -> Text,
+        #[max_length = 8]
+        format -> Varchar,
+        #[max_length = 16]
+        status -> Varchar,
+        #[max_length = 255]
+        requested_by -> Varchar,
+        event_count -> Int8,
+        archive_key -> Nullable<Text>,
+        archive_size -> Nullable<Int8>,
+        error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        started_at -> Nullable<Timestamptz>,
+        completed_at -> Nullable<Timestamptz>,
+        expires_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    erasure_requests (id) {
+        id -> Uuid,
+        project_id -> Uuid,
+        #[max_length = 16]
+        identifier_type -> Varchar,
+        identifier -> Nullable<Text>,
+        #[max_length = 64]
+        identifier_hash -> Varchar,
+        #[max_length = 16]
+        status -> Varchar,
+        #[max_length = 255]
+        requested_by -> Varchar,
+        resolved_ids -> Int4,
+        tables -> Jsonb,
+        error -> Nullable<Text>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        started_at -> Nullable<Timestamptz>,
+        completed_at -> Nullable<Timestamptz>,
+        archive_purged_at -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::table! {
     funnels (id) {
         id -> Uuid,
@@ -137,6 +197,17 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    suppressed_identifiers (id) {
+        id -> Uuid,
+        project_id -> Uuid,
+        #[max_length = 64]
+        identifier_hash -> Varchar,
+        erasure_request_id -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
 diesel::table! {
     team_projects (id) {
         id -> Uuid,
@@ -175,18 +246,56 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    webhook_dead_letters (id) {
+        id -> Uuid,
+        destination_id -> Uuid,
+        events -> Jsonb,
+        error -> Text,
+        attempts -> Int4,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    webhook_destinations (id) {
+        id -> Uuid,
+        project_id -> Uuid,
+        #[max_length = 255]
+        name -> Varchar,
+        #[max_length = 2048]
+        url -> Varchar,
+        #[max_length = 255]
+        secret -> Varchar,
+        #[max_length = 256]
+        event_name -> Varchar,
+        filters -> Jsonb,
+        batch_size -> Int4,
+        enabled -> Bool,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 diesel::joinable!(allowed_domains -> teams (team_id));
 diesel::joinable!(api_keys -> projects (project_id));
 diesel::joinable!(board_widgets -> boards (board_id));
 diesel::joinable!(boards -> projects (project_id));
+diesel::joinable!(retention_policies -> projects (project_id));
 diesel::joinable!(segments -> projects (project_id));
+diesel::joinable!(data_exports -> projects (project_id));
+diesel::joinable!(erasure_requests -> projects (project_id));
 diesel::joinable!(funnels -> projects (project_id));
 diesel::joinable!(invitations -> teams (team_id));
 diesel::joinable!(invitations -> users (invited_by));
+diesel::joinable!(suppressed_identifiers -> erasure_requests (erasure_request_id));
+diesel::joinable!(suppressed_identifiers -> projects (project_id));
 diesel::joinable!(team_members -> teams (team_id));
 diesel::joinable!(team_members -> users (user_id));
 diesel::joinable!(team_projects -> projects (project_id));
 diesel::joinable!(team_projects -> teams (team_id));
+diesel::joinable!(webhook_dead_letters -> webhook_destinations (destination_id));
+diesel::joinable!(webhook_destinations -> projects (project_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     allowed_domains,
@@ -194,11 +303,17 @@ diesel::allow_tables_to_appear_in_same_query!(
     board_widgets,
     boards,
     segments,
+    data_exports,
+    erasure_requests,
     funnels,
     invitations,
     projects,
+    retention_policies,
+    suppressed_identifiers,
     team_members,
     team_projects,
     teams,
     users,
+    webhook_dead_letters,
+    webhook_destinations,
 );
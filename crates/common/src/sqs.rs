@@ -1,13 +1,21 @@
 use anyhow::{Context, Result};
 pub use aws_sdk_sqs::types::Message;
 use aws_sdk_sqs::types::{
-    BatchResultErrorEntry, DeleteMessageBatchRequestEntry, MessageAttributeValue,
-    SendMessageBatchRequestEntry,
+    BatchResultErrorEntry, ChangeMessageVisibilityBatchRequestEntry,
+    DeleteMessageBatchRequestEntry, MessageAttributeValue, MessageSystemAttributeName,
+    QueueAttributeName, SendMessageBatchRequestEntry,
 };
 use aws_sdk_sqs::{Client, config::Region};
+use chrono::{DateTime, Utc};
 
 use crate::event::EnrichedEvent;
 
+/// Derives the dead-letter queue URL for a source queue by convention: the
+/// source queue URL with a `-dlq` suffix.
+pub fn default_dlq_url(queue_url: &str) -> String {
+    format!("{queue_url}-dlq")
+}
+
 /// Builds an SQS [`Client`] from the given region and optional endpoint URL.
 pub async fn build_sqs_client(region: &str, endpoint_url: Option<&str>) -> Result<Client> {
     let mut config_loader = aws_config::defaults(aws_config::BehaviorVersion::latest())
@@ -38,6 +46,35 @@ impl SqsProducer {
         &self.client
     }
 
+    /// Returns the approximate number of messages waiting in the queue
+    /// (visible plus delayed), as reported by `GetQueueAttributes`.
+    ///
+    /// Messages currently held by consumers (in flight) are not counted: they
+    /// are already being worked on and do not represent backlog.
+    pub async fn approximate_queue_depth(&self, queue_url: &str) -> Result<u64> {
+        let output = self
+            .client
+            .get_queue_attributes()
+            .queue_url(queue_url)
+            .attribute_names(QueueAttributeName::ApproximateNumberOfMessages)
+            .attribute_names(QueueAttributeName::ApproximateNumberOfMessagesDelayed)
+            .send()
+            .await
+            .context("SQS GetQueueAttributes failed")?;
+
+        let attributes = output.attributes.unwrap_or_default();
+        let depth = [
+            QueueAttributeName::ApproximateNumberOfMessages,
+            QueueAttributeName::ApproximateNumberOfMessagesDelayed,
+        ]
+        .iter()
+        .filter_map(|name| attributes.get(name))
+        .filter_map(|v| v.parse::<u64>().ok())
+        .sum();
+
+        Ok(depth)
+    }
+
     /// Sends a batch of enriched events to the given SQS queue.
     /// SQS allows at most 10 messages per `SendMessageBatch` call, so this
     /// method chunks the events accordingly.
@@ -124,13 +161,15 @@ impl SqsConsumer {
         Ok(Self { client })
     }
 
-    /// Receives messages from the given SQS queue.
+    /// Receives messages from the given SQS queue, keeping them invisible
+    /// for `visibility_timeout_secs` rather than the queue's default.
     #[tracing::instrument(name = "sqs.receive", skip(self))]
     pub async fn receive_messages(
         &self,
         queue_url: &str,
         max: i32,
         wait_secs: i32,
+        visibility_timeout_secs: i32,
     ) -> Result<Vec<Message>> {
         let output = self
             .client
@@ -138,6 +177,7 @@ impl SqsConsumer {
             .queue_url(queue_url)
             .max_number_of_messages(max)
             .wait_time_seconds(wait_secs)
+            .visibility_timeout(visibility_timeout_secs)
             .message_attribute_names("All")
             .send()
             .await
@@ -159,6 +199,72 @@ impl SqsConsumer {
         Ok(())
     }
 
+    /// Sets the visibility timeout of received messages, in chunks of 10.
+    /// A timeout of 0 makes them visible again immediately. Returns the
+    /// receipt handles SQS rejected, e.g. because the message was already
+    /// deleted or its receipt expired.
+    ///
+    /// A failed chunk is logged and skipped so the remaining chunks are
+    /// still changed; the call fails only if every chunk failed.
+    #[tracing::instrument(name = "sqs.change_visibility", skip(self, receipt_handles), fields(count = receipt_handles.len()))]
+    pub async fn change_visibility(
+        &self,
+        queue_url: &str,
+        receipt_handles: &[String],
+        timeout_secs: i32,
+    ) -> Result<Vec<String>> {
+        let mut rejected = Vec::new();
+        let mut last_error = None;
+        let mut changed_chunks = 0;
+        for chunk in receipt_handles.chunks(10) {
+            let entries: Vec<ChangeMessageVisibilityBatchRequestEntry> = chunk
+                .iter()
+                .enumerate()
+                .map(|(i, receipt_handle)| {
+                    ChangeMessageVisibilityBatchRequestEntry::builder()
+                        .id(format!("vis_{i}"))
+                        .receipt_handle(receipt_handle)
+                        .visibility_timeout(timeout_secs)
+                        .build()
+                        .expect("Failed to build ChangeMessageVisibilityBatchRequestEntry")
+                })
+                .collect();
+
+            let output = match self
+                .client
+                .change_message_visibility_batch()
+                .queue_url(queue_url)
+                .set_entries(Some(entries))
+                .send()
+                .await
+                .context("SQS ChangeMessageVisibilityBatch failed")
+            {
+                Ok(output) => output,
+                Err(e) => {
+                    tracing::warn!(
+                        count = chunk.len(),
+                        error = %format!("{e:#}"),
+                        "failed to change visibility of message chunk"
+                    );
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            changed_chunks += 1;
+
+            rejected.extend(output.failed.iter().filter_map(|f| {
+                f.id.strip_prefix("vis_")
+                    .and_then(|i| i.parse::<usize>().ok())
+                    .and_then(|i| chunk.get(i).cloned())
+            }));
+        }
+
+        match last_error {
+            Some(e) if changed_chunks == 0 => Err(e),
+            _ => Ok(rejected),
+        }
+    }
+
     /// Deletes a batch of messages from the queue.
     /// `entries` is a vector of `(id, receipt_handle)` pairs.
     #[tracing::instrument(name = "sqs.delete_batch", skip(self, entries))]
@@ -167,27 +273,179 @@ impl SqsConsumer {
         queue_url: &str,
         entries: Vec<(String, String)>,
     ) -> Result<()> {
-        for chunk in entries.chunks(10) {
-            let delete_entries: Vec<DeleteMessageBatchRequestEntry> = chunk
+        delete_entries(&self.client, queue_url, &entries).await
+    }
+}
+
+/// Deletes `(id, receipt_handle)` entries in chunks of 10 (the SQS batch limit).
+async fn delete_entries(
+    client: &Client,
+    queue_url: &str,
+    entries: &[(String, String)],
+) -> Result<()> {
+    for chunk in entries.chunks(10) {
+        let delete_entries: Vec<DeleteMessageBatchRequestEntry> = chunk
+            .iter()
+            .map(|(id, receipt_handle)| {
+                DeleteMessageBatchRequestEntry::builder()
+                    .id(id)
+                    .receipt_handle(receipt_handle)
+                    .build()
+                    .expect("Failed to build DeleteMessageBatchRequestEntry")
+            })
+            .collect();
+
+        client
+            .delete_message_batch()
+            .queue_url(queue_url)
+            .set_entries(Some(delete_entries))
+            .send()
+            .await
+            .context("SQS DeleteMessageBatch failed")?;
+    }
+
+    Ok(())
+}
+
+/// A message read back from a dead-letter queue.
+#[derive(Debug, Clone)]
+pub struct DlqMessage {
+    pub message_id: String,
+    /// Receipt handle from the scan that returned this message; valid until
+    /// the message is released or its visibility timeout expires.
+    pub receipt_handle: String,
+    pub body: String,
+    /// The `error_reason` attribute attached when the message was dead-lettered.
+    pub error_reason: Option<String>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub receive_count: u32,
+}
+
+/// Reads and manages messages on a dead-letter queue.
+///
+/// SQS has no "peek": messages are inspected by receiving them with a
+/// visibility timeout, which hides them from other readers until they are
+/// either deleted or released back to the queue with [`DlqClient::release`].
+pub struct DlqClient {
+    client: Client,
+}
+
+impl DlqClient {
+    /// Creates a new DLQ client. If `endpoint_url` is provided, it overrides the default
+    /// AWS endpoint (useful for local development with LocalStack).
+    pub async fn new(region: &str, endpoint_url: Option<&str>) -> Result<Self> {
+        let client = build_sqs_client(region, endpoint_url).await?;
+        Ok(Self { client })
+    }
+
+    /// Receives up to `max_messages` messages from the DLQ, hiding each one for
+    /// `visibility_secs`. Stops early once a receive call returns nothing.
+    ///
+    /// Callers must [`release`](Self::release) or [`delete`](Self::delete) the
+    /// returned messages; otherwise they stay hidden until the timeout expires.
+    #[tracing::instrument(name = "sqs.dlq_scan", skip(self))]
+    pub async fn scan(
+        &self,
+        dlq_url: &str,
+        max_messages: usize,
+        visibility_secs: i32,
+    ) -> Result<Vec<DlqMessage>> {
+        let mut out: Vec<DlqMessage> = Vec::new();
+
+        while out.len() < max_messages {
+            let batch = (max_messages - out.len()).min(10) as i32;
+            let output = self
+                .client
+                .receive_message()
+                .queue_url(dlq_url)
+                .max_number_of_messages(batch)
+                .wait_time_seconds(1)
+                .visibility_timeout(visibility_secs)
+                .message_attribute_names("error_reason")
+                .message_system_attribute_names(MessageSystemAttributeName::SentTimestamp)
+                .message_system_attribute_names(MessageSystemAttributeName::ApproximateReceiveCount)
+                .send()
+                .await
+                .context("SQS ReceiveMessage failed")?;
+
+            let messages = output.messages.unwrap_or_default();
+            if messages.is_empty() {
+                break;
+            }
+
+            for msg in messages {
+                let (Some(message_id), Some(receipt_handle)) =
+                    (msg.message_id(), msg.receipt_handle())
+                else {
+                    continue;
+                };
+                if out.iter().any(|m| m.message_id == message_id) {
+                    continue;
+                }
+
+                let system_attr = |name: MessageSystemAttributeName| {
+                    msg.attributes().and_then(|attrs| attrs.get(&name)).cloned()
+                };
+
+                out.push(DlqMessage {
+                    message_id: message_id.to_string(),
+                    receipt_handle: receipt_handle.to_string(),
+                    body: msg.body().unwrap_or_default().to_string(),
+                    error_reason: msg
+                        .message_attributes()
+                        .and_then(|attrs| attrs.get("error_reason"))
+                        .and_then(|v| v.string_value())
+                        .map(str::to_string),
+                    sent_at: system_attr(MessageSystemAttributeName::SentTimestamp)
+                        .and_then(|v| v.parse::<i64>().ok())
+                        .and_then(DateTime::from_timestamp_millis),
+                    receive_count: system_attr(MessageSystemAttributeName::ApproximateReceiveCount)
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or(0),
+                });
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Makes previously scanned messages visible again immediately.
+    #[tracing::instrument(name = "sqs.dlq_release", skip(self, messages), fields(count = messages.len()))]
+    pub async fn release(&self, dlq_url: &str, messages: &[DlqMessage]) -> Result<()> {
+        for chunk in messages.chunks(10) {
+            let entries: Vec<ChangeMessageVisibilityBatchRequestEntry> = chunk
                 .iter()
-                .map(|(id, receipt_handle)| {
-                    DeleteMessageBatchRequestEntry::builder()
-                        .id(id)
-                        .receipt_handle(receipt_handle)
+                .enumerate()
+                .map(|(i, m)| {
+                    ChangeMessageVisibilityBatchRequestEntry::builder()
+                        .id(format!("rel_{i}"))
+                        .receipt_handle(&m.receipt_handle)
+                        .visibility_timeout(0)
                         .build()
-                        .expect("Failed to build DeleteMessageBatchRequestEntry")
+                        .expect("Failed to build ChangeMessageVisibilityBatchRequestEntry")
                 })
                 .collect();
 
             self.client
-                .delete_message_batch()
-                .queue_url(queue_url)
-                .set_entries(Some(delete_entries))
+                .change_message_visibility_batch()
+                .queue_url(dlq_url)
+                .set_entries(Some(entries))
                 .send()
                 .await
-                .context("SQS DeleteMessageBatch failed")?;
+                .context("SQS ChangeMessageVisibilityBatch failed")?;
         }
 
         Ok(())
     }
+
+    /// Permanently removes previously scanned messages from the DLQ.
+    #[tracing::instrument(name = "sqs.dlq_delete", skip(self, messages), fields(count = messages.len()))]
+    pub async fn delete(&self, dlq_url: &str, messages: &[DlqMessage]) -> Result<()> {
+        let entries: Vec<(String, String)> = messages
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (format!("del_{i}"), m.receipt_handle.clone()))
+            .collect();
+        delete_entries(&self.client, dlq_url, &entries).await
+    }
 }
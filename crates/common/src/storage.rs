@@ -0,0 +1,47 @@
+//! Object storage for files kept outside the databases: ch-writer's Parquet
+//! archive and admin-api's data export archives.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail};
+use object_store::ObjectStore;
+use object_store::aws::AmazonS3Builder;
+use object_store::local::LocalFileSystem;
+use object_store::path::Path;
+
+/// Opens the store at `url` and returns it with the prefix under which files
+/// are kept.
+///
+/// `s3://bucket/prefix` uses the standard `AWS_*` credentials and `region`;
+/// `s3_endpoint_url` points it at an S3-compatible store such as MinIO or
+/// LocalStack. Anything else is a local directory (`file:///path` or a plain
+/// path), created if missing.
+pub fn open(
+    url: &str,
+    s3_endpoint_url: Option<&str>,
+    region: &str,
+) -> Result<(Arc<dyn ObjectStore>, Path)> {
+    if let Some(rest) = url.strip_prefix("s3://") {
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            bail!("storage URL must name a bucket: {url}");
+        }
+        let mut builder = AmazonS3Builder::from_env()
+            .with_bucket_name(bucket)
+            .with_region(region);
+        if let Some(endpoint) = s3_endpoint_url {
+            builder = builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http://"));
+        }
+        let store = builder.build().context("failed to configure S3 store")?;
+        Ok((Arc::new(store), Path::from(prefix)))
+    } else {
+        let dir = url.strip_prefix("file://").unwrap_or(url);
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create storage directory {dir}"))?;
+        let store =
+            LocalFileSystem::new_with_prefix(dir).context("failed to open local storage")?;
+        Ok((Arc::new(store), Path::default()))
+    }
+}
@@ -0,0 +1,325 @@
+//! Webhook destinations: per-project rules that forward matching events to
+//! an HTTP endpoint.
+//!
+//! Destinations are configured through admin-api and delivered by ch-writer.
+//! Each delivery is a JSON body `{"destination_id", "events": [...]}` signed
+//! with HMAC-SHA256 over `"{timestamp}.{body}"` using the destination secret.
+//! The hex digest is sent as `X-TrueSight-Signature: sha256=<hex>` alongside
+//! the Unix `X-TrueSight-Timestamp`, so receivers can verify the payload and
+//! reject replays.
+//!
+//! Destinations must be public endpoints: [`check_destination_url`] refuses
+//! local and private hosts when a destination is saved and again before each
+//! delivery, and ch-writer only connects to addresses passing
+//! [`is_public_ip`], so a hostname cannot resolve to an internal service.
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::event::EnrichedEvent;
+use crate::schema::{webhook_dead_letters, webhook_destinations};
+
+/// Header carrying `sha256=<hex HMAC>` of the signed payload.
+pub const SIGNATURE_HEADER: &str = "X-TrueSight-Signature";
+
+/// Header carrying the Unix timestamp (seconds) that was signed.
+pub const TIMESTAMP_HEADER: &str = "X-TrueSight-Timestamp";
+
+/// Event name that matches every event.
+pub const ANY_EVENT: &str = "*";
+
+/// Upper bound on events per delivery.
+pub const MAX_BATCH_SIZE: i32 = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = webhook_destinations)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WebhookDestination {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    pub name: String,
+    pub url: String,
+    pub secret: String,
+    pub event_name: String,
+    pub filters: serde_json::Value,
+    pub batch_size: i32,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = webhook_destinations)]
+pub struct NewWebhookDestination {
+    pub project_id: Uuid,
+    pub name: String,
+    pub url: String,
+    pub secret: String,
+    pub event_name: String,
+    pub filters: serde_json::Value,
+    pub batch_size: i32,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, AsChangeset)]
+#[diesel(table_name = webhook_destinations)]
+pub struct UpdateWebhookDestination {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub secret: Option<String>,
+    pub event_name: Option<String>,
+    pub filters: Option<serde_json::Value>,
+    pub batch_size: Option<i32>,
+    pub enabled: Option<bool>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A delivery that exhausted its retries.
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = webhook_dead_letters)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct WebhookDeadLetter {
+    pub id: Uuid,
+    pub destination_id: Uuid,
+    pub events: serde_json::Value,
+    pub error: String,
+    pub attempts: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = webhook_dead_letters)]
+pub struct NewWebhookDeadLetter {
+    pub destination_id: Uuid,
+    pub events: serde_json::Value,
+    pub error: String,
+    pub attempts: i32,
+}
+
+/// Property condition an event must satisfy. Uses the same shape and
+/// operator names as the admin-api query filters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookFilter {
+    pub property: String,
+    pub operator: String,
+    pub value: Option<serde_json::Value>,
+}
+
+const OPERATORS: &[&str] = &[
+    "eq",
+    "neq",
+    "contains",
+    "in",
+    "not_in",
+    "gt",
+    "gte",
+    "lt",
+    "lte",
+    "exists",
+    "not_exists",
+];
+
+/// Parses and checks a destination's `filters` JSON.
+pub fn parse_filters(filters: &serde_json::Value) -> Result<Vec<WebhookFilter>, String> {
+    let filters: Vec<WebhookFilter> =
+        serde_json::from_value(filters.clone()).map_err(|e| format!("invalid filters: {e}"))?;
+    for f in &filters {
+        if !OPERATORS.contains(&f.operator.as_str()) {
+            return Err(format!("unknown filter operator: {}", f.operator));
+        }
+        if matches!(f.operator.as_str(), "in" | "not_in")
+            && !f.value.as_ref().is_some_and(|v| v.is_array())
+        {
+            return Err(format!(
+                "filter '{}' requires an array of values",
+                f.operator
+            ));
+        }
+    }
+    Ok(filters)
+}
+
+/// Reads `key` from an event: a top-level field if one has that name,
+/// otherwise a top-level property stringified like `properties_map`.
+fn event_value(event: &EnrichedEvent, key: &str) -> Option<String> {
+    let ctx = &event.context;
+    match key {
+        "anonymous_id" => Some(event.anonymous_id.clone()),
+        "app_version" => ctx.app_version.clone(),
+        "device_id" => Some(ctx.device_id.clone()),
+        "device_model" => Some(ctx.device_model.clone()),
+        "environment" => Some(event.environment.clone()),
+        "event_name" => Some(event.event_name.clone()),
+        "event_type" => Some(
+            serde_json::to_value(&event.event_type)
+                .ok()?
+                .as_str()?
+                .to_string(),
+        ),
+        "locale" => Some(ctx.locale.clone()),
+        "network_type" => ctx.network_type.clone(),
+        "os_name" => Some(ctx.os_name.clone()),
+        "os_version" => Some(ctx.os_version.clone()),
+        "sdk_version" => Some(ctx.sdk_version.clone()),
+        "timezone" => Some(ctx.timezone.clone()),
+        "user_id" => event.user_id.clone(),
+        _ => match event.properties.as_ref()?.get(key)? {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(s) => Some(s.clone()),
+            other => Some(other.to_string()),
+        },
+    }
+}
+
+/// Compares numerically when both sides parse as numbers, else lexically.
+fn compare(actual: &str, expected: &str) -> std::cmp::Ordering {
+    match (actual.parse::<f64>(), expected.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal),
+        _ => actual.cmp(expected),
+    }
+}
+
+fn value_str(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl WebhookFilter {
+    /// Returns `true` if `event` satisfies this filter. A missing property
+    /// only satisfies `neq`, `not_in` and `not_exists`.
+    pub fn matches(&self, event: &EnrichedEvent) -> bool {
+        let actual = event_value(event, &self.property);
+        let expected = self.value.as_ref().map(value_str).unwrap_or_default();
+        let list = || -> Vec<String> {
+            self.value
+                .as_ref()
+                .and_then(|v| v.as_array())
+                .map(|items| items.iter().map(value_str).collect())
+                .unwrap_or_default()
+        };
+
+        match (self.operator.as_str(), actual) {
+            ("exists", actual) => actual.is_some(),
+            ("not_exists", actual) => actual.is_none(),
+            ("neq", None) | ("not_in", None) => true,
+            (_, None) => false,
+            ("eq", Some(a)) => a == expected,
+            ("neq", Some(a)) => a != expected,
+            ("contains", Some(a)) => a.to_lowercase().contains(&expected.to_lowercase()),
+            ("in", Some(a)) => list().contains(&a),
+            ("not_in", Some(a)) => !list().contains(&a),
+            ("gt", Some(a)) => compare(&a, &expected).is_gt(),
+            ("gte", Some(a)) => compare(&a, &expected).is_ge(),
+            ("lt", Some(a)) => compare(&a, &expected).is_lt(),
+            ("lte", Some(a)) => compare(&a, &expected).is_le(),
+            _ => false,
+        }
+    }
+}
+
+impl WebhookDestination {
+    /// Returns `true` if `event` belongs to this destination's project and
+    /// matches its event name and every filter.
+    pub fn matches(&self, event: &EnrichedEvent, filters: &[WebhookFilter]) -> bool {
+        self.enabled
+            && event.project_id == self.project_id
+            && (self.event_name == ANY_EVENT || self.event_name == event.event_name)
+            && filters.iter().all(|f| f.matches(event))
+    }
+}
+
+/// Generates a random destination secret (`whsec_` + 48 hex chars).
+pub fn generate_secret() -> String {
+    let bytes: [u8; 24] = rand::thread_rng().r#gen();
+    format!("whsec_{}", hex::encode(bytes))
+}
+
+/// Signs a delivery body; returns the value for [`SIGNATURE_HEADER`].
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Whether `ip` is a public unicast address a destination may connect to.
+/// Loopback, private (RFC 1918, unique local), link-local (including the
+/// `169.254.169.254` metadata endpoint), shared, documentation, multicast
+/// and reserved ranges are refused, also when embedded in IPv6.
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (b == 18 || b == 19))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(v4));
+            }
+            let segments = ip.segments();
+            // NAT64 (64:ff9b::/96) addresses reach the embedded IPv4 address.
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., a, b, c, d] = ip.octets();
+                return is_public_ip(IpAddr::V4(Ipv4Addr::new(a, b, c, d)));
+            }
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || segments[0] & 0xfe00 == 0xfc00
+                || segments[0] & 0xffc0 == 0xfe80
+                || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+        }
+    }
+}
+
+/// Checks that a destination URL is `http` or `https` and does not name a
+/// local host or a non-public address. Hostnames are checked by what they
+/// resolve to at delivery.
+pub fn check_destination_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("invalid url: {e}"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err("url must be http or https".into());
+    }
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| "url must have a host".to_string())?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.')
+        .to_ascii_lowercase();
+
+    let local_name = host == "localhost"
+        || [".localhost", ".local", ".internal"]
+            .iter()
+            .any(|suffix| host.ends_with(suffix));
+
+    match host.parse::<IpAddr>() {
+        Ok(ip) if !is_public_ip(ip) => {
+            Err("url must not point at a private, loopback or link-local address".into())
+        }
+        Err(_) if local_name => Err("url must not point at a local host".into()),
+        _ => Ok(()),
+    }
+}
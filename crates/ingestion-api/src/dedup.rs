@@ -0,0 +1,267 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use redis::aio::ConnectionManager;
+use serde::Serialize;
+use uuid::Uuid;
+
+use truesight_common::config::IngestionConfig;
+use truesight_common::event::IngestEvent;
+
+/// Prefix for dedup keys stored in Redis.
+const REDIS_KEY_PREFIX: &str = "truesight:dedup";
+
+/// Dedup key: `(project_id, event_id)`.
+type DedupKey = (Uuid, Uuid);
+
+/// Point-in-time view of the dedup cache, exposed via `/health`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DedupSnapshot {
+    pub enabled: bool,
+    pub backend: &'static str,
+    pub window_secs: u64,
+    pub duplicates_dropped: u64,
+}
+
+/// Bounded, time-windowed idempotency cache for ingested events.
+///
+/// SDKs retry batches on timeouts, so the same `event_id` regularly arrives
+/// more than once. Events whose `(project_id, event_id)` was already accepted
+/// within the window are dropped before they reach SQS, so analytics queries
+/// do not depend on `ReplacingMergeTree` merges to hide them.
+///
+/// When `REDIS_URL` is set the cache is shared across instances; otherwise
+/// each instance keeps its own in-memory cache bounded by
+/// `DEDUP_MAX_ENTRIES`. Redis failures fail open: events are accepted and
+/// ClickHouse merge-time dedup remains the safety net.
+#[derive(Clone)]
+pub struct DedupCache {
+    backend: Option<Backend>,
+    window: Duration,
+    duplicates_dropped: Arc<AtomicU64>,
+}
+
+#[derive(Clone)]
+enum Backend {
+    Memory(Arc<Mutex<MemoryStore>>),
+    Redis(Box<ConnectionManager>),
+}
+
+impl DedupCache {
+    /// Builds the cache from config. A window of 0 disables dedup entirely.
+    pub async fn from_config(config: &IngestionConfig) -> Self {
+        let window = Duration::from_secs(config.dedup_window_secs);
+
+        let backend = if window.is_zero() {
+            tracing::info!("Event dedup disabled (DEDUP_WINDOW_SECS=0)");
+            None
+        } else if let Some(url) = config.redis_url.as_deref().filter(|u| !u.is_empty()) {
+            match connect_redis(url).await {
+                Ok(conn) => {
+                    tracing::info!(window_secs = window.as_secs(), "Event dedup using Redis");
+                    Some(Backend::Redis(Box::new(conn)))
+                }
+                Err(e) => {
+                    tracing::error!(
+                        error = %e,
+                        "Failed to connect to Redis, falling back to in-memory event dedup"
+                    );
+                    Some(Backend::memory(config.dedup_max_entries))
+                }
+            }
+        } else {
+            tracing::info!(
+                window_secs = window.as_secs(),
+                max_entries = config.dedup_max_entries,
+                "Event dedup using in-memory cache"
+            );
+            Some(Backend::memory(config.dedup_max_entries))
+        };
+
+        Self {
+            backend,
+            window,
+            duplicates_dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Splits `events` into the ones not seen within the window (returned) and
+    /// duplicates (dropped and counted). Returned events are marked as seen.
+    ///
+    /// Duplicates within the same batch are dropped as well.
+    pub async fn filter_new(
+        &self,
+        project_id: Uuid,
+        events: Vec<IngestEvent>,
+    ) -> (Vec<IngestEvent>, usize) {
+        let Some(backend) = &self.backend else {
+            return (events, 0);
+        };
+
+        let keys: Vec<DedupKey> = events.iter().map(|e| (project_id, e.event_id)).collect();
+
+        let fresh = match backend {
+            Backend::Memory(store) => {
+                let now = Instant::now();
+                let mut store = store.lock().unwrap_or_else(|p| p.into_inner());
+                keys.iter()
+                    .map(|key| store.check_and_insert(*key, now, self.window))
+                    .collect::<Vec<bool>>()
+            }
+            Backend::Redis(conn) => {
+                match redis_set_nx((**conn).clone(), &keys, self.window).await {
+                    Ok(fresh) => fresh,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "Redis dedup check failed, accepting batch as-is");
+                        return (events, 0);
+                    }
+                }
+            }
+        };
+
+        let total = events.len();
+        let kept: Vec<IngestEvent> = events
+            .into_iter()
+            .zip(fresh)
+            .filter_map(|(event, is_new)| is_new.then_some(event))
+            .collect();
+
+        let duplicates = total - kept.len();
+        if duplicates > 0 {
+            self.duplicates_dropped
+                .fetch_add(duplicates as u64, Ordering::Relaxed);
+        }
+
+        (kept, duplicates)
+    }
+
+    /// Removes the given events from the cache. Called when enqueueing fails
+    /// so that the client's retry is not mistaken for a duplicate.
+    pub async fn forget(&self, project_id: Uuid, event_ids: &[Uuid]) {
+        let Some(backend) = &self.backend else {
+            return;
+        };
+
+        match backend {
+            Backend::Memory(store) => {
+                let mut store = store.lock().unwrap_or_else(|p| p.into_inner());
+                for event_id in event_ids {
+                    store.seen.remove(&(project_id, *event_id));
+                }
+            }
+            Backend::Redis(conn) => {
+                let keys: Vec<String> = event_ids
+                    .iter()
+                    .map(|event_id| redis_key(&(project_id, *event_id)))
+                    .collect();
+                let mut conn = (**conn).clone();
+                if let Err(e) = redis::cmd("DEL")
+                    .arg(&keys)
+                    .query_async::<()>(&mut conn)
+                    .await
+                {
+                    tracing::warn!(error = %e, "Failed to release dedup keys in Redis");
+                }
+            }
+        }
+    }
+
+    /// Returns a copy of the current cache state.
+    pub fn snapshot(&self) -> DedupSnapshot {
+        DedupSnapshot {
+            enabled: self.backend.is_some(),
+            backend: match self.backend {
+                Some(Backend::Memory(_)) => "memory",
+                Some(Backend::Redis(_)) => "redis",
+                None => "none",
+            },
+            window_secs: self.window.as_secs(),
+            duplicates_dropped: self.duplicates_dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Backend {
+    fn memory(max_entries: usize) -> Self {
+        Backend::Memory(Arc::new(Mutex::new(MemoryStore {
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+            max_entries: max_entries.max(1),
+        })))
+    }
+}
+
+/// In-memory dedup store.
+///
+/// `order` records insertions oldest-first so that expired entries, and the
+/// oldest entries once `max_entries` is reached, can be evicted from the front
+/// in O(1). Entries in `order` whose timestamp no longer matches `seen` are
+/// stale (re-inserted or forgotten) and are skipped on eviction.
+struct MemoryStore {
+    seen: HashMap<DedupKey, Instant>,
+    order: VecDeque<(DedupKey, Instant)>,
+    max_entries: usize,
+}
+
+impl MemoryStore {
+    /// Returns `true` if `key` was not seen within `window`, recording it.
+    fn check_and_insert(&mut self, key: DedupKey, now: Instant, window: Duration) -> bool {
+        self.evict(now, window);
+
+        if let Some(seen_at) = self.seen.get(&key)
+            && now.duration_since(*seen_at) < window
+        {
+            return false;
+        }
+
+        self.seen.insert(key, now);
+        self.order.push_back((key, now));
+        true
+    }
+
+    fn evict(&mut self, now: Instant, window: Duration) {
+        while let Some(&(key, inserted_at)) = self.order.front() {
+            let expired = now.duration_since(inserted_at) >= window;
+            if !expired && self.seen.len() < self.max_entries {
+                break;
+            }
+            self.order.pop_front();
+            if self.seen.get(&key) == Some(&inserted_at) {
+                self.seen.remove(&key);
+            }
+        }
+    }
+}
+
+async fn connect_redis(url: &str) -> redis::RedisResult<ConnectionManager> {
+    let client = redis::Client::open(url)?;
+    ConnectionManager::new(client).await
+}
+
+fn redis_key((project_id, event_id): &DedupKey) -> String {
+    format!("{REDIS_KEY_PREFIX}:{project_id}:{event_id}")
+}
+
+/// Issues one pipelined `SET key 1 NX PX <window>` per key. A key is new if
+/// the `SET` succeeded (`OK`), and a duplicate if it already existed (nil).
+async fn redis_set_nx(
+    mut conn: ConnectionManager,
+    keys: &[DedupKey],
+    window: Duration,
+) -> redis::RedisResult<Vec<bool>> {
+    let window_ms = window.as_millis() as u64;
+    let mut pipe = redis::pipe();
+    for key in keys {
+        pipe.cmd("SET")
+            .arg(redis_key(key))
+            .arg(1)
+            .arg("NX")
+            .arg("PX")
+            .arg(window_ms);
+    }
+    let results: Vec<Option<String>> = pipe.query_async(&mut conn).await?;
+    Ok(results.into_iter().map(|r| r.is_some()).collect())
+}
@@ -1,13 +1,26 @@
 use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 use diesel::prelude::*;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::time::Instant;
 
 use truesight_common::db::get_conn;
 use truesight_common::health::HealthStatus;
 
+use crate::dedup::DedupSnapshot;
+use crate::middleware::backpressure::BackpressureSnapshot;
 use crate::state::AppState;
 
+/// Health response for the ingestion API: the shared [`HealthStatus`] plus the
+/// current backpressure and dedup state.
+#[derive(Debug, Serialize)]
+struct IngestionHealth {
+    #[serde(flatten)]
+    health: HealthStatus,
+    backpressure: BackpressureSnapshot,
+    dedup: DedupSnapshot,
+}
+
 /// Lazy-initialized application start time used to compute uptime.
 static START: std::sync::OnceLock<Instant> = std::sync::OnceLock::new();
 
@@ -19,7 +32,9 @@ fn uptime_seconds() -> u64 {
 ///
 /// Checks the health of downstream dependencies (Postgres, SQS) and returns
 /// an aggregated status.  Returns 200 if all dependencies are healthy, 503
-/// otherwise.
+/// otherwise.  The backpressure state is reported alongside but does not
+/// affect the status code: a backed-up queue is not a reason to recycle the
+/// instance.
 pub async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
     let mut dependencies = HashMap::new();
 
@@ -66,7 +81,14 @@ pub async fn health_check(State(state): State<AppState>) -> impl IntoResponse {
         StatusCode::SERVICE_UNAVAILABLE
     };
 
-    (status_code, Json(health))
+    (
+        status_code,
+        Json(IngestionHealth {
+            health,
+            backpressure: state.backpressure.snapshot(),
+            dedup: state.dedup.snapshot(),
+        }),
+    )
 }
 
 /// Run a simple `SELECT 1` against Postgres to verify connectivity.
@@ -12,12 +12,13 @@ use crate::validation::{validate_batch, validate_event};
 
 /// POST /v1/events/batch
 ///
-/// Accepts a batch of analytics events, validates them, enriches each event
-/// with the authenticated project ID and a server-side timestamp, then
-/// forwards the batch to SQS for asynchronous processing.
+/// Accepts a batch of analytics events, validates them, drops events of
+/// erased users and events already accepted within the dedup window, enriches each remaining event with the
+/// authenticated project ID and a server-side timestamp, then forwards the
+/// batch to SQS for asynchronous processing.
 ///
-/// Returns 202 Accepted on success with the count of accepted events and
-/// the request ID for tracing.
+/// Returns 202 Accepted on success with the count of accepted events, the
+/// count of dropped duplicates, and the request ID for tracing.
 #[tracing::instrument(name = "ingest_batch", skip(state, batch_request), fields(project_id = %project_id.0, request_id = %request_id.0))]
 pub async fn ingest_batch(
     State(state): State<AppState>,
@@ -34,10 +35,44 @@ pub async fn ingest_batch(
         validate_event(event)?;
     }
 
+    // Drop events of erased users. Clients are not told, so the response
+    // does not reveal that a user was erased.
+    let (events, suppressed) = match &state.suppression {
+        Some(suppression) => suppression.filter(project_id.0, batch_request.batch),
+        None => (batch_request.batch, 0),
+    };
+    if suppressed > 0 {
+        tracing::info!(
+            request_id = %request_id.0,
+            project_id = %project_id.0,
+            suppressed,
+            "Dropped events of erased users"
+        );
+    }
+
+    // Drop events already accepted within the dedup window (SDK retries).
+    let (events, duplicates) = state.dedup.filter_new(project_id.0, events).await;
+
+    if events.is_empty() {
+        tracing::info!(
+            request_id = %request_id.0,
+            project_id = %project_id.0,
+            duplicates,
+            "Batch contained only duplicate events"
+        );
+        return Ok((
+            StatusCode::ACCEPTED,
+            Json(json!({
+                "accepted": 0,
+                "duplicates": duplicates,
+                "request_id": request_id.0,
+            })),
+        ));
+    }
+
     // Enrich events with project_id and server_timestamp.
     let now = Utc::now();
-    let enriched_events: Vec<EnrichedEvent> = batch_request
-        .batch
+    let enriched_events: Vec<EnrichedEvent> = events
         .into_iter()
         .map(|event| EnrichedEvent {
             event_id: event.event_id,
@@ -59,20 +94,24 @@ pub async fn ingest_batch(
 
     let accepted_count = enriched_events.len();
 
-    // Send to SQS.
-    state
+    // Send to SQS. On failure, release the dedup entries so the client's
+    // retry of this batch is not dropped as a duplicate.
+    if let Err(e) = state
         .sqs_producer
         .send_batch(&enriched_events, &state.config.sqs_queue_url)
         .await
-        .map_err(|e| {
-            tracing::error!(error = %e, "Failed to send events to SQS");
-            AppError::Sqs(format!("Failed to enqueue events: {e}"))
-        })?;
+    {
+        tracing::error!(error = %e, "Failed to send events to SQS");
+        let event_ids: Vec<_> = enriched_events.iter().map(|e| e.event_id).collect();
+        state.dedup.forget(project_id.0, &event_ids).await;
+        return Err(AppError::Sqs(format!("Failed to enqueue events: {e}")));
+    }
 
     tracing::info!(
         request_id = %request_id.0,
         project_id = %project_id.0,
         accepted = accepted_count,
+        duplicates,
         "Batch ingested successfully"
     );
 
@@ -80,6 +119,7 @@ pub async fn ingest_batch(
         StatusCode::ACCEPTED,
         Json(json!({
             "accepted": accepted_count,
+            "duplicates": duplicates,
             "request_id": request_id.0,
         })),
     ))
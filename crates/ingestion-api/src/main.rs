@@ -1,3 +1,4 @@
+mod dedup;
 mod handlers;
 mod middleware;
 mod routes;
@@ -5,6 +6,7 @@ mod state;
 mod validation;
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use tokio::net::TcpListener;
 use tower_http::cors::{Any, CorsLayer};
@@ -14,10 +16,13 @@ use tracing::info;
 use truesight_common::auth::ApiKeyCache;
 use truesight_common::config::IngestionConfig;
 use truesight_common::db::create_pool;
+use truesight_common::erasure::Suppression;
 use truesight_common::shutdown::shutdown_signal;
 use truesight_common::sqs::SqsProducer;
 use truesight_common::telemetry::init_telemetry;
 
+use crate::dedup::DedupCache;
+use crate::middleware::backpressure::Backpressure;
 use crate::middleware::rate_limit::RateLimiterMap;
 use crate::state::AppState;
 
@@ -50,12 +55,37 @@ async fn main() -> anyhow::Result<()> {
     // Create the API key cache.
     let api_key_cache = ApiKeyCache::new();
 
+    // Start sampling queue depth so ingestion can shed load when the writer
+    // falls behind.
+    let sqs_producer = Arc::new(sqs_producer);
+    let backpressure = Backpressure::from_config(&config);
+    backpressure.spawn_monitor(Arc::clone(&sqs_producer), config.sqs_queue_url.clone());
+
+    // Create the event dedup cache (Redis-backed when REDIS_URL is set).
+    let dedup = DedupCache::from_config(&config).await;
+
+    // Drop events of users erased through admin-api.
+    let suppression = match config.erasure_hash_key.clone().filter(|k| !k.is_empty()) {
+        Some(key) => Some(Suppression::spawn(
+            db_pool.clone(),
+            key,
+            Duration::from_secs(config.suppression_refresh_secs),
+        )),
+        None => {
+            tracing::warn!("ERASURE_HASH_KEY not set, events of erased users are not suppressed");
+            None
+        }
+    };
+
     // Build shared application state.
     let state = AppState {
-        sqs_producer: Arc::new(sqs_producer),
+        sqs_producer,
         api_key_cache: Arc::new(api_key_cache),
         db_pool,
         config: Arc::new(config),
+        backpressure,
+        dedup,
+        suppression,
     };
 
     // Create the per-project rate limiter map and inject it as a layer.
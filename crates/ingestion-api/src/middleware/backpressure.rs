@@ -0,0 +1,200 @@
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use truesight_common::config::IngestionConfig;
+use truesight_common::error::AppError;
+use truesight_common::sqs::SqsProducer;
+
+use crate::middleware::api_key_auth::Environment;
+use crate::state::AppState;
+
+/// How aggressively ingestion is currently shedding load.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PressureLevel {
+    /// Queue depth is below every threshold; all keys are accepted.
+    Normal,
+    /// Queue depth is above the soft limit; low-priority (test) keys are rejected.
+    ShedLowPriority,
+    /// Queue depth is above the hard limit; every key is rejected.
+    ShedAll,
+}
+
+/// Point-in-time view of the backpressure monitor, exposed via `/health`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackpressureSnapshot {
+    pub enabled: bool,
+    pub level: PressureLevel,
+    pub queue_depth: Option<u64>,
+    pub soft_limit: Option<u64>,
+    pub hard_limit: Option<u64>,
+    pub last_checked_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+/// Shared backpressure state.
+///
+/// A background task periodically samples the approximate SQS queue depth and
+/// derives a [`PressureLevel`] from the configured thresholds:
+/// - depth >= `BACKPRESSURE_SOFT_LIMIT`: reject low-priority (test) keys
+/// - depth >= `BACKPRESSURE_HARD_LIMIT`: reject every key
+///
+/// Rejected requests receive 503 Service Unavailable with a `Retry-After`
+/// header. When neither limit is configured, the monitor is disabled and all
+/// requests pass through.
+#[derive(Debug, Clone)]
+pub struct Backpressure {
+    inner: Arc<RwLock<BackpressureSnapshot>>,
+    poll_interval: Duration,
+    retry_after_secs: u64,
+}
+
+impl Backpressure {
+    pub fn from_config(config: &IngestionConfig) -> Self {
+        let soft_limit = config.backpressure_soft_limit;
+        let hard_limit = config.backpressure_hard_limit;
+
+        Self {
+            inner: Arc::new(RwLock::new(BackpressureSnapshot {
+                enabled: soft_limit.is_some() || hard_limit.is_some(),
+                level: PressureLevel::Normal,
+                queue_depth: None,
+                soft_limit,
+                hard_limit,
+                last_checked_at: None,
+                last_error: None,
+            })),
+            poll_interval: Duration::from_secs(config.backpressure_poll_interval_secs.max(1)),
+            retry_after_secs: config.backpressure_retry_after_secs.max(1),
+        }
+    }
+
+    /// Returns a copy of the current monitor state.
+    pub fn snapshot(&self) -> BackpressureSnapshot {
+        self.inner
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Returns `true` if a request authenticated with a key for `environment`
+    /// should be rejected at the current pressure level.
+    fn should_reject(&self, environment: &str) -> bool {
+        match self.snapshot().level {
+            PressureLevel::Normal => false,
+            PressureLevel::ShedLowPriority => !is_high_priority(environment),
+            PressureLevel::ShedAll => true,
+        }
+    }
+
+    /// Records a successful queue depth sample and recomputes the level.
+    fn record_depth(&self, depth: u64) {
+        let mut state = self
+            .inner
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let level = if state.hard_limit.is_some_and(|limit| depth >= limit) {
+            PressureLevel::ShedAll
+        } else if state.soft_limit.is_some_and(|limit| depth >= limit) {
+            PressureLevel::ShedLowPriority
+        } else {
+            PressureLevel::Normal
+        };
+
+        if level != state.level {
+            tracing::warn!(
+                queue_depth = depth,
+                from = ?state.level,
+                to = ?level,
+                "Ingestion backpressure level changed"
+            );
+        }
+
+        state.level = level;
+        state.queue_depth = Some(depth);
+        state.last_checked_at = Some(Utc::now());
+        state.last_error = None;
+    }
+
+    /// Records a failed sample. The previous level is kept so that a flaky
+    /// SQS control plane neither starts nor stops shedding on its own.
+    fn record_error(&self, error: String) {
+        let mut state = self
+            .inner
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.last_checked_at = Some(Utc::now());
+        state.last_error = Some(error);
+    }
+
+    /// Spawns the background task that samples the queue depth. Does nothing
+    /// when backpressure is disabled.
+    pub fn spawn_monitor(&self, sqs_producer: Arc<SqsProducer>, queue_url: String) {
+        if !self.snapshot().enabled {
+            tracing::info!("Ingestion backpressure disabled (no thresholds configured)");
+            return;
+        }
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(this.poll_interval);
+            loop {
+                interval.tick().await;
+                match sqs_producer.approximate_queue_depth(&queue_url).await {
+                    Ok(depth) => this.record_depth(depth),
+                    Err(e) => {
+                        tracing::warn!(error = %e, "Failed to sample SQS queue depth");
+                        this.record_error(e.to_string());
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Live keys are high priority; everything else (test keys) is shed first.
+/// Mirrors the environment mapping used when generating API keys.
+fn is_high_priority(environment: &str) -> bool {
+    matches!(environment, "live" | "production")
+}
+
+/// Middleware that rejects ingestion requests while the queue is backed up.
+///
+/// Requires that `Environment` has already been injected into request
+/// extensions (i.e., this middleware must run after `api_key_auth_middleware`).
+pub async fn backpressure_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let environment = match request.extensions().get::<Environment>() {
+        Some(env) => env.0.clone(),
+        None => {
+            // Should never happen if middleware ordering is correct.
+            return AppError::Unauthorized("Missing project context".to_string()).into_response();
+        }
+    };
+
+    if !state.backpressure.should_reject(&environment) {
+        return next.run(request).await;
+    }
+
+    let mut response = AppError::ServiceUnavailable(
+        "Ingestion is temporarily throttled because the event queue is backed up".to_string(),
+    )
+    .into_response();
+    response.headers_mut().insert(
+        "retry-after",
+        HeaderValue::from(state.backpressure.retry_after_secs),
+    );
+    response
+}
@@ -1,4 +1,5 @@
 pub mod api_key_auth;
+pub mod backpressure;
 pub mod rate_limit;
 pub mod request_id;
 pub mod zstd_decode;
@@ -3,12 +3,20 @@ use std::sync::Arc;
 use truesight_common::auth::ApiKeyCache;
 use truesight_common::config::IngestionConfig;
 use truesight_common::db::DbPool;
+use truesight_common::erasure::Suppression;
 use truesight_common::sqs::SqsProducer;
 
+use crate::dedup::DedupCache;
+use crate::middleware::backpressure::Backpressure;
+
 #[derive(Clone)]
 pub struct AppState {
     pub sqs_producer: Arc<SqsProducer>,
     pub api_key_cache: Arc<ApiKeyCache>,
     pub db_pool: DbPool,
     pub config: Arc<IngestionConfig>,
+    pub backpressure: Backpressure,
+    pub dedup: DedupCache,
+    /// `None` when `ERASURE_HASH_KEY` is not configured.
+    pub suppression: Option<Suppression>,
 }
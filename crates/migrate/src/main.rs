@@ -0,0 +1,311 @@
+//! TrueSight ClickHouse migration runner
+//!
+//! Applies the SQL files in `clickhouse-migrations/` in filename order and
+//! records each applied file with its SHA-256 checksum in
+//! `<database>.schema_migrations`, so every migration runs once. A file
+//! changed after it was applied is refused: write a new migration instead.
+//! The files reference the `truesight` database, which is replaced by
+//! `CLICKHOUSE_DATABASE`.
+//!
+//! `truesight-migrate --dry-run` prints what would run without changing
+//! anything; `truesight-migrate status` lists every migration's state.
+//!
+//! Migrations applied by the former shell runner have no checksum; theirs is
+//! recorded from the current file on the next run.
+
+mod migration;
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+use clap::{Parser, Subcommand};
+use serde::Deserialize;
+
+use crate::migration::Migration;
+
+#[derive(Parser)]
+#[command(
+    name = "truesight-migrate",
+    about = "Apply TrueSight ClickHouse migrations"
+)]
+struct Args {
+    /// Directory holding the migration files
+    #[arg(
+        long,
+        env = "CLICKHOUSE_MIGRATIONS_DIR",
+        default_value = "clickhouse-migrations"
+    )]
+    dir: PathBuf,
+
+    /// ClickHouse HTTP endpoint
+    #[arg(long, env = "CLICKHOUSE_URL", default_value = "http://localhost:8123")]
+    url: String,
+
+    /// ClickHouse user
+    #[arg(long, env = "CLICKHOUSE_USER", default_value = "default")]
+    user: String,
+
+    /// ClickHouse password
+    #[arg(
+        long,
+        env = "CLICKHOUSE_PASSWORD",
+        default_value = "",
+        hide_env_values = true
+    )]
+    password: String,
+
+    /// Database to migrate; replaces `truesight` in the migration files
+    #[arg(long, env = "CLICKHOUSE_DATABASE", default_value = "truesight")]
+    database: String,
+
+    /// Print the pending migrations' statements without running them
+    #[arg(long)]
+    dry_run: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List migrations and whether each is applied, pending or modified
+    Status,
+}
+
+#[derive(clickhouse::Row, Deserialize)]
+struct AppliedRow {
+    filename: String,
+    checksum: String,
+}
+
+#[derive(clickhouse::Row, Deserialize)]
+struct ColumnRow {
+    name: String,
+}
+
+/// Checksums of applied migrations by filename; empty for migrations
+/// applied before checksums were recorded.
+type Applied = BTreeMap<String, String>;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenvy::dotenv().ok();
+    let args = Args::parse();
+    migration::validate_database(&args.database)?;
+
+    let migrations = migration::load(&args.dir)?;
+    let client = clickhouse::Client::default()
+        .with_url(&args.url)
+        .with_user(&args.user)
+        .with_password(&args.password);
+
+    println!("ClickHouse URL: {}", args.url);
+    println!("ClickHouse database: {}", args.database);
+    println!("Migrations dir: {}", args.dir.display());
+    println!("---");
+
+    match args.command {
+        Some(Command::Status) => status(&client, &args.database, &migrations).await,
+        None => migrate(&client, &args.database, &migrations, args.dry_run).await,
+    }
+}
+
+async fn status(
+    client: &clickhouse::Client,
+    database: &str,
+    migrations: &[Migration],
+) -> Result<()> {
+    let applied = load_applied(client, database).await?.unwrap_or_default();
+    for migration in migrations {
+        let state = match applied.get(&migration.filename) {
+            None => "pending",
+            Some(checksum) if checksum.is_empty() => "applied (no checksum)",
+            Some(checksum) if *checksum == migration.checksum => "applied",
+            Some(_) => "MODIFIED",
+        };
+        println!("{:<50} {state}", migration.filename);
+    }
+    for filename in applied.keys() {
+        if !migrations.iter().any(|m| m.filename == *filename) {
+            println!("{filename:<50} applied (file missing)");
+        }
+    }
+    Ok(())
+}
+
+async fn migrate(
+    client: &clickhouse::Client,
+    database: &str,
+    migrations: &[Migration],
+    dry_run: bool,
+) -> Result<()> {
+    if !dry_run {
+        ensure_tracking_table(client, database).await?;
+    }
+    let applied = load_applied(client, database).await?.unwrap_or_default();
+
+    let modified: Vec<&str> = migrations
+        .iter()
+        .filter(|m| {
+            applied
+                .get(&m.filename)
+                .is_some_and(|checksum| !checksum.is_empty() && *checksum != m.checksum)
+        })
+        .map(|m| m.filename.as_str())
+        .collect();
+    if !modified.is_empty() {
+        bail!(
+            "migrations changed after being applied: {}. Restore them and add a new migration instead",
+            modified.join(", ")
+        );
+    }
+
+    for migration in migrations {
+        if applied
+            .get(&migration.filename)
+            .is_some_and(String::is_empty)
+        {
+            if dry_run {
+                println!("Would record checksum of {}", migration.filename);
+            } else {
+                record(client, database, migration).await?;
+                println!(
+                    "Recorded checksum of {} (applied before checksums were tracked)",
+                    migration.filename
+                );
+            }
+        }
+    }
+
+    let pending: Vec<&Migration> = migrations
+        .iter()
+        .filter(|m| !applied.contains_key(&m.filename))
+        .collect();
+    if pending.is_empty() {
+        println!("No pending migrations.");
+        return Ok(());
+    }
+
+    for migration in pending {
+        let statements = migration.statements(database);
+        if dry_run {
+            println!(
+                "-- Would run {} ({} statements)",
+                migration.filename,
+                statements.len()
+            );
+            for statement in &statements {
+                println!("{statement};\n");
+            }
+            continue;
+        }
+
+        print!("Running migration: {} ... ", migration.filename);
+        std::io::stdout().flush().ok();
+        for (i, statement) in statements.iter().enumerate() {
+            // `?` is a bind placeholder for the client; `??` is a literal `?`.
+            if let Err(e) = client.query(&statement.replace('?', "??")).execute().await {
+                println!("FAILED");
+                bail!(
+                    "{} failed at statement {} of {}: {e}\n\
+                     Statements before it were applied; fix the cause and complete or \
+                     revert them by hand before rerunning.\n{statement}",
+                    migration.filename,
+                    i + 1,
+                    statements.len()
+                );
+            }
+        }
+        record(client, database, migration).await?;
+        println!("done.");
+    }
+
+    println!("---");
+    if dry_run {
+        println!("Dry run: nothing was applied.");
+    } else {
+        println!("All ClickHouse migrations applied successfully.");
+    }
+    Ok(())
+}
+
+/// Creates the database and `schema_migrations`, adding the checksum column
+/// to a table created by the former shell runner.
+async fn ensure_tracking_table(client: &clickhouse::Client, database: &str) -> Result<()> {
+    let statements = [
+        format!("CREATE DATABASE IF NOT EXISTS {database}"),
+        format!(
+            "CREATE TABLE IF NOT EXISTS {database}.schema_migrations ( \
+             filename String, \
+             checksum String DEFAULT '', \
+             applied_at DateTime DEFAULT now() \
+             ) ENGINE = MergeTree() ORDER BY filename"
+        ),
+        format!(
+            "ALTER TABLE {database}.schema_migrations \
+             ADD COLUMN IF NOT EXISTS checksum String DEFAULT '' AFTER filename"
+        ),
+    ];
+    for statement in statements {
+        client
+            .query(&statement)
+            .execute()
+            .await
+            .context("failed to create schema_migrations")?;
+    }
+    Ok(())
+}
+
+/// Reads the applied migrations, or `None` if `schema_migrations` does not
+/// exist yet.
+async fn load_applied(client: &clickhouse::Client, database: &str) -> Result<Option<Applied>> {
+    let columns = client
+        .query(
+            "SELECT name FROM system.columns \
+             WHERE database = ? AND table = 'schema_migrations'",
+        )
+        .bind(database)
+        .fetch_all::<ColumnRow>()
+        .await
+        .context("failed to read system.columns")?;
+    if columns.is_empty() {
+        return Ok(None);
+    }
+    let checksum = if columns.iter().any(|c| c.name == "checksum") {
+        "checksum"
+    } else {
+        "''"
+    };
+
+    let rows = client
+        .query(&format!(
+            "SELECT filename, {checksum} AS checksum FROM {database}.schema_migrations"
+        ))
+        .fetch_all::<AppliedRow>()
+        .await
+        .context("failed to read schema_migrations")?;
+
+    // A legacy row and the row recording its checksum may both exist.
+    let mut applied = Applied::new();
+    for row in rows {
+        let entry = applied.entry(row.filename).or_default();
+        if entry.is_empty() {
+            *entry = row.checksum;
+        }
+    }
+    Ok(Some(applied))
+}
+
+async fn record(client: &clickhouse::Client, database: &str, migration: &Migration) -> Result<()> {
+    client
+        .query(&format!(
+            "INSERT INTO {database}.schema_migrations (filename, checksum) VALUES (?, ?)"
+        ))
+        .bind(migration.filename.as_str())
+        .bind(migration.checksum.as_str())
+        .execute()
+        .await
+        .with_context(|| format!("failed to record {}", migration.filename))
+}
@@ -0,0 +1,155 @@
+//! Migration files: loading, checksums, database substitution and splitting
+//! into statements.
+
+use std::path::Path;
+use std::sync::LazyLock;
+
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+/// Database name the migration files are written against.
+const SOURCE_DATABASE: &str = "truesight";
+
+/// `truesight.<table>` references.
+static QUALIFIED_NAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"\b{SOURCE_DATABASE}\.")).unwrap());
+
+/// `CREATE DATABASE [IF NOT EXISTS] truesight` and the like.
+static DATABASE_NAME: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)(\bDATABASE\s+(?:IF\s+(?:NOT\s+)?EXISTS\s+)?){SOURCE_DATABASE}\b"
+    ))
+    .unwrap()
+});
+
+static VALID_DATABASE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap());
+
+#[derive(Debug)]
+pub struct Migration {
+    pub filename: String,
+    /// SHA-256 of the file as written, before database substitution.
+    pub checksum: String,
+    sql: String,
+}
+
+impl Migration {
+    /// The migration's statements, targeting `database`.
+    pub fn statements(&self, database: &str) -> Vec<String> {
+        split_statements(&substitute_database(&self.sql, database))
+    }
+}
+
+/// Loads the `.sql` files of `dir`, ordered by filename.
+pub fn load(dir: &Path) -> Result<Vec<Migration>> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("cannot read {}", dir.display()))?;
+
+    let mut migrations = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("sql") {
+            continue;
+        }
+        let filename = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .with_context(|| format!("invalid file name {}", path.display()))?
+            .to_string();
+        let sql = std::fs::read_to_string(&path)
+            .with_context(|| format!("cannot read {}", path.display()))?;
+        migrations.push(Migration {
+            filename,
+            checksum: hex::encode(Sha256::digest(sql.as_bytes())),
+            sql,
+        });
+    }
+    migrations.sort_by(|a, b| a.filename.cmp(&b.filename));
+    Ok(migrations)
+}
+
+pub fn validate_database(database: &str) -> Result<()> {
+    if !VALID_DATABASE.is_match(database) {
+        bail!("invalid database name {database:?}");
+    }
+    Ok(())
+}
+
+/// Points the hardcoded `truesight` database references at `database`.
+fn substitute_database(sql: &str, database: &str) -> String {
+    let sql = QUALIFIED_NAME.replace_all(sql, format!("{database}."));
+    DATABASE_NAME
+        .replace_all(&sql, format!("${{1}}{database}"))
+        .into_owned()
+}
+
+/// Splits SQL on `;`, ignoring semicolons in string literals, quoted
+/// identifiers and comments. Statements consisting only of comments are
+/// dropped.
+fn split_statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut has_code = false;
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                has_code = true;
+                current.push(c);
+                while let Some(inner) = chars.next() {
+                    current.push(inner);
+                    if inner == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            current.push(escaped);
+                        }
+                    } else if inner == c {
+                        // A doubled quote is an escaped quote.
+                        if chars.peek() == Some(&c) {
+                            current.push(chars.next().unwrap());
+                        } else {
+                            break;
+                        }
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                current.push(c);
+                for inner in chars.by_ref() {
+                    current.push(inner);
+                    if inner == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                current.push(c);
+                current.push(chars.next().unwrap());
+                let mut prev = '\0';
+                for inner in chars.by_ref() {
+                    current.push(inner);
+                    if prev == '*' && inner == '/' {
+                        break;
+                    }
+                    prev = inner;
+                }
+            }
+            ';' => {
+                if has_code {
+                    statements.push(current.trim().to_string());
+                }
+                current.clear();
+                has_code = false;
+            }
+            _ => {
+                has_code |= !c.is_whitespace();
+                current.push(c);
+            }
+        }
+    }
+    if has_code {
+        statements.push(current.trim().to_string());
+    }
+    statements
+}
@@ -27,3 +27,7 @@ awslocal sqs create-queue \
   }"
 
 echo "SQS queues created successfully"
+
+echo "Creating S3 archive bucket..."
+awslocal s3 mb s3://truesight-archive-local
+echo "S3 archive bucket created successfully"
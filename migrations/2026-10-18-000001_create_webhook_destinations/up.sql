@@ -0,0 +1,24 @@
+CREATE TABLE webhook_destinations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    project_id UUID NOT NULL REFERENCES projects(id),
+    name VARCHAR(255) NOT NULL,
+    url VARCHAR(2048) NOT NULL,
+    secret VARCHAR(255) NOT NULL,
+    event_name VARCHAR(256) NOT NULL,
+    filters JSONB NOT NULL DEFAULT '[]',
+    batch_size INTEGER NOT NULL DEFAULT 100,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX idx_webhook_destinations_project_id ON webhook_destinations(project_id);
+
+CREATE TABLE webhook_dead_letters (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    destination_id UUID NOT NULL REFERENCES webhook_destinations(id) ON DELETE CASCADE,
+    events JSONB NOT NULL,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX idx_webhook_dead_letters_destination_id ON webhook_dead_letters(destination_id);
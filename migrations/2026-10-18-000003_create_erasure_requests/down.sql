@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS suppressed_identifiers;
+DROP TABLE IF EXISTS erasure_requests;
@@ -0,0 +1,28 @@
+CREATE TABLE erasure_requests (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    project_id UUID NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    identifier_type VARCHAR(16) NOT NULL,
+    -- Cleared once the erasure completes; the hash is kept for the audit trail.
+    identifier TEXT,
+    identifier_hash VARCHAR(64) NOT NULL,
+    status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    requested_by VARCHAR(255) NOT NULL,
+    resolved_ids INTEGER NOT NULL DEFAULT 0,
+    tables JSONB NOT NULL DEFAULT '[]',
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    started_at TIMESTAMPTZ,
+    completed_at TIMESTAMPTZ
+);
+CREATE INDEX idx_erasure_requests_project_id ON erasure_requests(project_id);
+CREATE INDEX idx_erasure_requests_status ON erasure_requests(status);
+
+CREATE TABLE suppressed_identifiers (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    project_id UUID NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    identifier_hash VARCHAR(64) NOT NULL,
+    erasure_request_id UUID NOT NULL REFERENCES erasure_requests(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (project_id, identifier_hash)
+);
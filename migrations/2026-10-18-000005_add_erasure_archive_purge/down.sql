@@ -0,0 +1 @@
+ALTER TABLE erasure_requests DROP COLUMN IF EXISTS archive_purged_at;
@@ -0,0 +1,2 @@
+-- Set by ch-writer once the user's events are removed from the Parquet archive.
+ALTER TABLE erasure_requests ADD COLUMN archive_purged_at TIMESTAMPTZ;
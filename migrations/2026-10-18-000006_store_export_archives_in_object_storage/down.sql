@@ -0,0 +1,2 @@
+ALTER TABLE data_exports DROP COLUMN archive_key;
+ALTER TABLE data_exports ADD COLUMN archive BYTEA;
@@ -0,0 +1,6 @@
+-- Export archives move to object storage (EXPORT_URL). Archives stored in
+-- Postgres are dropped; their exports read as expired.
+ALTER TABLE data_exports DROP COLUMN archive;
+-- Key of the zstd-compressed tar archive in the export store; cleared when
+-- the archive is deleted.
+ALTER TABLE data_exports ADD COLUMN archive_key TEXT;